@@ -4,6 +4,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::{Error, ErrorKind, Result};
 
@@ -106,9 +107,9 @@ pub trait AssetSource: Send + Sync + 'static {
 
 /// A set of [`AssetSource`]'s, that is also an [`AssetSource`] that delegates
 /// to multiple other sources based on path scheme.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct AssetSources {
-    sources: HashMap<String, Box<dyn AssetSource>>,
+    sources: HashMap<String, Arc<dyn AssetSource>>,
 }
 
 impl AssetSources {
@@ -122,7 +123,7 @@ impl AssetSources {
     /// If there was an existing set with the same scheme, it will be
     /// overwritten.
     pub fn add_source(&mut self, scheme: impl Into<String>, source: impl AssetSource) {
-        self.sources.insert(scheme.into(), Box::new(source));
+        self.sources.insert(scheme.into(), Arc::new(source));
     }
 
     /// Finds an [`AssetSource`] based on the scheme.
@@ -1,4 +1,17 @@
-use crate::math::Vec4;
+use crate::math::{Mat3, Vec3, Vec4};
+use crate::{Error, ErrorKind, Result};
+
+/// Color space used to interpolate colors, see [`Color::lerp_in`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Gamma-encoded sRGB, like most image editors and CSS by default.
+    Srgb,
+    /// Linear sRGB, the space [`Color`] is stored in.
+    #[default]
+    LinearSrgb,
+    /// Perceptually uniform Oklab, avoiding muddy and dark midpoints.
+    Oklab,
+}
 
 /// Color in linear sRGB color space with premultiplied alpha.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -34,6 +47,146 @@ impl Color {
     pub const fn rgb(r: f32, g: f32, b: f32) -> Color {
         Color::rgba(r, g, b, 1.0)
     }
+
+    /// Creates a color from 8-bit RGBA components in sRGB color space, with
+    /// straight (not premultiplied) alpha.
+    pub fn from_rgba8(r: u8, g: u8, b: u8, a: u8) -> Color {
+        let [r, g, b] = [r, g, b].map(|v| srgb_to_linear(f32::from(v) / 255.0));
+        let a = f32::from(a) / 255.0;
+        Color::rgba(r * a, g * a, b * a, a)
+    }
+
+    /// Parses a hex color in sRGB color space, such as `#ff8000`.
+    ///
+    /// Accepts `RGB`, `RGBA`, `RRGGBB` and `RRGGBBAA` forms, case-insensitive,
+    /// with an optional leading `#`. Alpha defaults to `ff`.
+    pub fn from_hex(hex: &str) -> Result<Color> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        let invalid = || Error::new(ErrorKind::Other, format!("invalid hex color: {hex:?}"));
+
+        let mut nibbles = [0xF; 8];
+        let num_nibbles = digits.len();
+        if !digits.is_ascii() || !matches!(num_nibbles, 3 | 4 | 6 | 8) {
+            return Err(invalid());
+        }
+
+        for (nibble, c) in nibbles.iter_mut().zip(digits.chars()) {
+            *nibble = c.to_digit(16).ok_or_else(invalid)? as u8;
+        }
+
+        let [r, g, b, a] = if num_nibbles <= 4 {
+            [0, 1, 2, 3].map(|i| nibbles[i] * 0x11)
+        } else {
+            [0, 2, 4, 6].map(|i| nibbles[i] << 4 | nibbles[i + 1])
+        };
+
+        Ok(Color::from_rgba8(r, g, b, a))
+    }
+
+    /// Creates a color from hue (in degrees), saturation and lightness in sRGB
+    /// color space.
+    ///
+    /// Alpha is set to `1`.
+    pub fn hsl(h: f32, s: f32, l: f32) -> Color {
+        Color::hsla(h, s, l, 1.0)
+    }
+
+    /// Creates a color from hue (in degrees), saturation, lightness and
+    /// straight (not premultiplied) alpha in sRGB color space.
+    pub fn hsla(h: f32, s: f32, l: f32, a: f32) -> Color {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let m = l - chroma / 2.0;
+        let [r, g, b] = [r, g, b].map(|v| srgb_to_linear(v + m));
+        let a = a.clamp(0.0, 1.0);
+        Color::rgba(r * a, g * a, b * a, a)
+    }
+
+    /// Linearly interpolates between two colors in linear sRGB color space.
+    ///
+    /// Returns `self` at `t = 0` and `other` at `t = 1`.
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        let v = Vec4::from(self).lerp(Vec4::from(other), t);
+        Color::rgba(v.x, v.y, v.z, v.w)
+    }
+
+    /// Linearly interpolates between two colors in the given color space.
+    ///
+    /// Components are premultiplied by alpha in that space before
+    /// interpolating, so transparent colors don't tint the result.
+    pub fn lerp_in(self, other: Color, t: f32, space: ColorSpace) -> Color {
+        if space == ColorSpace::LinearSrgb {
+            return self.lerp(other, t);
+        }
+
+        let a = self.to_space(space);
+        let b = other.to_space(space);
+        Color::from_space(a.lerp(b, t), space)
+    }
+
+    // premultiplied components in the given space
+    fn to_space(self, space: ColorSpace) -> Vec4 {
+        let rgb = if self.a > 0.0 {
+            Vec3::new(self.r, self.g, self.b) / self.a
+        } else {
+            Vec3::ZERO
+        };
+
+        let v = match space {
+            ColorSpace::Srgb => map_vec3(rgb, linear_to_srgb),
+            ColorSpace::LinearSrgb => rgb,
+            ColorSpace::Oklab => linear_to_oklab(rgb),
+        };
+
+        (v * self.a).extend(self.a)
+    }
+
+    fn from_space(v: Vec4, space: ColorSpace) -> Color {
+        let a = v.w.clamp(0.0, 1.0);
+        let v = if a > 0.0 {
+            v.truncate() / v.w
+        } else {
+            Vec3::ZERO
+        };
+
+        let rgb = match space {
+            ColorSpace::Srgb => map_vec3(v, srgb_to_linear),
+            ColorSpace::LinearSrgb => v,
+            ColorSpace::Oklab => oklab_to_linear(v),
+        };
+
+        let rgb = rgb.clamp(Vec3::ZERO, Vec3::ONE) * a;
+        Color::rgba(rgb.x, rgb.y, rgb.z, a)
+    }
+}
+
+pub(crate) fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub(crate) fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
 }
 
 impl From<Color> for Vec4 {
@@ -41,3 +194,87 @@ impl From<Color> for Vec4 {
         Vec4::new(c.r, c.g, c.b, c.a)
     }
 }
+
+// matrices from https://bottosson.github.io/posts/oklab/, kept as published
+#[allow(clippy::excessive_precision)]
+const LINEAR_TO_LMS: Mat3 = Mat3::from_cols(
+    Vec3::new(0.4122214708, 0.2119034982, 0.0883024619),
+    Vec3::new(0.5363325363, 0.6806995451, 0.2817188376),
+    Vec3::new(0.0514459929, 0.1073969566, 0.6299787005),
+);
+
+#[allow(clippy::excessive_precision)]
+const LMS_TO_OKLAB: Mat3 = Mat3::from_cols(
+    Vec3::new(0.2104542553, 1.9779984951, 0.0259040371),
+    Vec3::new(0.7936177850, -2.4285922050, 0.7827717662),
+    Vec3::new(-0.0040720468, 0.4505937099, -0.8086757660),
+);
+
+#[allow(clippy::excessive_precision)]
+const OKLAB_TO_LMS: Mat3 = Mat3::from_cols(
+    Vec3::new(1.0, 1.0, 1.0),
+    Vec3::new(0.3963377774, -0.1055613458, -0.0894841775),
+    Vec3::new(0.2158037573, -0.0638541728, -1.2914855480),
+);
+
+#[allow(clippy::excessive_precision)]
+const LMS_TO_LINEAR: Mat3 = Mat3::from_cols(
+    Vec3::new(4.0767416621, -1.2684380046, -0.0041960863),
+    Vec3::new(-3.3077115913, 2.6097574011, -0.7034186147),
+    Vec3::new(0.2309699292, -0.3413193965, 1.7076147010),
+);
+
+fn linear_to_oklab(rgb: Vec3) -> Vec3 {
+    let lms = map_vec3(LINEAR_TO_LMS * rgb, f32::cbrt);
+    LMS_TO_OKLAB * lms
+}
+
+fn oklab_to_linear(lab: Vec3) -> Vec3 {
+    let lms = map_vec3(OKLAB_TO_LMS * lab, |v| v * v * v);
+    LMS_TO_LINEAR * lms
+}
+
+fn map_vec3(v: Vec3, f: impl Fn(f32) -> f32) -> Vec3 {
+    Vec3::from_array(v.to_array().map(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+
+    // straight 8-bit sRGB components
+    fn to_rgba8(color: Color) -> [u8; 4] {
+        let [r, g, b] = [color.r, color.g, color.b].map(|v| {
+            let v = if color.a > 0.0 { v / color.a } else { 0.0 };
+            (linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8
+        });
+        [r, g, b, (color.a * 255.0).round() as u8]
+    }
+
+    #[test]
+    fn lerp_midpoints() {
+        let mid = |space| to_rgba8(RED.lerp_in(GREEN, 0.5, space));
+        assert_eq!(mid(ColorSpace::Srgb), [127, 127, 0, 255]);
+        assert_eq!(mid(ColorSpace::LinearSrgb), [188, 188, 0, 255]);
+        assert_eq!(mid(ColorSpace::Oklab), [208, 168, 0, 255]);
+    }
+
+    #[test]
+    fn lerp_endpoints() {
+        for space in [ColorSpace::Srgb, ColorSpace::LinearSrgb, ColorSpace::Oklab] {
+            assert_eq!(to_rgba8(RED.lerp_in(GREEN, 0.0, space)), [255, 0, 0, 255]);
+            assert_eq!(to_rgba8(RED.lerp_in(GREEN, 1.0, space)), [0, 255, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn lerp_to_transparent_keeps_color() {
+        for space in [ColorSpace::Srgb, ColorSpace::LinearSrgb, ColorSpace::Oklab] {
+            let mid = RED.lerp_in(Color::TRANSPAENT, 0.5, space);
+            assert_eq!(to_rgba8(mid), [255, 0, 0, 128]);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+/// Defines sizes of four rectangle edges, e.g. insets or margins.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Edges {
+    /// Size of the top edge.
+    pub top: f32,
+    /// Size of the right edge.
+    pub right: f32,
+    /// Size of the bottom edge.
+    pub bottom: f32,
+    /// Size of the left edge.
+    pub left: f32,
+}
+
+impl Edges {
+    /// Creates [`Edges`] with all four specified edges, starting from top in
+    /// clockwise order.
+    #[inline]
+    pub fn new(top: f32, right: f32, bottom: f32, left: f32) -> Edges {
+        Edges {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+
+    /// Creates [`Edges`] with all edges equal to the specified value.
+    #[inline]
+    pub fn new_equal(v: f32) -> Edges {
+        Edges {
+            top: v,
+            right: v,
+            bottom: v,
+            left: v,
+        }
+    }
+}
+
+impl From<[f32; 4]> for Edges {
+    #[inline]
+    fn from([t, r, b, l]: [f32; 4]) -> Self {
+        Self::new(t, r, b, l)
+    }
+}
+
+impl From<f32> for Edges {
+    #[inline]
+    fn from(v: f32) -> Self {
+        Self::new_equal(v)
+    }
+}
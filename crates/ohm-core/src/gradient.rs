@@ -0,0 +1,168 @@
+use std::hash::{Hash, Hasher};
+
+use crate::{Color, ColorSpace};
+
+/// Maximum number of stops a single [`GradientStops`] can hold.
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+/// A single color stop of a gradient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop {
+    /// Position of the stop along the gradient. Range: `0..=1`.
+    pub offset: f32,
+    /// Color at this stop.
+    pub color: Color,
+}
+
+/// A fixed-capacity list of gradient color stops, sorted by offset.
+///
+/// Stops are stored inline (up to [`MAX_GRADIENT_STOPS`]), so this type is
+/// cheap to copy and can be embedded directly into draw commands.
+#[derive(Debug, Clone, Copy)]
+pub struct GradientStops {
+    len: u8,
+    stops: [GradientStop; MAX_GRADIENT_STOPS],
+    space: ColorSpace,
+}
+
+impl GradientStops {
+    /// Creates an empty list of gradient stops.
+    pub const fn new() -> GradientStops {
+        GradientStops {
+            len: 0,
+            stops: [GradientStop {
+                offset: 0.0,
+                color: Color::TRANSPAENT,
+            }; MAX_GRADIENT_STOPS],
+            space: ColorSpace::LinearSrgb,
+        }
+    }
+
+    /// Sets the color space colors are interpolated in, returning the
+    /// modified list. Default: [`ColorSpace::LinearSrgb`].
+    pub fn with_space(mut self, space: ColorSpace) -> GradientStops {
+        self.space = space;
+        self
+    }
+
+    /// Returns the color space colors are interpolated in.
+    pub fn space(&self) -> ColorSpace {
+        self.space
+    }
+
+    /// Appends a stop to the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list already contains [`MAX_GRADIENT_STOPS`] stops.
+    pub fn push(&mut self, offset: f32, color: impl Into<Color>) {
+        assert!(
+            (self.len as usize) < MAX_GRADIENT_STOPS,
+            "too many gradient stops"
+        );
+
+        self.stops[self.len as usize] = GradientStop {
+            offset,
+            color: color.into(),
+        };
+        self.len += 1;
+    }
+
+    /// Appends a stop to the list, returning the modified list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list already contains [`MAX_GRADIENT_STOPS`] stops.
+    pub fn with(mut self, offset: f32, color: impl Into<Color>) -> GradientStops {
+        self.push(offset, color);
+        self
+    }
+
+    /// Returns the stops as a slice.
+    pub fn as_slice(&self) -> &[GradientStop] {
+        &self.stops[..self.len as usize]
+    }
+
+    /// Evaluates the gradient at position `t`, linearly interpolating between
+    /// adjacent stops in the color space of the list.
+    ///
+    /// Positions before the first stop and after the last stop are clamped to
+    /// the colors of those stops. An empty list yields a transparent color.
+    pub fn sample(&self, t: f32) -> Color {
+        let stops = self.as_slice();
+
+        let (Some(first), Some(last)) = (stops.first(), stops.last()) else {
+            return Color::TRANSPAENT;
+        };
+
+        if t <= first.offset {
+            return first.color;
+        }
+
+        for pair in stops.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if t > b.offset {
+                continue;
+            }
+
+            let range = b.offset - a.offset;
+            let f = if range > 0.0 {
+                (t - a.offset) / range
+            } else {
+                1.0
+            };
+
+            return a.color.lerp_in(b.color, f, self.space);
+        }
+
+        last.color
+    }
+}
+
+impl Default for GradientStops {
+    fn default() -> GradientStops {
+        GradientStops::new()
+    }
+}
+
+impl<const N: usize> From<[(f32, Color); N]> for GradientStops {
+    fn from(stops: [(f32, Color); N]) -> GradientStops {
+        let mut res = GradientStops::new();
+        for (offset, color) in stops {
+            res.push(offset, color);
+        }
+        res
+    }
+}
+
+impl PartialEq for GradientStops {
+    fn eq(&self, other: &Self) -> bool {
+        let lhs = self.as_slice().iter();
+        let rhs = other.as_slice().iter();
+        self.len == other.len
+            && self.space == other.space
+            && lhs.zip(rhs).all(|(a, b)| stop_bits(a) == stop_bits(b))
+    }
+}
+
+impl Eq for GradientStops {}
+
+impl Hash for GradientStops {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        self.space.hash(state);
+        for stop in self.as_slice() {
+            stop_bits(stop).hash(state);
+        }
+    }
+}
+
+fn stop_bits(stop: &GradientStop) -> [u32; 5] {
+    [
+        stop.offset.to_bits(),
+        stop.color.r.to_bits(),
+        stop.color.g.to_bits(),
+        stop.color.b.to_bits(),
+        stop.color.a.to_bits(),
+    ]
+}
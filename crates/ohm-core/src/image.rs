@@ -1,11 +1,16 @@
 //! Types and traits related to images.
 
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use crossbeam_queue::SegQueue;
 
-use crate::math::UVec2;
+use crate::asset::{AssetPath, AssetSource};
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::math::{URect, UVec2};
 use crate::{Error, ErrorKind, Result};
 
 slotmap::new_key_type! {
@@ -46,6 +51,67 @@ pub struct ImageData {
     pub size: UVec2,
     /// Tightly packed bytes for each pixel, row-major, without padding.
     pub data: Vec<u8>,
+    /// Whether the color channels are already multiplied by alpha. Only
+    /// meaningful for [`ImageFormat::Srgba8`]. Images that aren't are
+    /// premultiplied when uploaded.
+    pub premultiplied: bool,
+}
+
+impl ImageData {
+    /// Multiplies the color channels by alpha, in linear space, unless the
+    /// image is already premultiplied.
+    pub fn premultiply(&mut self) {
+        if self.premultiplied || self.format != ImageFormat::Srgba8 {
+            self.premultiplied = true;
+            return;
+        }
+
+        for px in self.data.chunks_exact_mut(4) {
+            match px[3] {
+                255 => {}
+                0 => px[..3].fill(0),
+                a => {
+                    let a = f32::from(a) / 255.0;
+                    for v in &mut px[..3] {
+                        *v = encode_srgb(decode_srgb(*v) * a);
+                    }
+                }
+            }
+        }
+
+        self.premultiplied = true;
+    }
+
+    /// Copies a region of the image into a new image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is out of bounds.
+    pub fn crop(&self, rect: URect) -> ImageData {
+        assert!(
+            rect.min.cmple(rect.max).all() && rect.max.cmple(self.size).all(),
+            "region {rect:?} is out of bounds of image of size {}",
+            self.size
+        );
+
+        let pixel_size = self.format.pixel_size();
+        let row_size = self.size.x as usize * pixel_size;
+        let start = rect.min.x as usize * pixel_size;
+        let end = rect.max.x as usize * pixel_size;
+
+        let mut data = Vec::with_capacity((end - start) * rect.size().y as usize);
+        for y in rect.min.y..rect.max.y {
+            let row = y as usize * row_size;
+            data.extend_from_slice(&self.data[row + start..row + end]);
+        }
+
+        ImageData {
+            format: self.format,
+            size: rect.size(),
+            data,
+            premultiplied: self.premultiplied,
+        }
+    }
 }
 
 impl fmt::Debug for ImageData {
@@ -53,6 +119,7 @@ impl fmt::Debug for ImageData {
         f.debug_struct("ImageData")
             .field("format", &self.format)
             .field("size", &self.size)
+            .field("premultiplied", &self.premultiplied)
             .finish_non_exhaustive()
     }
 }
@@ -60,10 +127,24 @@ impl fmt::Debug for ImageData {
 /// Format of image pixels.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ImageFormat {
-    /// sRGB (non-linear, non-premultiplied), 8 bits per channel.
+    /// sRGB (non-linear), 8 bits per channel. See [`ImageData::premultiplied`].
     Srgba8,
     /// Grayscale (linear).
     Gray8,
+    /// RGBA (linear), 8 bits per channel. Used for subpixel antialiased
+    /// glyphs, with per-channel coverage in RGB, and their maximum in alpha.
+    Rgba8,
+}
+
+impl ImageFormat {
+    /// Returns the size of a single pixel in bytes.
+    pub fn pixel_size(self) -> usize {
+        match self {
+            ImageFormat::Srgba8 => 4,
+            ImageFormat::Gray8 => 1,
+            ImageFormat::Rgba8 => 4,
+        }
+    }
 }
 
 /// An image decoder. Can support one or multiple formats
@@ -99,9 +180,9 @@ pub trait ImageDecoder: Send + Sync + 'static {
 /// A set of [`ImageDecoders`]'s, that is also an [`ImageDecoders`] that tries
 /// to decode the provided image using one of the decoders based on the
 /// [`ImageDecoder::probe`] method.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ImageDecoders {
-    decoders: Vec<Box<dyn ImageDecoder>>,
+    decoders: Vec<Arc<dyn ImageDecoder>>,
 }
 
 impl ImageDecoders {
@@ -112,7 +193,7 @@ impl ImageDecoders {
 
     /// Registers an [`ImageDecoder`] into the set.
     pub fn add_decoder(&mut self, decoder: impl ImageDecoder) {
-        self.decoders.push(Box::new(decoder));
+        self.decoders.push(Arc::new(decoder));
     }
 }
 
@@ -148,3 +229,182 @@ impl fmt::Debug for ImageDecoders {
         f.debug_struct("ImageDecoders").finish_non_exhaustive()
     }
 }
+
+/// A decoded animation, such as an animated GIF or APNG.
+///
+/// Every frame is already composited onto the full canvas, so any frame can
+/// be shown on its own, e.g. by writing it into a dynamic image (see
+/// [`TextureCache::add_dynamic_image`](crate::texture::TextureCache::add_dynamic_image)).
+#[derive(Debug, Clone, Default)]
+pub struct AnimatedImage {
+    /// Frames in playback order, with how long each of them is shown.
+    pub frames: Vec<(ImageData, Duration)>,
+}
+
+impl AnimatedImage {
+    /// Returns the duration of a single loop of the animation.
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|(_, delay)| *delay).sum()
+    }
+
+    /// Returns the index of the frame shown at `time` since the start,
+    /// looping the animation. Returns `None` if there are no frames.
+    pub fn frame_index_at(&self, time: Duration) -> Option<usize> {
+        let duration = self.duration();
+        if duration.is_zero() {
+            return (!self.frames.is_empty()).then_some(0);
+        }
+
+        let mut time = Duration::from_nanos((time.as_nanos() % duration.as_nanos()) as u64);
+
+        for (i, (_, delay)) in self.frames.iter().enumerate() {
+            if time < *delay {
+                return Some(i);
+            }
+
+            time -= *delay;
+        }
+
+        Some(self.frames.len() - 1)
+    }
+}
+
+/// A decoder of animated images. Unlike [`ImageDecoder`], it returns every
+/// frame of an animation instead of only the first one.
+pub trait AnimatedImageDecoder: Send + Sync + 'static {
+    /// Checks the extension and magic bytes in the provided `data`, returning
+    /// `true` if the image can potentially be decoded as an animation.
+    fn probe_animated(&self, extension: Option<&str>, data: &[u8]) -> bool;
+
+    /// Decodes all frames of an animation from raw bytes. Images of a
+    /// supported format, which aren't animated, decode into a single frame.
+    fn decode_animated(&self, extension: Option<&str>, data: &[u8]) -> Result<AnimatedImage>;
+}
+
+fn decode_srgb(v: u8) -> f32 {
+    srgb_to_linear(f32::from(v) / 255.0)
+}
+
+pub(crate) fn encode_srgb(v: f32) -> u8 {
+    (linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8
+}
+
+/// Loads and decodes an image from an [`AssetSource`].
+pub(crate) fn load_image(
+    source: &dyn AssetSource,
+    decoder: &dyn ImageDecoder,
+    path: &AssetPath<'_>,
+    requested_size: Option<UVec2>,
+) -> Result<ImageData> {
+    let raw_data = source
+        .load(path.as_borrowed())
+        .map_err(|e| e.with_context(format!("failed to load image from {path}")))?;
+
+    decoder
+        .decode(path.extension(), &raw_data, requested_size)
+        .map_err(|e| e.with_context(format!("failed to decode image from {path}")))
+}
+
+pub(crate) struct DecodeJob {
+    pub image: ImageId,
+    pub path: AssetPath<'static>,
+    pub requested_size: Option<UVec2>,
+}
+
+pub(crate) struct DecodeResult {
+    pub image: ImageId,
+    pub result: Result<ImageData>,
+}
+
+/// A pool of background threads that load and decode images, so that large
+/// images don't stall rendering.
+///
+/// Decoded images are uploaded by
+/// [`TextureCache::load_images_in_background`](crate::texture::TextureCache::load_images_in_background).
+/// Dropping the pool cancels queued jobs and waits for running ones.
+pub struct ImageDecodePool {
+    jobs: Option<mpsc::Sender<DecodeJob>>,
+    closed: Arc<AtomicBool>,
+    results: Arc<SegQueue<DecodeResult>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ImageDecodePool {
+    /// Spawns `num_threads` threads (at least one), which load images from
+    /// `source` and decode them with `decoder`.
+    pub fn new(
+        num_threads: usize,
+        source: Arc<dyn AssetSource>,
+        decoder: Arc<dyn ImageDecoder>,
+    ) -> ImageDecodePool {
+        let (sender, receiver) = mpsc::channel::<DecodeJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let results = Arc::new(SegQueue::new());
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let threads = (0..num_threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                let results = results.clone();
+                let closed = closed.clone();
+                let source = source.clone();
+                let decoder = decoder.clone();
+
+                thread::Builder::new()
+                    .name(format!("ohm-image-decoder-{i}"))
+                    .spawn(move || loop {
+                        let Ok(job) = receiver.lock().unwrap().recv() else {
+                            break;
+                        };
+
+                        if closed.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        let result = load_image(&*source, &*decoder, &job.path, job.requested_size);
+                        results.push(DecodeResult {
+                            image: job.image,
+                            result,
+                        });
+                    })
+                    .expect("failed to spawn image decoder thread")
+            })
+            .collect();
+
+        ImageDecodePool {
+            jobs: Some(sender),
+            closed,
+            results,
+            threads,
+        }
+    }
+
+    pub(crate) fn submit(&self, job: DecodeJob) {
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(job);
+        }
+    }
+
+    pub(crate) fn poll(&self) -> Option<DecodeResult> {
+        self.results.pop()
+    }
+}
+
+impl Drop for ImageDecodePool {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.jobs = None;
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl fmt::Debug for ImageDecodePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageDecodePool")
+            .field("num_threads", &self.threads.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -8,25 +8,59 @@ pub mod renderer;
 pub mod asset;
 mod color;
 mod corner_radii;
+mod edges;
 mod error;
+mod gradient;
 pub mod image;
+mod palette;
 mod path;
+#[cfg(test)]
+mod testing;
 pub mod text;
 pub mod texture;
 
 pub use self::color::*;
 pub use self::corner_radii::*;
+pub use self::edges::*;
 pub use self::error::*;
+pub use self::gradient::*;
 use self::image::ImageId;
+pub use self::palette::*;
 pub use self::path::*;
 use self::renderer::SurfaceId;
 use crate::math::{Affine2, Rect, Vec2};
-use crate::text::{FontId, GlyphId};
+use crate::text::{FontId, FontVariations, GlyphFormat, GlyphId, GlyphKey};
 
 #[derive(Debug, Clone, Copy)]
 pub struct DrawList<'a> {
     pub surface: SurfaceId,
     pub commands: &'a [Command<'a>],
+    /// Hint that the list mostly consists of opaque content. Enables
+    /// detection of opaque solid rectangles, which are then drawn without
+    /// blending. Translucent content is still blended correctly.
+    pub opaque: bool,
+    /// Transform applied to the whole list when rendering, e.g. to reposition
+    /// a retained list without encoding it again.
+    pub transform: Affine2,
+    /// Physical pixels per unit of the list's coordinates, applied after
+    /// `transform`. Glyphs are rasterized and paths are tessellated at the
+    /// physical size, so text stays crisp. Usually `1.0`, or the surface
+    /// scale when drawing in logical pixels.
+    pub scale: f32,
+}
+
+impl<'a> DrawList<'a> {
+    /// Returns the list with `transform` applied after its current transform.
+    pub fn with_transform(mut self, transform: impl Into<Affine2>) -> DrawList<'a> {
+        self.transform = transform.into() * self.transform;
+        self
+    }
+
+    /// Returns the transform from the list's coordinates to physical pixels,
+    /// including the scale.
+    pub fn physical_transform(&self) -> Affine2 {
+        Affine2::from_scale(Vec2::splat(self.scale)) * self.transform
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +68,7 @@ pub enum Command<'a> {
     ClearRect(ClearRect),
     DrawRect(DrawRect),
     DrawGlyph(DrawGlyph),
+    DrawGlyphGrid(DrawGlyphGrid<'a>),
     DrawLayer(DrawLayer<'a>),
     FillPath(FillPath),
     StrokePath(StrokePath),
@@ -54,6 +89,9 @@ pub struct DrawRect {
     pub corner_radii: CornerRadii,
     pub border: Option<Border>,
     pub shadow: Option<Shadow>,
+    /// Rounds the position and size to whole physical pixels, for crisp
+    /// edges. Ignored under transforms that rotate or skew.
+    pub pixel_snap: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -63,14 +101,116 @@ pub struct DrawGlyph {
     pub font: FontId,
     pub glyph: GlyphId,
     pub color: Color,
+    pub variations: FontVariations,
+    pub format: GlyphFormat,
+    /// Grows the glyph coverage by this radius and draws it as a mask in
+    /// `color`, for text outlines and shadows. Color glyphs are drawn as masks
+    /// too, glyphs without outlines aren't drawn. `None` draws the glyph
+    /// itself.
+    pub dilation: Option<f32>,
+}
+
+/// Grid of monospace glyphs, e.g. of a terminal, see
+/// [`MonoAtlas`](text::MonoAtlas).
+///
+/// Every glyph is looked up once per grid, and cells are drawn straight from
+/// the allocations. Cells are snapped to whole physical pixels, so that all
+/// cells of a glyph share one rasterization.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawGlyphGrid<'a> {
+    pub pos: Vec2,
+    pub size: f32,
+    pub font: FontId,
+    /// Distance from the top of a cell to the baseline.
+    pub baseline: f32,
+    /// Glyphs of the grid, referenced by the cells.
+    pub glyphs: &'a [GlyphId],
+    pub cells: &'a [GlyphCell],
+}
+
+impl DrawGlyphGrid<'_> {
+    /// Returns the glyph at the baseline of a cell at `pos`, relative to the
+    /// grid.
+    pub fn draw_glyph(&self, pos: Vec2, glyph: GlyphId, color: Color) -> DrawGlyph {
+        DrawGlyph {
+            pos: self.pos + pos + Vec2::new(0.0, self.baseline),
+            size: self.size,
+            font: self.font,
+            glyph,
+            color,
+            variations: FontVariations::default(),
+            format: GlyphFormat::Auto,
+            dilation: None,
+        }
+    }
+
+    /// Returns the cache key of a glyph rasterized at `scale`, shared by all
+    /// cells.
+    pub fn glyph_key(&self, glyph: GlyphId, scale: f32) -> GlyphKey {
+        let mut glyph = self.draw_glyph(Vec2::ZERO, glyph, Color::TRANSPAENT);
+        glyph.pos = Vec2::ZERO;
+        GlyphKey::new(&glyph, scale)
+    }
+}
+
+/// Cell of a [`DrawGlyphGrid`].
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphCell {
+    /// Position of the top left corner, relative to the grid.
+    pub pos: Vec2,
+    /// Index into [`DrawGlyphGrid::glyphs`].
+    pub glyph: u32,
+    pub color: Color,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct DrawLayer<'a> {
     pub commands: &'a [Command<'a>],
     pub tint: Color,
+    pub opacity: f32,
     pub scissor: Option<Scissor>,
     pub transform: Affine2,
+    pub crisp_text: bool,
+    pub blend_mode: BlendMode,
+    pub clip_path: Option<&'a ClipPath>,
+    /// Radius of a Gaussian blur applied to the layer before compositing, in
+    /// the local space of the layer. The standard deviation is half the
+    /// radius, `0` disables the blur.
+    pub blur: f32,
+    /// Shadow cast by the layer contents, drawn beneath them. It follows the
+    /// alpha of the contents, so the spread radius isn't supported.
+    pub shadow: Option<Shadow>,
+}
+
+/// Path clipping a layer, in the layer's local space.
+#[derive(Debug, Clone)]
+pub struct ClipPath {
+    /// Outline of the visible area.
+    pub path: Path,
+    /// Fill options used to tessellate the path.
+    pub options: FillOptions,
+}
+
+/// How a layer is composited onto the content below it.
+///
+/// Modes other than [`BlendMode::Normal`] always render the layer into an
+/// intermediate texture first. [`BlendMode::Multiply`] assumes an opaque
+/// backdrop.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum BlendMode {
+    /// Premultiplied alpha "over" compositing.
+    #[default]
+    Normal,
+    /// Multiplies the colors, darkening the backdrop.
+    Multiply,
+    /// Multiplies the inverted colors, lightening the backdrop.
+    Screen,
+    /// Adds the colors, for light and glow effects.
+    Add,
+    /// Keeps the lighter of both colors, per channel.
+    Lighten,
+    /// Keeps the darker of both colors, per channel.
+    Darken,
 }
 
 #[derive(Debug, Clone)]
@@ -100,6 +240,7 @@ pub struct Scissor {
 pub enum Fill {
     Solid(Color),
     Image(FillImage),
+    ConicGradient(FillConicGradient),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -107,6 +248,62 @@ pub struct FillImage {
     pub image: ImageId,
     pub tint: Color,
     pub clip_rect: Option<Rect>,
+    pub sampler: SamplerMode,
+    pub repeat: RepeatMode,
+    /// Size of a single tile relative to the image size in pixels. Only
+    /// affects repeated axes.
+    pub tile_scale: Vec2,
+}
+
+/// How an image fill covers the filled area.
+///
+/// Along repeated axes, the image is tiled with a period of its size times
+/// `tile_scale`, starting at the origin of the rect or path. Along other axes,
+/// the image is stretched across the whole area.
+///
+/// Tiles are wrapped in the shader, since most images share an atlas texture
+/// with other images. Texels at tile edges are therefore filtered without
+/// wrapping, and mipmapped images may bleed neighboring atlas entries at
+/// small scales. Use [`SamplerMode::Nearest`] or a mipmap-free image for
+/// seamless tiling.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum RepeatMode {
+    /// Stretch the image across the area.
+    #[default]
+    Clamp,
+    /// Repeat along both axes.
+    Repeat,
+    /// Repeat horizontally only.
+    RepeatX,
+    /// Repeat vertically only.
+    RepeatY,
+}
+
+impl RepeatMode {
+    /// Returns whether the image repeats horizontally and vertically.
+    pub fn axes(self) -> (bool, bool) {
+        match self {
+            RepeatMode::Clamp => (false, false),
+            RepeatMode::Repeat => (true, true),
+            RepeatMode::RepeatX => (true, false),
+            RepeatMode::RepeatY => (false, true),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum SamplerMode {
+    #[default]
+    Linear,
+    /// Nearest-neighbor filtering without mipmaps, for pixel art.
+    Nearest,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FillConicGradient {
+    pub center: Vec2,
+    pub start_angle: f32,
+    pub stops: GradientStops,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -115,7 +312,7 @@ pub struct Border {
     pub width: f32,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Shadow {
     pub blur_radius: f32,
     pub spread_radius: f32,
@@ -73,4 +73,11 @@ impl Rect {
     pub fn union(self, other: Rect) -> Rect {
         Rect::new(self.min.min(other.min), self.max.max(other.max))
     }
+
+    /// Computes the intersection of `self` and `other` rectangles. Returns
+    /// [`None`] if they don't overlap.
+    pub fn intersection(self, other: Rect) -> Option<Rect> {
+        let rect = Rect::new(self.min.max(other.min), self.max.min(other.max));
+        (rect.min.x < rect.max.x && rect.min.y < rect.max.y).then_some(rect)
+    }
 }
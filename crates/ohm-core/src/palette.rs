@@ -0,0 +1,119 @@
+use std::borrow::Cow;
+
+use indexmap::IndexMap;
+
+use crate::Color;
+
+/// Reference to a color, either given directly or by its name in a
+/// [`Palette`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorRef {
+    /// A literal color.
+    Color(Color),
+    /// A named palette color.
+    Named(Cow<'static, str>),
+}
+
+impl ColorRef {
+    /// Creates a reference to a named palette color.
+    pub fn named(name: impl Into<Cow<'static, str>>) -> ColorRef {
+        ColorRef::Named(name.into())
+    }
+}
+
+impl From<Color> for ColorRef {
+    fn from(color: Color) -> ColorRef {
+        ColorRef::Color(color)
+    }
+}
+
+impl From<&'static str> for ColorRef {
+    fn from(name: &'static str) -> ColorRef {
+        ColorRef::Named(Cow::Borrowed(name))
+    }
+}
+
+impl From<String> for ColorRef {
+    fn from(name: String) -> ColorRef {
+        ColorRef::Named(Cow::Owned(name))
+    }
+}
+
+/// A set of named colors, for theming.
+///
+/// Draw code refers to colors by name (see [`ColorRef`]) and resolves them
+/// every frame, so replacing the palette changes the colors of everything
+/// drawn afterwards. Names that aren't in the palette resolve to the fallback
+/// color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: IndexMap<Cow<'static, str>, Color>,
+    fallback: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            colors: IndexMap::new(),
+            fallback: Color::BLACK,
+        }
+    }
+}
+
+impl Palette {
+    /// Creates an empty palette, with black as the fallback color.
+    pub fn new() -> Palette {
+        Palette::default()
+    }
+
+    /// Adds a named color, replacing the previous color with the same name.
+    pub fn with(mut self, name: impl Into<Cow<'static, str>>, color: Color) -> Palette {
+        self.set(name, color);
+        self
+    }
+
+    /// Sets the color used for names that aren't in the palette.
+    pub fn with_fallback(mut self, fallback: Color) -> Palette {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Adds a named color, returning the color previously associated with the
+    /// name.
+    pub fn set(&mut self, name: impl Into<Cow<'static, str>>, color: Color) -> Option<Color> {
+        self.colors.insert(name.into(), color)
+    }
+
+    /// Removes a named color from the palette.
+    pub fn remove(&mut self, name: &str) -> Option<Color> {
+        self.colors.shift_remove(name)
+    }
+
+    /// Returns a named color, if it's in the palette.
+    pub fn get(&self, name: &str) -> Option<Color> {
+        self.colors.get(name).copied()
+    }
+
+    /// Returns the color used for names that aren't in the palette.
+    pub fn fallback(&self) -> Color {
+        self.fallback
+    }
+
+    /// Sets the color used for names that aren't in the palette.
+    pub fn set_fallback(&mut self, fallback: Color) {
+        self.fallback = fallback;
+    }
+
+    /// Resolves a color reference. Literal colors are returned as is.
+    pub fn resolve(&self, color: impl Into<ColorRef>) -> Color {
+        match color.into() {
+            ColorRef::Color(color) => color,
+            ColorRef::Named(name) => self.get(&name).unwrap_or(self.fallback),
+        }
+    }
+
+    /// Returns an iterator over the named colors, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Color)> + '_ {
+        self.colors.iter().map(|(name, &color)| (&**name, color))
+    }
+}
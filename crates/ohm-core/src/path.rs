@@ -1,7 +1,8 @@
+use std::f32::consts::FRAC_PI_2;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-use crate::math::Vec2;
+use crate::math::{Affine2, Mat2, Rect, Vec2};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PathEvent {
@@ -15,6 +16,8 @@ pub enum PathEvent {
 #[derive(Default)]
 pub struct PathBuilder {
     events: Vec<PathEvent>,
+    current_point: Option<Vec2>,
+    subpath_start: Option<Vec2>,
 }
 
 impl PathBuilder {
@@ -24,18 +27,24 @@ impl PathBuilder {
 
     pub fn clear(&mut self) {
         self.events.clear();
+        self.current_point = None;
+        self.subpath_start = None;
     }
 
     pub fn move_to(&mut self, point: Vec2) {
         self.events.push(PathEvent::MoveTo { point });
+        self.current_point = Some(point);
+        self.subpath_start = Some(point);
     }
 
     pub fn line_to(&mut self, point: Vec2) {
         self.events.push(PathEvent::LineTo { point });
+        self.current_point = Some(point);
     }
 
     pub fn quad_to(&mut self, control: Vec2, point: Vec2) {
         self.events.push(PathEvent::QuadTo { control, point });
+        self.current_point = Some(point);
     }
 
     pub fn cubic_to(&mut self, control1: Vec2, control2: Vec2, point: Vec2) {
@@ -43,10 +52,84 @@ impl PathBuilder {
             control: [control1, control2],
             point,
         });
+        self.current_point = Some(point);
     }
 
     pub fn close(&mut self) {
         self.events.push(PathEvent::Close);
+        self.current_point = self.subpath_start;
+    }
+
+    /// Appends a circular arc to the current subpath. Angles are in radians,
+    /// measured from the positive X axis towards the positive Y axis.
+    ///
+    /// If there is a current point, it's connected to the start of the arc
+    /// with a line, otherwise a new subpath is started.
+    pub fn arc(&mut self, center: Vec2, radius: f32, start_angle: f32, sweep_angle: f32) {
+        self.elliptic_arc(center, Vec2::splat(radius), 0.0, start_angle, sweep_angle);
+    }
+
+    /// Appends a closed ellipse as a new subpath.
+    pub fn ellipse(&mut self, center: Vec2, radii: Vec2, rotation: f32) {
+        self.current_point = None;
+        self.elliptic_arc(center, radii, rotation, 0.0, 2.0 * std::f32::consts::PI);
+        self.close();
+    }
+
+    /// Appends a closed circle as a new subpath.
+    pub fn circle(&mut self, center: Vec2, radius: f32) {
+        self.ellipse(center, Vec2::splat(radius), 0.0);
+    }
+
+    fn elliptic_arc(
+        &mut self,
+        center: Vec2,
+        radii: Vec2,
+        rotation: f32,
+        start_angle: f32,
+        sweep_angle: f32,
+    ) {
+        // maximum distance between the approximation and the true arc
+        const TOLERANCE: f32 = 0.01;
+        // approximation error of a unit quarter circle, the error grows
+        // roughly with the sixth power of the segment angle
+        const QUARTER_ERROR: f32 = 2.7e-4;
+
+        let transform = Mat2::from_angle(rotation) * Mat2::from_diagonal(radii);
+        let point_at = |angle: f32| center + transform * Vec2::from_angle(angle);
+        let tangent_at = |angle: f32| transform * Vec2::new(-angle.sin(), angle.cos());
+
+        let start = point_at(start_angle);
+        match self.current_point {
+            Some(point) if point.distance_squared(start) > TOLERANCE * TOLERANCE => {
+                self.line_to(start)
+            }
+            Some(_) => {}
+            None => self.move_to(start),
+        }
+
+        let radius = radii.abs().max_element();
+        if sweep_angle == 0.0 || radius == 0.0 {
+            return;
+        }
+
+        // large arcs need more segments to stay within the tolerance
+        let max_error = QUARTER_ERROR * radius;
+        let max_segment_angle = FRAC_PI_2 * (TOLERANCE / max_error).powf(1.0 / 6.0).min(1.0);
+        let num_segments = (sweep_angle.abs() / max_segment_angle).ceil().max(1.0) as usize;
+
+        let segment_angle = sweep_angle / num_segments as f32;
+        let k = 4.0 / 3.0 * (segment_angle / 4.0).tan();
+
+        for i in 0..num_segments {
+            let a0 = start_angle + segment_angle * i as f32;
+            let a1 = a0 + segment_angle;
+
+            let p0 = point_at(a0);
+            let p1 = point_at(a1);
+
+            self.cubic_to(p0 + tangent_at(a0) * k, p1 - tangent_at(a1) * k, p1);
+        }
     }
 
     pub fn finish(&mut self) -> Path {
@@ -67,6 +150,127 @@ impl Path {
     pub fn events(&self) -> &[PathEvent] {
         &self.events
     }
+
+    /// Computes the tight bounding box of the path, including the extrema of
+    /// curves rather than their control points. Returns `None` for an empty
+    /// path.
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut rect: Option<Rect> = None;
+        let mut add = |point: Vec2| {
+            rect = Some(match rect {
+                Some(rect) => Rect::new(rect.min.min(point), rect.max.max(point)),
+                None => Rect::new(point, point),
+            });
+        };
+
+        let mut cur_pos = Vec2::ZERO;
+        let mut start_pos = Vec2::ZERO;
+
+        for event in self.events.iter() {
+            match *event {
+                PathEvent::MoveTo { point } => {
+                    add(point);
+                    start_pos = point;
+                    cur_pos = point;
+                }
+                PathEvent::LineTo { point } => {
+                    add(point);
+                    cur_pos = point;
+                }
+                PathEvent::QuadTo { control, point } => {
+                    add(point);
+
+                    // zero of the derivative, for each axis
+                    let denom = cur_pos - 2.0 * control + point;
+                    for axis in 0..2 {
+                        if denom[axis].abs() > f32::EPSILON {
+                            let t = (cur_pos[axis] - control[axis]) / denom[axis];
+                            if t > 0.0 && t < 1.0 {
+                                add(eval_quad(cur_pos, control, point, t));
+                            }
+                        }
+                    }
+
+                    cur_pos = point;
+                }
+                PathEvent::CubicTo { control, point } => {
+                    add(point);
+
+                    // the derivative is a quadratic `a t^2 + b t + c`
+                    let [c1, c2] = control;
+                    let a = 3.0 * (point - cur_pos) + 9.0 * (c1 - c2);
+                    let b = 6.0 * (cur_pos - 2.0 * c1 + c2);
+                    let c = 3.0 * (c1 - cur_pos);
+                    for axis in 0..2 {
+                        for t in quadratic_roots(a[axis], b[axis], c[axis])
+                            .into_iter()
+                            .flatten()
+                        {
+                            if t > 0.0 && t < 1.0 {
+                                add(eval_cubic(cur_pos, c1, c2, point, t));
+                            }
+                        }
+                    }
+
+                    cur_pos = point;
+                }
+                PathEvent::Close => cur_pos = start_pos,
+            }
+        }
+
+        rect
+    }
+
+    /// Returns a copy of the path with every point mapped through
+    /// `transform`. Affine transforms map control points of curves exactly.
+    pub fn transformed(&self, transform: Affine2) -> Path {
+        let map = |point| transform.transform_point2(point);
+        let events = self.events.iter().map(|event| match *event {
+            PathEvent::MoveTo { point } => PathEvent::MoveTo { point: map(point) },
+            PathEvent::LineTo { point } => PathEvent::LineTo { point: map(point) },
+            PathEvent::QuadTo { control, point } => PathEvent::QuadTo {
+                control: map(control),
+                point: map(point),
+            },
+            PathEvent::CubicTo { control, point } => PathEvent::CubicTo {
+                control: control.map(map),
+                point: map(point),
+            },
+            PathEvent::Close => PathEvent::Close,
+        });
+
+        Path {
+            events: events.collect(),
+        }
+    }
+}
+
+fn eval_quad(p0: Vec2, p1: Vec2, p2: Vec2, t: f32) -> Vec2 {
+    let s = 1.0 - t;
+    s * s * p0 + 2.0 * s * t * p1 + t * t * p2
+}
+
+fn eval_cubic(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let s = 1.0 - t;
+    s * s * s * p0 + 3.0 * s * s * t * p1 + 3.0 * s * t * t * p2 + t * t * t * p3
+}
+
+fn quadratic_roots(a: f32, b: f32, c: f32) -> [Option<f32>; 2] {
+    if a.abs() <= f32::EPSILON {
+        if b.abs() <= f32::EPSILON {
+            return [None, None];
+        }
+
+        return [Some(-c / b), None];
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return [None, None];
+    }
+
+    let sqrt = discriminant.sqrt();
+    [Some((-b + sqrt) / (2.0 * a)), Some((-b - sqrt) / (2.0 * a))]
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
@@ -76,9 +280,34 @@ pub enum FillRule {
     NonZero,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[derive(Debug, Clone, Copy)]
 pub struct FillOptions {
     pub fill_rule: FillRule,
+    pub tolerance: f32,
+}
+
+impl Default for FillOptions {
+    fn default() -> Self {
+        Self {
+            fill_rule: FillRule::default(),
+            tolerance: 0.1,
+        }
+    }
+}
+
+impl PartialEq for FillOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.fill_rule == other.fill_rule && self.tolerance.to_bits() == other.tolerance.to_bits()
+    }
+}
+
+impl Eq for FillOptions {}
+
+impl Hash for FillOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.fill_rule.hash(state);
+        self.tolerance.to_bits().hash(state);
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
@@ -104,6 +333,7 @@ pub struct StrokeOptions {
     pub line_join: LineJoin,
     pub line_width: f32,
     pub mitter_limit: f32,
+    pub tolerance: f32,
 }
 
 impl Default for StrokeOptions {
@@ -113,6 +343,7 @@ impl Default for StrokeOptions {
             line_join: LineJoin::default(),
             line_width: 1.0,
             mitter_limit: 4.0,
+            tolerance: 0.1,
         }
     }
 }
@@ -123,6 +354,7 @@ impl PartialEq for StrokeOptions {
             && self.line_join == other.line_join
             && self.line_width.to_bits() == other.line_width.to_bits()
             && self.mitter_limit.to_bits() == other.mitter_limit.to_bits()
+            && self.tolerance.to_bits() == other.tolerance.to_bits()
     }
 }
 
@@ -134,5 +366,6 @@ impl Hash for StrokeOptions {
         self.line_join.hash(state);
         self.line_width.to_bits().hash(state);
         self.mitter_limit.to_bits().hash(state);
+        self.tolerance.to_bits().hash(state);
     }
 }
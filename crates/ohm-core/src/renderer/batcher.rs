@@ -4,15 +4,32 @@ use std::ops::Range;
 use super::{Mesh, PathCache, SurfaceId};
 use crate::image::ImageFormat;
 use crate::math::{Affine2, Rect, UVec2, Vec2, Vec4};
-use crate::text::{GlyphKey, SubpixelBin};
-use crate::texture::{TextureCache, TextureId};
+use crate::text::{glyph_scale, GlyphKey};
+use crate::texture::{AllocatedGlyph, TextureCache, TextureId};
 use crate::{
-    ClearRect, Color, Command, CornerRadii, DrawGlyph, DrawLayer, DrawList, DrawRect, Fill,
-    FillPath, StrokePath,
+    BlendMode, ClearRect, ClipPath, Color, Command, CornerRadii, DrawGlyph, DrawGlyphGrid,
+    DrawLayer, DrawList, DrawRect, Fill, FillOptions, FillPath, GlyphCell, PathBuilder, RepeatMode,
+    SamplerMode, Scissor, Shadow, StrokeOptions, StrokePath,
 };
 
 pub const INSTANCE_FILL: u32 = 4294967295;
 pub const INSTANCE_FILL_GRAY: u32 = 4294967294;
+/// Instance ID of subpixel antialiased glyphs, see [`Batch::subpixel_color`].
+pub const INSTANCE_FILL_SUBPIXEL: u32 = 4294967293;
+/// Instance ID of layers composited with [`BlendMode::Darken`]. The layer is
+/// drawn over white, so that its transparent areas keep the backdrop.
+pub const INSTANCE_FILL_DARKEN: u32 = 4294967292;
+/// Instance ID of layer shadows, only the alpha of the texture is used.
+pub const INSTANCE_FILL_ALPHA: u32 = 4294967291;
+
+/// Maximum number of nested scissors applied to a batch. Only the innermost
+/// ones are applied when nested deeper.
+pub const MAX_CLIP_SHAPES: usize = 4;
+
+pub const INSTANCE_FLAG_MESH: u32 = 1;
+pub const INSTANCE_FLAG_CONIC_GRADIENT: u32 = 2;
+pub const INSTANCE_FLAG_REPEAT_X: u32 = 4;
+pub const INSTANCE_FLAG_REPEAT_Y: u32 = 8;
 
 #[repr(packed)]
 #[derive(Debug, Clone, Copy)]
@@ -22,6 +39,8 @@ pub struct Vertex {
     pub tex: Vec2,
     pub color: Vec4,
     pub instance_id: u32,
+    /// Extra data for custom shaders, ignored by the default pipeline.
+    pub custom: Vec4,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -47,6 +66,9 @@ pub struct Instance {
     pub border_width: f32,
     pub shadow_blur_radius: f32,
     pub shadow_spread_radius: f32,
+    pub flags: u32,
+    pub gradient: Vec4,
+    pub gradient_tex: Vec4,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
@@ -55,14 +77,87 @@ pub struct FramebufferId(pub u64);
 #[derive(Debug)]
 pub struct Batch {
     pub clear: bool,
+    pub opaque: bool,
+    /// Premultiplied color of subpixel antialiased glyphs in this batch. The
+    /// color is applied during blending, as glyph coverage differs per
+    /// channel.
+    pub subpixel_color: Option<Color>,
+    /// Blend mode of a layer composite, [`BlendMode::Normal`] for everything
+    /// else.
+    pub blend_mode: BlendMode,
+    /// Multiplies the target by the alpha of the source, used for clip paths.
+    pub mask: bool,
+    /// Scissors clipping this batch, see [`Batcher::clip_shapes`].
+    pub clip: Option<ClipId>,
     pub msaa_resolve: bool,
+    /// Radius of a Gaussian blur of the target after this batch, in target
+    /// pixels. Only set on the last batch of a blurred layer, see
+    /// [`DrawLayer::blur`].
+    pub blur_radius: f32,
     pub target: Target,
     pub source: Source,
+    pub sampler: SamplerMode,
     pub index_range: Range<u32>,
     pub vertex_range: Range<u32>,
     pub instance_buffer_id: usize,
 }
 
+/// Index of a set of scissors, see [`Batcher::clip_shapes`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ClipId(pub usize);
+
+/// Amount of geometry produced by a [`Batcher`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct GeometryStats {
+    /// Number of vertices.
+    pub vertices: usize,
+    /// Number of indices.
+    pub indices: usize,
+    /// Number of instances.
+    pub instances: usize,
+}
+
+/// Rounded rectangle clipping a batch, from a layer [`Scissor`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipShape {
+    /// Maps target pixel coordinates to the local space of the rectangle.
+    pub transform: Affine2,
+    /// Rectangle in the local space.
+    pub rect: Rect,
+    /// Radii of the rectangle corners.
+    pub corner_radii: CornerRadii,
+}
+
+/// Outlines drawn on top of the rendered output, for debugging batching and
+/// layers. Everything is outlined in surface pixels, after the frame is
+/// batched, so the outlines themselves are never part of it.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub struct DebugOverlay {
+    /// Bounds of every batch, in red.
+    pub batches: bool,
+    /// Placement of every intermediate layer, in green.
+    pub intermediates: bool,
+    /// Every glyph, in blue.
+    pub glyphs: bool,
+    /// Every quad, including glyphs and composited layers, in yellow.
+    pub quads: bool,
+}
+
+impl DebugOverlay {
+    /// All outlines enabled.
+    pub const ALL: DebugOverlay = DebugOverlay {
+        batches: true,
+        intermediates: true,
+        glyphs: true,
+        quads: true,
+    };
+
+    /// Returns `true` if any outline is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.batches || self.intermediates || self.glyphs || self.quads
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct Intermediate {
     pub size: UVec2,
@@ -93,6 +188,12 @@ pub struct BatcherScratch {
     batches: Vec<Batch>,
     transform_stack: Vec<Affine2>,
     intermediates: Vec<Intermediate>,
+    clip_stack: Vec<ClipShape>,
+    clips: Vec<Range<usize>>,
+    clip_shapes: Vec<ClipShape>,
+    intermediate_origins: Vec<(Target, Vec2)>,
+    debug_quads: Vec<(Target, [Vec2; 4])>,
+    debug_glyphs: Vec<(Target, [Vec2; 4])>,
 }
 
 impl BatcherScratch {
@@ -107,6 +208,12 @@ impl BatcherScratch {
         self.batches.clear();
         self.transform_stack.clear();
         self.intermediates.clear();
+        self.clip_stack.clear();
+        self.clips.clear();
+        self.clip_shapes.clear();
+        self.intermediate_origins.clear();
+        self.debug_quads.clear();
+        self.debug_glyphs.clear();
     }
 }
 
@@ -124,10 +231,33 @@ pub struct Batcher<'a> {
     batches: &'a mut Vec<Batch>,
     transform_stack: &'a mut Vec<Affine2>,
     intermediates: &'a mut Vec<Intermediate>,
+    clip_stack: &'a mut Vec<ClipShape>,
+    clips: &'a mut Vec<Range<usize>>,
+    clip_shapes: &'a mut Vec<ClipShape>,
+    // parent target and position in it of every intermediate
+    intermediate_origins: &'a mut Vec<(Target, Vec2)>,
+    debug_quads: &'a mut Vec<(Target, [Vec2; 4])>,
+    debug_glyphs: &'a mut Vec<(Target, [Vec2; 4])>,
+    debug_overlay: DebugOverlay,
     path_cache: &'a mut PathCache,
+    layer_transform: Affine2,
+    crisp_text: bool,
+    list_scale: f32,
+    tint: Vec4,
+    max_intermediates: usize,
+    skipped_intermediates: usize,
+    max_vertices: usize,
+    max_indices: usize,
     cur_clear: bool,
+    cur_opaque: bool,
+    cur_subpixel_color: Option<Color>,
+    cur_blend_mode: BlendMode,
+    cur_mask: bool,
+    cur_clip: Option<ClipId>,
+    opaque_hint: bool,
     cur_target: Target,
     cur_source: Source,
+    cur_sampler: SamplerMode,
     max_instances_per_buffer: usize,
     cur_instance_buffer_id: usize,
     last_index: u32,
@@ -150,10 +280,32 @@ impl Batcher<'_> {
             batches: &mut scratch.batches,
             transform_stack: &mut scratch.transform_stack,
             intermediates: &mut scratch.intermediates,
+            clip_stack: &mut scratch.clip_stack,
+            clips: &mut scratch.clips,
+            clip_shapes: &mut scratch.clip_shapes,
+            intermediate_origins: &mut scratch.intermediate_origins,
+            debug_quads: &mut scratch.debug_quads,
+            debug_glyphs: &mut scratch.debug_glyphs,
+            debug_overlay: DebugOverlay::default(),
             path_cache,
+            layer_transform: Affine2::IDENTITY,
+            crisp_text: false,
+            list_scale: 1.0,
+            tint: Vec4::ONE,
+            max_intermediates: usize::MAX,
+            skipped_intermediates: 0,
+            max_vertices: usize::MAX,
+            max_indices: usize::MAX,
             cur_clear: false,
+            cur_opaque: false,
+            cur_subpixel_color: None,
+            cur_blend_mode: BlendMode::Normal,
+            cur_mask: false,
+            cur_clip: None,
+            opaque_hint: false,
             cur_target: Target::Intermediate(IntermediateId(0)),
             cur_source: Source::White,
+            cur_sampler: SamplerMode::Linear,
             max_instances_per_buffer,
             cur_instance_buffer_id: 0,
             last_index: 0,
@@ -161,22 +313,207 @@ impl Batcher<'_> {
         }
     }
 
+    /// Limits the number of intermediates allocated by this batcher. Layers
+    /// exceeding the limit are drawn inline, with tint applied per vertex and
+    /// instance, which is incorrect for overlapping translucent content, and
+    /// with normal blending.
+    pub fn set_max_intermediates(&mut self, max_intermediates: usize) {
+        self.max_intermediates = max_intermediates;
+    }
+
+    /// Returns the number of layers drawn inline because of the intermediate
+    /// limit.
+    pub fn skipped_intermediates(&self) -> usize {
+        self.skipped_intermediates
+    }
+
+    /// Limits the number of vertices. Once exceeded, remaining commands are
+    /// ignored, see [`Batcher::geometry_exceeded`].
+    pub fn set_max_vertices(&mut self, max_vertices: usize) {
+        self.max_vertices = max_vertices;
+    }
+
+    /// Limits the number of indices. Once exceeded, remaining commands are
+    /// ignored, see [`Batcher::geometry_exceeded`].
+    pub fn set_max_indices(&mut self, max_indices: usize) {
+        self.max_indices = max_indices;
+    }
+
+    /// Returns `true` if the vertex or index limit was exceeded, in which
+    /// case the batches are incomplete and shouldn't be rendered.
+    pub fn geometry_exceeded(&self) -> bool {
+        self.vertices.len() > self.max_vertices || self.indices.len() > self.max_indices
+    }
+
+    /// Enables recording of debug outlines, which are drawn by
+    /// [`Batcher::prepare_debug_overlay`]. Must be set before preparing any
+    /// draw lists.
+    pub fn set_debug_overlay(&mut self, debug_overlay: DebugOverlay) {
+        self.debug_overlay = debug_overlay;
+    }
+
+    /// Returns the amount of geometry produced so far.
+    pub fn geometry_stats(&self) -> GeometryStats {
+        GeometryStats {
+            vertices: self.vertices.len(),
+            indices: self.indices.len(),
+            instances: self.instances.len(),
+        }
+    }
+
     pub fn prepare(&mut self, draw_list: &DrawList) {
         if draw_list.commands.is_empty() {
             return;
         }
 
         self.set_target(Target::Surface(draw_list.surface));
+        self.opaque_hint = draw_list.opaque;
+
+        let transform = draw_list.physical_transform();
+        self.layer_transform = transform;
+        self.list_scale = draw_list.scale;
 
         if Self::should_enable_msaa(draw_list.commands) {
-            self.draw_intermediate_layer(draw_list.commands, Color::WHITE, Affine2::IDENTITY, true);
+            let layer = DrawLayer {
+                commands: draw_list.commands,
+                tint: Color::WHITE,
+                opacity: 1.0,
+                scissor: None,
+                transform,
+                crisp_text: false,
+                blend_mode: BlendMode::Normal,
+                clip_path: None,
+                blur: 0.0,
+                shadow: None,
+            };
+
+            self.draw_intermediate_layer(&layer, true);
         } else {
+            self.push_transform(transform);
             self.dispatch_commands(draw_list.commands);
+            self.pop_transform();
+        }
+
+        self.flush();
+        self.layer_transform = Affine2::IDENTITY;
+        self.list_scale = 1.0;
+    }
+
+    /// Outlines everything prepared so far, as enabled by
+    /// [`Batcher::set_debug_overlay`], on top of each surface. Call after
+    /// preparing all draw lists.
+    pub fn prepare_debug_overlay(&mut self) {
+        if !self.debug_overlay.is_enabled() {
+            return;
+        }
+
+        // batches, intermediates, quads and glyphs of each surface
+        let mut outlines: Vec<(SurfaceId, [PathBuilder; 4])> = Vec::new();
+        let mut add_outline =
+            |(surface, offset): (SurfaceId, Vec2), i: usize, corners: [Vec2; 4]| {
+                let builders = match outlines.iter().position(|(id, _)| *id == surface) {
+                    Some(idx) => &mut outlines[idx].1,
+                    None => {
+                        outlines.push((surface, Default::default()));
+                        &mut outlines.last_mut().unwrap().1
+                    }
+                };
+
+                let builder = &mut builders[i];
+                builder.move_to(corners[0] + offset);
+                for corner in &corners[1..] {
+                    builder.line_to(*corner + offset);
+                }
+                builder.close();
+            };
+
+        if self.debug_overlay.batches {
+            for batch in self.batches.iter() {
+                let vertices = &self.vertices
+                    [batch.vertex_range.start as usize..batch.vertex_range.end as usize];
+                let Some(first) = vertices.first() else {
+                    continue;
+                };
+
+                let first_pos = first.pos;
+                let rect = vertices
+                    .iter()
+                    .fold(Rect::new(first_pos, first_pos), |rect, v| {
+                        let pos = v.pos;
+                        Rect::new(rect.min.min(pos), rect.max.max(pos))
+                    });
+
+                add_outline(self.surface_origin(batch.target), 0, rect_corners(rect));
+            }
+        }
+
+        if self.debug_overlay.intermediates {
+            for (intermediate, &(parent, pos)) in self
+                .intermediates
+                .iter()
+                .zip(self.intermediate_origins.iter())
+            {
+                let rect = Rect::new(pos, pos + intermediate.size.as_vec2());
+                add_outline(self.surface_origin(parent), 1, rect_corners(rect));
+            }
+        }
+
+        for (&(target, corners), i) in self
+            .debug_quads
+            .iter()
+            .map(|v| (v, 2))
+            .chain(self.debug_glyphs.iter().map(|v| (v, 3)))
+        {
+            add_outline(self.surface_origin(target), i, corners);
+        }
+
+        let colors = [
+            Color::rgb(1.0, 0.0, 0.0),
+            Color::rgb(0.0, 1.0, 0.0),
+            Color::rgb(1.0, 1.0, 0.0),
+            Color::rgb(0.0, 0.0, 1.0),
+        ];
+
+        self.tint = Vec4::ONE;
+        self.set_clip(None);
+
+        for (surface, mut builders) in outlines {
+            self.set_target(Target::Surface(surface));
+
+            for (builder, color) in builders.iter_mut().zip(colors) {
+                let path = builder.finish();
+                if path.events().is_empty() {
+                    continue;
+                }
+
+                self.cmd_stroke_path(&StrokePath {
+                    pos: Vec2::ZERO,
+                    path,
+                    options: StrokeOptions::default(),
+                    fill: Fill::Solid(color),
+                });
+            }
         }
 
         self.flush();
     }
 
+    // surface an intermediate is eventually composited onto, and the position
+    // of the target in it
+    fn surface_origin(&self, mut target: Target) -> (SurfaceId, Vec2) {
+        let mut offset = Vec2::ZERO;
+        loop {
+            match target {
+                Target::Surface(id) => return (id, offset),
+                Target::Intermediate(id) => {
+                    let (parent, pos) = self.intermediate_origins[id.0];
+                    offset += pos;
+                    target = parent;
+                }
+            }
+        }
+    }
+
     pub fn batches(&self) -> &[Batch] {
         self.batches
     }
@@ -197,6 +534,11 @@ impl Batcher<'_> {
         self.intermediates
     }
 
+    /// Returns the scissors of a batch, from the outermost to the innermost.
+    pub fn clip_shapes(&self, id: ClipId) -> &[ClipShape] {
+        &self.clip_shapes[self.clips[id.0].clone()]
+    }
+
     fn compute_bouding_rect(&mut self, commands: &[Command]) -> Option<Rect> {
         let mut bounding_rect: Option<Rect> = None;
 
@@ -220,25 +562,31 @@ impl Batcher<'_> {
                 }
 
                 Command::DrawGlyph(glyph) => {
-                    let pos = glyph.pos;
-                    let glyph_key = GlyphKey {
-                        font: glyph.font,
-                        glyph: glyph.glyph,
-                        size: glyph.size.to_bits(),
-                        subpixel_bin: SubpixelBin::new(pos),
+                    let Some((_, rect)) = self.get_glyph(glyph) else {
+                        continue;
                     };
+                    rect
+                }
 
-                    let Some(glyph) = self.texture_cache.get_glyph(&glyph_key) else {
+                Command::DrawGlyphGrid(grid) => {
+                    let (scale, glyphs) = self.get_grid_glyphs(grid);
+                    let rect = grid.cells.iter().filter_map(|cell| {
+                        let glyph = glyphs.get(cell.glyph as usize)?.as_ref()?;
+                        Some(grid_cell_rect(grid, cell, glyph, scale))
+                    });
+
+                    let Some(rect) = rect.reduce(Rect::union) else {
                         continue;
                     };
-
-                    let pos = pos.trunc() + glyph.offset;
-                    let size = glyph.rect.size().as_vec2();
-                    Rect::new(pos, pos + size)
+                    rect
                 }
 
                 Command::DrawLayer(layer) => {
-                    let Some(rect) = self.compute_bouding_rect(layer.commands) else {
+                    let old_state = self.enter_layer(layer);
+                    let rect = self.layer_bounding_rect(layer);
+                    self.leave_layer(old_state);
+
+                    let Some(rect) = rect else {
                         continue;
                     };
 
@@ -246,7 +594,8 @@ impl Batcher<'_> {
                 }
 
                 Command::FillPath(path) => {
-                    let mesh = self.path_cache.fill(&path.path, &path.options);
+                    let options = self.fill_options(&path.options);
+                    let mesh = self.path_cache.fill(&path.path, &options);
                     let Some(rect) = mesh.bounding_rect else {
                         continue;
                     };
@@ -254,7 +603,8 @@ impl Batcher<'_> {
                 }
 
                 Command::StrokePath(path) => {
-                    let mesh = self.path_cache.stroke(&path.path, &path.options);
+                    let options = self.stroke_options(&path.options);
+                    let mesh = self.path_cache.stroke(&path.path, &options);
                     let Some(rect) = mesh.bounding_rect else {
                         continue;
                     };
@@ -273,11 +623,7 @@ impl Batcher<'_> {
             match command {
                 Command::FillPath(_) | Command::StrokePath(_) => return true,
                 Command::DrawLayer(layer) => {
-                    let is_no_tint = layer.tint == Color::WHITE;
-                    let is_compatible_scissor = layer.scissor.is_none();
-                    let is_fast_path = is_no_tint && is_compatible_scissor;
-
-                    if is_fast_path && Self::should_enable_msaa(layer.commands) {
+                    if Self::is_fast_path(layer) && Self::should_enable_msaa(layer.commands) {
                         return true;
                     }
                 }
@@ -301,8 +647,69 @@ impl Batcher<'_> {
         self.transform_stack.pop();
     }
 
-    fn alloc_intermediate(&mut self, size: UVec2, msaa: bool) -> IntermediateId {
+    fn enter_layer(&mut self, layer: &DrawLayer<'_>) -> (Affine2, bool) {
+        let old_state = (self.layer_transform, self.crisp_text);
+        self.layer_transform *= layer.transform;
+        self.crisp_text |= layer.crisp_text;
+        old_state
+    }
+
+    fn leave_layer(&mut self, (layer_transform, crisp_text): (Affine2, bool)) {
+        self.layer_transform = layer_transform;
+        self.crisp_text = crisp_text;
+    }
+
+    // physical pixels per unit glyphs are rasterized at
+    fn glyph_raster_scale(&self) -> f32 {
+        if self.crisp_text {
+            glyph_scale(&self.layer_transform)
+        } else {
+            self.list_scale
+        }
+    }
+
+    fn get_glyph(&self, glyph: &DrawGlyph) -> Option<(AllocatedGlyph, Rect)> {
+        let scale = self.glyph_raster_scale();
+        let allocated = self.texture_cache.get_glyph(&GlyphKey::new(glyph, scale))?;
+
+        // the glyph is rasterized at the scaled size, so snap and place it in
+        // scaled space, then map back to layer space
+        let pos = ((glyph.pos * scale).trunc() + allocated.offset) / scale;
+        let size = allocated.rect.size().as_vec2() / scale;
+
+        Some((allocated, Rect::new(pos, pos + size)))
+    }
+
+    // every glyph of a grid is looked up once, and shared by its cells
+    fn get_grid_glyphs(&self, grid: &DrawGlyphGrid<'_>) -> (f32, Vec<Option<AllocatedGlyph>>) {
+        let scale = self.glyph_raster_scale();
+        let glyphs = grid
+            .glyphs
+            .iter()
+            .map(|&glyph| self.texture_cache.get_glyph(&grid.glyph_key(glyph, scale)))
+            .collect();
+        (scale, glyphs)
+    }
+
+    // tolerance is in physical pixels, paths are tessellated in list units
+    fn fill_options(&self, options: &FillOptions) -> FillOptions {
+        FillOptions {
+            tolerance: options.tolerance / self.list_scale,
+            ..*options
+        }
+    }
+
+    fn stroke_options(&self, options: &StrokeOptions) -> StrokeOptions {
+        StrokeOptions {
+            tolerance: options.tolerance / self.list_scale,
+            ..*options
+        }
+    }
+
+    // `pos` is the position in the current target
+    fn alloc_intermediate(&mut self, size: UVec2, msaa: bool, pos: Vec2) -> IntermediateId {
         self.intermediates.push(Intermediate { size, msaa });
+        self.intermediate_origins.push((self.cur_target, pos));
         IntermediateId(self.intermediates.len() - 1)
     }
 
@@ -310,10 +717,16 @@ impl Batcher<'_> {
         let first_batch = self.batches.len();
 
         for command in commands {
+            // a single command may still exceed the limit, e.g. a huge path
+            if self.geometry_exceeded() {
+                break;
+            }
+
             match command {
                 Command::ClearRect(rect) => self.cmd_clear_rect(rect),
                 Command::DrawRect(rect) => self.cmd_draw_rect(rect),
                 Command::DrawGlyph(glyph) => self.cmd_draw_glyph(glyph),
+                Command::DrawGlyphGrid(grid) => self.cmd_draw_glyph_grid(grid),
                 Command::DrawLayer(layer) => self.cmd_draw_layer(layer),
                 Command::FillPath(path) => self.cmd_fill_path(path),
                 Command::StrokePath(path) => self.cmd_stroke_path(path),
@@ -327,6 +740,7 @@ impl Batcher<'_> {
 
     fn cmd_clear_rect(&mut self, rect: &ClearRect) {
         self.set_clear(true);
+        self.set_subpixel_color(None);
         self.set_source(Source::White);
 
         self.add_quad(Quad {
@@ -339,16 +753,42 @@ impl Batcher<'_> {
     }
 
     fn cmd_draw_rect(&mut self, rect: &DrawRect) {
+        let rect = &self.snap_rect(rect);
+
         self.set_clear(false);
+        self.set_subpixel_color(None);
 
         let (color, source, mut tex_min, mut tex_max) = self.get_fill(&rect.fill);
+        let sampler = fill_sampler(&rect.fill);
+
+        self.set_source_with_sampler(source, sampler);
+
+        if let Some(tile_size) = self.tile_size(&rect.fill) {
+            // tiles are wrapped in the shader, using coordinates in tile units
+            let (repeat_x, repeat_y) = fill_repeat(&rect.fill);
+            let tiles = rect.size / tile_size;
+            tex_min = Vec2::ZERO;
+            tex_max = Vec2::new(
+                if repeat_x { tiles.x } else { 1.0 },
+                if repeat_y { tiles.y } else { 1.0 },
+            );
+        }
 
-        self.set_source(source);
+        let fill_instance = self.fill_instance(&rect.fill);
 
         if rect.border.is_none()
             && rect.shadow.is_none()
             && rect.corner_radii == CornerRadii::default()
+            && fill_instance.is_none()
         {
+            // opaque solid rectangles don't need blending
+            // clipped edges need blending
+            let opaque = self.opaque_hint
+                && matches!(rect.fill, Fill::Solid(c) if c.a >= 1.0)
+                && self.tint.w >= 1.0
+                && self.cur_clip.is_none();
+            self.set_opaque(opaque);
+
             self.add_quad(Quad {
                 min: rect.pos,
                 max: rect.pos + rect.size,
@@ -367,23 +807,20 @@ impl Batcher<'_> {
         let shadow_blur_radius = rect.shadow.map(|s| s.blur_radius).unwrap_or(0.0);
         let shadow_spread_radius = rect.shadow.map(|s| s.spread_radius).unwrap_or(0.0);
 
+        // tinted like vertex colors, for layers drawn inline
+        let border_color = rect.border.map(|b| b.color).unwrap_or(Color::TRANSPAENT);
+        let shadow_color = rect.shadow.map(|s| s.color).unwrap_or(Color::TRANSPAENT);
+
         let instance_id = self.add_instance(Instance {
             corner_radii: rect.corner_radii.into(),
-            border_color: rect
-                .border
-                .map(|b| b.color)
-                .unwrap_or(Color::TRANSPAENT)
-                .into(),
-            shadow_color: rect
-                .shadow
-                .map(|s| s.color)
-                .unwrap_or(Color::TRANSPAENT)
-                .into(),
+            border_color: Vec4::from(border_color) * self.tint,
+            shadow_color: Vec4::from(shadow_color) * self.tint,
             shadow_offset,
             size: rect.size,
             border_width: rect.border.map(|b| b.width).unwrap_or(0.0),
             shadow_blur_radius,
             shadow_spread_radius,
+            ..fill_instance.unwrap_or_default()
         });
 
         let rect_min = rect.pos;
@@ -413,40 +850,86 @@ impl Batcher<'_> {
         });
     }
 
+    // position and size are rounded separately, so that the size doesn't
+    // change when the rect moves. Shadows are placed relative to the result.
+    fn snap_rect(&self, rect: &DrawRect) -> DrawRect {
+        let transform = self.transform_stack.last().copied();
+        let transform = transform.unwrap_or(Affine2::IDENTITY);
+        let matrix = transform.matrix2;
+        let scale = Vec2::new(matrix.x_axis.x, matrix.y_axis.y);
+
+        let is_axis_aligned = matrix.x_axis.y == 0.0 && matrix.y_axis.x == 0.0;
+        if !rect.pixel_snap || !is_axis_aligned || scale.cmpeq(Vec2::ZERO).any() {
+            return *rect;
+        }
+
+        let size = rect.size * scale;
+        let mut snapped_size = size.round();
+
+        // keep hairlines visible
+        for i in 0..2 {
+            if snapped_size[i] == 0.0 && size[i] != 0.0 {
+                snapped_size[i] = size[i].signum();
+            }
+        }
+
+        let pos = transform.transform_point2(rect.pos).round();
+
+        DrawRect {
+            pos: (pos - transform.translation) / scale,
+            size: snapped_size / scale,
+            ..*rect
+        }
+    }
+
     fn cmd_draw_glyph(&mut self, glyph: &DrawGlyph) {
         self.set_clear(false);
 
         let color = glyph.color;
-        let pos = glyph.pos;
-
-        let glyph_key = GlyphKey {
-            font: glyph.font,
-            glyph: glyph.glyph,
-            size: glyph.size.to_bits(),
-            subpixel_bin: SubpixelBin::new(pos),
-        };
 
-        let Some(glyph) = self.texture_cache.get_glyph(&glyph_key) else {
+        let Some((glyph, rect)) = self.get_glyph(glyph) else {
             return;
         };
 
+        self.add_glyph_quad(&glyph, rect, color);
+    }
+
+    fn cmd_draw_glyph_grid(&mut self, grid: &DrawGlyphGrid<'_>) {
+        self.set_clear(false);
+
+        let (scale, glyphs) = self.get_grid_glyphs(grid);
+
+        for cell in grid.cells {
+            let Some(Some(glyph)) = glyphs.get(cell.glyph as usize) else {
+                continue;
+            };
+
+            let rect = grid_cell_rect(grid, cell, glyph, scale);
+            self.add_glyph_quad(glyph, rect, cell.color);
+        }
+    }
+
+    fn add_glyph_quad(&mut self, glyph: &AllocatedGlyph, rect: Rect, color: Color) {
         self.set_source(Source::Texture(glyph.texture));
 
         let tex_min = glyph.rect.min.as_vec2() / glyph.texture_size.as_vec2();
         let tex_max = glyph.rect.max.as_vec2() / glyph.texture_size.as_vec2();
 
-        let pos = pos.trunc() + glyph.offset;
-        let size = glyph.rect.size().as_vec2();
-
-        let (color, instance_id) = if glyph.format == ImageFormat::Gray8 {
-            (color, INSTANCE_FILL_GRAY)
-        } else {
-            (Color::WHITE, INSTANCE_FILL)
+        let (color, instance_id) = match glyph.format {
+            ImageFormat::Gray8 => (color, INSTANCE_FILL_GRAY),
+            ImageFormat::Rgba8 => (color, INSTANCE_FILL_SUBPIXEL),
+            ImageFormat::Srgba8 => (Color::WHITE, INSTANCE_FILL),
         };
 
+        let subpixel_color = (instance_id == INSTANCE_FILL_SUBPIXEL).then(|| {
+            let [r, g, b, a] = (Vec4::from(color) * self.tint).to_array();
+            Color::rgba(r, g, b, a)
+        });
+        self.set_subpixel_color(subpixel_color);
+
         self.add_quad(Quad {
-            min: pos,
-            max: pos + size,
+            min: rect.min,
+            max: rect.max,
             local_min: Vec2::ZERO,
             local_max: Vec2::ZERO,
             tex_min,
@@ -454,21 +937,39 @@ impl Batcher<'_> {
             color: color.into(),
             instance_id,
         });
+
+        if self.debug_overlay.glyphs {
+            let corners = self.last_quad_corners();
+            self.debug_glyphs.push((self.cur_target, corners));
+        }
     }
 
     fn cmd_draw_layer(&mut self, layer: &DrawLayer<'_>) {
         self.set_clear(false);
+        self.set_subpixel_color(None);
+
+        let old_state = self.enter_layer(layer);
+        self.cmd_draw_layer_inner(layer);
+        self.leave_layer(old_state);
+    }
 
-        let is_no_tint = layer.tint == Color::WHITE;
-        let is_compatible_scissor = layer.scissor.is_none();
-        let is_fast_path = is_no_tint && is_compatible_scissor;
+    // layers that can be drawn inline, without an intermediate
+    fn is_fast_path(layer: &DrawLayer<'_>) -> bool {
+        let is_no_tint = layer.tint == Color::WHITE && layer.opacity >= 1.0;
+        let is_normal_blend = layer.blend_mode == BlendMode::Normal;
+        let is_unclipped = layer.clip_path.is_none();
+        let is_unblurred = layer.blur <= 0.0;
+        let is_unshadowed = layer.shadow.is_none();
+        is_no_tint && is_normal_blend && is_unclipped && is_unblurred && is_unshadowed
+    }
 
-        if is_fast_path {
+    fn cmd_draw_layer_inner(&mut self, layer: &DrawLayer<'_>) {
+        if Self::is_fast_path(layer) {
             if layer.transform != Affine2::IDENTITY {
                 self.push_transform(layer.transform);
             }
 
-            self.dispatch_commands(layer.commands);
+            self.dispatch_clipped(layer.commands, layer.scissor.as_ref());
 
             if layer.transform != Affine2::IDENTITY {
                 self.pop_transform();
@@ -478,20 +979,47 @@ impl Batcher<'_> {
         }
 
         let enable_msaa = Self::should_enable_msaa(layer.commands);
-        self.draw_intermediate_layer(layer.commands, layer.tint, layer.transform, enable_msaa);
+        self.draw_intermediate_layer(layer, enable_msaa);
     }
 
-    fn draw_intermediate_layer(
-        &mut self,
-        commands: &[Command],
-        tint: Color,
-        transform: Affine2,
-        enable_msaa: bool,
-    ) {
-        let Some(local_rect) = self.compute_bouding_rect(commands) else {
+    // content outside of the scissor is clipped, so it doesn't need space in
+    // the intermediate. The blur spreads past it, like shadows do
+    fn layer_bounding_rect(&mut self, layer: &DrawLayer<'_>) -> Option<Rect> {
+        let rect = self.compute_bouding_rect(layer.commands)?;
+        let rect = match &layer.scissor {
+            Some(scissor) => {
+                rect.intersection(Rect::new(scissor.pos, scissor.pos + scissor.size))?
+            }
+            None => rect,
+        };
+
+        let blur = Vec2::splat(layer.blur.max(0.0));
+        Some(Rect::new(rect.min - blur, rect.max + blur))
+    }
+
+    fn draw_intermediate_layer(&mut self, layer: &DrawLayer<'_>, enable_msaa: bool) {
+        let commands = layer.commands;
+        let transform = layer.transform;
+        let blend_mode = layer.blend_mode;
+
+        // premultiplied, so opacity scales the tint as a whole
+        let tint = Vec4::from(layer.tint) * layer.opacity;
+        let tint = Color::rgba(tint.x, tint.y, tint.z, tint.w);
+
+        let Some(local_rect) = self.layer_bounding_rect(layer) else {
             return;
         };
 
+        // clip paths need another intermediate for the mask, and shadows one
+        // for the blurred alpha
+        let num_intermediates =
+            1 + usize::from(layer.clip_path.is_some()) + usize::from(layer.shadow.is_some());
+        if self.intermediates.len() + num_intermediates > self.max_intermediates {
+            self.skipped_intermediates += 1;
+            self.draw_inline_layer(commands, tint, transform, layer.scissor.as_ref());
+            return;
+        }
+
         let layer_transform = self
             .transform_stack
             .last()
@@ -504,11 +1032,16 @@ impl Batcher<'_> {
 
         self.flush();
 
-        let intermediate = self.alloc_intermediate(rect.size().as_uvec2(), enable_msaa);
+        let intermediate = self.alloc_intermediate(rect.size().as_uvec2(), enable_msaa, rect.min);
 
         let old_target = self.cur_target;
         self.set_target(Target::Intermediate(intermediate));
 
+        // scissors of the parent are in a different space, they are applied
+        // when compositing
+        let old_clip_stack = std::mem::take(self.clip_stack);
+        self.update_clip();
+
         self.transform_stack.push(Affine2::IDENTITY);
         self.cmd_clear_rect(&ClearRect {
             pos: Vec2::ZERO,
@@ -519,22 +1052,60 @@ impl Batcher<'_> {
 
         self.transform_stack
             .push(Affine2::from_translation(-rect.min) * layer_transform);
-        self.dispatch_commands(commands);
+        self.dispatch_clipped(commands, layer.scissor.as_ref());
+
+        if let Some(clip_path) = layer.clip_path {
+            self.draw_clip_mask(intermediate, rect.size(), clip_path);
+        }
+
         self.transform_stack.pop();
 
         self.flush();
 
         if enable_msaa {
-            for batch in self.batches.iter_mut().rev() {
-                if batch.target == Target::Intermediate(intermediate) {
-                    batch.msaa_resolve = true;
-                    break;
-                }
-            }
+            self.mark_msaa_resolve(intermediate);
+        }
+
+        if layer.blur > 0.0 {
+            self.mark_blur(intermediate, layer.blur * glyph_scale(&layer_transform));
         }
 
+        let shadow = layer.shadow.and_then(|shadow| {
+            self.draw_layer_shadow(intermediate, rect, &layer_transform, &shadow, tint.a)
+        });
+
+        *self.clip_stack = old_clip_stack;
+        self.update_clip();
+
         self.set_target(old_target);
+
+        if let Some((shadow, shadow_rect)) = shadow {
+            self.set_source(Source::Intermediate(shadow));
+            self.set_clear(false);
+            self.set_subpixel_color(None);
+
+            self.transform_stack.push(Affine2::IDENTITY);
+            self.add_quad(Quad {
+                min: shadow_rect.min,
+                max: shadow_rect.max,
+                tex_min: Vec2::ZERO,
+                tex_max: Vec2::ONE,
+                color: Vec4::ONE,
+                instance_id: INSTANCE_FILL,
+                ..Quad::default()
+            });
+            self.transform_stack.pop();
+        }
+
         self.set_source(Source::Intermediate(intermediate));
+        self.set_clear(false);
+        self.set_subpixel_color(None);
+        self.set_blend_mode(blend_mode);
+
+        let instance_id = match blend_mode {
+            BlendMode::Darken => INSTANCE_FILL_DARKEN,
+            _ => INSTANCE_FILL,
+        };
 
         self.transform_stack.push(Affine2::IDENTITY);
         self.add_quad(Quad {
@@ -545,44 +1116,248 @@ impl Batcher<'_> {
             tex_min: Vec2::ZERO,
             tex_max: Vec2::ONE,
             color: tint.into(),
+            instance_id,
+        });
+        self.transform_stack.pop();
+
+        self.set_blend_mode(BlendMode::Normal);
+    }
+
+    // renders the clip path into a mask, using the current transform, and
+    // multiplies the intermediate by it
+    fn draw_clip_mask(&mut self, intermediate: IntermediateId, size: Vec2, clip_path: &ClipPath) {
+        self.flush();
+
+        let mask = self.alloc_intermediate(size.as_uvec2(), true, Vec2::ZERO);
+        self.set_target(Target::Intermediate(mask));
+
+        self.transform_stack.push(Affine2::IDENTITY);
+        self.cmd_clear_rect(&ClearRect {
+            pos: Vec2::ZERO,
+            size,
+            color: Color::TRANSPAENT,
+        });
+        self.transform_stack.pop();
+
+        let old_tint = std::mem::replace(&mut self.tint, Vec4::ONE);
+        self.cmd_fill_path(&FillPath {
+            pos: Vec2::ZERO,
+            path: clip_path.path.clone(),
+            options: clip_path.options,
+            fill: Fill::Solid(Color::WHITE),
+        });
+        self.tint = old_tint;
+
+        self.flush();
+        self.mark_msaa_resolve(mask);
+
+        self.set_target(Target::Intermediate(intermediate));
+        self.set_source(Source::Intermediate(mask));
+        self.set_clear(false);
+        self.set_subpixel_color(None);
+        self.set_mask(true);
+
+        self.transform_stack.push(Affine2::IDENTITY);
+        self.add_quad(Quad {
+            min: Vec2::ZERO,
+            max: size,
+            tex_min: Vec2::ZERO,
+            tex_max: Vec2::ONE,
+            color: Vec4::ONE,
             instance_id: INSTANCE_FILL,
+            ..Quad::default()
+        });
+        self.transform_stack.pop();
+
+        self.set_mask(false);
+    }
+
+    fn mark_msaa_resolve(&mut self, intermediate: IntermediateId) {
+        for batch in self.batches.iter_mut().rev() {
+            if batch.target == Target::Intermediate(intermediate) {
+                batch.msaa_resolve = true;
+                break;
+            }
+        }
+    }
+
+    // renders the alpha of the intermediate, tinted with the shadow color, into
+    // another one and blurs it. Returns the shadow and its rect in the parent
+    // target
+    fn draw_layer_shadow(
+        &mut self,
+        intermediate: IntermediateId,
+        rect: Rect,
+        transform: &Affine2,
+        shadow: &Shadow,
+        alpha: f32,
+    ) -> Option<(IntermediateId, Rect)> {
+        if shadow.color.a <= 0.0 || alpha <= 0.0 {
+            return None;
+        }
+
+        let blur_radius = shadow.blur_radius.max(0.0) * glyph_scale(transform);
+        let padding = Vec2::splat(blur_radius.ceil());
+        let offset = transform.transform_vector2(shadow.offset);
+        let shadow_rect = Rect::new(rect.min - padding + offset, rect.max + padding + offset);
+        let size = rect.size() + padding * 2.0;
+
+        let id = self.alloc_intermediate(size.as_uvec2(), false, shadow_rect.min);
+        self.set_target(Target::Intermediate(id));
+
+        self.transform_stack.push(Affine2::IDENTITY);
+        self.cmd_clear_rect(&ClearRect {
+            pos: Vec2::ZERO,
+            size,
+            color: Color::TRANSPAENT,
+        });
+
+        self.set_source(Source::Intermediate(intermediate));
+        self.set_clear(false);
+        self.set_subpixel_color(None);
+        self.add_quad(Quad {
+            min: padding,
+            max: padding + rect.size(),
+            tex_min: Vec2::ZERO,
+            tex_max: Vec2::ONE,
+            color: Vec4::from(shadow.color) * alpha,
+            instance_id: INSTANCE_FILL_ALPHA,
+            ..Quad::default()
         });
         self.transform_stack.pop();
+
+        self.flush();
+
+        if blur_radius > 0.0 {
+            self.mark_blur(id, blur_radius);
+        }
+
+        Some((id, shadow_rect))
+    }
+
+    fn mark_blur(&mut self, intermediate: IntermediateId, radius: f32) {
+        for batch in self.batches.iter_mut().rev() {
+            if batch.target == Target::Intermediate(intermediate) {
+                batch.blur_radius = radius;
+                break;
+            }
+        }
+    }
+
+    fn draw_inline_layer(
+        &mut self,
+        commands: &[Command],
+        tint: Color,
+        transform: Affine2,
+        scissor: Option<&Scissor>,
+    ) {
+        let old_tint = self.tint;
+        self.tint *= Vec4::from(tint);
+
+        self.push_transform(transform);
+        self.dispatch_clipped(commands, scissor);
+        self.pop_transform();
+
+        self.tint = old_tint;
+    }
+
+    // the scissor is in the space of the current transform
+    fn dispatch_clipped(&mut self, commands: &[Command], scissor: Option<&Scissor>) {
+        let Some(scissor) = scissor else {
+            self.dispatch_commands(commands);
+            return;
+        };
+
+        let transform = self.transform_stack.last().copied();
+        self.clip_stack.push(ClipShape {
+            transform: transform.unwrap_or(Affine2::IDENTITY).inverse(),
+            rect: Rect::new(scissor.pos, scissor.pos + scissor.size),
+            corner_radii: scissor.corner_radii,
+        });
+        self.update_clip();
+
+        self.dispatch_commands(commands);
+
+        self.clip_stack.pop();
+        self.update_clip();
+    }
+
+    fn update_clip(&mut self) {
+        if self.clip_stack.is_empty() {
+            self.set_clip(None);
+            return;
+        }
+
+        // only the innermost scissors, see `MAX_CLIP_SHAPES`
+        let start = self.clip_stack.len().saturating_sub(MAX_CLIP_SHAPES);
+        let first = self.clip_shapes.len();
+        self.clip_shapes
+            .extend_from_slice(&self.clip_stack[start..]);
+        self.clips.push(first..self.clip_shapes.len());
+        self.set_clip(Some(ClipId(self.clips.len() - 1)));
     }
 
     fn cmd_fill_path(&mut self, path: &FillPath) {
+        self.set_clear(false);
+        self.set_subpixel_color(None);
+
         let (color, source, tex_min, tex_max) = self.get_fill(&path.fill);
+        let sampler = fill_sampler(&path.fill);
+
+        self.set_source_with_sampler(source, sampler);
 
-        self.set_source(source);
+        let instance_id = self.add_mesh_instance(&path.fill);
+
+        let tile_size = self.tile_size(&path.fill);
+        let options = self.fill_options(&path.options);
+        let mesh = self.path_cache.fill(&path.path, &options);
+        let (tex_min, tex_max) = match tile_size {
+            Some(tile_size) => mesh_tile_coords(mesh, tile_size, fill_repeat(&path.fill)),
+            None => (tex_min, tex_max),
+        };
 
-        let mesh = self.path_cache.fill(&path.path, &path.options);
         Self::draw_mesh(
             self.vertices,
             self.indices,
             self.transform_stack,
             path.pos,
             mesh,
-            color,
+            Vec4::from(color) * self.tint,
             tex_min,
             tex_max,
+            instance_id,
         );
     }
 
     fn cmd_stroke_path(&mut self, path: &StrokePath) {
+        self.set_clear(false);
+        self.set_subpixel_color(None);
+
         let (color, source, tex_min, tex_max) = self.get_fill(&path.fill);
+        let sampler = fill_sampler(&path.fill);
+
+        self.set_source_with_sampler(source, sampler);
 
-        self.set_source(source);
+        let instance_id = self.add_mesh_instance(&path.fill);
+
+        let tile_size = self.tile_size(&path.fill);
+        let options = self.stroke_options(&path.options);
+        let mesh = self.path_cache.stroke(&path.path, &options);
+        let (tex_min, tex_max) = match tile_size {
+            Some(tile_size) => mesh_tile_coords(mesh, tile_size, fill_repeat(&path.fill)),
+            None => (tex_min, tex_max),
+        };
 
-        let mesh = self.path_cache.stroke(&path.path, &path.options);
         Self::draw_mesh(
             self.vertices,
             self.indices,
             self.transform_stack,
             path.pos,
             mesh,
-            color,
+            Vec4::from(color) * self.tint,
             tex_min,
             tex_max,
+            instance_id,
         );
     }
 
@@ -593,9 +1368,10 @@ impl Batcher<'_> {
         transform_stack: &[Affine2],
         origin: Vec2,
         mesh: &Mesh,
-        color: Color,
+        color: Vec4,
         tex_min: Vec2,
         tex_max: Vec2,
+        instance_id: u32,
     ) {
         let Some(rect) = mesh.bounding_rect else {
             return;
@@ -613,7 +1389,8 @@ impl Batcher<'_> {
             vertices.push(Vertex {
                 pos,
                 tex: tex_min + (vertex.pos - rect.min) * tex_scale,
-                color: color.into(),
+                color,
+                instance_id,
                 ..vertex
             });
         }
@@ -644,7 +1421,104 @@ impl Batcher<'_> {
                 })
                 .unwrap_or((fill.tint, Source::White, Vec2::ZERO, Vec2::ZERO)),
             Fill::Solid(color) => (*color, Source::White, Vec2::ZERO, Vec2::ZERO),
+            Fill::ConicGradient(gradient) => self
+                .texture_cache
+                .get_gradient(&gradient.stops)
+                .map(|ramp| {
+                    (
+                        Color::WHITE,
+                        Source::Texture(ramp.texture),
+                        Vec2::ZERO,
+                        Vec2::ZERO,
+                    )
+                })
+                .unwrap_or((Color::TRANSPAENT, Source::White, Vec2::ZERO, Vec2::ZERO)),
+        }
+    }
+
+    fn gradient_instance(&self, fill: &Fill) -> Option<Instance> {
+        let Fill::ConicGradient(gradient) = fill else {
+            return None;
+        };
+
+        let ramp = self.texture_cache.get_gradient(&gradient.stops)?;
+        let texture_size = ramp.texture_size.as_vec2();
+
+        // sample at texel centers so that neighboring atlas entries don't bleed
+        let tex_min = (ramp.rect.min.as_vec2() + 0.5) / texture_size;
+        let tex_max = (ramp.rect.max.as_vec2() - 0.5) / texture_size;
+
+        Some(Instance {
+            flags: INSTANCE_FLAG_CONIC_GRADIENT,
+            gradient: Vec4::new(
+                gradient.center.x,
+                gradient.center.y,
+                gradient.start_angle,
+                0.0,
+            ),
+            gradient_tex: Vec4::new(tex_min.x, tex_max.x, tex_min.y, 0.0),
+            ..Instance::default()
+        })
+    }
+
+    fn repeat_instance(&self, fill: &Fill) -> Option<Instance> {
+        let Fill::Image(image) = fill else {
+            return None;
+        };
+
+        let (repeat_x, repeat_y) = image.repeat.axes();
+        if !repeat_x && !repeat_y {
+            return None;
+        }
+
+        let mut flags = 0;
+        if repeat_x {
+            flags |= INSTANCE_FLAG_REPEAT_X;
+        }
+        if repeat_y {
+            flags |= INSTANCE_FLAG_REPEAT_Y;
+        }
+
+        // region of the image in the texture, tiles are wrapped inside it
+        let (_, _, tex_min, tex_max) = self.get_fill(fill);
+
+        Some(Instance {
+            flags,
+            gradient_tex: Vec4::new(tex_min.x, tex_min.y, tex_max.x, tex_max.y),
+            ..Instance::default()
+        })
+    }
+
+    fn fill_instance(&self, fill: &Fill) -> Option<Instance> {
+        self.gradient_instance(fill)
+            .or_else(|| self.repeat_instance(fill))
+    }
+
+    /// Returns the tile size in pixels of a repeated image fill.
+    fn tile_size(&self, fill: &Fill) -> Option<Vec2> {
+        let Fill::Image(fill) = fill else {
+            return None;
+        };
+
+        if fill.repeat == RepeatMode::Clamp {
+            return None;
         }
+
+        let image = self.texture_cache.get_image(fill.image)?;
+        let image_size = image.rect.size().as_vec2();
+        let clip_size = fill.clip_rect.map_or(Vec2::ONE, |clip| clip.size());
+        let tile_size = image_size * clip_size * fill.tile_scale;
+
+        (tile_size.cmpgt(Vec2::ZERO).all()).then_some(tile_size)
+    }
+
+    fn add_mesh_instance(&mut self, fill: &Fill) -> u32 {
+        let Some(mut instance) = self.fill_instance(fill) else {
+            return INSTANCE_FILL;
+        };
+
+        instance.flags |= INSTANCE_FLAG_MESH;
+        self.add_instance(instance)
     }
 
     fn flush(&mut self) {
@@ -660,9 +1534,16 @@ impl Batcher<'_> {
 
         self.batches.push(Batch {
             clear: self.cur_clear,
+            opaque: self.cur_opaque,
+            subpixel_color: self.cur_subpixel_color,
+            blend_mode: self.cur_blend_mode,
+            mask: self.cur_mask,
+            clip: self.cur_clip,
             msaa_resolve: false,
+            blur_radius: 0.0,
             target: self.cur_target,
             source: self.cur_source,
+            sampler: self.cur_sampler,
             index_range,
             vertex_range,
             instance_buffer_id: self.cur_instance_buffer_id,
@@ -670,11 +1551,16 @@ impl Batcher<'_> {
     }
 
     fn set_source(&mut self, source: Source) {
-        if self.cur_source != source {
+        self.set_source_with_sampler(source, SamplerMode::Linear);
+    }
+
+    fn set_source_with_sampler(&mut self, source: Source, sampler: SamplerMode) {
+        if self.cur_source != source || self.cur_sampler != sampler {
             self.flush();
         }
 
         self.cur_source = source;
+        self.cur_sampler = sampler;
     }
 
     fn set_target(&mut self, target: Target) {
@@ -691,6 +1577,47 @@ impl Batcher<'_> {
         }
 
         self.cur_clear = clear;
+        self.set_opaque(false);
+    }
+
+    fn set_opaque(&mut self, opaque: bool) {
+        if self.cur_opaque != opaque {
+            self.flush();
+        }
+
+        self.cur_opaque = opaque;
+    }
+
+    fn set_subpixel_color(&mut self, color: Option<Color>) {
+        if self.cur_subpixel_color != color {
+            self.flush();
+        }
+
+        self.cur_subpixel_color = color;
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        if self.cur_blend_mode != blend_mode {
+            self.flush();
+        }
+
+        self.cur_blend_mode = blend_mode;
+    }
+
+    fn set_mask(&mut self, mask: bool) {
+        if self.cur_mask != mask {
+            self.flush();
+        }
+
+        self.cur_mask = mask;
+    }
+
+    fn set_clip(&mut self, clip: Option<ClipId>) {
+        if self.cur_clip != clip {
+            self.flush();
+        }
+
+        self.cur_clip = clip;
     }
 
     fn add_vertex(&mut self, mut vertex: Vertex) -> u32 {
@@ -700,6 +1627,10 @@ impl Batcher<'_> {
             vertex.pos = transform.transform_point2(vertex.pos);
         }
 
+        // fields of a packed struct can't be borrowed
+        let color = vertex.color;
+        vertex.color = color * self.tint;
+
         self.vertices.push(vertex);
 
         idx
@@ -711,7 +1642,8 @@ impl Batcher<'_> {
             self.cur_instance_buffer_id += 1;
         }
 
-        let idx = self.instances.len() as u32;
+        // relative to the instance buffer
+        let idx = (self.instances.len() % self.max_instances_per_buffer) as u32;
         self.instances.push(instance);
         idx
     }
@@ -723,6 +1655,7 @@ impl Batcher<'_> {
             tex: Vec2::new(quad.tex_min.x, quad.tex_min.y),
             color: quad.color,
             instance_id: quad.instance_id,
+            custom: Vec4::ZERO,
         });
         let b = self.add_vertex(Vertex {
             pos: Vec2::new(quad.max.x, quad.min.y),
@@ -730,6 +1663,7 @@ impl Batcher<'_> {
             tex: Vec2::new(quad.tex_max.x, quad.tex_min.y),
             color: quad.color,
             instance_id: quad.instance_id,
+            custom: Vec4::ZERO,
         });
         let c = self.add_vertex(Vertex {
             pos: Vec2::new(quad.max.x, quad.max.y),
@@ -737,6 +1671,7 @@ impl Batcher<'_> {
             tex: Vec2::new(quad.tex_max.x, quad.tex_max.y),
             color: quad.color,
             instance_id: quad.instance_id,
+            custom: Vec4::ZERO,
         });
         let d = self.add_vertex(Vertex {
             pos: Vec2::new(quad.min.x, quad.max.y),
@@ -744,8 +1679,82 @@ impl Batcher<'_> {
             tex: Vec2::new(quad.tex_min.x, quad.tex_max.y),
             color: quad.color,
             instance_id: quad.instance_id,
+            custom: Vec4::ZERO,
         });
         self.indices.extend_from_slice(&[a, b, c, c, d, a]);
+
+        if self.debug_overlay.quads {
+            let corners = self.last_quad_corners();
+            self.debug_quads.push((self.cur_target, corners));
+        }
+    }
+
+    fn last_quad_corners(&self) -> [Vec2; 4] {
+        let first = self.vertices.len() - 4;
+        [0, 1, 2, 3].map(|i| self.vertices[first + i].pos)
+    }
+}
+
+// cells are snapped to whole physical pixels, matching the subpixel bin of
+// the grid's glyph keys
+fn grid_cell_rect(
+    grid: &DrawGlyphGrid<'_>,
+    cell: &GlyphCell,
+    glyph: &AllocatedGlyph,
+    scale: f32,
+) -> Rect {
+    let pos = grid.pos + cell.pos + Vec2::new(0.0, grid.baseline);
+    let pos = ((pos * scale).round() + glyph.offset) / scale;
+    let size = glyph.rect.size().as_vec2() / scale;
+    Rect::new(pos, pos + size)
+}
+
+fn rect_corners(rect: Rect) -> [Vec2; 4] {
+    [
+        rect.min,
+        Vec2::new(rect.max.x, rect.min.y),
+        rect.max,
+        Vec2::new(rect.min.x, rect.max.y),
+    ]
+}
+
+fn fill_repeat(fill: &Fill) -> (bool, bool) {
+    match fill {
+        Fill::Image(image) => image.repeat.axes(),
+        _ => (false, false),
+    }
+}
+
+/// Computes texture coordinates of a mesh filled with a repeated image. These
+/// are in tile units along repeated axes, and normalized elsewhere.
+fn mesh_tile_coords(
+    mesh: &Mesh,
+    tile_size: Vec2,
+    (repeat_x, repeat_y): (bool, bool),
+) -> (Vec2, Vec2) {
+    let Some(rect) = mesh.bounding_rect else {
+        return (Vec2::ZERO, Vec2::ONE);
+    };
+
+    let tiles_min = rect.min / tile_size;
+    let tiles_max = rect.max / tile_size;
+
+    (
+        Vec2::new(
+            if repeat_x { tiles_min.x } else { 0.0 },
+            if repeat_y { tiles_min.y } else { 0.0 },
+        ),
+        Vec2::new(
+            if repeat_x { tiles_max.x } else { 1.0 },
+            if repeat_y { tiles_max.y } else { 1.0 },
+        ),
+    )
+}
+
+fn fill_sampler(fill: &Fill) -> SamplerMode {
+    match fill {
+        Fill::Image(image) => image.sampler,
+        _ => SamplerMode::Linear,
     }
 }
 
@@ -754,3 +1763,331 @@ impl fmt::Debug for Batcher<'_> {
         f.debug_struct("Batcher").finish_non_exhaustive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetSources;
+    use crate::image::{ImageData, ImageDecoders};
+    use crate::testing::TestFontDatabase;
+    use crate::text::{
+        FontFace, FontId, FontVariations, GlyphFormat, GlyphId, RasterizedGlyph, Rasterizer,
+        SubpixelBin,
+    };
+    use crate::texture::MipmapMode;
+    use crate::{Border, FillImage};
+
+    // records the requested sizes, and returns a square bitmap of that size
+    #[derive(Default)]
+    struct TestRasterizer {
+        sizes: Vec<f32>,
+    }
+
+    impl Rasterizer for TestRasterizer {
+        fn rasterize(
+            &mut self,
+            _font_face: &FontFace,
+            _glyph_id: GlyphId,
+            size: f32,
+            _subpixel_bin: SubpixelBin,
+            _variations: &FontVariations,
+        ) -> Option<RasterizedGlyph> {
+            self.sizes.push(size);
+            let size = size as u32;
+            Some(RasterizedGlyph {
+                image: ImageData {
+                    format: ImageFormat::Gray8,
+                    size: UVec2::splat(size),
+                    data: vec![255; (size * size) as usize],
+                    premultiplied: false,
+                },
+                offset: Vec2::ZERO,
+            })
+        }
+    }
+
+    fn draw_list<'a>(commands: &'a [Command<'a>]) -> DrawList<'a> {
+        DrawList {
+            surface: SurfaceId::default(),
+            commands,
+            opaque: false,
+            transform: Affine2::IDENTITY,
+            scale: 1.0,
+        }
+    }
+
+    fn draw_rect(pos: Vec2, size: Vec2, fill: Fill) -> Command<'static> {
+        Command::DrawRect(DrawRect {
+            pos,
+            size,
+            fill,
+            corner_radii: CornerRadii::default(),
+            border: None,
+            shadow: None,
+            pixel_snap: false,
+        })
+    }
+
+    fn draw_layer<'a>(
+        commands: &'a [Command<'a>],
+        opacity: f32,
+        scissor: Option<Scissor>,
+    ) -> Command<'a> {
+        Command::DrawLayer(DrawLayer {
+            commands,
+            tint: Color::WHITE,
+            opacity,
+            scissor,
+            transform: Affine2::IDENTITY,
+            crisp_text: false,
+            blend_mode: BlendMode::Normal,
+            clip_path: None,
+            blur: 0.0,
+            shadow: None,
+        })
+    }
+
+    // alpha of every vertex drawn into `target`, excluding clears
+    fn vertex_alphas(batcher: &Batcher<'_>, target: Target) -> Vec<f32> {
+        let batches = batcher.batches().iter();
+        let batches = batches.filter(|batch| batch.target == target && !batch.clear);
+        batches
+            .flat_map(|batch| {
+                let range = batch.vertex_range.start as usize..batch.vertex_range.end as usize;
+                batcher.vertices()[range].iter().map(|vertex| {
+                    let color = vertex.color;
+                    color.w
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scaled_list_uses_physical_sizes() {
+        let glyph = DrawGlyph {
+            pos: Vec2::new(10.0, 20.0),
+            size: 16.0,
+            font: FontId(0),
+            glyph: GlyphId(1),
+            color: Color::BLACK,
+            variations: FontVariations::default(),
+            format: GlyphFormat::Auto,
+            dilation: None,
+        };
+
+        let mut builder = PathBuilder::new();
+        builder.circle(Vec2::ZERO, 10.0);
+        let path = builder.finish();
+        let options = FillOptions::default();
+        let fill_path = FillPath {
+            pos: Vec2::ZERO,
+            path: path.clone(),
+            options,
+            fill: Fill::Solid(Color::BLACK),
+        };
+
+        let commands = [Command::DrawGlyph(glyph), Command::FillPath(fill_path)];
+        let list = DrawList {
+            scale: 2.0,
+            ..draw_list(&commands)
+        };
+
+        // a 16px glyph is rasterized at 32 physical pixels
+        let mut texture_cache = TextureCache::new();
+        texture_cache.add_glyphs_from_lists(&[list]);
+        let mut rasterizer = TestRasterizer::default();
+        let mut texture_commands = Vec::new();
+        texture_cache
+            .load_glyphs(
+                &TestFontDatabase::new(),
+                &mut rasterizer,
+                &mut texture_commands,
+            )
+            .unwrap();
+        assert_eq!(rasterizer.sizes, [32.0]);
+
+        let key = GlyphKey::new(&glyph, 2.0);
+        assert_eq!(f32::from_bits(key.size), 32.0);
+        let allocated = texture_cache.get_glyph(&key).expect("glyph isn't loaded");
+        assert_eq!(allocated.image_size, UVec2::splat(32));
+
+        let mut scratch = BatcherScratch::new();
+        let mut path_cache = PathCache::new();
+        let mut batcher = Batcher::new(&mut scratch, &texture_cache, &mut path_cache, 1000);
+        batcher.prepare(&list);
+        assert!(batcher
+            .batches()
+            .iter()
+            .any(|batch| batch.source == Source::Texture(allocated.texture)));
+
+        // the path is tessellated in list units, at half the physical tolerance
+        let stats = path_cache.stats();
+        assert_eq!(stats.misses, 1);
+        let physical = FillOptions {
+            tolerance: options.tolerance / 2.0,
+            ..options
+        };
+        path_cache.fill(&path, &physical);
+        assert_eq!(path_cache.stats().hits, stats.hits + 1);
+        assert_eq!(path_cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn rounded_scissor_clips_image() {
+        let mut texture_cache = TextureCache::new();
+        let image = texture_cache.add_image(
+            ImageData {
+                format: ImageFormat::Srgba8,
+                size: UVec2::new(4, 4),
+                data: vec![255; 4 * 4 * 4],
+                premultiplied: false,
+            },
+            MipmapMode::Disabled,
+        );
+
+        let mut commands = Vec::new();
+        texture_cache
+            .load_images(&AssetSources::new(), &ImageDecoders::new(), &mut commands)
+            .unwrap();
+        let texture = texture_cache.get_image(image.id()).unwrap().texture;
+
+        let radii = CornerRadii::new_equal(8.0);
+        let scissor = Scissor {
+            pos: Vec2::new(10.0, 10.0),
+            size: Vec2::new(40.0, 40.0),
+            corner_radii: radii,
+        };
+
+        let fill = Fill::Image(FillImage {
+            image: image.id(),
+            tint: Color::WHITE,
+            clip_rect: None,
+            sampler: SamplerMode::Linear,
+            repeat: RepeatMode::Clamp,
+            tile_scale: Vec2::ONE,
+        });
+        let inner = [draw_rect(Vec2::ZERO, Vec2::splat(60.0), fill)];
+
+        // drawn inline, and through an intermediate
+        for opacity in [1.0, 0.5] {
+            let commands = [draw_layer(&inner, opacity, Some(scissor))];
+
+            let mut scratch = BatcherScratch::new();
+            let mut path_cache = PathCache::new();
+            let mut batcher = Batcher::new(&mut scratch, &texture_cache, &mut path_cache, 1000);
+            batcher.prepare(&draw_list(&commands));
+
+            let batch = batcher.batches().iter();
+            let batch = batch
+                .filter(|batch| batch.source == Source::Texture(texture))
+                .collect::<Vec<_>>();
+            assert_eq!(batch.len(), 1);
+
+            let clip = batch[0].clip.expect("image isn't clipped");
+            let shapes = batcher.clip_shapes(clip);
+            assert_eq!(shapes.len(), 1);
+            assert_eq!(
+                shapes[0].rect,
+                Rect::new(scissor.pos, scissor.pos + scissor.size)
+            );
+            assert_eq!(shapes[0].corner_radii, radii);
+
+            // the intermediate is bounded by the scissor, and its composite
+            // isn't clipped again
+            if opacity < 1.0 {
+                assert_eq!(batcher.intermediates()[0].size, UVec2::splat(42));
+
+                let composite = batcher.batches().iter();
+                let composite = composite
+                    .filter(|batch| matches!(batch.source, Source::Intermediate(_)))
+                    .collect::<Vec<_>>();
+                assert_eq!(composite.len(), 1);
+                assert_eq!(composite[0].clip, None);
+            }
+        }
+    }
+
+    #[test]
+    fn group_opacity_composites_once() {
+        let texture_cache = TextureCache::new();
+
+        let fill = Fill::Solid(Color::rgba(0.0, 0.0, 0.0, 0.5));
+        let inner = [
+            draw_rect(Vec2::ZERO, Vec2::splat(20.0), fill),
+            draw_rect(Vec2::splat(10.0), Vec2::splat(20.0), fill),
+        ];
+        let commands = [draw_layer(&inner, 0.5, None)];
+
+        let mut scratch = BatcherScratch::new();
+        let mut path_cache = PathCache::new();
+        let mut batcher = Batcher::new(&mut scratch, &texture_cache, &mut path_cache, 1000);
+        batcher.prepare(&draw_list(&commands));
+
+        assert_eq!(batcher.intermediates().len(), 1);
+        let intermediate = IntermediateId(0);
+
+        // both rects are drawn into the intermediate at their own opacity
+        let inner_alphas = vertex_alphas(&batcher, Target::Intermediate(intermediate));
+        assert_eq!(inner_alphas, [0.5; 8]);
+
+        // and composited once, at the group opacity
+        let composite = batcher.batches().iter();
+        let composite = composite
+            .filter(|batch| batch.source == Source::Intermediate(intermediate))
+            .collect::<Vec<_>>();
+        assert_eq!(composite.len(), 1);
+        assert_eq!(composite[0].target, Target::Surface(SurfaceId::default()));
+
+        let outer_alphas = vertex_alphas(&batcher, Target::Surface(SurfaceId::default()));
+        assert_eq!(outer_alphas, [0.5; 4]);
+
+        // the overlap is as dark as a single layer composite of the flattened
+        // group, and lighter than blending each rect at 25%
+        let over = |src: f32, dst: f32| src + dst * (1.0 - src);
+        let group = over(inner_alphas[0], inner_alphas[4]) * outer_alphas[0];
+        let tinted = over(0.25, 0.25);
+        assert_eq!(group, 0.375);
+        assert!(group < tinted);
+    }
+
+    #[test]
+    fn inline_layer_tints_instance_colors() {
+        let texture_cache = TextureCache::new();
+
+        let inner = [Command::DrawRect(DrawRect {
+            pos: Vec2::ZERO,
+            size: Vec2::splat(20.0),
+            fill: Fill::Solid(Color::WHITE),
+            corner_radii: CornerRadii::new_equal(4.0),
+            border: Some(Border {
+                color: Color::WHITE,
+                width: 2.0,
+            }),
+            shadow: Some(Shadow {
+                blur_radius: 4.0,
+                spread_radius: 0.0,
+                offset: Vec2::ZERO,
+                color: Color::BLACK,
+            }),
+            pixel_snap: false,
+        })];
+        let commands = [draw_layer(&inner, 0.5, None)];
+
+        let mut scratch = BatcherScratch::new();
+        let mut path_cache = PathCache::new();
+        let mut batcher = Batcher::new(&mut scratch, &texture_cache, &mut path_cache, 1000);
+        batcher.set_max_intermediates(0);
+        batcher.prepare(&draw_list(&commands));
+
+        assert_eq!(batcher.skipped_intermediates(), 1);
+        assert!(batcher.intermediates().is_empty());
+
+        let instance = batcher
+            .instances()
+            .iter()
+            .find(|instance| instance.border_width > 0.0)
+            .expect("rect has no instance");
+        assert_eq!(instance.border_color, Vec4::splat(0.5));
+        assert_eq!(instance.shadow_color, Vec4::new(0.0, 0.0, 0.0, 0.5));
+    }
+}
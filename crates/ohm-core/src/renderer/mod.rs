@@ -4,9 +4,11 @@
 use std::sync::Arc;
 
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+use smallvec::{smallvec, SmallVec};
 
-use crate::math::UVec2;
-use crate::texture::{TextureCache, TextureCommand};
+use crate::image::{ImageData, ImageFormat};
+use crate::math::{URect, UVec2};
+use crate::texture::{TextureCache, TextureCommand, TextureId};
 use crate::{DrawList, Result};
 
 mod batcher;
@@ -19,6 +21,44 @@ slotmap::new_key_type! {
     pub struct SurfaceId;
 }
 
+/// Features and limits of a renderer, see [`Renderer::capabilities`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RendererCapabilities {
+    /// Maximum width and height of a texture, in pixels.
+    pub max_texture_size: u32,
+    /// Supported MSAA sample counts, in increasing order. Always contains `1`.
+    pub msaa_sample_counts: SmallVec<[u32; 4]>,
+    /// Whether storage buffers are available. Without them, draw calls are
+    /// split into smaller chunks of instances.
+    pub storage_buffers: bool,
+    /// Formats textures can be created with.
+    pub image_formats: SmallVec<[ImageFormat; 4]>,
+}
+
+impl RendererCapabilities {
+    /// Returns whether the given MSAA sample count is supported.
+    pub fn supports_msaa_samples(&self, samples: u32) -> bool {
+        self.msaa_sample_counts.contains(&samples)
+    }
+
+    /// Returns whether textures can be created with the given format.
+    pub fn supports_image_format(&self, format: ImageFormat) -> bool {
+        self.image_formats.contains(&format)
+    }
+}
+
+impl Default for RendererCapabilities {
+    /// Conservative capabilities, supported by any renderer.
+    fn default() -> RendererCapabilities {
+        RendererCapabilities {
+            max_texture_size: 2048,
+            msaa_sample_counts: smallvec![1],
+            storage_buffers: false,
+            image_formats: smallvec![ImageFormat::Srgba8, ImageFormat::Gray8, ImageFormat::Rgba8],
+        }
+    }
+}
+
 /// A trait implemented by all renderers.
 ///
 /// A renderer should manage resources like surfaces (windows) and textures, and
@@ -28,6 +68,13 @@ pub trait Renderer: Send + Sync + 'static {
     /// initial size.
     fn create_surface(&mut self, window: Arc<dyn WindowHandle>, size: UVec2) -> Result<SurfaceId>;
 
+    /// Creates a surface not attached to any window, rendering into an
+    /// internal render target instead.
+    ///
+    /// Useful for headless rendering, e.g. on CI servers or for generating
+    /// thumbnails. Presenting an offscreen surface does nothing.
+    fn create_offscreen_surface(&mut self, size: UVec2) -> Result<SurfaceId>;
+
     /// Resizes a surface.
     ///
     /// # Panics
@@ -44,6 +91,27 @@ pub trait Renderer: Send + Sync + 'static {
     /// invalid.
     fn get_surface_size(&self, surface: SurfaceId) -> UVec2;
 
+    /// Sets the scale factor (DPI scaling) of a surface. The scale factor is
+    /// preserved across resizes.
+    ///
+    /// Ohm works in physical pixels unless a draw list opts into it by setting
+    /// [`DrawList::scale`](crate::DrawList::scale), otherwise this value is
+    /// only stored for use by higher level code. Default: `1.0`.
+    ///
+    /// # Panics
+    ///
+    /// This method is allowed to panic if the provided [`SurfaceId`] is
+    /// invalid.
+    fn set_surface_scale(&mut self, id: SurfaceId, scale: f32);
+
+    /// Returns the scale factor of the provided surface.
+    ///
+    /// # Panics
+    ///
+    /// This method is allowed to panic if the provided [`SurfaceId`] is
+    /// invalid.
+    fn get_surface_scale(&self, id: SurfaceId) -> f32;
+
     /// Destroys a surface, releasing all of its associated memory.
     ///
     /// # Panics
@@ -61,6 +129,13 @@ pub trait Renderer: Send + Sync + 'static {
     /// The provided vector is expected to be empty after a successful return.
     fn update_textures(&mut self, commands: &mut Vec<TextureCommand>) -> Result<()>;
 
+    /// Reads back a region of a texture, e.g. a single glyph in an atlas.
+    ///
+    /// Returns an error if the texture doesn't exist or can't be read back,
+    /// e.g. because of its format or usage, or if the region is empty or out
+    /// of bounds.
+    fn read_texture_region(&mut self, id: TextureId, rect: URect) -> Result<ImageData>;
+
     /// Renders each [`DrawList`] into its associated surface.
     fn render(
         &mut self,
@@ -71,6 +146,12 @@ pub trait Renderer: Send + Sync + 'static {
 
     /// Presents all touched surfaces to the screen.
     fn present(&mut self) -> Result<()>;
+
+    /// Returns the features and limits of the renderer.
+    ///
+    /// Renderers that pick a device lazily may return
+    /// [`RendererCapabilities::default`] until the first surface is created.
+    fn capabilities(&self) -> RendererCapabilities;
 }
 
 /// A trait for window handles. In most cases, this will be a `Window` from
@@ -1,3 +1,4 @@
+use std::hash::{Hash, Hasher};
 use std::num::NonZeroUsize;
 
 use glam::Vec4;
@@ -12,15 +13,58 @@ use crate::math::{Rect, Vec2};
 use crate::path::{FillOptions, FillRule, LineCap, LineJoin, Path, PathEvent, StrokeOptions};
 use crate::renderer::{Vertex, INSTANCE_FILL};
 
-const CAPACITY: usize = 100;
+const DEFAULT_CAPACITY: usize = 100;
+
+// paths are compared by their contents, since the address of the events may
+// be reused by a different path after the old one is dropped
+#[derive(Debug, Clone)]
+struct PathKey(Path);
+
+impl PartialEq for PathKey {
+    fn eq(&self, other: &Self) -> bool {
+        let lhs = self.0.events();
+        let rhs = other.0.events();
+        std::ptr::eq(lhs, rhs)
+            || (lhs.len() == rhs.len()
+                && lhs
+                    .iter()
+                    .zip(rhs)
+                    .all(|(a, b)| event_bits(a) == event_bits(b)))
+    }
+}
+
+impl Eq for PathKey {}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct PathKey(*const PathEvent);
+impl Hash for PathKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let events = self.0.events();
+        events.len().hash(state);
+        for event in events {
+            event_bits(event).hash(state);
+        }
+    }
+}
 
-unsafe impl Send for PathKey {}
-unsafe impl Sync for PathKey {}
+fn event_bits(event: &PathEvent) -> (u8, [u32; 6]) {
+    let bits = |points: &[Vec2]| {
+        let mut res = [0; 6];
+        for (i, point) in points.iter().enumerate() {
+            res[i * 2] = point.x.to_bits();
+            res[i * 2 + 1] = point.y.to_bits();
+        }
+        res
+    };
+
+    match *event {
+        PathEvent::MoveTo { point } => (0, bits(&[point])),
+        PathEvent::LineTo { point } => (1, bits(&[point])),
+        PathEvent::QuadTo { control, point } => (2, bits(&[control, point])),
+        PathEvent::CubicTo { control, point } => (3, bits(&[control[0], control[1], point])),
+        PathEvent::Close => (4, [0; 6]),
+    }
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum Key {
     Fill(PathKey, FillOptions),
     Stroke(PathKey, StrokeOptions),
@@ -33,24 +77,75 @@ pub struct Mesh {
     pub indices: Vec<u32>,
 }
 
+/// Statistics of a [`PathCache`], for tuning its capacity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathCacheStats {
+    /// Number of lookups that found a cached mesh.
+    pub hits: u64,
+    /// Number of lookups that tessellated the path.
+    pub misses: u64,
+    /// Number of cached meshes.
+    pub len: usize,
+    /// Maximum number of cached meshes.
+    pub capacity: usize,
+}
+
 pub struct PathCache {
     lru: LruCache<Key, Mesh>,
     stroke_tessellator: StrokeTessellator,
     fill_tessellator: FillTessellator,
+    lookups: u64,
+    misses: u64,
 }
 
 impl PathCache {
     pub fn new() -> PathCache {
+        PathCache::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates a cache holding at most `capacity` meshes, at least one.
+    pub fn with_capacity(capacity: usize) -> PathCache {
         PathCache {
-            lru: LruCache::new(NonZeroUsize::new(CAPACITY).unwrap()),
+            lru: LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap()),
             stroke_tessellator: StrokeTessellator::new(),
             fill_tessellator: FillTessellator::new(),
+            lookups: 0,
+            misses: 0,
         }
     }
 
+    /// Changes the maximum number of cached meshes, evicting the least
+    /// recently used ones if there are more.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.lru.resize(NonZeroUsize::new(capacity.max(1)).unwrap());
+    }
+
+    /// Returns the maximum number of cached meshes.
+    pub fn capacity(&self) -> usize {
+        self.lru.cap().get()
+    }
+
+    /// Returns the hit and miss counters and the size of the cache.
+    pub fn stats(&self) -> PathCacheStats {
+        PathCacheStats {
+            hits: self.lookups - self.misses,
+            misses: self.misses,
+            len: self.lru.len(),
+            capacity: self.capacity(),
+        }
+    }
+
+    /// Resets the hit and miss counters, e.g. to measure a single frame.
+    pub fn reset_stats(&mut self) {
+        self.lookups = 0;
+        self.misses = 0;
+    }
+
     pub fn fill(&mut self, path: &Path, options: &FillOptions) -> &Mesh {
-        let path_key = PathKey(path.events().as_ptr() as *const _);
+        let path_key = PathKey(path.clone());
+        self.lookups += 1;
         self.lru.get_or_insert(Key::Fill(path_key, *options), || {
+            self.misses += 1;
             let mut buffers = VertexBuffers::new();
 
             let mut output = BuffersBuilder::new(&mut buffers, |vertex: FillVertex<'_>| {
@@ -61,6 +156,7 @@ impl PathCache {
                     tex: pos,
                     color: Vec4::new(1.0, 1.0, 1.0, 1.0),
                     instance_id: INSTANCE_FILL,
+                    custom: Vec4::ZERO,
                 }
             });
 
@@ -81,8 +177,10 @@ impl PathCache {
     }
 
     pub fn stroke(&mut self, path: &Path, options: &StrokeOptions) -> &Mesh {
-        let path_key = PathKey(path.events().as_ptr() as *const _);
+        let path_key = PathKey(path.clone());
+        self.lookups += 1;
         self.lru.get_or_insert(Key::Stroke(path_key, *options), || {
+            self.misses += 1;
             let mut buffers = VertexBuffers::new();
 
             let mut output = BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex<'_, '_>| {
@@ -93,6 +191,7 @@ impl PathCache {
                     tex: pos,
                     color: Vec4::new(1.0, 1.0, 1.0, 1.0),
                     instance_id: INSTANCE_FILL,
+                    custom: Vec4::ZERO,
                 }
             });
 
@@ -209,7 +308,9 @@ fn path_to_events(path: &Path) -> impl Iterator<Item = LyonPathEvent> + '_ {
 }
 
 fn lyon_fill_options(options: &FillOptions) -> lyon_tessellation::FillOptions {
-    lyon_tessellation::FillOptions::DEFAULT.with_fill_rule(lyon_fill_rule(options.fill_rule))
+    lyon_tessellation::FillOptions::DEFAULT
+        .with_fill_rule(lyon_fill_rule(options.fill_rule))
+        .with_tolerance(options.tolerance)
 }
 
 fn lyon_fill_rule(rule: FillRule) -> lyon_tessellation::FillRule {
@@ -225,6 +326,7 @@ fn lyon_stroke_options(options: &StrokeOptions) -> lyon_tessellation::StrokeOpti
         .with_line_join(lyon_line_join(options.line_join))
         .with_line_width(options.line_width)
         .with_miter_limit(options.mitter_limit)
+        .with_tolerance(options.tolerance)
 }
 
 fn lyon_line_cap(cap: LineCap) -> lyon_tessellation::LineCap {
@@ -247,3 +349,82 @@ fn lyon_line_join(join: LineJoin) -> lyon_tessellation::LineJoin {
 fn lyon_point(p: Vec2) -> Point<f32> {
     Point::new(p.x, p.y)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::PathBuilder;
+
+    fn triangle(builder: &mut PathBuilder, size: f32) -> Path {
+        builder.move_to(Vec2::ZERO);
+        builder.line_to(Vec2::new(size, 0.0));
+        builder.line_to(Vec2::new(0.0, size));
+        builder.close();
+        builder.finish()
+    }
+
+    #[test]
+    fn reused_allocation_isnt_served_stale_mesh() {
+        let mut path_cache = PathCache::new();
+        let mut builder = PathBuilder::new();
+        let options = FillOptions::default();
+
+        let a = triangle(&mut builder, 10.0);
+        let rect = path_cache.fill(&a, &options).bounding_rect;
+        assert_eq!(rect, Some(Rect::new(Vec2::ZERO, Vec2::splat(10.0))));
+        drop(a);
+
+        // the mesh of A is still cached, B comes from the same builder with as
+        // many events
+        let b = triangle(&mut builder, 20.0);
+        let rect = path_cache.fill(&b, &options).bounding_rect;
+        assert_eq!(rect, Some(Rect::new(Vec2::ZERO, Vec2::splat(20.0))));
+        assert_eq!(path_cache.stats().misses, 2);
+
+        // the same contents at another address hit
+        let c = triangle(&mut builder, 20.0);
+        assert!(!std::ptr::eq(b.events(), c.events()));
+        let rect = path_cache.fill(&c, &options).bounding_rect;
+        assert_eq!(rect, Some(Rect::new(Vec2::ZERO, Vec2::splat(20.0))));
+        assert_eq!(path_cache.stats().hits, 1);
+    }
+
+    fn mesh_area(mesh: &Mesh) -> f32 {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| {
+                    let vertex = mesh.vertices[triangle[i] as usize];
+                    vertex.pos
+                });
+                (b - a).perp_dot(c - a).abs() / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn circle_area_matches_pi_r_squared() {
+        let mut path_cache = PathCache::new();
+        let mut builder = PathBuilder::new();
+
+        // flattening adds little to the error of the arc approximation
+        let options = FillOptions {
+            tolerance: 0.001,
+            ..FillOptions::default()
+        };
+
+        for radius in [4.0, 1000.0] {
+            builder.circle(Vec2::splat(radius), radius);
+            let path = builder.finish();
+
+            let area = mesh_area(path_cache.fill(&path, &options));
+            let expected = std::f32::consts::PI * radius * radius;
+
+            // the arcs bend at most 0.01 away from the circle, and large ones
+            // only stay within that with more segments
+            let max_error = 2.0 * (0.01 + options.tolerance) / radius;
+            let error = (area - expected).abs() / expected;
+            assert!(error <= max_error, "radius {radius}: error {error}");
+        }
+    }
+}
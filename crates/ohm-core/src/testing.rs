@@ -0,0 +1,155 @@
+//! Fixtures shared by unit tests.
+
+use std::sync::Arc;
+
+use crate::math::Vec2;
+use crate::text::{
+    FontAttrs, FontDatabase, FontFace, FontId, FontVariations, GlyphId, OtFeatures, ShapedGlyph,
+    TextShaper,
+};
+use crate::Result;
+
+const UNITS_PER_EM: u16 = 1000;
+const ASCENDER: i16 = 800;
+const DESCENDER: i16 = -200;
+
+// minimal TrueType font with the tables needed by `FontFace`, no glyph
+// outlines
+pub(crate) fn test_font_data() -> Vec<u8> {
+    fn be16(out: &mut Vec<u8>, v: u16) {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+
+    let mut head = Vec::new();
+    head.extend_from_slice(&0x0001_0000u32.to_be_bytes()); // version
+    head.extend_from_slice(&0u32.to_be_bytes()); // font revision
+    head.extend_from_slice(&0u32.to_be_bytes()); // checksum adjustment
+    head.extend_from_slice(&0x5F0F_3CF5u32.to_be_bytes()); // magic
+    be16(&mut head, 0); // flags
+    be16(&mut head, UNITS_PER_EM);
+    head.extend_from_slice(&[0; 16]); // created, modified
+    head.extend_from_slice(&[0; 8]); // bounding box
+    head.extend_from_slice(&[0; 6]); // mac style, lowest ppem, direction
+    be16(&mut head, 0); // index to loc format
+    be16(&mut head, 0); // glyph data format
+
+    let mut hhea = Vec::new();
+    hhea.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    be16(&mut hhea, ASCENDER as u16);
+    be16(&mut hhea, DESCENDER as u16);
+    hhea.extend_from_slice(&[0; 26]); // line gap to metric data format
+    be16(&mut hhea, 0); // number of h metrics
+
+    let mut maxp = Vec::new();
+    maxp.extend_from_slice(&0x0000_5000u32.to_be_bytes());
+    be16(&mut maxp, 2); // number of glyphs
+
+    let family = "Test".encode_utf16().flat_map(u16::to_be_bytes);
+    let family = family.collect::<Vec<_>>();
+    let mut name = Vec::new();
+    for v in [0, 1, 6 + 12, 3, 1, 0x0409, 1, family.len() as u16, 0] {
+        be16(&mut name, v);
+    }
+    name.extend_from_slice(&family);
+
+    let tables = [
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"maxp", maxp),
+        (b"name", name),
+    ];
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for v in [tables.len() as u16, 0, 0, 0] {
+        be16(&mut data, v);
+    }
+
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, table) in &tables {
+        data.extend_from_slice(*tag);
+        data.extend_from_slice(&0u32.to_be_bytes()); // checksum
+        data.extend_from_slice(&(offset as u32).to_be_bytes());
+        data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += table.len().next_multiple_of(4);
+    }
+
+    for (_, table) in &tables {
+        data.extend_from_slice(table);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    data
+}
+
+pub(crate) struct TestFontDatabase {
+    face: FontFace,
+}
+
+impl TestFontDatabase {
+    pub(crate) fn new() -> TestFontDatabase {
+        let data = Arc::new(test_font_data());
+        let face = FontFace::new(FontId(0), data, 0).unwrap();
+        TestFontDatabase { face }
+    }
+}
+
+impl FontDatabase for TestFontDatabase {
+    fn query(&self, _attrs: &FontAttrs) -> Option<FontId> {
+        Some(self.face.id())
+    }
+
+    fn load(&mut self, _id: FontId) -> Result<&FontFace> {
+        Ok(&self.face)
+    }
+
+    fn get(&self, _id: FontId) -> Option<&FontFace> {
+        Some(&self.face)
+    }
+
+    fn get_or_load(&mut self, _id: FontId) -> Result<&FontFace> {
+        Ok(&self.face)
+    }
+
+    fn add_face(
+        &mut self,
+        _data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        _index: u32,
+    ) -> Result<FontId> {
+        Ok(self.face.id())
+    }
+
+    fn families(&self) -> Vec<String> {
+        vec!["Test".into()]
+    }
+
+    fn set_last_resort_font(&mut self, _font: Option<FontId>) {}
+
+    fn last_resort_font(&self) -> Option<FontId> {
+        None
+    }
+}
+
+// one glyph per character, half an em wide, in logical order like the glyphs
+// of a real shaper
+pub(crate) struct TestTextShaper;
+
+impl TextShaper for TestTextShaper {
+    fn shape(
+        &mut self,
+        _font_face: &FontFace,
+        text: &str,
+        size: f32,
+        _is_rtl: bool,
+        _features: &OtFeatures,
+        _variations: &FontVariations,
+        buf: &mut Vec<ShapedGlyph>,
+    ) {
+        buf.extend(text.char_indices().map(|(cluster, _)| ShapedGlyph {
+            glyph_id: GlyphId(1),
+            cluster,
+            x_advance: size * 0.5,
+            offset: Vec2::ZERO,
+        }));
+    }
+}
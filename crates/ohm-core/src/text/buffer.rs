@@ -1,15 +1,17 @@
 use std::ops::Range;
+use std::sync::Arc;
 
 use smallvec::SmallVec;
 use unicode_bidi::{BidiInfo, Level as BidiLevel, ParagraphInfo as BidiParagraph};
 use unicode_linebreak::BreakOpportunity;
 
-use crate::math::Vec2;
+use crate::math::{Rect, Vec2};
 use crate::text::{
-    FontAttrs, FontDatabase, FontFace, FontFamily, FontId, LineHeight, ShapedGlyph, TextAlign,
-    TextAttrs, TextShaper,
+    FontAttrs, FontDatabase, FontFace, FontFamily, FontId, FontVariations, GlyphFormat, Hyphenator,
+    LineHeight, OtFeatures, OverflowWrap, ShapedGlyph, TextAlign, TextAttrs, TextShaper,
+    VerticalAlign,
 };
-use crate::Color;
+use crate::{Color, Shadow};
 
 #[derive(Debug)]
 pub struct TextBuffer {
@@ -20,9 +22,22 @@ pub struct TextBuffer {
     lines: Vec<Line>,
     bidi_paragraphs: Vec<BidiParagraph>,
     scratch_indices: Vec<usize>,
+    scratch_breaks: Vec<(usize, BreakOpportunity)>,
+    // byte offsets of hyphenation points from the hyphenator, sorted
+    hyphen_breaks: Vec<usize>,
+    hyphenator: Option<Arc<dyn Hyphenator>>,
+    // sorted and non-overlapping
+    color_ranges: Vec<(Range<usize>, Color)>,
     max_width: f32,
+    max_lines: Option<usize>,
+    ellipsis: bool,
+    is_truncated: bool,
+    snap_baseline: bool,
+    width: f32,
     height: f32,
     dirty: bool,
+    // cached until the text changes, see `measure`
+    metrics: Option<TextMetrics>,
 }
 
 #[derive(Debug, Clone)]
@@ -41,14 +56,69 @@ pub struct Run {
     pub linebreak: Option<BreakOpportunity>,
     pub font: FontId,
     pub font_size: f32,
+    pub variations: FontVariations,
+    pub glyph_format: GlyphFormat,
     pub line_height: f32,
     pub text_height: f32,
+    /// Height above the baseline, from the font metrics.
+    pub ascent: f32,
+    /// Depth below the baseline, from the font metrics.
+    pub descent: f32,
     pub color: Color,
+    pub shadow: Option<Shadow>,
+    pub outline: Option<(Color, f32)>,
     pub width: f32,
     pub trailing_whitespace_width: f32,
+    /// Width of the hyphen shown when the line is broken after this run, zero
+    /// if it isn't a hyphenation point.
+    pub hyphen_width: f32,
     pub pos: Vec2,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HitTestResult {
+    /// Index of the line under the point.
+    pub line_idx: usize,
+    /// Index of the run under the point, see [`TextBuffer::runs`].
+    pub run_idx: usize,
+    /// Byte offset of the cluster under the point.
+    pub cluster: usize,
+    /// Whether the point is on the trailing half of the glyph (in logical
+    /// order), i.e. the caret belongs after the cluster rather than before it.
+    pub is_trailing: bool,
+}
+
+/// A laid out line of a [`TextBuffer`], see [`TextBuffer::lines`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineInfo {
+    /// Top of the line box.
+    pub top: f32,
+    /// Baseline of the line, runs with a [`VerticalAlign`] other than
+    /// [`VerticalAlign::Baseline`] may be placed differently.
+    pub baseline: f32,
+    /// Bottom of the line box, the top of the next line.
+    pub bottom: f32,
+    /// Horizontal extent of the glyphs, without trailing whitespace.
+    pub width: f32,
+    /// Byte range of the line in the text, including trailing whitespace and
+    /// the line break.
+    pub range: Range<usize>,
+    /// Whether the line is in a right-to-left paragraph.
+    pub is_rtl: bool,
+}
+
+/// Intrinsic size of a text, independent of the max width.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TextMetrics {
+    /// Width of the widest word, i.e. when broken at every opportunity.
+    pub min_content_width: f32,
+    /// Width of the widest line, when only broken at forced line breaks.
+    pub max_content_width: f32,
+    /// Height of the lines when only broken at forced line breaks, limited by
+    /// the max lines.
+    pub height: f32,
+}
+
 #[derive(Debug, Clone, Default)]
 struct Line {
     range: Range<usize>,
@@ -57,6 +127,9 @@ struct Line {
     width: f32,
     whitespace_width: f32,
     height: f32,
+    // offset from the top of the line
+    baseline: f32,
+    top: f32,
     is_linebreak_forced: bool,
 }
 
@@ -70,9 +143,19 @@ impl TextBuffer {
             lines: Vec::new(),
             bidi_paragraphs: Vec::new(),
             scratch_indices: Vec::new(),
+            scratch_breaks: Vec::new(),
+            hyphen_breaks: Vec::new(),
+            hyphenator: None,
+            color_ranges: Vec::new(),
             max_width: f32::INFINITY,
+            max_lines: None,
+            ellipsis: false,
+            is_truncated: false,
+            snap_baseline: false,
+            width: 0.0,
             height: 0.0,
             dirty: true,
+            metrics: None,
         }
     }
 
@@ -84,11 +167,25 @@ impl TextBuffer {
         self.lines.clear();
         self.bidi_paragraphs.clear();
         self.scratch_indices.clear();
+        self.scratch_breaks.clear();
+        self.hyphen_breaks.clear();
+        self.color_ranges.clear();
         self.max_width = f32::INFINITY;
+        self.max_lines = None;
+        self.ellipsis = false;
+        self.is_truncated = false;
+        self.snap_baseline = false;
+        self.width = 0.0;
         self.height = 0.0;
         self.dirty = false;
+        self.metrics = None;
     }
 
+    /// Appends a section of text with its own attributes. Sections may start
+    /// mid-word or mid-line, e.g. to change the color or size of a few
+    /// characters. Runs of different sizes on one line share a common
+    /// baseline unless [`TextAttrs::vertical_align`] says otherwise, and the
+    /// line is tall enough to fit all of them.
     pub fn push(&mut self, attrs: TextAttrs, text: &str) {
         self.text.push_str(text);
         self.sections.push(Section {
@@ -98,6 +195,7 @@ impl TextBuffer {
         });
 
         self.dirty = true;
+        self.metrics = None;
     }
 
     pub fn text(&self) -> &str {
@@ -113,6 +211,61 @@ impl TextBuffer {
         self.dirty = true;
     }
 
+    /// Limits the number of lines, the rest of the text is hidden. `None`
+    /// shows all lines.
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        if self.max_lines == max_lines {
+            return;
+        }
+
+        self.max_lines = max_lines;
+        self.dirty = true;
+        self.metrics = None;
+    }
+
+    /// Ends the last visible line with an ellipsis when lines are hidden by
+    /// [`set_max_lines`]. The end of the line is trimmed until the ellipsis
+    /// fits within the max width, if even the ellipsis alone doesn't fit, it's
+    /// shown on its own.
+    ///
+    /// [`set_max_lines`]: TextBuffer::set_max_lines
+    pub fn set_ellipsis(&mut self, ellipsis: bool) {
+        if self.ellipsis == ellipsis {
+            return;
+        }
+
+        self.ellipsis = ellipsis;
+        self.dirty = true;
+    }
+
+    /// Returns whether lines were hidden by [`set_max_lines`] in the last
+    /// computed layout.
+    ///
+    /// [`set_max_lines`]: TextBuffer::set_max_lines
+    pub fn is_truncated(&self) -> bool {
+        self.is_truncated
+    }
+
+    /// Sets the hyphenator finding hyphenation points in sections with
+    /// [`TextAttrs::hyphenate`]. Kept when the buffer is reset.
+    pub fn set_hyphenator(&mut self, hyphenator: Option<Arc<dyn Hyphenator>>) {
+        self.hyphenator = hyphenator;
+        self.dirty = true;
+        self.metrics = None;
+    }
+
+    /// Rounds the baseline of every run to whole pixels, so that text doesn't
+    /// shimmer vertically when scrolled. Horizontal positions are kept
+    /// fractional, and line spacing may become slightly uneven.
+    pub fn set_snap_baseline(&mut self, snap_baseline: bool) {
+        if self.snap_baseline == snap_baseline {
+            return;
+        }
+
+        self.snap_baseline = snap_baseline;
+        self.dirty = true;
+    }
+
     pub fn compute_layout(&mut self, font_db: &mut dyn FontDatabase, shaper: &mut dyn TextShaper) {
         if !self.dirty {
             return;
@@ -123,12 +276,18 @@ impl TextBuffer {
         self.lines.clear();
         self.bidi_paragraphs.clear();
         self.scratch_indices.clear();
+        self.is_truncated = false;
+        self.width = 0.0;
+        self.height = 0.0;
 
         self.split_runs_by_bidi_levels();
         self.shape_runs(font_db, shaper);
         self.split_runs_by_words();
         self.measure_runs();
+        self.shape_hyphens(font_db, shaper);
         self.break_lines();
+        self.truncate_lines(font_db, shaper);
+        self.insert_hyphens(font_db, shaper);
         self.measure_lines();
         self.bidi_reorder_runs();
         self.layout_lines();
@@ -136,11 +295,112 @@ impl TextBuffer {
         self.dirty = false;
     }
 
+    /// Measures the intrinsic size of the text, for sizing its container
+    /// before the max width is known. Only shapes the text, and the computed
+    /// layout is kept. The result is cached until the text changes.
+    pub fn measure(
+        &mut self,
+        font_db: &mut dyn FontDatabase,
+        shaper: &mut dyn TextShaper,
+    ) -> TextMetrics {
+        if let Some(metrics) = self.metrics {
+            return metrics;
+        }
+
+        // the shaping stages work in place, so keep the layout aside
+        let runs = std::mem::take(&mut self.runs);
+        let glyphs = std::mem::take(&mut self.glyphs);
+        let bidi_paragraphs = std::mem::take(&mut self.bidi_paragraphs);
+
+        self.split_runs_by_bidi_levels();
+        self.shape_runs(font_db, shaper);
+        self.split_runs_by_words();
+        self.measure_runs();
+        self.shape_hyphens(font_db, shaper);
+
+        let mut metrics = TextMetrics::default();
+        let mut word_width = 0.0;
+        let mut line_width = 0.0;
+        let mut line_start = 0;
+        let mut num_lines = 0;
+        let mut prev_trailing_whitespace = 0.0;
+        let mut is_word_start = true;
+
+        for (run_idx, run) in self.runs.iter().enumerate() {
+            // whitespace only counts when followed by another run, words are
+            // broken after it
+            line_width += prev_trailing_whitespace + run.width;
+
+            let overflow_wrap = self.sections[run.section_idx].attrs.overflow_wrap;
+            if overflow_wrap == OverflowWrap::Anywhere {
+                // can be broken between any clusters, so only the widest
+                // cluster counts
+                let glyphs = &self.glyphs[run.glyph_range.clone()];
+                let mut cluster_width = 0.0;
+                for (i, glyph) in glyphs.iter().enumerate() {
+                    cluster_width += glyph.x_advance;
+                    if glyphs.get(i + 1).is_none_or(|v| v.cluster != glyph.cluster) {
+                        let is_whitespace = self.text[glyph.cluster..]
+                            .chars()
+                            .next()
+                            .is_some_and(char::is_whitespace);
+                        if !is_whitespace {
+                            metrics.min_content_width =
+                                metrics.min_content_width.max(cluster_width);
+                        }
+                        cluster_width = 0.0;
+                    }
+                }
+
+                word_width = 0.0;
+            } else if is_word_start {
+                word_width = run.width;
+            } else {
+                word_width += prev_trailing_whitespace + run.width;
+            }
+
+            prev_trailing_whitespace = run.trailing_whitespace_width;
+            is_word_start = overflow_wrap == OverflowWrap::Anywhere;
+
+            let is_last = run_idx + 1 == self.runs.len();
+
+            if run.linebreak.is_some() || is_last {
+                let word_width = word_width + run.hyphen_width;
+                metrics.min_content_width = metrics.min_content_width.max(word_width);
+                is_word_start = true;
+            }
+
+            if run.linebreak == Some(BreakOpportunity::Mandatory) || is_last {
+                metrics.max_content_width = metrics.max_content_width.max(line_width);
+                if self.max_lines.is_none_or(|max_lines| num_lines < max_lines) {
+                    let runs = &self.runs[line_start..run_idx + 1];
+                    metrics.height += Self::line_box(runs, &self.sections).0;
+                }
+
+                num_lines += 1;
+                line_start = run_idx + 1;
+                line_width = 0.0;
+                prev_trailing_whitespace = 0.0;
+            }
+        }
+
+        self.runs = runs;
+        self.glyphs = glyphs;
+        self.bidi_paragraphs = bidi_paragraphs;
+
+        self.metrics = Some(metrics);
+        metrics
+    }
+
     fn split_runs_by_bidi_levels(&mut self) {
         let bidi_info = BidiInfo::new(&self.text, None);
         self.bidi_paragraphs = bidi_info.paragraphs;
 
         for (section_idx, section) in self.sections.iter().enumerate() {
+            if section.range.is_empty() {
+                continue;
+            }
+
             Self::split_bidi_helper(
                 &bidi_info.levels,
                 section.range.clone(),
@@ -153,11 +413,18 @@ impl TextBuffer {
                         linebreak: None,
                         font: FontId::DUMMY,
                         font_size: 0.0,
+                        variations: FontVariations::new(),
+                        glyph_format: GlyphFormat::Auto,
                         line_height: 0.0,
                         text_height: 0.0,
+                        ascent: 0.0,
+                        descent: 0.0,
                         color: Color::BLACK,
+                        shadow: None,
+                        outline: None,
                         width: 0.0,
                         trailing_whitespace_width: 0.0,
+                        hyphen_width: 0.0,
                         pos: Vec2::ZERO,
                     });
                 },
@@ -233,11 +500,25 @@ impl TextBuffer {
                 LineHeight::Relative(v) => v * font_size,
             };
             let color = section.attrs.color;
+            let shadow = section.attrs.text_shadow;
+            let outline = section.attrs.text_outline;
             let text = &self.text[run.range.clone()];
 
-            // try shaping with each font until success
-            for font_index in 0..section.attrs.fonts.len() {
-                let Some(font) = Self::get_section_font(font_db, section, font_index) else {
+            // try shaping with each font until success, then with the last resort
+            // font, which is used even if glyphs are still missing
+            let num_fonts = section.attrs.fonts.len();
+            for font_index in 0..=num_fonts {
+                let is_last_resort = font_index == num_fonts;
+                let font = if is_last_resort {
+                    let Some(id) = font_db.last_resort_font() else {
+                        continue;
+                    };
+                    font_db.get_or_load(id).ok()
+                } else {
+                    Self::get_section_font(font_db, section, font_index)
+                };
+
+                let Some(font) = font else {
                     continue;
                 };
 
@@ -248,6 +529,8 @@ impl TextBuffer {
                     text,
                     font_size,
                     run.bidi_level.is_rtl(),
+                    &section.attrs.features,
+                    &section.attrs.variations,
                     &mut self.glyphs,
                 );
 
@@ -267,18 +550,24 @@ impl TextBuffer {
                             .is_some_and(char::is_whitespace)
                 };
 
-                if glyphs.iter().all(|v| !is_missing(v)) {
+                if is_last_resort || glyphs.iter().all(|v| !is_missing(v)) {
                     // no missing glyphs, success
                     let run = &mut self.runs[run_idx];
                     run.glyph_range = glyphs_start..glyphs_end;
 
-                    let metrics = font.metrics();
+                    let variations = section.attrs.variations;
+                    let metrics = font.metrics_with_variations(&variations);
                     run.font = font.id();
                     run.font_size = font_size;
+                    run.variations = variations;
+                    run.glyph_format = section.attrs.glyph_format;
                     run.color = color;
-                    run.text_height = ((metrics.ascender + metrics.descender) as f32)
-                        / (metrics.units_per_em as f32)
-                        * font_size;
+                    run.shadow = shadow;
+                    run.outline = outline;
+                    let scale = font_size / (metrics.units_per_em as f32);
+                    run.ascent = f32::from(metrics.ascender) * scale;
+                    run.descent = -f32::from(metrics.descender) * scale;
+                    run.text_height = run.ascent + run.descent;
                     run.line_height = line_height.max(run.text_height);
                     break;
                 }
@@ -379,13 +668,54 @@ impl TextBuffer {
         self.runs.sort_unstable_by_key(|run| run.range.start);
     }
 
+    fn find_breaks(&mut self) {
+        self.scratch_breaks.clear();
+        self.hyphen_breaks.clear();
+
+        let mut word_start = 0;
+        for (linebreak_idx, linebreak) in unicode_linebreak::linebreaks(&self.text) {
+            if let Some(hyphenator) = &self.hyphenator {
+                let word = &self.text[word_start..linebreak_idx];
+                // leave out punctuation and whitespace around the word
+                let trimmed = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+                let start = word_start + word.len() - trimmed.len();
+                let trimmed = trimmed.trim_end_matches(|c: char| !c.is_alphanumeric());
+
+                let section = self.sections.iter().find(|v| v.range.contains(&start));
+                let language = section
+                    .filter(|v| v.attrs.hyphenate)
+                    .and_then(|v| v.attrs.language.as_deref());
+
+                if let Some(language) = language.filter(|_| !trimmed.is_empty()) {
+                    let breaks_start = self.hyphen_breaks.len();
+                    hyphenator.hyphenate(trimmed, language, &mut self.hyphen_breaks);
+
+                    let breaks = self.hyphen_breaks.split_off(breaks_start);
+                    for offset in breaks {
+                        if offset > 0 && offset < trimmed.len() && trimmed.is_char_boundary(offset)
+                        {
+                            self.hyphen_breaks.push(start + offset);
+                            self.scratch_breaks
+                                .push((start + offset, BreakOpportunity::Allowed));
+                        }
+                    }
+                }
+            }
+
+            self.scratch_breaks.push((linebreak_idx, linebreak));
+            word_start = linebreak_idx;
+        }
+    }
+
     fn split_runs_by_words(&mut self) {
         // push splitted words at the end of self.runs, then remove old unsplitted runs
 
+        self.find_breaks();
+
         let mut run_idx = 0;
         let max_run_idx = self.runs.len();
 
-        for (linebreak_idx, linebreak) in unicode_linebreak::linebreaks(&self.text) {
+        for &(linebreak_idx, linebreak) in &self.scratch_breaks {
             while run_idx < max_run_idx {
                 let run = &mut self.runs[run_idx];
                 if run.range.start >= linebreak_idx {
@@ -424,21 +754,84 @@ impl TextBuffer {
 
     fn measure_runs(&mut self) {
         for run in &mut self.runs {
-            for glyph in &self.glyphs[run.glyph_range.clone()] {
-                let char = self.text[glyph.cluster..].chars().next();
-                let is_whitespace = char.is_some_and(char::is_whitespace);
+            Self::measure_run(&self.text, &self.glyphs, run);
+        }
+    }
 
-                if is_whitespace {
-                    run.trailing_whitespace_width += glyph.x_advance;
-                } else {
-                    run.trailing_whitespace_width = 0.0;
-                }
+    fn measure_run(text: &str, glyphs: &[ShapedGlyph], run: &mut Run) {
+        run.width = 0.0;
+        run.trailing_whitespace_width = 0.0;
+
+        for glyph in &glyphs[run.glyph_range.clone()] {
+            let char = text[glyph.cluster..].chars().next();
+            let is_whitespace = char.is_some_and(char::is_whitespace);
 
-                run.width += glyph.x_advance;
+            if is_whitespace {
+                run.trailing_whitespace_width += glyph.x_advance;
+            } else {
+                run.trailing_whitespace_width = 0.0;
+            }
+
+            run.width += glyph.x_advance;
+        }
+
+        run.width -= run.trailing_whitespace_width;
+    }
+
+    // runs ending with a soft hyphen or at a hyphenation point show a hyphen
+    // when the line is broken after them
+    fn shape_hyphens(&mut self, font_db: &mut dyn FontDatabase, shaper: &mut dyn TextShaper) {
+        let glyphs_start = self.glyphs.len();
+
+        for run in &mut self.runs {
+            if run.linebreak != Some(BreakOpportunity::Allowed) {
+                continue;
+            }
+
+            let is_soft_hyphen = self.text[..run.range.end].ends_with('\u{AD}');
+            let is_hyphen_break = self.hyphen_breaks.binary_search(&run.range.end).is_ok();
+            if !is_soft_hyphen && !is_hyphen_break {
+                continue;
             }
 
-            run.width -= run.trailing_whitespace_width;
+            let features = &self.sections[run.section_idx].attrs.features;
+            run.hyphen_width = Self::shape_hyphen(font_db, shaper, run, features, &mut self.glyphs);
+            self.glyphs.truncate(glyphs_start);
+        }
+    }
+
+    // pushes the hyphen glyphs, returning their width
+    fn shape_hyphen(
+        font_db: &mut dyn FontDatabase,
+        shaper: &mut dyn TextShaper,
+        run: &Run,
+        features: &OtFeatures,
+        glyphs: &mut Vec<ShapedGlyph>,
+    ) -> f32 {
+        let Ok(font) = font_db.get_or_load(run.font) else {
+            return 0.0;
+        };
+
+        // hyphen-minus for fonts without the hyphen character
+        let glyphs_start = glyphs.len();
+        for text in ["\u{2010}", "-"] {
+            glyphs.truncate(glyphs_start);
+            shaper.shape(
+                font,
+                text,
+                run.font_size,
+                run.bidi_level.is_rtl(),
+                features,
+                &run.variations,
+                glyphs,
+            );
+
+            if glyphs[glyphs_start..].iter().all(|v| v.glyph_id.0 != 0) {
+                break;
+            }
         }
+
+        glyphs[glyphs_start..].iter().map(|v| v.x_advance).sum()
     }
 
     fn break_lines(&mut self) {
@@ -446,8 +839,29 @@ impl TextBuffer {
         let mut prev_trailing_whitespace = 0.0;
         let mut prev_break_opportunity = None;
 
-        for (run_idx, run) in self.runs.iter().enumerate() {
+        let mut run_idx = 0;
+        while run_idx < self.runs.len() {
+            let run = &self.runs[run_idx];
             let fits = line.width + prev_trailing_whitespace + run.width <= self.max_width;
+            let overflow_wrap = self.sections[run.section_idx].attrs.overflow_wrap;
+            let can_wrap = overflow_wrap != OverflowWrap::Normal;
+
+            if !fits && can_wrap && prev_break_opportunity.is_none() {
+                let is_line_empty = line.run_range.start == run_idx;
+                let available_width = self.max_width - line.width - prev_trailing_whitespace;
+
+                if self.split_run(run_idx, available_width, is_line_empty) {
+                    // the start of the run fits now and can be broken after
+                    continue;
+                }
+
+                if !is_line_empty {
+                    // not even one cluster fits, break before the run
+                    prev_break_opportunity = Some(run_idx - 1);
+                }
+            }
+
+            let run = &self.runs[run_idx];
 
             if fits {
                 line.width += prev_trailing_whitespace + run.width;
@@ -472,15 +886,23 @@ impl TextBuffer {
                     .sum::<f32>();
 
                 line.width -= subtract_width;
+                line.width += self.runs[idx].hyphen_width;
                 line.run_range.end = idx + 1;
 
                 self.lines.push(line.clone());
 
                 line.run_range.start = idx + 1;
-                line.width = add_width + run.width;
+                prev_break_opportunity = None;
+
+                if can_wrap && add_width + run.width > self.max_width {
+                    // doesn't fit on the new line either, split it first
+                    line.width = add_width;
+                    prev_trailing_whitespace = 0.0;
+                    continue;
+                }
 
+                line.width = add_width + run.width;
                 prev_trailing_whitespace = run.trailing_whitespace_width;
-                prev_break_opportunity = None;
             }
 
             match run.linebreak {
@@ -500,9 +922,15 @@ impl TextBuffer {
                 prev_break_opportunity = None;
             }
 
-            if run.linebreak == Some(BreakOpportunity::Allowed) {
+            // breaking with a hyphen is only an option if the hyphen fits
+            let fits_hyphen = line.width + run.hyphen_width <= self.max_width;
+            if run.linebreak == Some(BreakOpportunity::Allowed)
+                && (fits_hyphen || prev_break_opportunity.is_none())
+            {
                 prev_break_opportunity = Some(run_idx);
             }
+
+            run_idx += 1;
         }
 
         // handle last line
@@ -532,6 +960,254 @@ impl TextBuffer {
         }
     }
 
+    // splits a run at the last glyph cluster boundary within the width, the
+    // first part is allowed to be broken after. with `at_least_one`, the first
+    // cluster is split off even if it doesn't fit. returns whether the run was
+    // split
+    fn split_run(&mut self, run_idx: usize, max_width: f32, at_least_one: bool) -> bool {
+        let run = &self.runs[run_idx];
+        let glyphs = &self.glyphs[run.glyph_range.clone()];
+
+        // glyphs are in logical order, clusters are never split so combining
+        // marks stay with their base
+        let mut split = None;
+        let mut width = 0.0;
+        for (i, glyph) in glyphs.iter().enumerate() {
+            width += glyph.x_advance;
+
+            let Some(next) = glyphs.get(i + 1) else {
+                break;
+            };
+
+            if next.cluster == glyph.cluster {
+                continue;
+            }
+
+            if width <= max_width || (split.is_none() && at_least_one) {
+                split = Some((run.glyph_range.start + i + 1, next.cluster));
+            }
+
+            if width > max_width {
+                break;
+            }
+        }
+
+        let Some((glyph_idx, cluster)) = split else {
+            return false;
+        };
+
+        let mut head = Run {
+            range: run.range.start..cluster,
+            glyph_range: run.glyph_range.start..glyph_idx,
+            linebreak: Some(BreakOpportunity::Allowed),
+            hyphen_width: 0.0,
+            ..run.clone()
+        };
+
+        Self::measure_run(&self.text, &self.glyphs, &mut head);
+
+        let tail = &mut self.runs[run_idx];
+        tail.range.start = cluster;
+        tail.glyph_range.start = glyph_idx;
+        Self::measure_run(&self.text, &self.glyphs, tail);
+
+        self.runs.insert(run_idx, head);
+        true
+    }
+
+    // runs are still in logical order, so the end of the line is trimmed, which
+    // puts the ellipsis on the left of RTL lines
+    fn truncate_lines(&mut self, font_db: &mut dyn FontDatabase, shaper: &mut dyn TextShaper) {
+        let Some(max_lines) = self.max_lines else {
+            return;
+        };
+
+        if self.lines.len() <= max_lines {
+            return;
+        }
+
+        self.is_truncated = true;
+        self.lines.truncate(max_lines);
+
+        let Some(line) = self.lines.last().cloned() else {
+            self.runs.clear();
+            return;
+        };
+
+        self.runs.truncate(line.run_range.end);
+
+        if !self.ellipsis {
+            return;
+        }
+
+        let last_run = self.runs[line.run_range.end - 1].clone();
+        let features = &self.sections[last_run.section_idx].attrs.features;
+        let bidi_level = self
+            .bidi_paragraphs
+            .iter()
+            .find(|paragraph| paragraph.range.contains(&line.range.start))
+            .map_or(BidiLevel::ltr(), |paragraph| paragraph.level);
+
+        let Ok(font) = font_db.get_or_load(last_run.font) else {
+            return;
+        };
+
+        // three dots for fonts without the ellipsis character
+        let glyphs_start = self.glyphs.len();
+        for text in ["\u{2026}", "..."] {
+            self.glyphs.truncate(glyphs_start);
+            shaper.shape(
+                font,
+                text,
+                last_run.font_size,
+                bidi_level.is_rtl(),
+                features,
+                &last_run.variations,
+                &mut self.glyphs,
+            );
+
+            if self.glyphs[glyphs_start..]
+                .iter()
+                .all(|v| v.glyph_id.0 != 0)
+            {
+                break;
+            }
+        }
+
+        let ellipsis_width = self.glyphs[glyphs_start..]
+            .iter()
+            .map(|v| v.x_advance)
+            .sum::<f32>();
+
+        let is_whitespace = |cluster: usize| {
+            let char = self.text[cluster..].chars().next();
+            char.is_some_and(char::is_whitespace)
+        };
+
+        // keep whole clusters while they fit together with the ellipsis, the
+        // ellipsis follows the last visible glyph
+        let first_run = &self.runs[line.run_range.start];
+        let mut cut = (line.run_range.start, first_run.glyph_range.start);
+        let mut kept_width = 0.0;
+        let mut width = 0.0;
+
+        'outer: for run_idx in line.run_range.clone() {
+            let glyph_range = self.runs[run_idx].glyph_range.clone();
+            for glyph_idx in glyph_range.clone() {
+                let glyph = self.glyphs[glyph_idx];
+                width += glyph.x_advance;
+
+                let next_cluster = self.glyphs[glyph_idx + 1..glyph_range.end]
+                    .first()
+                    .map(|v| v.cluster);
+
+                if next_cluster == Some(glyph.cluster) || is_whitespace(glyph.cluster) {
+                    continue;
+                }
+
+                if width + ellipsis_width > self.max_width {
+                    break 'outer;
+                }
+
+                cut = (run_idx, glyph_idx + 1);
+                kept_width = width;
+            }
+        }
+
+        let (cut_run_idx, cut_glyph_idx) = cut;
+        let cut_run = &mut self.runs[cut_run_idx];
+        let hidden_start = self.glyphs[cut_glyph_idx..cut_run.glyph_range.end]
+            .first()
+            .map_or(cut_run.range.end, |v| v.cluster);
+
+        let num_runs = if cut_glyph_idx == cut_run.glyph_range.start {
+            cut_run_idx
+        } else {
+            cut_run_idx + 1
+        };
+
+        cut_run.range.end = hidden_start;
+        cut_run.glyph_range.end = cut_glyph_idx;
+        self.runs.truncate(num_runs);
+
+        // the ellipsis stands for all hidden text, its glyphs point at the first
+        // hidden character that isn't whitespace
+        let hidden_text = &self.text[hidden_start..];
+        let cluster = hidden_start + hidden_text.len() - hidden_text.trim_start().len();
+        for glyph in &mut self.glyphs[glyphs_start..] {
+            glyph.cluster = cluster;
+        }
+
+        self.runs.push(Run {
+            range: hidden_start..self.text.len(),
+            glyph_range: glyphs_start..self.glyphs.len(),
+            bidi_level,
+            linebreak: None,
+            width: ellipsis_width,
+            trailing_whitespace_width: 0.0,
+            hyphen_width: 0.0,
+            ..last_run
+        });
+
+        let line = self.lines.last_mut().unwrap();
+        line.range.end = self.text.len();
+        line.run_range.end = self.runs.len();
+        line.width = kept_width + ellipsis_width;
+    }
+
+    // appends a hyphen run to lines broken at a hyphenation point
+    fn insert_hyphens(&mut self, font_db: &mut dyn FontDatabase, shaper: &mut dyn TextShaper) {
+        let is_broken_at_hyphen =
+            |run: &Run| run.hyphen_width > 0.0 && run.linebreak == Some(BreakOpportunity::Allowed);
+
+        let has_hyphens = self.lines.iter().any(|line| {
+            let last_run = &self.runs[line.run_range.end - 1];
+            is_broken_at_hyphen(last_run)
+        });
+
+        if !has_hyphens {
+            return;
+        }
+
+        let mut runs = Vec::with_capacity(self.runs.len() + self.lines.len());
+
+        for line in &mut self.lines {
+            let run_start = runs.len();
+            runs.extend_from_slice(&self.runs[line.run_range.clone()]);
+
+            let last_run = &self.runs[line.run_range.end - 1];
+            if is_broken_at_hyphen(last_run) {
+                let features = &self.sections[last_run.section_idx].attrs.features;
+                let glyphs_start = self.glyphs.len();
+                let width =
+                    Self::shape_hyphen(font_db, shaper, last_run, features, &mut self.glyphs);
+
+                // the hyphen isn't part of the text, its glyphs point at the
+                // last cluster of the line
+                let cluster = self.glyphs[last_run.glyph_range.clone()]
+                    .last()
+                    .map_or(last_run.range.start, |v| v.cluster);
+                for glyph in &mut self.glyphs[glyphs_start..] {
+                    glyph.cluster = cluster;
+                }
+
+                runs.push(Run {
+                    range: last_run.range.end..last_run.range.end,
+                    glyph_range: glyphs_start..self.glyphs.len(),
+                    linebreak: None,
+                    width,
+                    trailing_whitespace_width: 0.0,
+                    hyphen_width: 0.0,
+                    ..last_run.clone()
+                });
+            }
+
+            line.run_range = run_start..runs.len();
+        }
+
+        self.runs = runs;
+    }
+
     fn measure_lines(&mut self) {
         let mut bidi_paragraph_idx = 0;
 
@@ -545,10 +1221,8 @@ impl TextBuffer {
                 }
             }
 
-            line.height = self.runs[line.run_range.clone()]
-                .iter()
-                .map(|v| v.line_height)
-                .fold(0.0, f32::max);
+            let runs = &self.runs[line.run_range.clone()];
+            (line.height, line.baseline) = Self::line_box(runs, &self.sections);
 
             let glyphs = self.runs[line.run_range.clone()]
                 .iter()
@@ -564,6 +1238,48 @@ impl TextBuffer {
         }
     }
 
+    // returns the height of the line box and the offset of the baseline from
+    // its top. runs aligned to the line box only make it taller, the baseline
+    // is placed by the others
+    fn line_box(runs: &[Run], sections: &[Section]) -> (f32, f32) {
+        let mut above = 0.0f32;
+        let mut below = 0.0f32;
+        let mut height = 0.0f32;
+
+        for run in runs {
+            let (run_above, run_below) = Self::run_box(run);
+            let vertical_align = sections[run.section_idx].attrs.vertical_align;
+
+            match Self::baseline_shift(run, vertical_align) {
+                Some(shift) => {
+                    above = above.max(run_above + shift);
+                    below = below.max(run_below - shift);
+                }
+                None => height = height.max(run_above + run_below),
+            }
+        }
+
+        (height.max(above + below), above)
+    }
+
+    // height of the box of a run above and below the baseline, the leading is
+    // split evenly between them
+    fn run_box(run: &Run) -> (f32, f32) {
+        let half_leading = (run.line_height - run.text_height) * 0.5;
+        (run.ascent + half_leading, run.descent + half_leading)
+    }
+
+    // how much the baseline of a run is raised above the line baseline, `None`
+    // if the run is aligned to the line box instead
+    fn baseline_shift(run: &Run, vertical_align: VerticalAlign) -> Option<f32> {
+        match vertical_align {
+            VerticalAlign::Baseline => Some(0.0),
+            VerticalAlign::Superscript => Some(run.font_size / 3.0),
+            VerticalAlign::Subscript => Some(-run.font_size / 5.0),
+            VerticalAlign::Middle | VerticalAlign::Top | VerticalAlign::Bottom => None,
+        }
+    }
+
     fn bidi_reorder_runs(&mut self) {
         if self.runs.iter().all(|v| v.bidi_level.is_ltr()) {
             return;
@@ -709,12 +1425,20 @@ impl TextBuffer {
 
         let mut pos = Vec2::ZERO;
 
-        for line in &self.lines {
+        for line in &mut self.lines {
+            line.top = pos.y;
+
             if line.run_range.is_empty() {
                 continue;
             }
 
-            let align = self.sections[self.runs[line.run_range.start].section_idx]
+            // runs are in visual order, so take the alignment from the start of
+            // the line in logical order
+            let align = self
+                .sections
+                .iter()
+                .find(|section| section.range.contains(&line.range.start))
+                .unwrap_or(&self.sections[self.runs[line.run_range.start].section_idx])
                 .attrs
                 .align;
 
@@ -744,7 +1468,19 @@ impl TextBuffer {
 
             while line.run_range.contains(&run_idx) {
                 let run = &mut self.runs[run_idx];
-                run.pos.y = pos.y + (line.height - run.line_height) * 0.5 + run.line_height;
+                let vertical_align = self.sections[run.section_idx].attrs.vertical_align;
+                let (run_above, run_below) = Self::run_box(run);
+                let baseline = match Self::baseline_shift(run, vertical_align) {
+                    Some(shift) => line.baseline - shift,
+                    None if vertical_align == VerticalAlign::Top => run_above,
+                    None if vertical_align == VerticalAlign::Bottom => line.height - run_below,
+                    None => (line.height - run_above - run_below) * 0.5 + run_above,
+                };
+
+                run.pos.y = pos.y + baseline;
+                if self.snap_baseline {
+                    run.pos.y = run.pos.y.round();
+                }
 
                 if is_left_aligned {
                     run.pos.x = pos.x;
@@ -772,8 +1508,11 @@ impl TextBuffer {
                 }
             }
 
+            self.width = self.width.max((pos.x - start).abs());
             pos.y += line.height;
         }
+
+        self.height = pos.y;
     }
 
     pub fn glyphs(&self) -> &[ShapedGlyph] {
@@ -783,6 +1522,331 @@ impl TextBuffer {
     pub fn runs(&self) -> &[Run] {
         &self.runs
     }
+
+    /// Returns the size of the computed layout: the width of the widest line,
+    /// including justification, and the total height of the lines. Zero
+    /// until [`compute_layout`] is called, and while the layout is out of
+    /// date.
+    ///
+    /// [`compute_layout`]: TextBuffer::compute_layout
+    pub fn size(&self) -> Vec2 {
+        if self.dirty {
+            return Vec2::ZERO;
+        }
+
+        Vec2::new(self.width, self.height)
+    }
+
+    /// Returns the lines of the computed layout, from top to bottom.
+    pub fn lines(&self) -> impl Iterator<Item = LineInfo> + '_ {
+        let mut top = 0.0;
+        self.lines.iter().map(move |line| {
+            let runs = &self.runs[line.run_range.clone()];
+
+            let mut baseline = top + line.baseline;
+            if self.snap_baseline {
+                baseline = baseline.round();
+            }
+
+            let (min_x, max_x) = runs
+                .iter()
+                .map(|run| {
+                    let glyphs = &self.glyphs[run.glyph_range.clone()];
+                    let width = glyphs.iter().map(|v| v.x_advance).sum::<f32>();
+                    (run.pos.x, run.pos.x + width)
+                })
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), (a, b)| {
+                    (min.min(a), max.max(b))
+                });
+
+            let info = LineInfo {
+                top,
+                baseline,
+                bottom: top + line.height,
+                width: (max_x - min_x).max(0.0),
+                range: line.range.clone(),
+                is_rtl: line.is_rtl,
+            };
+
+            top += line.height;
+            info
+        })
+    }
+
+    /// Changes the color of all text, without recomputing the layout. Color
+    /// overrides set with [`set_color_range`] still take priority.
+    ///
+    /// [`set_color_range`]: TextBuffer::set_color_range
+    pub fn set_color(&mut self, color: Color) {
+        for section in &mut self.sections {
+            section.attrs.color = color;
+        }
+
+        for run in &mut self.runs {
+            run.color = color;
+        }
+    }
+
+    /// Changes the color of a single run. The change is lost when the layout
+    /// is recomputed.
+    pub fn set_run_color(&mut self, run_idx: usize, color: Color) {
+        if let Some(run) = self.runs.get_mut(run_idx) {
+            run.color = color;
+        }
+    }
+
+    /// Overrides the color of glyphs whose clusters start within a byte range
+    /// of the text. Unlike pushing a separate section, this doesn't affect
+    /// shaping and doesn't require recomputing the layout. Later calls take
+    /// priority over earlier ones.
+    pub fn set_color_range(&mut self, range: Range<usize>, color: Color) {
+        if range.is_empty() {
+            return;
+        }
+
+        let start = self
+            .color_ranges
+            .partition_point(|(r, _)| r.end <= range.start);
+        let end = self
+            .color_ranges
+            .partition_point(|(r, _)| r.start < range.end);
+
+        // keep the parts of overlapped ranges sticking out on either side
+        let mut replacement = SmallVec::<[_; 3]>::new();
+
+        if let Some((r, c)) = self.color_ranges[start..end].first() {
+            if r.start < range.start {
+                replacement.push((r.start..range.start, *c));
+            }
+        }
+
+        replacement.push((range.clone(), color));
+
+        if let Some((r, c)) = self.color_ranges[start..end].last() {
+            if r.end > range.end {
+                replacement.push((range.end..r.end, *c));
+            }
+        }
+
+        self.color_ranges.splice(start..end, replacement);
+    }
+
+    /// Removes all color overrides set with [`set_color_range`].
+    ///
+    /// [`set_color_range`]: TextBuffer::set_color_range
+    pub fn clear_color_ranges(&mut self) {
+        self.color_ranges.clear();
+    }
+
+    /// Returns the color of a glyph in a run, taking color overrides into
+    /// account.
+    pub fn glyph_color(&self, run: &Run, glyph: &ShapedGlyph) -> Color {
+        let idx = self
+            .color_ranges
+            .partition_point(|(r, _)| r.end <= glyph.cluster);
+
+        match self.color_ranges.get(idx) {
+            Some((r, color)) if r.contains(&glyph.cluster) => *color,
+            _ => run.color,
+        }
+    }
+
+    /// Finds the cluster under a point relative to the text, e.g. to place the
+    /// caret on click. Points outside the text are clamped to the nearest line
+    /// and glyph. Returns `None` if the text has no lines.
+    pub fn hit_test(&self, pos: Vec2) -> Option<HitTestResult> {
+        let last_line_idx = self.lines.len().checked_sub(1)?;
+
+        // points above the first line or below the last are clamped
+        let mut line_y = 0.0;
+        let mut line_idx = last_line_idx;
+        for (i, line) in self.lines.iter().enumerate() {
+            if pos.y < line_y + line.height {
+                line_idx = i;
+                break;
+            }
+            line_y += line.height;
+        }
+
+        let line = &self.lines[line_idx];
+
+        // find the glyph under the point, clamping to the first and last glyphs
+        // of the line in visual order
+        let mut closest = None;
+
+        'outer: for run_idx in line.run_range.clone() {
+            let run = &self.runs[run_idx];
+            let mut x = run.pos.x;
+
+            for glyph in &self.glyphs[run.glyph_range.clone()] {
+                let hit = (run_idx, glyph.cluster, x, x + glyph.x_advance);
+
+                if closest.is_none() || pos.x >= x {
+                    closest = Some(hit);
+                }
+
+                if pos.x < x + glyph.x_advance {
+                    break 'outer;
+                }
+
+                x += glyph.x_advance;
+            }
+        }
+
+        let Some((run_idx, cluster, min_x, max_x)) = closest else {
+            // line without visible glyphs (e.g. only whitespace)
+            return Some(HitTestResult {
+                line_idx,
+                run_idx: line.run_range.start,
+                cluster: line.range.start,
+                is_trailing: false,
+            });
+        };
+
+        let is_right_half = pos.x >= (min_x + max_x) * 0.5;
+        let is_rtl = self.runs[run_idx].bidi_level.is_rtl();
+
+        Some(HitTestResult {
+            line_idx,
+            run_idx,
+            cluster,
+            is_trailing: is_right_half != is_rtl,
+        })
+    }
+
+    /// Returns the caret before the character at a byte offset into the text,
+    /// as a zero-width rect spanning the line. Offsets between lines belong to
+    /// the next line, and offsets between runs to the run starting there.
+    /// `text.len()` places the caret at the end of the text. Returns `None` if
+    /// the offset is past the end, or the text has no lines.
+    pub fn cursor_rect(&self, byte_offset: usize) -> Option<Rect> {
+        if byte_offset > self.text.len() {
+            return None;
+        }
+
+        // offsets on a line boundary belong to the next line
+        let line = self
+            .lines
+            .iter()
+            .find(|line| byte_offset < line.range.end)
+            .or(self.lines.last())?;
+
+        // prefer the run starting at the offset over the one ending at it
+        let runs = &self.runs[line.run_range.clone()];
+        let run = runs
+            .iter()
+            .find(|run| run.range.contains(&byte_offset))
+            .or_else(|| runs.iter().find(|run| run.range.end == byte_offset))
+            .or_else(|| runs.iter().max_by_key(|run| run.range.end))?;
+
+        let x = self.caret_x(run, byte_offset);
+
+        Some(Rect::new(
+            Vec2::new(x, line.top),
+            Vec2::new(x, line.top + line.height),
+        ))
+    }
+
+    /// Computes the rects covering a byte range of the text, e.g. to highlight
+    /// a selection. Each line gets a rect per visually contiguous span, a range
+    /// crossing a bidi boundary can give several. Selected trailing whitespace
+    /// and line breaks extend the rect past the end of the line. The rects are
+    /// appended to `out`, which isn't cleared.
+    pub fn selection_rects(&self, range: Range<usize>, out: &mut Vec<Rect>) {
+        if range.is_empty() {
+            return;
+        }
+
+        for line in &self.lines {
+            if line.range.end <= range.start || range.end <= line.range.start {
+                continue;
+            }
+
+            let line_start = out.len();
+            let mut line_top = None;
+            let mut line_min_x = f32::INFINITY;
+            let mut line_max_x = f32::NEG_INFINITY;
+
+            for run in &self.runs[line.run_range.clone()] {
+                let top = line.top;
+                line_top = Some(top);
+
+                let start_x = self.caret_x(run, run.range.start);
+                let end_x = self.caret_x(run, run.range.end);
+                line_min_x = line_min_x.min(start_x.min(end_x));
+                line_max_x = line_max_x.max(start_x.max(end_x));
+
+                if run.range.end <= range.start || range.end <= run.range.start {
+                    continue;
+                }
+
+                let sel_start_x = self.caret_x(run, range.start.max(run.range.start));
+                let sel_end_x = self.caret_x(run, range.end.min(run.range.end));
+
+                let min = Vec2::new(sel_start_x.min(sel_end_x), top);
+                let max = Vec2::new(sel_start_x.max(sel_end_x), top + line.height);
+                Self::push_selection_rect(out, line_start, Rect::new(min, max));
+            }
+
+            let Some(top) = line_top else {
+                continue;
+            };
+
+            // trailing whitespace (including forced line breaks) has no glyphs,
+            // so it's drawn past the visual end of the line
+            let visible_end = line.range.start + self.text[line.range.clone()].trim_end().len();
+            if range.end > visible_end {
+                let width = self.runs[line.run_range.clone()]
+                    .iter()
+                    .max_by_key(|run| run.range.end)
+                    .map(|run| run.trailing_whitespace_width)
+                    .unwrap_or(0.0);
+
+                let (min_x, max_x) = if line.is_rtl {
+                    (line_min_x - width, line_min_x)
+                } else {
+                    (line_max_x, line_max_x + width)
+                };
+
+                if width > 0.0 {
+                    let min = Vec2::new(min_x, top);
+                    let max = Vec2::new(max_x, top + line.height);
+                    Self::push_selection_rect(out, line_start, Rect::new(min, max));
+                }
+            }
+        }
+    }
+
+    fn push_selection_rect(out: &mut Vec<Rect>, line_start: usize, rect: Rect) {
+        const EPSILON: f32 = 1e-3;
+
+        // merge visually contiguous rects on the same line
+        if out.len() > line_start {
+            if let Some(last) = out.last_mut() {
+                if (last.max.x - rect.min.x).abs() < EPSILON
+                    || (rect.max.x - last.min.x).abs() < EPSILON
+                {
+                    *last = last.union(rect);
+                    return;
+                }
+            }
+        }
+
+        out.push(rect);
+    }
+
+    fn caret_x(&self, run: &Run, byte_offset: usize) -> f32 {
+        // glyphs are in visual order, so in RTL runs the caret for an offset
+        // sits on the right edge of its cluster
+        let is_rtl = run.bidi_level.is_rtl();
+        let advance = self.glyphs[run.glyph_range.clone()]
+            .iter()
+            .filter(|glyph| (glyph.cluster < byte_offset) != is_rtl)
+            .map(|glyph| glyph.x_advance)
+            .sum::<f32>();
+
+        run.pos.x + advance
+    }
 }
 
 impl Default for TextBuffer {
@@ -790,3 +1854,153 @@ impl Default for TextBuffer {
         TextBuffer::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestFontDatabase, TestTextShaper};
+    use crate::text::FontFamilies;
+
+    fn attrs(size: f32) -> TextAttrs {
+        TextAttrs {
+            size,
+            fonts: FontFamilies::new(FontFamily::new("Test")),
+            ..TextAttrs::default()
+        }
+    }
+
+    #[test]
+    fn mixed_sizes_share_baseline() {
+        let mut buffer = TextBuffer::new();
+        buffer.push(attrs(48.0), "Big ");
+        buffer.push(attrs(16.0), "small");
+        buffer.compute_layout(&mut TestFontDatabase::new(), &mut TestTextShaper);
+
+        let runs = buffer.runs();
+        assert!(runs.iter().any(|run| run.font_size == 48.0));
+        assert!(runs.iter().any(|run| run.font_size == 16.0));
+
+        let lines = buffer.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+
+        // the 48px run decides: half leading of (57.6 - 48) / 2, then the
+        // ascent of 0.8 em
+        let baseline = lines[0].baseline;
+        assert!((baseline - (4.8 + 38.4)).abs() < 1e-3, "{baseline}");
+
+        for run in runs {
+            assert_eq!(run.pos.y, baseline);
+        }
+
+        assert!((lines[0].bottom - 57.6).abs() < 1e-3);
+    }
+
+    // "abc " then three Hebrew letters, 10px per character, and a second line.
+    // The Hebrew run is drawn reversed at 40..70
+    fn mixed_direction_buffer() -> TextBuffer {
+        let mut buffer = TextBuffer::new();
+        buffer.push(attrs(20.0), "abc \u{5d0}\u{5d1}\u{5d2}\nxy");
+        buffer.compute_layout(&mut TestFontDatabase::new(), &mut TestTextShaper);
+        buffer
+    }
+
+    #[test]
+    fn hit_test_mixed_directions() {
+        let buffer = mixed_direction_buffer();
+        let hit = |x, y| buffer.hit_test(Vec2::new(x, y)).unwrap();
+        let result = |line_idx, run_idx, cluster, is_trailing| HitTestResult {
+            line_idx,
+            run_idx,
+            cluster,
+            is_trailing,
+        };
+
+        // "b" at 10..20
+        assert_eq!(hit(12.0, 5.0), result(0, 0, 1, false));
+        assert_eq!(hit(18.0, 5.0), result(0, 0, 1, true));
+
+        // the second Hebrew letter at 50..60, its logical end is on the left
+        assert_eq!(hit(52.0, 5.0), result(0, 1, 6, true));
+        assert_eq!(hit(58.0, 5.0), result(0, 1, 6, false));
+
+        // the first Hebrew letter is the rightmost glyph
+        assert_eq!(hit(68.0, 5.0), result(0, 1, 4, false));
+        assert_eq!(hit(100.0, 5.0), result(0, 1, 4, false));
+
+        // above the first line, and below the last
+        assert_eq!(hit(2.0, -10.0), result(0, 0, 0, false));
+        assert_eq!(hit(42.0, -10.0), result(0, 1, 8, true));
+        assert_eq!(hit(2.0, 100.0), result(1, 3, 11, false));
+        assert_eq!(hit(100.0, 100.0), result(1, 3, 12, true));
+    }
+
+    #[test]
+    fn cursor_rect_at_rtl_run_boundaries() {
+        // two Hebrew letters, " ab " and a third one: drawn as the third letter,
+        // a space, "ab" at 20..40, and the space and first two letters at 40..70
+        let mut buffer = TextBuffer::new();
+        buffer.push(attrs(20.0), "\u{5d0}\u{5d1} ab \u{5d2}");
+        buffer.compute_layout(&mut TestFontDatabase::new(), &mut TestTextShaper);
+        let caret_x = |offset| buffer.cursor_rect(offset).map(|rect| rect.min.x);
+
+        // the RTL paragraph starts on the right and ends on the left
+        assert_eq!(caret_x(0), Some(70.0));
+        assert_eq!(caret_x(10), Some(0.0));
+        assert_eq!(caret_x(11), None);
+
+        // on the right edge of a character in RTL runs
+        assert_eq!(caret_x(4), Some(50.0));
+        assert_eq!(caret_x(8), Some(10.0));
+
+        // between runs, in the one starting at the offset: before "a" in the
+        // LTR run, and then right of the space in the RTL run after it
+        assert_eq!(caret_x(5), Some(20.0));
+        assert_eq!(caret_x(6), Some(30.0));
+        assert_eq!(caret_x(7), Some(20.0));
+
+        let rect = buffer.cursor_rect(5).unwrap();
+        assert_eq!(rect, Rect::new(Vec2::new(20.0, 0.0), Vec2::new(20.0, 24.0)));
+
+        // the start of the RTL run in an LTR paragraph is its right edge, and
+        // the offset after the line break is on the next line
+        let buffer = mixed_direction_buffer();
+        assert_eq!(buffer.cursor_rect(4).map(|rect| rect.min.x), Some(70.0));
+        assert_eq!(buffer.cursor_rect(8).map(|rect| rect.min.x), Some(50.0));
+        let rect = buffer.cursor_rect(11).unwrap();
+        assert_eq!(rect, Rect::new(Vec2::new(0.0, 24.0), Vec2::new(0.0, 48.0)));
+    }
+
+    #[test]
+    fn selection_rects_across_directions() {
+        let buffer = mixed_direction_buffer();
+        let rect = |min_x, max_x| Rect::new(Vec2::new(min_x, 0.0), Vec2::new(max_x, 24.0));
+
+        // "c " and the first two Hebrew letters, with the third one between
+        let mut out = vec![Rect::new(Vec2::ZERO, Vec2::ONE)];
+        buffer.selection_rects(2..8, &mut out);
+        assert_eq!(out[1..], [rect(20.0, 40.0), rect(50.0, 70.0)]);
+
+        // extending to the third letter joins the spans
+        out.clear();
+        buffer.selection_rects(2..10, &mut out);
+        assert_eq!(out, [rect(20.0, 70.0)]);
+    }
+
+    #[test]
+    fn selection_rects_across_line_break() {
+        let buffer = mixed_direction_buffer();
+
+        // the last two Hebrew letters, the line break drawn past the end of the
+        // line, and "x" on the next line
+        let mut out = Vec::new();
+        buffer.selection_rects(6..12, &mut out);
+        assert_eq!(
+            out,
+            [
+                Rect::new(Vec2::new(40.0, 0.0), Vec2::new(60.0, 24.0)),
+                Rect::new(Vec2::new(70.0, 0.0), Vec2::new(80.0, 24.0)),
+                Rect::new(Vec2::new(0.0, 24.0), Vec2::new(10.0, 48.0)),
+            ]
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use std::borrow::Cow;
+use std::collections::{hash_map, HashMap};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
 use smallvec::{smallvec, SmallVec};
-pub use ttf_parser::GlyphId;
-use ttf_parser::{name_id, Face, Language, Tag};
+use ttf_parser::{name_id, Face, Language};
+pub use ttf_parser::{GlyphId, Tag};
 
 use crate::{Error, ErrorKind, Result};
 
@@ -58,6 +60,22 @@ impl FontFace {
         })
     }
 
+    pub fn parse_collection(
+        data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        mut make_id: impl FnMut(u32) -> FontId,
+    ) -> Result<Vec<FontFace>> {
+        let num_faces = FontFace::count_faces((*data).as_ref());
+
+        (0..num_faces)
+            .map(|face_index| FontFace::new(make_id(face_index), Arc::clone(&data), face_index))
+            .collect()
+    }
+
+    pub fn count_faces(data: &[u8]) -> u32 {
+        // plain font files are not collections, but still contain one face
+        ttf_parser::fonts_in_collection(data).unwrap_or(1)
+    }
+
     pub fn id(&self) -> FontId {
         self.id
     }
@@ -81,6 +99,26 @@ impl FontFace {
     pub fn metrics(&self) -> &FontMetrics {
         &self.metrics
     }
+
+    pub fn ttfp_face_with_variations(&self, variations: &FontVariations) -> Cow<'_, Face<'_>> {
+        if variations.is_empty() || !self.ttfp_face().is_variable() {
+            return Cow::Borrowed(self.ttfp_face());
+        }
+
+        let mut face = self.ttfp_face().clone();
+        for variation in variations.as_slice() {
+            face.set_variation(variation.tag, variation.value);
+        }
+
+        Cow::Owned(face)
+    }
+
+    pub fn metrics_with_variations(&self, variations: &FontVariations) -> FontMetrics {
+        match self.ttfp_face_with_variations(variations) {
+            Cow::Borrowed(_) => self.metrics,
+            Cow::Owned(face) => FontMetrics::from_ttfp_face(&face),
+        }
+    }
 }
 
 impl fmt::Debug for FontFace {
@@ -94,6 +132,60 @@ impl fmt::Debug for FontFace {
     }
 }
 
+/// Faces with variations applied, for rasterizers that look up many glyphs of
+/// the same font instance. Unlike
+/// [`FontFace::ttfp_face_with_variations`], the face isn't cloned for every
+/// lookup.
+#[derive(Default)]
+pub struct VariationFaceCache {
+    faces: HashMap<(FontId, FontVariations), FaceRef>,
+}
+
+impl VariationFaceCache {
+    pub fn new() -> VariationFaceCache {
+        VariationFaceCache::default()
+    }
+
+    /// Returns the face of `font` with `variations` applied.
+    pub fn get<'a>(&'a mut self, font: &'a FontFace, variations: &FontVariations) -> &'a Face<'a> {
+        if variations.is_empty() || !font.ttfp_face().is_variable() {
+            return font.ttfp_face();
+        }
+
+        let key = (font.id(), *variations);
+        if let hash_map::Entry::Vacant(entry) = self.faces.entry(key) {
+            let face_ref = FaceRef::try_new(Arc::clone(font.data()), |data| {
+                let mut face = Face::parse((**data).as_ref(), font.face_index())?;
+                for variation in variations.as_slice() {
+                    face.set_variation(variation.tag, variation.value);
+                }
+
+                Ok::<_, ttf_parser::FaceParsingError>(face)
+            });
+
+            match face_ref {
+                Ok(face_ref) => entry.insert(face_ref),
+                Err(_) => return font.ttfp_face(),
+            };
+        }
+
+        self.faces[&key].borrow_dependent()
+    }
+
+    /// Drops the faces of a font, e.g. after its data was replaced.
+    pub fn invalidate_font(&mut self, font: FontId) {
+        self.faces.retain(|&(id, _), _| id != font);
+    }
+}
+
+impl fmt::Debug for VariationFaceCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VariationFaceCache")
+            .field("len", &self.faces.len())
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub struct FontAttrs {
     pub family: FontFamily,
@@ -131,6 +223,104 @@ impl FontAttrs {
     }
 }
 
+pub const MAX_FONT_VARIATIONS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontVariation {
+    pub tag: Tag,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FontVariations {
+    len: u8,
+    list: [FontVariation; MAX_FONT_VARIATIONS],
+}
+
+impl FontVariations {
+    pub const fn new() -> FontVariations {
+        FontVariations {
+            len: 0,
+            list: [FontVariation {
+                tag: Tag(0),
+                value: 0.0,
+            }; MAX_FONT_VARIATIONS],
+        }
+    }
+
+    pub fn set(&mut self, tag: Tag, value: f32) {
+        let len = self.len as usize;
+        if let Some(variation) = self.list[..len].iter_mut().find(|v| v.tag == tag) {
+            variation.value = value;
+            return;
+        }
+
+        assert!(len < MAX_FONT_VARIATIONS, "too many font variations");
+
+        self.list[len] = FontVariation { tag, value };
+        self.len += 1;
+    }
+
+    pub fn with(mut self, tag: Tag, value: f32) -> FontVariations {
+        self.set(tag, value);
+        self
+    }
+
+    pub fn get(&self, tag: Tag) -> Option<f32> {
+        self.as_slice()
+            .iter()
+            .find(|v| v.tag == tag)
+            .map(|v| v.value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[FontVariation] {
+        &self.list[..self.len as usize]
+    }
+}
+
+impl Default for FontVariations {
+    fn default() -> FontVariations {
+        FontVariations::new()
+    }
+}
+
+impl<const N: usize> From<[(Tag, f32); N]> for FontVariations {
+    fn from(list: [(Tag, f32); N]) -> FontVariations {
+        let mut res = FontVariations::new();
+        for (tag, value) in list {
+            res.set(tag, value);
+        }
+        res
+    }
+}
+
+impl PartialEq for FontVariations {
+    fn eq(&self, other: &Self) -> bool {
+        let lhs = self.as_slice().iter();
+        let rhs = other.as_slice().iter();
+        self.len == other.len
+            && lhs
+                .zip(rhs)
+                .all(|(a, b)| a.tag == b.tag && a.value.to_bits() == b.value.to_bits())
+    }
+}
+
+impl Eq for FontVariations {}
+
+impl Hash for FontVariations {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for variation in self.as_slice() {
+            variation.tag.hash(state);
+            variation.value.to_bits().hash(state);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct FontFamily {
     name: Cow<'static, str>,
@@ -1,5 +1,7 @@
+use std::sync::Arc;
+
 use crate::text::{FontAttrs, FontFace, FontId};
-use crate::Result;
+use crate::{Error, ErrorKind, Result};
 
 pub trait FontDatabase: Send + Sync + 'static {
     fn query(&self, attrs: &FontAttrs) -> Option<FontId>;
@@ -9,6 +11,30 @@ pub trait FontDatabase: Send + Sync + 'static {
     fn get(&self, id: FontId) -> Option<&FontFace>;
 
     fn get_or_load(&mut self, id: FontId) -> Result<&FontFace>;
+
+    /// Registers a face from in-memory font data, making it available to
+    /// [`query`](FontDatabase::query). `index` selects a face within a font
+    /// collection, and is `0` for regular font files.
+    fn add_face(&mut self, data: Arc<dyn AsRef<[u8]> + Send + Sync>, index: u32) -> Result<FontId>;
+
+    /// Drops the loaded face of a font, so that it's loaded again from its
+    /// source on next use.
+    fn invalidate_font(&mut self, _font: FontId) {}
+
+    /// Returns names of all available font families, sorted alphabetically
+    /// and without case-insensitive duplicates.
+    fn families(&self) -> Vec<String>;
+
+    /// Sets the font tried after all fonts of a text, for characters none of
+    /// them cover. Characters it doesn't cover either are drawn with its
+    /// missing glyph, so a font with a recognizable box glyph gives
+    /// consistent tofu across fonts. Without one, such characters aren't
+    /// drawn. Default: `None`.
+    fn set_last_resort_font(&mut self, font: Option<FontId>);
+
+    /// Returns the font set by
+    /// [`set_last_resort_font`](FontDatabase::set_last_resort_font).
+    fn last_resort_font(&self) -> Option<FontId>;
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -30,4 +56,25 @@ impl FontDatabase for DummyFontDatabase {
     fn get_or_load(&mut self, _id: FontId) -> Result<&FontFace> {
         unimplemented!()
     }
+
+    fn add_face(
+        &mut self,
+        _data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+        _index: u32,
+    ) -> Result<FontId> {
+        Err(Error::new(
+            ErrorKind::Other,
+            "DummyFontDatabase can't load fonts",
+        ))
+    }
+
+    fn families(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn set_last_resort_font(&mut self, _font: Option<FontId>) {}
+
+    fn last_resort_font(&self) -> Option<FontId> {
+        None
+    }
 }
@@ -0,0 +1,18 @@
+use std::fmt;
+
+/// Finds the points where words can be broken with a hyphen, usually from a
+/// dictionary of hyphenation patterns. Used by [`TextBuffer`] for sections
+/// with [`TextAttrs::hyphenate`].
+///
+/// No implementation is bundled yet. A backend for the `hyphenation` crate is
+/// a follow-up, as a separate integration crate behind its own feature, like
+/// the font database and shaper backends.
+///
+/// [`TextBuffer`]: crate::text::TextBuffer
+/// [`TextAttrs::hyphenate`]: crate::text::TextAttrs::hyphenate
+pub trait Hyphenator: fmt::Debug + Send + Sync + 'static {
+    /// Pushes the byte offsets within `word` where it can be broken, in
+    /// ascending order. `language` is a BCP 47 tag, e.g. `en-US`. Languages
+    /// without a dictionary push nothing.
+    fn hyphenate(&self, word: &str, language: &str, breaks: &mut Vec<usize>);
+}
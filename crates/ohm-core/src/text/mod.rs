@@ -4,23 +4,30 @@
 mod buffer;
 mod font;
 mod font_db;
+mod hyphenation;
+mod mono;
 mod rasterization;
 mod shaping;
 
+use std::borrow::Cow;
+
 pub use self::buffer::*;
 pub use self::font::*;
 pub use self::font_db::*;
+pub use self::hyphenation::*;
+pub use self::mono::*;
 pub use self::rasterization::*;
 pub use self::shaping::*;
-use crate::Color;
+use crate::{Color, Shadow};
 
 /// Attributes of a text section.
 #[derive(Debug, Clone, PartialEq)]
 pub struct TextAttrs {
-    /// Font size in physical pixels.
+    /// Font size in the units of the draw list.
     ///
-    /// Note that Ohm doesn't do DPI scaling, so all coordinates and dimensions
-    /// are in physical pixels (unaffected by DPI scaling factor).
+    /// These are physical pixels unless the draw list has a
+    /// [`scale`](crate::DrawList::scale), in which case glyphs are rasterized
+    /// at the scaled size.
     ///
     /// Default: `16.0`.
     pub size: f32,
@@ -30,11 +37,17 @@ pub struct TextAttrs {
     /// Default: [`Color::BLACK`].
     pub color: Color,
 
-    /// Horizontal text alignment.
+    /// Horizontal text alignment. Applies to whole lines, taken from the
+    /// section at the start of the line.
     ///
     /// Default: [`TextAlign::Start`].
     pub align: TextAlign,
 
+    /// How words that don't fit within the max width are broken.
+    ///
+    /// Default: [`OverflowWrap::Normal`].
+    pub overflow_wrap: OverflowWrap,
+
     /// List of font families in fallback order.
     ///
     /// Default: sans-serif.
@@ -56,10 +69,60 @@ pub struct TextAttrs {
     pub style: FontStyle,
 
     /// Line height. Similar to the CSS property, can be relative or absolute.
-    /// Commonly used to set the distance between lines.
+    /// Commonly used to set the distance between lines. A line is as tall as
+    /// the largest line height of its runs.
     ///
     /// Default: `1.2` (relative).
     pub line_height: LineHeight,
+
+    /// Vertical alignment of the text within its line, e.g. to raise footnote
+    /// markers or center inline icons.
+    ///
+    /// Default: [`VerticalAlign::Baseline`].
+    pub vertical_align: VerticalAlign,
+
+    /// OpenType features to enable or disable, e.g. `tnum` for tabular
+    /// numbers or `liga` for ligatures.
+    ///
+    /// Default: empty (shaper defaults).
+    pub features: OtFeatures,
+
+    /// Variation axis coordinates for variable fonts, e.g. `wght` = `350.0`
+    /// or `wdth` = `85.0`. Ignored by non-variable fonts.
+    ///
+    /// Default: empty (default instance).
+    pub variations: FontVariations,
+
+    /// Shadow drawn behind the text, in a single color. Glyphs without
+    /// outlines, like bitmap emoji, cast no shadow.
+    ///
+    /// Default: `None`.
+    pub text_shadow: Option<Shadow>,
+
+    /// Outline color and width, drawn behind the text (and in front of the
+    /// shadow). Glyphs without outlines, like bitmap emoji, aren't outlined.
+    ///
+    /// Default: `None`.
+    pub text_outline: Option<(Color, f32)>,
+
+    /// Whether to prefer color or monochrome glyphs, for fonts that have both
+    /// (e.g. emoji). Monochrome glyphs are drawn in the text color.
+    ///
+    /// Default: [`GlyphFormat::Auto`].
+    pub glyph_format: GlyphFormat,
+
+    /// Whether words can be broken at hyphenation points found by the
+    /// [`Hyphenator`] of the buffer, which requires `language`. Soft hyphens
+    /// (U+00AD) are always honored.
+    ///
+    /// Default: `false`.
+    pub hyphenate: bool,
+
+    /// Language of the text as a BCP 47 tag, e.g. `en-US`. Used for
+    /// hyphenation.
+    ///
+    /// Default: `None`.
+    pub language: Option<Cow<'static, str>>,
 }
 
 impl Default for TextAttrs {
@@ -68,11 +131,20 @@ impl Default for TextAttrs {
             size: 16.0,
             color: Color::BLACK,
             align: TextAlign::Start,
+            overflow_wrap: OverflowWrap::Normal,
             fonts: FontFamilies::new(FontFamily::sans_serif()),
             weight: FontWeight::NORMAL,
             width: FontWidth::Normal,
             style: FontStyle::Normal,
             line_height: LineHeight::Relative(1.2),
+            vertical_align: VerticalAlign::Baseline,
+            features: OtFeatures::new(),
+            variations: FontVariations::new(),
+            text_shadow: None,
+            text_outline: None,
+            glyph_format: GlyphFormat::Auto,
+            hyphenate: false,
+            language: None,
         }
     }
 }
@@ -96,6 +168,44 @@ pub enum TextAlign {
     Justify,
 }
 
+/// How words that don't fit within the max width are broken, similar to the
+/// CSS property.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum OverflowWrap {
+    /// Words are only broken at break opportunities, and overflow the line
+    /// otherwise.
+    #[default]
+    Normal,
+    /// Words that don't fit on a line of their own are broken between any two
+    /// glyph clusters. The min-content width is still that of the widest word.
+    BreakWord,
+    /// Same as [`BreakWord`](OverflowWrap::BreakWord), but the min-content
+    /// width is that of the widest glyph cluster.
+    Anywhere,
+}
+
+/// Vertical alignment of a text section within its line, similar to the CSS
+/// property.
+///
+/// Every run has a box made of the font's ascent and descent, with the rest
+/// of the line height split evenly above and below.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum VerticalAlign {
+    /// On the baseline of the line.
+    #[default]
+    Baseline,
+    /// Centered in the line box.
+    Middle,
+    /// Top of the box at the top of the line box.
+    Top,
+    /// Bottom of the box at the bottom of the line box.
+    Bottom,
+    /// Baseline raised by a third of the font size.
+    Superscript,
+    /// Baseline lowered by a fifth of the font size.
+    Subscript,
+}
+
 /// Height of a line box.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineHeight {
@@ -0,0 +1,138 @@
+use crate::math::Vec2;
+use crate::text::{FontFace, FontId, GlyphId, GlyphKey};
+use crate::{Color, DrawGlyph, DrawGlyphGrid, GlyphCell};
+
+/// Glyphs of a monospace font at a fixed size, for drawing character grids
+/// (terminals, code editors) without shaping.
+///
+/// Glyph IDs of printable ASCII and Latin-1 characters are looked up once.
+/// Cell sizes are rounded to whole pixels, so that every cell reuses the same
+/// rasterized glyphs. Grids are drawn with [`DrawGlyphGrid`].
+#[derive(Debug, Clone)]
+pub struct MonoAtlas {
+    font: FontId,
+    size: f32,
+    cell_size: Vec2,
+    baseline: f32,
+    // index into `glyphs` of every character
+    chars: Vec<Option<u32>>,
+    glyphs: Vec<GlyphId>,
+}
+
+impl MonoAtlas {
+    const NUM_CHARS: u32 = 256;
+
+    /// Creates an atlas for a font face at `size` pixels.
+    pub fn new(face: &FontFace, size: f32) -> MonoAtlas {
+        let ttfp_face = face.ttfp_face();
+        let metrics = face.metrics();
+        let scale = size / f32::from(metrics.units_per_em.max(1));
+
+        let mut glyphs = Vec::new();
+        let chars = (0..Self::NUM_CHARS)
+            .map(|c| {
+                let c = char::from_u32(c).filter(|c| !c.is_control())?;
+                let glyph = ttfp_face.glyph_index(c)?;
+                let index = glyphs.iter().position(|&v| v == glyph).unwrap_or_else(|| {
+                    glyphs.push(glyph);
+                    glyphs.len() - 1
+                });
+                Some(index as u32)
+            })
+            .collect::<Vec<_>>();
+
+        let advance = ['M', '0', ' ']
+            .into_iter()
+            .find_map(|c| ttfp_face.glyph_hor_advance(ttfp_face.glyph_index(c)?))
+            .map(|v| f32::from(v) * scale)
+            .unwrap_or(size * 0.5);
+
+        let ascender = f32::from(metrics.ascender) * scale;
+        let descender = f32::from(metrics.descender) * scale;
+        let line_gap = f32::from(metrics.line_gap) * scale;
+        let height = ascender - descender + line_gap;
+
+        MonoAtlas {
+            font: face.id(),
+            size,
+            cell_size: Vec2::new(advance.round().max(1.0), height.round().max(1.0)),
+            baseline: ascender.round(),
+            chars,
+            glyphs,
+        }
+    }
+
+    /// Font of the glyphs.
+    pub fn font(&self) -> FontId {
+        self.font
+    }
+
+    /// Font size in pixels.
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Size of a single grid cell in pixels.
+    pub fn cell_size(&self) -> Vec2 {
+        self.cell_size
+    }
+
+    /// Distance from the top of a cell to the baseline.
+    pub fn baseline(&self) -> f32 {
+        self.baseline
+    }
+
+    /// Returns the glyph of a character, if it's covered by the atlas.
+    pub fn glyph(&self, c: char) -> Option<GlyphId> {
+        self.glyph_index(c).map(|i| self.glyphs[i as usize])
+    }
+
+    /// Returns the index of the glyph of a character in
+    /// [`glyphs`](MonoAtlas::glyphs), if it's covered by the atlas.
+    pub fn glyph_index(&self, c: char) -> Option<u32> {
+        self.chars.get(c as usize).copied().flatten()
+    }
+
+    /// Returns all distinct glyphs of the atlas.
+    pub fn glyphs(&self) -> &[GlyphId] {
+        &self.glyphs
+    }
+
+    /// Returns the position of the top left corner of a cell, relative to the
+    /// grid origin.
+    pub fn cell_pos(&self, row: u32, col: u32) -> Vec2 {
+        Vec2::new(col as f32, row as f32) * self.cell_size
+    }
+
+    /// Returns a grid at `pos` of cells referencing `glyphs`, usually
+    /// [`MonoAtlas::glyphs`].
+    pub fn grid<'a>(
+        &self,
+        pos: Vec2,
+        glyphs: &'a [GlyphId],
+        cells: &'a [GlyphCell],
+    ) -> DrawGlyphGrid<'a> {
+        DrawGlyphGrid {
+            pos,
+            size: self.size,
+            font: self.font,
+            baseline: self.baseline,
+            glyphs,
+            cells,
+        }
+    }
+
+    /// Returns a glyph drawn at the baseline of a cell at `pos`.
+    pub fn draw_glyph(&self, pos: Vec2, glyph: GlyphId, color: Color) -> DrawGlyph {
+        self.grid(Vec2::ZERO, &[], &[]).draw_glyph(pos, glyph, color)
+    }
+
+    /// Returns cache keys of all glyphs in the atlas rasterized at `scale`,
+    /// physical pixels per unit of the draw list.
+    pub fn glyph_keys(&self, scale: f32) -> impl Iterator<Item = GlyphKey> + '_ {
+        let grid = self.grid(Vec2::ZERO, &[], &[]);
+        self.glyphs
+            .iter()
+            .map(move |&glyph| grid.glyph_key(glyph, scale))
+    }
+}
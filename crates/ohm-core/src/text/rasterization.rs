@@ -1,8 +1,9 @@
 use std::fmt;
 
-use crate::image::ImageData;
-use crate::math::Vec2;
-use crate::text::{FontFace, FontId, GlyphId};
+use crate::image::{ImageData, ImageFormat};
+use crate::math::{Affine2, Vec2};
+use crate::text::{FontFace, FontId, FontVariations, GlyphId};
+use crate::DrawGlyph;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct GlyphKey {
@@ -10,6 +11,47 @@ pub struct GlyphKey {
     pub glyph: GlyphId,
     pub size: u32,
     pub subpixel_bin: SubpixelBin,
+    pub variations: FontVariations,
+    pub format: GlyphFormat,
+    pub dilation: Option<u32>,
+}
+
+impl GlyphKey {
+    /// Creates a key for a glyph rasterized at `scale` times its size.
+    pub fn new(glyph: &DrawGlyph, scale: f32) -> GlyphKey {
+        let size = if scale == 1.0 {
+            glyph.size
+        } else {
+            // quantize to limit the number of distinct sizes in the atlas
+            (glyph.size * scale * 4.0).round() / 4.0
+        };
+
+        let dilation = glyph
+            .dilation
+            .map(|radius| ((radius.max(0.0) * scale * 4.0).round() / 4.0).to_bits());
+
+        GlyphKey {
+            font: glyph.font,
+            glyph: glyph.glyph,
+            size: size.to_bits(),
+            subpixel_bin: SubpixelBin::new(glyph.pos * scale),
+            variations: glyph.variations,
+            format: glyph.format,
+            dilation,
+        }
+    }
+}
+
+/// Returns the uniform scale factor of a transform, ignoring rotation and
+/// translation. Used for rasterizing glyphs of transformed layers at their
+/// on-screen size.
+pub fn glyph_scale(transform: &Affine2) -> f32 {
+    let scale = transform.matrix2.determinant().abs().sqrt();
+    if scale.is_finite() && scale > 0.0 {
+        scale
+    } else {
+        1.0
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -32,6 +74,48 @@ impl SubpixelBin {
     }
 }
 
+/// Antialiasing of rasterized glyphs.
+///
+/// Subpixel modes produce [`ImageFormat::Rgba8`] glyphs, with coverage for
+/// each color channel. They only look right when glyphs are drawn at whole
+/// pixel scale onto opaque content, on a panel with the matching subpixel
+/// order.
+///
+/// [`ImageFormat::Rgba8`]: crate::image::ImageFormat::Rgba8
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum AntialiasMode {
+    /// Single coverage value per pixel.
+    #[default]
+    Grayscale,
+    /// Horizontal subpixel antialiasing, for panels with RGB subpixel order.
+    SubpixelRgb,
+    /// Horizontal subpixel antialiasing, for panels with BGR subpixel order.
+    SubpixelBgr,
+}
+
+impl AntialiasMode {
+    /// Returns `true` for subpixel modes.
+    pub fn is_subpixel(self) -> bool {
+        self != AntialiasMode::Grayscale
+    }
+}
+
+/// Preferred representation of glyphs that exist both in color and as
+/// outlines, e.g. emoji in fonts with a monochrome fallback.
+///
+/// When the preferred representation isn't available, the other one is used.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum GlyphFormat {
+    /// Whatever the first rasterizer in the chain produces, which is color
+    /// for the default rasterizers.
+    #[default]
+    Auto,
+    /// Outlines drawn in the text color.
+    Monochrome,
+    /// Color glyphs ([`ImageFormat::Srgba8`]).
+    Color,
+}
+
 #[derive(Debug, Clone)]
 pub struct RasterizedGlyph {
     pub image: ImageData,
@@ -45,7 +129,45 @@ pub trait Rasterizer {
         glyph_id: GlyphId,
         size: f32,
         subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
     ) -> Option<RasterizedGlyph>;
+
+    /// Rasterizes a glyph, preferring the given representation. Only
+    /// rasterizers that choose between others, like [`FontRasterizers`],
+    /// act on the preference.
+    fn rasterize_with_format(
+        &mut self,
+        font_face: &FontFace,
+        glyph_id: GlyphId,
+        size: f32,
+        subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
+        _format: GlyphFormat,
+    ) -> Option<RasterizedGlyph> {
+        self.rasterize(font_face, glyph_id, size, subpixel_bin, variations)
+    }
+
+    /// Rasterizes the coverage of a glyph outline grown by `radius` pixels,
+    /// as an [`ImageFormat::Gray8`] mask. Used for text outlines and shadows.
+    /// Returns `None` for glyphs without outlines, and by default.
+    fn rasterize_dilated(
+        &mut self,
+        _font_face: &FontFace,
+        _glyph_id: GlyphId,
+        _size: f32,
+        _subpixel_bin: SubpixelBin,
+        _variations: &FontVariations,
+        _radius: f32,
+    ) -> Option<RasterizedGlyph> {
+        None
+    }
+
+    /// Drops any cached state associated with a font.
+    fn invalidate_font(&mut self, _font: FontId) {}
+
+    /// Sets the antialiasing of outline glyphs. Rasterizers that don't
+    /// support subpixel antialiasing keep producing grayscale glyphs.
+    fn set_antialias_mode(&mut self, _mode: AntialiasMode) {}
 }
 
 #[derive(Default)]
@@ -70,14 +192,86 @@ impl Rasterizer for FontRasterizers {
         glyph_id: GlyphId,
         size: f32,
         subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
     ) -> Option<RasterizedGlyph> {
+        self.rasterize_with_format(
+            font_face,
+            glyph_id,
+            size,
+            subpixel_bin,
+            variations,
+            GlyphFormat::Auto,
+        )
+    }
+
+    // later rasterizers are tried until one produces the preferred format,
+    // falling back to the first result
+    fn rasterize_with_format(
+        &mut self,
+        font_face: &FontFace,
+        glyph_id: GlyphId,
+        size: f32,
+        subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
+        format: GlyphFormat,
+    ) -> Option<RasterizedGlyph> {
+        let mut fallback = None;
+
         for rasterizer in &mut self.rasterizers {
-            if let Some(res) = rasterizer.rasterize(font_face, glyph_id, size, subpixel_bin) {
+            let Some(res) =
+                rasterizer.rasterize(font_face, glyph_id, size, subpixel_bin, variations)
+            else {
+                continue;
+            };
+
+            let is_color = res.image.format == ImageFormat::Srgba8;
+            let is_preferred = match format {
+                GlyphFormat::Auto => true,
+                GlyphFormat::Monochrome => !is_color,
+                GlyphFormat::Color => is_color,
+            };
+
+            if is_preferred {
                 return Some(res);
             }
+
+            fallback.get_or_insert(res);
         }
 
-        None
+        fallback
+    }
+
+    fn rasterize_dilated(
+        &mut self,
+        font_face: &FontFace,
+        glyph_id: GlyphId,
+        size: f32,
+        subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
+        radius: f32,
+    ) -> Option<RasterizedGlyph> {
+        self.rasterizers.iter_mut().find_map(|rasterizer| {
+            rasterizer.rasterize_dilated(
+                font_face,
+                glyph_id,
+                size,
+                subpixel_bin,
+                variations,
+                radius,
+            )
+        })
+    }
+
+    fn invalidate_font(&mut self, font: FontId) {
+        for rasterizer in &mut self.rasterizers {
+            rasterizer.invalidate_font(font);
+        }
+    }
+
+    fn set_antialias_mode(&mut self, mode: AntialiasMode) {
+        for rasterizer in &mut self.rasterizers {
+            rasterizer.set_antialias_mode(mode);
+        }
     }
 }
 
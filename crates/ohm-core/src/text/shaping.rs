@@ -1,5 +1,7 @@
+use smallvec::SmallVec;
+
 use crate::math::Vec2;
-use crate::text::{FontFace, GlyphId};
+use crate::text::{FontFace, FontId, FontVariations, GlyphId, Tag};
 
 #[derive(Debug, Clone, Copy)]
 pub struct ShapedGlyph {
@@ -9,15 +11,86 @@ pub struct ShapedGlyph {
     pub offset: Vec2,
 }
 
+/// A single OpenType feature setting, applied to the whole shaped run.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct OtFeature {
+    /// Feature tag, e.g. `liga`, `kern` or `tnum`.
+    pub tag: Tag,
+    /// Feature value. `0` disables the feature, `1` enables it. Some features
+    /// (like `aalt`) accept larger values to select alternates.
+    pub value: u32,
+}
+
+/// List of OpenType features to enable or disable during shaping.
+///
+/// Features not present in the list are left at the shaper's default.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct OtFeatures {
+    list: SmallVec<[OtFeature; 4]>,
+}
+
+impl OtFeatures {
+    /// Creates an empty feature list.
+    pub fn new() -> OtFeatures {
+        OtFeatures::default()
+    }
+
+    /// Sets the value of a feature, replacing any previous setting of the
+    /// same tag.
+    pub fn set(&mut self, tag: Tag, value: u32) {
+        match self.list.iter_mut().find(|v| v.tag == tag) {
+            Some(feature) => feature.value = value,
+            None => self.list.push(OtFeature { tag, value }),
+        }
+    }
+
+    /// Sets the value of a feature, returning the modified list.
+    pub fn with(mut self, tag: Tag, value: u32) -> OtFeatures {
+        self.set(tag, value);
+        self
+    }
+
+    /// Enables a feature, returning the modified list.
+    pub fn enable(self, tag: Tag) -> OtFeatures {
+        self.with(tag, 1)
+    }
+
+    /// Disables a feature, returning the modified list.
+    pub fn disable(self, tag: Tag) -> OtFeatures {
+        self.with(tag, 0)
+    }
+
+    /// Returns the value of a feature, if it was set.
+    pub fn get(&self, tag: Tag) -> Option<u32> {
+        self.list.iter().find(|v| v.tag == tag).map(|v| v.value)
+    }
+
+    /// Returns `true` if no features were set.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Iterates over all feature settings in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &OtFeature> + '_ {
+        self.list.iter()
+    }
+}
+
 pub trait TextShaper: Send + Sync + 'static {
+    #[allow(clippy::too_many_arguments)]
     fn shape(
         &mut self,
         font_face: &FontFace,
         text: &str,
         size: f32,
         is_rtl: bool,
+        features: &OtFeatures,
+        variations: &FontVariations,
         buf: &mut Vec<ShapedGlyph>,
     );
+
+    /// Drops any cached state associated with a font.
+    fn invalidate_font(&mut self, _font: FontId) {}
 }
 
 #[derive(Debug, Copy, Clone, Default)]
@@ -30,6 +103,8 @@ impl TextShaper for DummyTextShaper {
         _text: &str,
         _size: f32,
         _is_rtl: bool,
+        _features: &OtFeatures,
+        _variations: &FontVariations,
         _buf: &mut Vec<ShapedGlyph>,
     ) {
         unimplemented!()
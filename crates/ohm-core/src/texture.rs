@@ -4,18 +4,23 @@ use std::sync::Arc;
 
 use crossbeam_queue::SegQueue;
 use guillotiere::{AllocId, AtlasAllocator};
+use indexmap::IndexMap;
 use slotmap::SlotMap;
 
 use crate::asset::{AssetPath, AssetSource};
-use crate::image::{ImageData, ImageDecoder, ImageFormat, ImageHandle};
+use crate::image::{
+    encode_srgb, load_image, DecodeJob, ImageData, ImageDecodePool, ImageDecoder, ImageFormat,
+    ImageHandle,
+};
 use crate::math::{Affine2, URect, UVec2, Vec2};
 use crate::renderer::PathCache;
-use crate::text::{FontDatabase, GlyphKey, Rasterizer, SubpixelBin};
+use crate::text::{glyph_scale, FontDatabase, FontId, GlyphKey, Rasterizer};
 use crate::{
-    Command, DrawList, DrawRect, Error, ErrorKind, Fill, FillPath, ImageId, Result, StrokePath,
+    Command, DrawList, DrawRect, Error, ErrorKind, Fill, FillPath, GradientStops, ImageId, Result,
+    StrokePath,
 };
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub struct TextureId(pub u64);
 
 #[derive(Debug)]
@@ -25,6 +30,8 @@ pub enum TextureCommand {
         data: ImageData,
         mipmap_mode: MipmapMode,
     },
+    /// Creates a texture for atlases. Its contents must be initialized to
+    /// zero (transparent), as atlas regions are sampled past their edges.
     CreateDynamic {
         id: TextureId,
         format: ImageFormat,
@@ -42,11 +49,68 @@ pub enum TextureCommand {
         dst_rect: URect,
         data: ImageData,
     },
+    /// Multiple writes to the same texture, which can be uploaded at once.
+    /// Produced by [`TextureCommand::coalesce_writes`].
+    WriteMany {
+        /// Destination texture.
+        dst_id: TextureId,
+        /// Destination rectangles and their data. Rectangles don't overlap.
+        writes: Vec<(URect, ImageData)>,
+    },
     Free {
         id: TextureId,
     },
 }
 
+impl TextureCommand {
+    /// Merges consecutive writes into a single [`TextureCommand::WriteMany`]
+    /// per texture, so that backends can upload them together. Writes aren't
+    /// reordered relative to other commands.
+    pub fn coalesce_writes(commands: &mut Vec<TextureCommand>) {
+        let mut coalesced = Vec::with_capacity(commands.len());
+        let mut run = IndexMap::new();
+
+        for command in commands.drain(..) {
+            match command {
+                TextureCommand::Write {
+                    dst_id,
+                    dst_rect,
+                    data,
+                } => {
+                    run.entry(dst_id)
+                        .or_insert_with(Vec::new)
+                        .push((dst_rect, data));
+                }
+                command => {
+                    Self::flush_writes(&mut run, &mut coalesced);
+                    coalesced.push(command);
+                }
+            }
+        }
+
+        Self::flush_writes(&mut run, &mut coalesced);
+        *commands = coalesced;
+    }
+
+    fn flush_writes(
+        run: &mut IndexMap<TextureId, Vec<(URect, ImageData)>>,
+        commands: &mut Vec<TextureCommand>,
+    ) {
+        for (dst_id, mut writes) in run.drain(..) {
+            if writes.len() == 1 {
+                let (dst_rect, data) = writes.pop().unwrap();
+                commands.push(TextureCommand::Write {
+                    dst_id,
+                    dst_rect,
+                    data,
+                });
+            } else {
+                commands.push(TextureCommand::WriteMany { dst_id, writes });
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum MipmapMode {
     Disabled,
@@ -61,10 +125,16 @@ slotmap::new_key_type! {
 pub struct TextureCache {
     images: SlotMap<ImageId, ImageEntry>,
     images_by_path: HashMap<AssetPath<'static>, ImageId>,
-    glyphs: HashMap<GlyphKey, GlyphEntry>,
+    // ordered maps, so that atlas packing doesn't depend on hash order
+    glyphs: IndexMap<GlyphKey, GlyphEntry>,
+    gradients: IndexMap<GradientStops, GradientEntry>,
     atlases: TextureAtlasPool,
     id_allocator: TextureIdAllocator,
     image_cleanup_queue: Arc<SegQueue<ImageId>>,
+    memory_budget: Option<u64>,
+    mipmaps_disabled: bool,
+    glyph_mipmaps: bool,
+    frame: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -77,6 +147,25 @@ struct ImageEntry {
     alloc_id: Option<(AtlasId, AllocId)>,
     requested_size: UVec2,
     max_size: UVec2,
+    // format and mipmap mode of the standalone texture
+    texture_format: Option<ImageFormat>,
+    texture_mipmap_mode: MipmapMode,
+    last_used: u64,
+    evicted: bool,
+    // waiting for a background decoding job
+    decoding: bool,
+    // the last background decoding job failed, it's not submitted again until
+    // the requested size changes
+    failed: bool,
+    dynamic: Option<DynamicImage>,
+}
+
+// image updated by the user, in its own texture
+#[derive(Debug, Clone)]
+struct DynamicImage {
+    format: ImageFormat,
+    // applied by the next `load_images`, in order
+    writes: Vec<(URect, ImageData)>,
 }
 
 #[derive(Debug, Clone)]
@@ -92,9 +181,17 @@ struct GlyphEntry {
     rect: URect,
     alloc_id: Option<(AtlasId, AllocId)>,
     offset: Vec2,
+    image_size: UVec2,
     is_empty: bool,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct GradientEntry {
+    used: bool,
+    rect: URect,
+    alloc_id: Option<(AtlasId, AllocId)>,
+}
+
 #[derive(Debug, Clone)]
 pub struct AllocatedGlyph {
     pub texture: TextureId,
@@ -102,10 +199,13 @@ pub struct AllocatedGlyph {
     pub texture_size: UVec2,
     pub rect: URect,
     pub offset: Vec2,
+    pub subpixel_offset: Vec2,
+    pub image_size: UVec2,
 }
 
 impl TextureCache {
     const MIN_STANDALONE_SIZE: UVec2 = UVec2::new(1024, 1024);
+    const GRADIENT_RAMP_WIDTH: u32 = 256;
 
     pub fn new() -> TextureCache {
         TextureCache::default()
@@ -120,12 +220,170 @@ impl TextureCache {
             alloc_id: None,
             requested_size: data.size,
             max_size: data.size,
+            texture_format: None,
+            texture_mipmap_mode: MipmapMode::Disabled,
+            last_used: self.frame,
+            evicted: false,
+            decoding: false,
+            failed: false,
+            dynamic: None,
             data: Some(data),
         });
 
         ImageHandle::new(id, self.image_cleanup_queue.clone())
     }
 
+    /// Adds an image with contents that can be updated every frame, e.g. for
+    /// video or procedurally generated textures. It has its own texture,
+    /// initially transparent, and no mipmaps.
+    ///
+    /// Like other images, it lives until the returned handle is dropped and
+    /// [`TextureCache::cleanup`] is called.
+    pub fn add_dynamic_image(&mut self, size: UVec2, format: ImageFormat) -> ImageHandle {
+        let id = self.images.insert(ImageEntry {
+            path: None,
+            data: None,
+            mipmap_mode: MipmapMode::Disabled,
+            texture: None,
+            rect: URect::new(UVec2::ZERO, size),
+            alloc_id: None,
+            requested_size: UVec2::ZERO,
+            max_size: UVec2::ZERO,
+            texture_format: None,
+            texture_mipmap_mode: MipmapMode::Disabled,
+            last_used: self.frame,
+            evicted: false,
+            decoding: false,
+            failed: false,
+            dynamic: Some(DynamicImage {
+                format,
+                writes: Vec::new(),
+            }),
+        });
+
+        ImageHandle::new(id, self.image_cleanup_queue.clone())
+    }
+
+    /// Replaces a region of a dynamic image, see
+    /// [`TextureCache::add_dynamic_image`]. The write is uploaded by the next
+    /// [`load_images`], so draws of the image in the following frames show the
+    /// new contents.
+    ///
+    /// Returns an error if the image isn't dynamic, or if the data doesn't
+    /// match the format of the image or the size of the region, or if the
+    /// region is out of bounds.
+    ///
+    /// [`load_images`]: TextureCache::load_images
+    pub fn update_dynamic_image(
+        &mut self,
+        image: &ImageHandle,
+        rect: URect,
+        mut data: ImageData,
+    ) -> Result<()> {
+        let entry = self.images.get_mut(image.id());
+        let Some((dynamic, image_rect)) =
+            entry.and_then(|entry| Some((entry.dynamic.as_mut()?, entry.rect)))
+        else {
+            return Err(Error::new(ErrorKind::Other, "image isn't a dynamic image"));
+        };
+
+        check_region_write(dynamic.format, image_rect.size(), rect, &data)?;
+
+        // earlier writes are fully overwritten, e.g. the previous video frame
+        if rect == image_rect {
+            dynamic.writes.clear();
+        }
+
+        data.premultiply();
+        dynamic.writes.push((rect, data));
+
+        Ok(())
+    }
+
+    /// Same as [`TextureCache::update_dynamic_image`], but `data` holds the
+    /// whole image, and only the given region of it is uploaded. Useful for
+    /// images kept in memory and changing a little every frame, e.g. a
+    /// terminal with a blinking cursor.
+    pub fn update_dynamic_image_region(
+        &mut self,
+        image: &ImageHandle,
+        rect: URect,
+        data: &ImageData,
+    ) -> Result<()> {
+        let entry = self.images.get(image.id());
+        let Some(image_rect) = entry.filter(|e| e.dynamic.is_some()).map(|e| e.rect) else {
+            return Err(Error::new(ErrorKind::Other, "image isn't a dynamic image"));
+        };
+
+        if data.size != image_rect.size() {
+            return Err(Error::new(
+                ErrorKind::InvalidImage,
+                format!(
+                    "image data size {} doesn't match dynamic image size {}",
+                    data.size,
+                    image_rect.size()
+                ),
+            ));
+        }
+
+        if rect.min.cmpgt(rect.max).any() || rect.max.cmpgt(data.size).any() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "region {rect:?} is out of bounds of image of size {}",
+                    data.size
+                ),
+            ));
+        }
+
+        self.update_dynamic_image(image, rect, data.crop(rect))
+    }
+
+    /// Replaces a region of an uploaded image, e.g. one cell of a sprite sheet,
+    /// without uploading the whole image again. The region is in pixels of
+    /// the uploaded image, see [`TextureCache::get_image_size`].
+    ///
+    /// Returns an error if the image hasn't been uploaded yet, or if the data
+    /// doesn't match the format of the image or the size of the region, or if
+    /// the region is out of bounds. Changes to images loaded from a path are
+    /// lost when they're evicted, see [`TextureCache::set_memory_budget`].
+    pub fn write_image_region(
+        &mut self,
+        id: ImageId,
+        rect: URect,
+        mut data: ImageData,
+        commands: &mut Vec<TextureCommand>,
+    ) -> Result<()> {
+        let Some(entry) = self.images.get(id) else {
+            return Err(Error::new(ErrorKind::Other, "image doesn't exist"));
+        };
+
+        let (dst_id, format) = match (entry.texture, entry.alloc_id) {
+            (_, Some((atlas_id, _))) => {
+                let atlas = &self.atlases.atlases[atlas_id];
+                (atlas.texture, atlas.format)
+            }
+            (Some(texture), None) => match entry.texture_format {
+                Some(format) => (texture, format),
+                None => return Err(Error::new(ErrorKind::Other, "image hasn't been uploaded")),
+            },
+            (None, None) => {
+                return Err(Error::new(ErrorKind::Other, "image hasn't been uploaded"));
+            }
+        };
+
+        check_region_write(format, entry.rect.size(), rect, &data)?;
+
+        data.premultiply();
+        commands.push(TextureCommand::Write {
+            dst_id,
+            dst_rect: URect::new(entry.rect.min + rect.min, entry.rect.min + rect.max),
+            data,
+        });
+
+        Ok(())
+    }
+
     pub fn add_image_from_path<'a>(
         &mut self,
         path: impl Into<AssetPath<'a>>,
@@ -154,6 +412,13 @@ impl TextureCache {
             alloc_id: None,
             requested_size: UVec2::ZERO,
             max_size: UVec2::ZERO,
+            texture_format: None,
+            texture_mipmap_mode: MipmapMode::Disabled,
+            last_used: self.frame,
+            evicted: false,
+            decoding: false,
+            failed: false,
+            dynamic: None,
         });
 
         self.images_by_path.insert(path, id);
@@ -168,32 +433,92 @@ impl TextureCache {
             alloc_id: None,
             is_empty: false,
             offset: Vec2::ZERO,
+            image_size: UVec2::ZERO,
         });
     }
 
     pub fn add_glyphs_from_lists(&mut self, lists: &[DrawList]) {
         for list in lists {
-            self.add_glyphs_from_commands(list.commands);
+            self.add_glyphs_from_commands_inner(
+                list.commands,
+                list.physical_transform(),
+                list.scale,
+                false,
+            );
         }
     }
 
     pub fn add_glyphs_from_commands(&mut self, commands: &[Command]) {
+        self.add_glyphs_from_commands_inner(commands, Affine2::IDENTITY, 1.0, false);
+    }
+
+    fn add_glyphs_from_commands_inner(
+        &mut self,
+        commands: &[Command],
+        transform: Affine2,
+        list_scale: f32,
+        crisp_text: bool,
+    ) {
+        let scale = if crisp_text {
+            glyph_scale(&transform)
+        } else {
+            list_scale
+        };
+
         for command in commands {
             match command {
-                Command::DrawLayer(layer) => self.add_glyphs_from_commands(layer.commands),
-                Command::DrawGlyph(glyph) => {
-                    self.add_glyph(GlyphKey {
-                        font: glyph.font,
-                        glyph: glyph.glyph,
-                        size: glyph.size.to_bits(),
-                        subpixel_bin: SubpixelBin::new(glyph.pos),
-                    });
+                Command::DrawLayer(layer) => self.add_glyphs_from_commands_inner(
+                    layer.commands,
+                    transform * layer.transform,
+                    list_scale,
+                    crisp_text || layer.crisp_text,
+                ),
+                Command::DrawGlyph(glyph) => self.add_glyph(GlyphKey::new(glyph, scale)),
+                Command::DrawGlyphGrid(grid) => {
+                    for &glyph in grid.glyphs {
+                        self.add_glyph(grid.glyph_key(glyph, scale));
+                    }
                 }
                 _ => (),
             }
         }
     }
 
+    pub fn add_gradient(&mut self, stops: GradientStops) {
+        let entry = self.gradients.entry(stops).or_insert(GradientEntry {
+            used: true,
+            rect: URect::ZERO,
+            alloc_id: None,
+        });
+
+        entry.used = true;
+    }
+
+    pub fn add_gradients_from_lists(&mut self, lists: &[DrawList]) {
+        for list in lists {
+            self.add_gradients_from_commands(list.commands);
+        }
+    }
+
+    pub fn add_gradients_from_commands(&mut self, commands: &[Command]) {
+        for command in commands {
+            let fill = match command {
+                Command::DrawLayer(layer) => {
+                    self.add_gradients_from_commands(layer.commands);
+                    continue;
+                }
+                Command::DrawRect(DrawRect { fill, .. }) => fill,
+                Command::FillPath(FillPath { fill, .. }) => fill,
+                Command::StrokePath(StrokePath { fill, .. }) => fill,
+                _ => continue,
+            };
+
+            if let Fill::ConicGradient(gradient) = fill {
+                self.add_gradient(gradient.stops);
+            }
+        }
+    }
+
     pub fn set_image_sizes_from_lists(&mut self, path_cache: &mut PathCache, lists: &[DrawList]) {
         for list in lists {
             self.set_image_sizes_from_commands(path_cache, list.commands, Affine2::IDENTITY);
@@ -256,6 +581,8 @@ impl TextureCache {
                 continue;
             };
 
+            image.last_used = self.frame;
+
             if image.max_size != UVec2::ZERO && image.max_size.cmpge(image.requested_size).any() {
                 continue;
             }
@@ -266,8 +593,128 @@ impl TextureCache {
             };
 
             let size = (size * scale).as_uvec2();
-            image.requested_size = image.requested_size.max(size);
+            let requested_size = image.requested_size.max(size);
+            if image.requested_size != requested_size {
+                image.requested_size = requested_size;
+                image.failed = false;
+            }
+        }
+    }
+
+    /// Sets the approximate amount of texture memory in bytes, which the cache
+    /// tries to stay under. When the budget is exceeded, [`load_images`]
+    /// evicts least recently used images loaded from a path. They are loaded
+    /// again once drawn. Images added from memory are never evicted.
+    ///
+    /// [`load_images`]: TextureCache::load_images
+    pub fn set_memory_budget(&mut self, bytes: Option<u64>) {
+        self.memory_budget = bytes;
+    }
+
+    /// Enables or disables mipmaps for all images, regardless of the
+    /// [`MipmapMode`] they were added with. Disabling them saves about a
+    /// quarter of image texture memory and the mipmap generation, at the cost
+    /// of aliasing when images are drawn downscaled.
+    ///
+    /// Only affects images uploaded afterwards. Default: enabled.
+    pub fn set_mipmaps_enabled(&mut self, enabled: bool) {
+        self.mipmaps_disabled = !enabled;
+    }
+
+    /// Returns whether mipmaps are enabled, see
+    /// [`TextureCache::set_mipmaps_enabled`].
+    pub fn mipmaps_enabled(&self) -> bool {
+        !self.mipmaps_disabled
+    }
+
+    /// Enables or disables mipmaps for glyph atlases, which reduces shimmering
+    /// of text drawn downscaled under a transform. Mipmapped glyphs are packed
+    /// into their own atlases. At small mip levels neighbouring glyphs bleed
+    /// into each other unless [`AtlasPolicy::padding`] is increased.
+    ///
+    /// Only affects glyphs rasterized afterwards. Default: disabled.
+    pub fn set_glyph_mipmaps_enabled(&mut self, enabled: bool) {
+        self.glyph_mipmaps = enabled;
+    }
+
+    /// Enables reusing the ids of freed textures for new ones, which keeps ids
+    /// small and the same across runs. Disabled by default, in which case
+    /// every texture gets a new id.
+    pub fn set_texture_id_recycling(&mut self, enabled: bool) {
+        if enabled != self.id_allocator.free_ids.is_some() {
+            self.id_allocator.free_ids = enabled.then(Vec::new);
+        }
+    }
+
+    /// Limits the size of textures to the maximum texture size of the
+    /// renderer, see [`RendererCapabilities::max_texture_size`]. Glyphs and
+    /// images that don't fit into an atlas of this size aren't drawn, and
+    /// loading images larger than it in either dimension fails.
+    ///
+    /// [`RendererCapabilities::max_texture_size`]: crate::renderer::RendererCapabilities::max_texture_size
+    pub fn set_max_texture_size(&mut self, size: Option<u32>) {
+        self.atlases.max_texture_size = size;
+    }
+
+    /// Sets how images, glyphs and gradients are packed into atlases. Only
+    /// affects new allocations.
+    pub fn set_atlas_policy(&mut self, policy: AtlasPolicy) {
+        self.atlases.policy = policy;
+    }
+
+    /// Returns the current [`AtlasPolicy`].
+    pub fn atlas_policy(&self) -> AtlasPolicy {
+        self.atlases.policy
+    }
+
+    /// Returns the approximate amount of texture memory in bytes used by all
+    /// atlases and standalone textures.
+    pub fn memory_usage(&self) -> u64 {
+        let standalone = self
+            .images
+            .values()
+            .filter_map(|image| image.standalone_memory_usage())
+            .sum::<u64>();
+
+        standalone + self.atlases.memory_usage()
+    }
+
+    /// Collects statistics about cached resources and texture memory.
+    pub fn stats(&self) -> TextureCacheStats {
+        let mut stats = TextureCacheStats {
+            images: self.images.len(),
+            glyphs: self.glyphs.len(),
+            gradients: self.gradients.len(),
+            ..TextureCacheStats::default()
+        };
+
+        for atlas in self.atlases.atlases.values() {
+            let atlas_stats = atlas.stats();
+
+            let format_stats = stats.format_mut(atlas.format);
+            format_stats.atlases += 1;
+            format_stats.bytes += atlas_stats.bytes;
+
+            stats.total_bytes += atlas_stats.bytes;
+            stats.atlases.push(atlas_stats);
         }
+
+        for image in self.images.values() {
+            let (Some(bytes), Some(format)) =
+                (image.standalone_memory_usage(), image.texture_format)
+            else {
+                continue;
+            };
+
+            let format_stats = stats.format_mut(format);
+            format_stats.standalone_textures += 1;
+            format_stats.bytes += bytes;
+
+            stats.standalone_textures += 1;
+            stats.total_bytes += bytes;
+        }
+
+        stats
     }
 
     pub fn load_images(
@@ -276,58 +723,227 @@ impl TextureCache {
         decoder: &dyn ImageDecoder,
         commands: &mut Vec<TextureCommand>,
     ) -> Result<()> {
+        let res = self.load_images_inner(source, decoder, commands);
+        self.evict_images(commands);
+        self.frame += 1;
+        res
+    }
+
+    fn load_images_inner(
+        &mut self,
+        source: &dyn AssetSource,
+        decoder: &dyn ImageDecoder,
+        commands: &mut Vec<TextureCommand>,
+    ) -> Result<()> {
+        self.upload_dynamic_images(commands);
+
         for image in self.images.values_mut() {
-            if image.texture.is_some() || image.alloc_id.is_some() {
+            if !image.needs_loading(self.frame) {
                 continue;
             }
 
-            let requested_size = Some(image.requested_size).filter(|v| v.x != 0 && v.y != 0);
+            let requested_size = image.requested_size();
 
             let data = if let Some(data) = image.data.take() {
                 data
             } else if let Some(path) = image.path.as_ref() {
-                let raw_data = source
-                    .load(path.as_borrowed())
-                    .map_err(|e| e.with_context(format!("failed to load image from {path}")))?;
-
-                decoder
-                    .decode(path.extension(), &raw_data, requested_size)
-                    .map_err(|e| e.with_context(format!("failed to decode image from {path}")))?
+                load_image(source, decoder, path, requested_size)?
             } else {
                 continue;
             };
 
-            if requested_size.is_some_and(|v| v.cmpgt(data.size).any()) {
-                image.max_size = data.size;
+            image.upload(
+                data,
+                requested_size,
+                !self.mipmaps_disabled,
+                &mut self.atlases,
+                &mut self.id_allocator,
+                commands,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`load_images`](TextureCache::load_images), but images with
+    /// asset paths are loaded and decoded by a [`ImageDecodePool`].
+    ///
+    /// Images are uploaded by a later call, once they're decoded. Until then,
+    /// they're not drawn. Use [`has_pending_images`] to check whether another
+    /// frame should be rendered. An image that fails to decode is reported
+    /// once, and only decoded again when it's requested at a different size.
+    ///
+    /// [`has_pending_images`]: TextureCache::has_pending_images
+    pub fn load_images_in_background(
+        &mut self,
+        pool: &ImageDecodePool,
+        commands: &mut Vec<TextureCommand>,
+    ) -> Result<()> {
+        let res = self.load_images_in_background_inner(pool, commands);
+        self.evict_images(commands);
+        self.frame += 1;
+        res
+    }
+
+    fn load_images_in_background_inner(
+        &mut self,
+        pool: &ImageDecodePool,
+        commands: &mut Vec<TextureCommand>,
+    ) -> Result<()> {
+        self.upload_dynamic_images(commands);
+
+        let mut res = Ok(());
+
+        while let Some(decoded) = pool.poll() {
+            // the image could've been removed while decoding
+            let Some(image) = self.images.get_mut(decoded.image) else {
+                continue;
+            };
+
+            if !image.decoding {
+                continue;
+            }
+
+            image.decoding = false;
+
+            let requested_size = image.requested_size();
+            let upload = decoded.result.and_then(|data| {
+                image.upload(
+                    data,
+                    requested_size,
+                    !self.mipmaps_disabled,
+                    &mut self.atlases,
+                    &mut self.id_allocator,
+                    commands,
+                )
+            });
+
+            image.failed = upload.is_err();
+
+            if res.is_ok() {
+                res = upload;
             }
+        }
 
-            if data.size.cmpge(Self::MIN_STANDALONE_SIZE).any() {
-                let texture_id = self.id_allocator.alloc();
+        for (id, image) in &mut self.images {
+            if image.decoding || image.failed || !image.needs_loading(self.frame) {
+                continue;
+            }
 
-                image.texture = Some(texture_id);
-                image.rect = URect::new(UVec2::ZERO, data.size);
+            let requested_size = image.requested_size();
 
-                commands.push(TextureCommand::CreateStatic {
-                    id: texture_id,
+            if let Some(data) = image.data.take() {
+                let upload = image.upload(
                     data,
-                    mipmap_mode: image.mipmap_mode,
+                    requested_size,
+                    !self.mipmaps_disabled,
+                    &mut self.atlases,
+                    &mut self.id_allocator,
+                    commands,
+                );
+
+                if res.is_ok() {
+                    res = upload;
+                }
+            } else if let Some(path) = image.path.as_ref() {
+                image.decoding = true;
+                pool.submit(DecodeJob {
+                    image: id,
+                    path: path.clone(),
+                    requested_size,
                 });
+            }
+        }
+
+        res
+    }
 
+    fn upload_dynamic_images(&mut self, commands: &mut Vec<TextureCommand>) {
+        for image in self.images.values_mut() {
+            let Some(dynamic) = &mut image.dynamic else {
                 continue;
+            };
+
+            let dst_id = *image.texture.get_or_insert_with(|| {
+                let id = self.id_allocator.alloc();
+                commands.push(TextureCommand::CreateDynamic {
+                    id,
+                    format: dynamic.format,
+                    size: image.rect.size(),
+                    mipmap_mode: MipmapMode::Disabled,
+                });
+                id
+            });
+
+            image.texture_format = Some(dynamic.format);
+
+            for (dst_rect, data) in dynamic.writes.drain(..) {
+                commands.push(TextureCommand::Write {
+                    dst_id,
+                    dst_rect,
+                    data,
+                });
             }
+        }
+    }
 
-            let (alloc_id, rect) = self
-                .atlases
-                .alloc(&mut self.id_allocator, commands, data, image.mipmap_mode)
-                .ok_or_else(|| {
-                    Error::new(ErrorKind::AtlasAlloc, "failed to allocate image in atlas")
-                })?;
+    /// Returns `true` if some images are still being decoded in background.
+    pub fn has_pending_images(&self) -> bool {
+        self.images.values().any(|image| image.decoding)
+    }
 
-            image.alloc_id = Some(alloc_id);
-            image.rect = rect;
+    fn evict_images(&mut self, commands: &mut Vec<TextureCommand>) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+
+        let mut usage = self.memory_usage();
+        if usage <= budget {
+            return;
         }
 
-        Ok(())
+        let mut candidates = self
+            .images
+            .iter()
+            .filter(|(_, image)| {
+                image.path.is_some()
+                    && image.last_used != self.frame
+                    && (image.texture.is_some() || image.alloc_id.is_some())
+            })
+            .map(|(id, image)| (image.last_used, id))
+            .collect::<Vec<_>>();
+
+        candidates.sort_unstable();
+
+        for (_, id) in candidates {
+            if usage <= budget {
+                break;
+            }
+
+            let image = &mut self.images[id];
+
+            if let Some(bytes) = image.standalone_memory_usage() {
+                usage = usage.saturating_sub(bytes);
+            }
+
+            if let Some(texture) = image.texture.take() {
+                commands.push(TextureCommand::Free { id: texture });
+                self.id_allocator.free(texture);
+            }
+
+            if let Some(alloc_id) = image.alloc_id.take() {
+                // atlas memory is only released once the whole atlas is empty
+                if self.atlases.free(alloc_id) {
+                    usage = usage.saturating_sub(self.atlases.atlas_memory_usage(alloc_id.0));
+                }
+            }
+
+            image.rect = URect::ZERO;
+            image.texture_format = None;
+            image.evicted = true;
+        }
+
+        self.atlases.cleanup(&mut self.id_allocator, commands);
     }
 
     pub fn load_glyphs(
@@ -345,31 +961,80 @@ impl TextureCache {
                 continue;
             };
 
-            let Some(result) = rasterizer.rasterize(
-                font,
-                glyph_key.glyph,
-                f32::from_bits(glyph_key.size),
-                glyph_key.subpixel_bin,
-            ) else {
+            let size = f32::from_bits(glyph_key.size);
+            let result = match glyph_key.dilation {
+                Some(radius) => rasterizer.rasterize_dilated(
+                    font,
+                    glyph_key.glyph,
+                    size,
+                    glyph_key.subpixel_bin,
+                    &glyph_key.variations,
+                    f32::from_bits(radius),
+                ),
+                None => rasterizer.rasterize_with_format(
+                    font,
+                    glyph_key.glyph,
+                    size,
+                    glyph_key.subpixel_bin,
+                    &glyph_key.variations,
+                    glyph_key.format,
+                ),
+            };
+
+            let Some(result) = result else {
                 glyph.is_empty = true;
                 continue;
             };
 
+            let mut image = result.image;
+            image.premultiply();
+
+            let mipmap_mode = if self.glyph_mipmaps {
+                MipmapMode::Enabled
+            } else {
+                MipmapMode::Disabled
+            };
+
+            let image_size = image.size;
+            let (alloc_id, rect) = self
+                .atlases
+                .alloc(&mut self.id_allocator, commands, image, mipmap_mode)
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::AtlasAlloc, "failed to allocate glyph in atlas")
+                })?;
+
+            glyph.alloc_id = Some(alloc_id);
+            glyph.rect = rect;
+            glyph.offset = result.offset;
+            glyph.image_size = image_size;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_gradients(&mut self, commands: &mut Vec<TextureCommand>) -> Result<()> {
+        for (stops, gradient) in &mut self.gradients {
+            if gradient.alloc_id.is_some() {
+                continue;
+            }
+
             let (alloc_id, rect) = self
                 .atlases
                 .alloc(
                     &mut self.id_allocator,
                     commands,
-                    result.image,
+                    rasterize_gradient(stops, Self::GRADIENT_RAMP_WIDTH),
                     MipmapMode::Disabled,
                 )
                 .ok_or_else(|| {
-                    Error::new(ErrorKind::AtlasAlloc, "failed to allocate glyph in atlas")
+                    Error::new(
+                        ErrorKind::AtlasAlloc,
+                        "failed to allocate gradient in atlas",
+                    )
                 })?;
 
-            glyph.alloc_id = Some(alloc_id);
-            glyph.rect = rect;
-            glyph.offset = result.offset;
+            gradient.alloc_id = Some(alloc_id);
+            gradient.rect = rect;
         }
 
         Ok(())
@@ -392,6 +1057,28 @@ impl TextureCache {
         })
     }
 
+    /// Returns the size of an image in pixels, or `None` if it hasn't been
+    /// loaded yet.
+    pub fn get_image_size(&self, id: ImageId) -> Option<UVec2> {
+        let entry = self.images.get(id)?;
+        match &entry.data {
+            Some(data) => Some(data.size),
+            None if entry.texture.is_some()
+                || entry.alloc_id.is_some()
+                || entry.dynamic.is_some() =>
+            {
+                Some(entry.rect.size())
+            }
+            None => None,
+        }
+    }
+
+    /// Returns the asset path of an image, or `None` if it was added from
+    /// memory.
+    pub fn get_image_path(&self, id: ImageId) -> Option<&AssetPath<'static>> {
+        self.images.get(id)?.path.as_ref()
+    }
+
     pub fn get_glyph(&self, key: &GlyphKey) -> Option<AllocatedGlyph> {
         self.glyphs.get(key).and_then(|entry| {
             let atlas_id = entry.alloc_id?.0;
@@ -402,10 +1089,74 @@ impl TextureCache {
                 rect: entry.rect,
                 format: atlas.format,
                 offset: entry.offset,
+                subpixel_offset: key.subpixel_bin.offset(),
+                image_size: entry.image_size,
             })
         })
     }
 
+    pub fn get_gradient(&self, stops: &GradientStops) -> Option<AllocatedImage> {
+        self.gradients.get(stops).and_then(|entry| {
+            let atlas_id = entry.alloc_id?.0;
+            let atlas = &self.atlases.atlases[atlas_id];
+            Some(AllocatedImage {
+                texture: atlas.texture,
+                texture_size: atlas.size,
+                rect: entry.rect,
+            })
+        })
+    }
+
+    /// Removes all glyphs of a font, so that they are rasterized again on the
+    /// next frame. Use this after replacing the font data behind `font`.
+    pub fn invalidate_font(&mut self, font: FontId, commands: &mut Vec<TextureCommand>) {
+        self.glyphs.retain(|key, glyph| {
+            if key.font != font {
+                return true;
+            }
+
+            if let Some(alloc_id) = glyph.alloc_id {
+                self.atlases.free(alloc_id);
+            }
+
+            false
+        });
+
+        self.atlases.cleanup(&mut self.id_allocator, commands);
+    }
+
+    /// Drops all rasterized glyphs, so that they're rasterized again when
+    /// drawn. Used after changing rasterization settings.
+    pub fn invalidate_glyphs(&mut self, commands: &mut Vec<TextureCommand>) {
+        for glyph in self.glyphs.values() {
+            if let Some(alloc_id) = glyph.alloc_id {
+                self.atlases.free(alloc_id);
+            }
+        }
+
+        self.glyphs.clear();
+        self.atlases.cleanup(&mut self.id_allocator, commands);
+    }
+
+    /// Frees every texture and drops all images, glyphs and gradients. Handles
+    /// to the dropped images stay valid, but they are no longer drawn.
+    pub fn clear_all(&mut self, commands: &mut Vec<TextureCommand>) {
+        for image in self.images.values() {
+            if image.alloc_id.is_none() {
+                if let Some(id) = image.texture {
+                    commands.push(TextureCommand::Free { id });
+                    self.id_allocator.free(id);
+                }
+            }
+        }
+
+        self.images.clear();
+        self.images_by_path.clear();
+        self.glyphs.clear();
+        self.gradients.clear();
+        self.atlases.clear(&mut self.id_allocator, commands);
+    }
+
     pub fn cleanup(&mut self, commands: &mut Vec<TextureCommand>) {
         while let Some(image_id) = self.image_cleanup_queue.pop() {
             let Some(image) = self.images.remove(image_id) else {
@@ -416,6 +1167,7 @@ impl TextureCache {
                 self.atlases.free(alloc_id);
             } else if let Some(id) = image.texture {
                 commands.push(TextureCommand::Free { id });
+                self.id_allocator.free(id);
             }
 
             if let Some(path) = &image.path {
@@ -435,7 +1187,243 @@ impl TextureCache {
             false
         });
 
-        self.atlases.cleanup(commands);
+        self.gradients.retain(|_, gradient| {
+            if gradient.used {
+                gradient.used = false;
+                return true;
+            }
+
+            if let Some(alloc_id) = gradient.alloc_id {
+                self.atlases.free(alloc_id);
+            }
+
+            false
+        });
+
+        self.atlases.cleanup(&mut self.id_allocator, commands);
+    }
+}
+
+/// Checks that `data` can be written into `rect` of an image.
+fn check_region_write(
+    format: ImageFormat,
+    image_size: UVec2,
+    rect: URect,
+    data: &ImageData,
+) -> Result<()> {
+    if data.format != format {
+        return Err(Error::new(
+            ErrorKind::InvalidImage,
+            format!(
+                "image data format {:?} doesn't match image format {format:?}",
+                data.format
+            ),
+        ));
+    }
+
+    if rect.min.cmpgt(rect.max).any() || rect.max.cmpgt(image_size).any() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("region {rect:?} is out of bounds of image of size {image_size}"),
+        ));
+    }
+
+    if data.size != rect.size() {
+        return Err(Error::new(
+            ErrorKind::InvalidImage,
+            format!(
+                "image data size {} doesn't match region size {}",
+                data.size,
+                rect.size()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Surrounds an image with a transparent border.
+fn pad_image(image: ImageData, padding: u32) -> ImageData {
+    if padding == 0 {
+        return image;
+    }
+
+    let pixel_size = image.format.pixel_size();
+    let size = image.size + UVec2::splat(padding * 2);
+    let row_size = size.x as usize * pixel_size;
+    let src_row_size = image.size.x as usize * pixel_size;
+    let offset = padding as usize * pixel_size;
+
+    let mut data = vec![0; row_size * size.y as usize];
+    if src_row_size > 0 {
+        for (y, src_row) in image.data.chunks_exact(src_row_size).enumerate() {
+            let start = (y + padding as usize) * row_size + offset;
+            data[start..start + src_row_size].copy_from_slice(src_row);
+        }
+    }
+
+    ImageData {
+        format: image.format,
+        size,
+        data,
+        premultiplied: image.premultiplied,
+    }
+}
+
+impl ImageEntry {
+    fn needs_loading(&self, frame: u64) -> bool {
+        if self.texture.is_some() || self.alloc_id.is_some() {
+            return false;
+        }
+
+        // evicted images are only reloaded once they're drawn again
+        !self.evicted || self.last_used == frame
+    }
+
+    fn requested_size(&self) -> Option<UVec2> {
+        Some(self.requested_size).filter(|v| v.x != 0 && v.y != 0)
+    }
+
+    fn upload(
+        &mut self,
+        mut data: ImageData,
+        requested_size: Option<UVec2>,
+        mipmaps_enabled: bool,
+        atlases: &mut TextureAtlasPool,
+        id_allocator: &mut TextureIdAllocator,
+        commands: &mut Vec<TextureCommand>,
+    ) -> Result<()> {
+        let mipmap_mode = if mipmaps_enabled {
+            self.mipmap_mode
+        } else {
+            MipmapMode::Disabled
+        };
+
+        if requested_size.is_some_and(|v| v.cmpgt(data.size).any()) {
+            self.max_size = data.size;
+        }
+
+        self.evicted = false;
+
+        data.premultiply();
+
+        if data.size.cmpge(TextureCache::MIN_STANDALONE_SIZE).any() {
+            let max_size = atlases.max_texture_size.unwrap_or(u32::MAX);
+            if data.size.max_element() > max_size {
+                return Err(Error::new(
+                    ErrorKind::Gpu,
+                    format!(
+                        "image size {}x{} exceeds the max texture size {max_size}",
+                        data.size.x, data.size.y
+                    ),
+                ));
+            }
+
+            let texture_id = id_allocator.alloc();
+
+            self.texture = Some(texture_id);
+            self.rect = URect::new(UVec2::ZERO, data.size);
+            self.texture_format = Some(data.format);
+            self.texture_mipmap_mode = mipmap_mode;
+
+            commands.push(TextureCommand::CreateStatic {
+                id: texture_id,
+                data,
+                mipmap_mode,
+            });
+
+            return Ok(());
+        }
+
+        let (alloc_id, rect) = atlases
+            .alloc(id_allocator, commands, data, mipmap_mode)
+            .ok_or_else(|| {
+                Error::new(ErrorKind::AtlasAlloc, "failed to allocate image in atlas")
+            })?;
+
+        self.alloc_id = Some(alloc_id);
+        self.rect = rect;
+
+        Ok(())
+    }
+
+    fn standalone_memory_usage(&self) -> Option<u64> {
+        self.texture?;
+        let format = self.texture_format?;
+        Some(texture_bytes(
+            self.rect.size(),
+            format,
+            self.texture_mipmap_mode,
+        ))
+    }
+}
+
+/// Statistics of a [`TextureCache`], for debugging memory usage and atlas
+/// fragmentation.
+#[derive(Debug, Clone, Default)]
+pub struct TextureCacheStats {
+    /// All texture atlases.
+    pub atlases: Vec<AtlasStats>,
+    /// Number of images stored in their own textures rather than an atlas.
+    pub standalone_textures: usize,
+    /// Number of images, loaded or not.
+    pub images: usize,
+    /// Number of cached glyphs, including empty ones.
+    pub glyphs: usize,
+    /// Number of cached gradient ramps.
+    pub gradients: usize,
+    /// Approximate memory usage of all textures in bytes.
+    pub total_bytes: u64,
+    /// Breakdown of texture memory by format.
+    pub formats: Vec<FormatStats>,
+}
+
+/// Statistics of a single texture atlas.
+#[derive(Debug, Clone)]
+pub struct AtlasStats {
+    /// Current texture of the atlas. Changes when the atlas grows.
+    pub texture: TextureId,
+    /// Format of the atlas texture.
+    pub format: ImageFormat,
+    /// Whether the atlas texture has mipmaps.
+    pub mipmap_mode: MipmapMode,
+    /// Size of the atlas texture in pixels.
+    pub size: UVec2,
+    /// Fraction of the atlas area covered by allocations, from 0 to 1.
+    pub occupancy: f32,
+    /// Approximate memory usage in bytes.
+    pub bytes: u64,
+}
+
+/// Texture memory usage of a single image format.
+#[derive(Debug, Clone)]
+pub struct FormatStats {
+    /// Texture format.
+    pub format: ImageFormat,
+    /// Number of atlases of this format.
+    pub atlases: usize,
+    /// Number of standalone textures of this format.
+    pub standalone_textures: usize,
+    /// Approximate memory usage in bytes.
+    pub bytes: u64,
+}
+
+impl TextureCacheStats {
+    fn format_mut(&mut self, format: ImageFormat) -> &mut FormatStats {
+        let idx = match self.formats.iter().position(|v| v.format == format) {
+            Some(idx) => idx,
+            None => {
+                self.formats.push(FormatStats {
+                    format,
+                    atlases: 0,
+                    standalone_textures: 0,
+                    bytes: 0,
+                });
+                self.formats.len() - 1
+            }
+        };
+
+        &mut self.formats[idx]
     }
 }
 
@@ -445,15 +1433,35 @@ impl fmt::Debug for TextureCache {
             .field("images", &self.images)
             .field("images_by_path", &self.images_by_path)
             .field("glyphs", &self.glyphs)
+            .field("gradients", &self.gradients)
             .field("atlases", &self.atlases)
             .field("id_allocator", &self.id_allocator)
             .finish_non_exhaustive()
     }
 }
 
+/// Packing policy of texture atlases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasPolicy {
+    /// Width of the transparent border written around every allocation.
+    /// Linear filtering samples past the edges of a region, which would
+    /// otherwise pick up neighboring glyphs or stale data of freed regions.
+    ///
+    /// Default: `1`.
+    pub padding: u32,
+}
+
+impl Default for AtlasPolicy {
+    fn default() -> Self {
+        AtlasPolicy { padding: 1 }
+    }
+}
+
 #[derive(Debug, Default)]
 struct TextureAtlasPool {
     atlases: SlotMap<AtlasId, TextureAtlas>,
+    policy: AtlasPolicy,
+    max_texture_size: Option<u32>,
 }
 
 impl TextureAtlasPool {
@@ -464,8 +1472,10 @@ impl TextureAtlasPool {
         data: ImageData,
         mipmap_mode: MipmapMode,
     ) -> Option<((AtlasId, AllocId), URect)> {
-        let alloc_size = data.size;
         let alloc_format = data.format;
+        let padding = self.policy.padding;
+        let max_size = self.max_texture_size.unwrap_or(u32::MAX);
+        let alloc_size = data.size + UVec2::splat(padding * 2);
         let mut data = Some(data);
 
         for (atlas_id, atlas) in &mut self.atlases {
@@ -477,15 +1487,23 @@ impl TextureAtlasPool {
                 continue;
             }
 
-            if let Some((alloc_id, rect)) =
-                atlas.alloc(id_allocator, commands, alloc_size, &mut data)
-            {
+            if let Some((alloc_id, rect)) = atlas.alloc(
+                id_allocator,
+                commands,
+                alloc_size,
+                padding,
+                max_size,
+                &mut data,
+            ) {
                 return Some(((atlas_id, alloc_id), rect));
             }
         }
 
-        let new_atlas_size =
-            TextureAtlas::MIN_SIZE.max(alloc_size.max_element().next_power_of_two());
+        let new_atlas_size = (TextureAtlas::MIN_SIZE.min(max_size))
+            .max(alloc_size.max_element().next_power_of_two());
+        if new_atlas_size > max_size {
+            return None;
+        }
 
         let mut new_atlas = TextureAtlas::new(
             id_allocator,
@@ -495,27 +1513,61 @@ impl TextureAtlasPool {
             mipmap_mode,
         );
 
-        let res = new_atlas.alloc(id_allocator, commands, alloc_size, &mut data);
+        let res = new_atlas.alloc(
+            id_allocator,
+            commands,
+            alloc_size,
+            padding,
+            max_size,
+            &mut data,
+        );
         let atlas_id = self.atlases.insert(new_atlas);
 
         res.map(|(alloc_id, rect)| ((atlas_id, alloc_id), rect))
     }
 
-    fn free(&mut self, (atlas_id, alloc_id): (AtlasId, AllocId)) {
-        self.atlases[atlas_id].free(alloc_id);
+    /// Frees an allocation, returning `true` if its atlas became empty.
+    fn free(&mut self, (atlas_id, alloc_id): (AtlasId, AllocId)) -> bool {
+        let atlas = &mut self.atlases[atlas_id];
+        atlas.free(alloc_id);
+        atlas.is_empty()
+    }
+
+    fn atlas_memory_usage(&self, atlas_id: AtlasId) -> u64 {
+        let atlas = &self.atlases[atlas_id];
+        texture_bytes(atlas.size, atlas.format, atlas.mipmap_mode)
     }
 
-    fn cleanup(&mut self, commands: &mut Vec<TextureCommand>) {
+    fn memory_usage(&self) -> u64 {
+        self.atlases
+            .keys()
+            .map(|atlas_id| self.atlas_memory_usage(atlas_id))
+            .sum()
+    }
+
+    fn cleanup(
+        &mut self,
+        id_allocator: &mut TextureIdAllocator,
+        commands: &mut Vec<TextureCommand>,
+    ) {
         self.atlases.retain(|_, atlas| {
             if !atlas.is_empty() {
                 return true;
             }
 
             commands.push(TextureCommand::Free { id: atlas.texture });
+            id_allocator.free(atlas.texture);
 
             false
         });
     }
+
+    fn clear(&mut self, id_allocator: &mut TextureIdAllocator, commands: &mut Vec<TextureCommand>) {
+        for (_, atlas) in self.atlases.drain() {
+            commands.push(TextureCommand::Free { id: atlas.texture });
+            id_allocator.free(atlas.texture);
+        }
+    }
 }
 
 struct TextureAtlas {
@@ -560,9 +1612,11 @@ impl TextureAtlas {
         id_allocator: &mut TextureIdAllocator,
         commands: &mut Vec<TextureCommand>,
         alloc_size: UVec2,
+        padding: u32,
+        max_size: u32,
         data: &mut Option<ImageData>,
     ) -> Option<(AllocId, URect)> {
-        if let Some(res) = self.try_alloc(commands, alloc_size, data) {
+        if let Some(res) = self.try_alloc(commands, alloc_size, padding, data) {
             return Some(res);
         }
 
@@ -571,7 +1625,10 @@ impl TextureAtlas {
             new_size *= 2;
         }
 
-        if new_size.x >= Self::MAX_SIZE || new_size.y >= Self::MAX_SIZE {
+        if new_size.x >= Self::MAX_SIZE
+            || new_size.y >= Self::MAX_SIZE
+            || new_size.max_element() > max_size
+        {
             return None;
         }
 
@@ -592,29 +1649,36 @@ impl TextureAtlas {
             dst_rect: URect::new(UVec2::ZERO, self.size),
         });
 
+        commands.push(TextureCommand::Free { id: src_id });
+        id_allocator.free(src_id);
+
         self.texture = dst_id;
         self.size = new_size;
         self.allocator.grow(size2d(new_size));
 
-        self.try_alloc(commands, alloc_size, data)
+        self.try_alloc(commands, alloc_size, padding, data)
     }
 
+    /// Allocates a region of `size`, including `padding` on each side.
+    /// Returns the region of the data, without padding.
     fn try_alloc(
         &mut self,
         commands: &mut Vec<TextureCommand>,
         size: UVec2,
+        padding: u32,
         data: &mut Option<ImageData>,
     ) -> Option<(AllocId, URect)> {
         let alloc = self.allocator.allocate(size2d(size))?;
-        let rect = URect::new(
-            UVec2::new(alloc.rectangle.min.x as u32, alloc.rectangle.min.y as u32),
-            UVec2::new(alloc.rectangle.max.x as u32, alloc.rectangle.max.y as u32),
-        );
+        let min = UVec2::new(alloc.rectangle.min.x as u32, alloc.rectangle.min.y as u32);
+        let padded_rect = URect::new(min, min + size);
+
+        let data = data.take().unwrap();
+        let rect = URect::new(min + padding, min + padding + data.size);
 
         commands.push(TextureCommand::Write {
             dst_id: self.texture,
-            dst_rect: rect,
-            data: data.take().unwrap(),
+            dst_rect: padded_rect,
+            data: pad_image(data, padding),
         });
 
         Some((alloc.id, rect))
@@ -627,6 +1691,24 @@ impl TextureAtlas {
     fn is_empty(&self) -> bool {
         self.allocator.is_empty()
     }
+
+    fn stats(&self) -> AtlasStats {
+        let mut allocated_area = 0u64;
+        self.allocator.for_each_allocated_rectangle(|_, rect| {
+            allocated_area += rect.area() as u64;
+        });
+
+        let total_area = self.size.x as u64 * self.size.y as u64;
+
+        AtlasStats {
+            texture: self.texture,
+            format: self.format,
+            mipmap_mode: self.mipmap_mode,
+            size: self.size,
+            occupancy: allocated_area as f32 / total_area.max(1) as f32,
+            bytes: texture_bytes(self.size, self.format, self.mipmap_mode),
+        }
+    }
 }
 
 impl fmt::Debug for TextureAtlas {
@@ -639,6 +1721,46 @@ impl fmt::Debug for TextureAtlas {
     }
 }
 
+fn rasterize_gradient(stops: &GradientStops, width: u32) -> ImageData {
+    let mut data = Vec::with_capacity(width as usize * 4);
+
+    for i in 0..width {
+        let t = i as f32 / (width - 1) as f32;
+        let color = stops.sample(t);
+
+        // ramps are stored as non-premultiplied sRGB, the shader premultiplies
+        // after sampling
+        let (r, g, b) = if color.a > 0.0 {
+            (color.r / color.a, color.g / color.a, color.b / color.a)
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        data.extend_from_slice(&[
+            encode_srgb(r),
+            encode_srgb(g),
+            encode_srgb(b),
+            (color.a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]);
+    }
+
+    ImageData {
+        format: ImageFormat::Srgba8,
+        size: UVec2::new(width, 1),
+        data,
+        premultiplied: false,
+    }
+}
+
+fn texture_bytes(size: UVec2, format: ImageFormat, mipmap_mode: MipmapMode) -> u64 {
+    let bytes = size.x as u64 * size.y as u64 * format.pixel_size() as u64;
+    match mipmap_mode {
+        // the full mip chain adds about a third
+        MipmapMode::Enabled => bytes * 4 / 3,
+        MipmapMode::Disabled => bytes,
+    }
+}
+
 fn size2d(size: UVec2) -> guillotiere::Size {
     guillotiere::Size::new(size.x as i32, size.y as i32)
 }
@@ -646,12 +1768,26 @@ fn size2d(size: UVec2) -> guillotiere::Size {
 #[derive(Debug, Default)]
 struct TextureIdAllocator {
     next_id: TextureId,
+    // `None` if ids aren't recycled
+    free_ids: Option<Vec<TextureId>>,
 }
 
 impl TextureIdAllocator {
     fn alloc(&mut self) -> TextureId {
+        if let Some(id) = self.free_ids.as_mut().and_then(Vec::pop) {
+            return id;
+        }
+
         let id = self.next_id;
         self.next_id.0 += 1;
         id
     }
+
+    // the renderer processes commands in order, so a freed id can be reused
+    // by a texture created afterwards
+    fn free(&mut self, id: TextureId) {
+        if let Some(free_ids) = &mut self.free_ids {
+            free_ids.push(id);
+        }
+    }
 }
@@ -0,0 +1,964 @@
+//! Vector export of draw lists.
+//!
+//! Draw lists can be converted to SVG with [`to_svg`], or with an
+//! [`SvgExporter`] for more control over the output. The export is a best
+//! effort translation, some features are approximated:
+//!
+//! - conic gradients are filled with their average color,
+//! - layer tints only apply their alpha, as opacity,
+//! - glyphs are exported as monochrome outlines,
+//! - images added from memory are skipped, as their pixels aren't retained.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use ohm_core::asset::AssetSource;
+use ohm_core::math::{Affine2, Rect, Vec2};
+use ohm_core::text::{FontDatabase, FontId, FontVariations, GlyphId, VariationFaceCache};
+use ohm_core::texture::TextureCache;
+use ohm_core::{
+    BlendMode, Color, Command, CornerRadii, DrawGlyph, DrawLayer, DrawList, DrawRect, Fill,
+    FillImage, FillPath, FillRule, LineCap, LineJoin, PathEvent, StrokePath,
+};
+
+/// Converts a draw list to an SVG document, sized to fit its contents.
+///
+/// Images are embedded as data URIs, loaded from `asset_source` by their asset
+/// paths. Images that fail to load are referenced by their paths instead.
+pub fn to_svg(
+    draw_list: &DrawList,
+    texture_cache: &TextureCache,
+    font_db: &dyn FontDatabase,
+    asset_source: &dyn AssetSource,
+) -> String {
+    SvgExporter::new(texture_cache, font_db)
+        .asset_source(asset_source)
+        .export(draw_list)
+}
+
+/// Configurable SVG exporter.
+pub struct SvgExporter<'a> {
+    texture_cache: &'a TextureCache,
+    font_db: &'a dyn FontDatabase,
+    asset_source: Option<&'a dyn AssetSource>,
+    size: Option<Vec2>,
+}
+
+impl<'a> SvgExporter<'a> {
+    /// Creates an exporter. Glyphs are only exported for fonts already loaded
+    /// into `font_db`.
+    pub fn new(texture_cache: &'a TextureCache, font_db: &'a dyn FontDatabase) -> SvgExporter<'a> {
+        SvgExporter {
+            texture_cache,
+            font_db,
+            asset_source: None,
+            size: None,
+        }
+    }
+
+    /// Embeds images as data URIs, loading them from `asset_source`. By
+    /// default, images are referenced by their asset paths.
+    pub fn asset_source(mut self, asset_source: &'a dyn AssetSource) -> Self {
+        self.asset_source = Some(asset_source);
+        self
+    }
+
+    /// Sets the size of the document, usually the size of the surface. By
+    /// default, the document fits the contents of the draw list.
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Converts a draw list to an SVG document.
+    pub fn export(&self, draw_list: &DrawList) -> String {
+        let transform = draw_list.physical_transform();
+        let view_box = match self.size {
+            Some(size) => Rect::new(Vec2::ZERO, size),
+            None => commands_bounds(draw_list.commands, transform)
+                .unwrap_or(Rect::ZERO)
+                .union(Rect::ZERO),
+        };
+
+        let mut writer = SvgWriter {
+            exporter: self,
+            defs: String::new(),
+            body: String::new(),
+            next_id: 0,
+            glyphs: HashMap::new(),
+            faces: VariationFaceCache::new(),
+            image_hrefs: HashMap::new(),
+        };
+
+        if transform != Affine2::IDENTITY {
+            let attrs = transform_attr(transform);
+            let _ = write!(writer.body, "<g{attrs}>");
+            writer.commands(draw_list.commands);
+            writer.body.push_str("</g>");
+        } else {
+            writer.commands(draw_list.commands);
+        }
+
+        let size = view_box.size();
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+            num(size.x),
+            num(size.y),
+            num(view_box.min.x),
+            num(view_box.min.y),
+            num(size.x),
+            num(size.y),
+        );
+
+        if !writer.defs.is_empty() {
+            out.push_str("<defs>");
+            out.push_str(&writer.defs);
+            out.push_str("</defs>");
+        }
+
+        out.push_str(&writer.body);
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphDefKey {
+    font: FontId,
+    glyph: GlyphId,
+    size: u32,
+    variations: FontVariations,
+}
+
+struct SvgWriter<'e, 'a> {
+    exporter: &'e SvgExporter<'a>,
+    defs: String,
+    body: String,
+    next_id: usize,
+    glyphs: HashMap<GlyphDefKey, Option<usize>>,
+    faces: VariationFaceCache,
+    image_hrefs: HashMap<ohm_core::image::ImageId, Option<String>>,
+}
+
+impl SvgWriter<'_, '_> {
+    fn alloc_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn commands(&mut self, commands: &[Command]) {
+        for command in commands {
+            match command {
+                Command::ClearRect(rect) => {
+                    let d = rounded_rect(rect.pos, rect.size, CornerRadii::default());
+                    let fill = color_attrs("fill", rect.color);
+                    let _ = write!(self.body, r#"<path d="{d}"{fill}/>"#);
+                }
+                Command::DrawRect(rect) => self.rect(rect),
+                Command::DrawGlyph(glyph) => self.glyph(glyph),
+                Command::DrawGlyphGrid(grid) => {
+                    for cell in grid.cells {
+                        if let Some(&glyph) = grid.glyphs.get(cell.glyph as usize) {
+                            self.glyph(&grid.draw_glyph(cell.pos, glyph, cell.color));
+                        }
+                    }
+                }
+                Command::DrawLayer(layer) => self.layer(layer),
+                Command::FillPath(path) => self.fill_path(path),
+                Command::StrokePath(path) => self.stroke_path(path),
+            }
+        }
+    }
+
+    fn rect(&mut self, rect: &DrawRect) {
+        if let Some(shadow) = rect.shadow.filter(|s| s.color.a > 0.0) {
+            let spread = Vec2::splat(shadow.spread_radius);
+            let radii = offset_radii(rect.corner_radii, shadow.spread_radius);
+            let d = rounded_rect(
+                rect.pos + shadow.offset - spread,
+                rect.size + spread * 2.0,
+                radii,
+            );
+            let fill = color_attrs("fill", shadow.color);
+
+            let mut filter = String::new();
+            if shadow.blur_radius > 0.0 {
+                let id = self.alloc_id();
+                let _ = write!(
+                    self.defs,
+                    r#"<filter id="f{id}" x="-50%" y="-50%" width="200%" height="200%"><feGaussianBlur stdDeviation="{}"/></filter>"#,
+                    num(shadow.blur_radius / 2.0),
+                );
+                filter = format!(r#" filter="url(#f{id})""#);
+            }
+
+            let _ = write!(self.body, r#"<path d="{d}"{fill}{filter}/>"#);
+        }
+
+        let d = rounded_rect(rect.pos, rect.size, rect.corner_radii);
+        let fill = self.paint_attrs(
+            "fill",
+            &rect.fill,
+            Rect::new(rect.pos, rect.pos + rect.size),
+        );
+        let _ = write!(self.body, r#"<path d="{d}"{fill}/>"#);
+
+        if let Some(border) = rect.border.filter(|b| b.width > 0.0) {
+            // borders are drawn inside of the rect
+            let half = border.width / 2.0;
+            let radii = offset_radii(rect.corner_radii, -half);
+            let d = rounded_rect(
+                rect.pos + Vec2::splat(half),
+                rect.size - Vec2::splat(border.width),
+                radii,
+            );
+            let stroke = color_attrs("stroke", border.color);
+            let _ = write!(
+                self.body,
+                r#"<path d="{d}" fill="none"{stroke} stroke-width="{}"/>"#,
+                num(border.width),
+            );
+        }
+    }
+
+    fn glyph(&mut self, glyph: &DrawGlyph) {
+        let key = GlyphDefKey {
+            font: glyph.font,
+            glyph: glyph.glyph,
+            size: glyph.size.to_bits(),
+            variations: glyph.variations,
+        };
+
+        let id = match self.glyphs.get(&key) {
+            Some(&id) => id,
+            None => {
+                let id = self.glyph_def(glyph);
+                self.glyphs.insert(key, id);
+                id
+            }
+        };
+
+        let Some(id) = id else {
+            return;
+        };
+
+        let mut fill = color_attrs("fill", glyph.color);
+
+        // dilated glyphs are outlined by a round stroke of twice the radius
+        if let Some(radius) = glyph.dilation.filter(|&v| v > 0.0) {
+            fill.push_str(&color_attrs("stroke", glyph.color));
+            let _ = write!(
+                fill,
+                r#" stroke-width="{}" stroke-linejoin="round""#,
+                num(radius * 2.0)
+            );
+        }
+
+        let _ = write!(
+            self.body,
+            r##"<use href="#g{id}" x="{}" y="{}"{fill}/>"##,
+            num(glyph.pos.x),
+            num(glyph.pos.y),
+        );
+    }
+
+    fn glyph_def(&mut self, glyph: &DrawGlyph) -> Option<usize> {
+        let exporter = self.exporter;
+        let font = exporter.font_db.get(glyph.font)?;
+        let face = self.faces.get(font, &glyph.variations);
+        let scale = glyph.size / f32::from(font.metrics().units_per_em.max(1));
+
+        let mut outliner = Outliner {
+            d: String::new(),
+            // glyph outlines have the Y axis pointing up
+            transform: Affine2::from_scale(Vec2::new(scale, -scale)),
+        };
+
+        face.outline_glyph(glyph.glyph, &mut outliner)?;
+
+        let id = self.alloc_id();
+        let _ = write!(
+            self.defs,
+            r#"<path id="g{id}" d="{}"/>"#,
+            outliner.d.trim_end()
+        );
+        Some(id)
+    }
+
+    fn layer(&mut self, layer: &DrawLayer) {
+        let mut attrs = String::new();
+
+        if layer.transform != Affine2::IDENTITY {
+            attrs.push_str(&transform_attr(layer.transform));
+        }
+
+        let opacity = layer.tint.a * layer.opacity;
+        if opacity < 1.0 {
+            let _ = write!(attrs, r#" opacity="{}""#, num(opacity.max(0.0)));
+        }
+
+        let blend_mode = match layer.blend_mode {
+            BlendMode::Normal => None,
+            BlendMode::Multiply => Some("multiply"),
+            BlendMode::Screen => Some("screen"),
+            BlendMode::Add => Some("plus-lighter"),
+            BlendMode::Lighten => Some("lighten"),
+            BlendMode::Darken => Some("darken"),
+        };
+
+        if let Some(blend_mode) = blend_mode {
+            let _ = write!(attrs, r#" style="mix-blend-mode:{blend_mode}""#);
+        }
+
+        // filters apply before the clip-path of the same element, but blurs
+        // and shadows spread past the scissor, so they get their own groups
+        let mut nested = Vec::new();
+
+        let mut filter = String::new();
+
+        if layer.blur > 0.0 {
+            let _ = write!(
+                filter,
+                r#"<feGaussianBlur stdDeviation="{}"/>"#,
+                num(layer.blur / 2.0)
+            );
+        }
+
+        if let Some(shadow) = layer.shadow.filter(|s| s.color.a > 0.0) {
+            let color = shadow.color;
+            let [r, g, b] = [color.r, color.g, color.b].map(|v| encode_srgb(v / color.a));
+            let _ = write!(
+                filter,
+                r##"<feDropShadow dx="{}" dy="{}" stdDeviation="{}" flood-color="#{r:02x}{g:02x}{b:02x}" flood-opacity="{}"/>"##,
+                num(shadow.offset.x),
+                num(shadow.offset.y),
+                num(shadow.blur_radius.max(0.0) / 2.0),
+                num(color.a.min(1.0)),
+            );
+        }
+
+        if !filter.is_empty() {
+            let id = self.alloc_id();
+            let _ = write!(
+                self.defs,
+                r#"<filter id="f{id}" x="-50%" y="-50%" width="200%" height="200%">{filter}</filter>"#
+            );
+            nested.push(format!(r#" filter="url(#f{id})""#));
+        }
+
+        if let Some(scissor) = &layer.scissor {
+            let id = self.alloc_id();
+            let d = rounded_rect(scissor.pos, scissor.size, scissor.corner_radii);
+            let _ = write!(
+                self.defs,
+                r#"<clipPath id="c{id}"><path d="{d}"/></clipPath>"#
+            );
+
+            if nested.is_empty() {
+                let _ = write!(attrs, r#" clip-path="url(#c{id})""#);
+            } else {
+                nested.push(format!(r#" clip-path="url(#c{id})""#));
+            }
+        }
+
+        let _ = write!(self.body, "<g{attrs}>");
+
+        for attrs in &nested {
+            let _ = write!(self.body, "<g{attrs}>");
+        }
+
+        // a nested group, as the scissor already uses the clip-path attribute
+        if let Some(clip_path) = layer.clip_path {
+            let id = self.alloc_id();
+            let d = path_data(clip_path.path.events(), Vec2::ZERO);
+            let clip_rule = fill_rule(clip_path.options.fill_rule);
+            let _ = write!(
+                self.defs,
+                r#"<clipPath id="c{id}"><path d="{d}" clip-rule="{clip_rule}"/></clipPath>"#
+            );
+            let _ = write!(self.body, r#"<g clip-path="url(#c{id})">"#);
+        }
+
+        self.commands(layer.commands);
+
+        if layer.clip_path.is_some() {
+            self.body.push_str("</g>");
+        }
+
+        for _ in &nested {
+            self.body.push_str("</g>");
+        }
+
+        self.body.push_str("</g>");
+    }
+
+    fn fill_path(&mut self, path: &FillPath) {
+        let Some(bounds) = path_bounds(path.path.events(), path.pos) else {
+            return;
+        };
+
+        let d = path_data(path.path.events(), path.pos);
+        let fill = self.paint_attrs("fill", &path.fill, bounds);
+        let fill_rule = fill_rule(path.options.fill_rule);
+
+        let _ = write!(
+            self.body,
+            r#"<path d="{d}"{fill} fill-rule="{fill_rule}"/>"#
+        );
+    }
+
+    fn stroke_path(&mut self, path: &StrokePath) {
+        let Some(bounds) = path_bounds(path.path.events(), path.pos) else {
+            return;
+        };
+
+        let options = &path.options;
+        let half = Vec2::splat(options.line_width / 2.0);
+        let bounds = Rect::new(bounds.min - half, bounds.max + half);
+
+        let d = path_data(path.path.events(), path.pos);
+        let stroke = self.paint_attrs("stroke", &path.fill, bounds);
+
+        let line_cap = match options.line_cap {
+            LineCap::Butt => "butt",
+            LineCap::Square => "square",
+            LineCap::Round => "round",
+        };
+
+        let line_join = match options.line_join {
+            LineJoin::Miter => "miter",
+            LineJoin::MiterClip => "miter-clip",
+            LineJoin::Round => "round",
+            LineJoin::Bevel => "bevel",
+        };
+
+        let _ = write!(
+            self.body,
+            r#"<path d="{d}" fill="none"{stroke} stroke-width="{}" stroke-linecap="{line_cap}" stroke-linejoin="{line_join}" stroke-miterlimit="{}"/>"#,
+            num(options.line_width),
+            num(options.mitter_limit.max(1.0)),
+        );
+    }
+
+    /// Returns fill or stroke attributes of a [`Fill`] covering `area`.
+    fn paint_attrs(&mut self, attr: &str, fill: &Fill, area: Rect) -> String {
+        match fill {
+            Fill::Solid(color) => color_attrs(attr, *color),
+            Fill::ConicGradient(gradient) => {
+                const SAMPLES: usize = 16;
+
+                let sum = (0..SAMPLES)
+                    .map(|i| gradient.stops.sample((i as f32 + 0.5) / SAMPLES as f32))
+                    .fold(Color::TRANSPAENT, |acc, c| {
+                        Color::rgba(acc.r + c.r, acc.g + c.g, acc.b + c.b, acc.a + c.a)
+                    });
+
+                let n = SAMPLES as f32;
+                color_attrs(
+                    attr,
+                    Color::rgba(sum.r / n, sum.g / n, sum.b / n, sum.a / n),
+                )
+            }
+            Fill::Image(image) => match self.image_pattern(image, area) {
+                Some(id) => format!(r#" {attr}="url(#p{id})""#),
+                None => format!(r#" {attr}="none""#),
+            },
+        }
+    }
+
+    fn image_pattern(&mut self, fill: &FillImage, area: Rect) -> Option<usize> {
+        let href = self.image_href(fill)?;
+
+        // size of the whole image, when stretched across the area
+        let clip = fill.clip_rect.unwrap_or(Rect::new(Vec2::ZERO, Vec2::ONE));
+        let mut image_size = area.size() / clip.size();
+        let mut image_pos = -clip.min * image_size;
+        let mut tile_size = area.size();
+
+        let (repeat_x, repeat_y) = fill.repeat.axes();
+        if repeat_x || repeat_y {
+            let pixel_size = self.exporter.texture_cache.get_image_size(fill.image)?;
+            let tile = pixel_size.as_vec2() * fill.tile_scale;
+
+            if repeat_x {
+                image_size.x = tile.x;
+                image_pos.x = 0.0;
+                tile_size.x = tile.x;
+            }
+
+            if repeat_y {
+                image_size.y = tile.y;
+                image_pos.y = 0.0;
+                tile_size.y = tile.y;
+            }
+        }
+
+        if tile_size.cmple(Vec2::ZERO).any() || !image_size.is_finite() {
+            return None;
+        }
+
+        let mut attrs = String::new();
+        if fill.tint.a < 1.0 {
+            let _ = write!(attrs, r#" opacity="{}""#, num(fill.tint.a.max(0.0)));
+        }
+
+        let id = self.alloc_id();
+        let _ = write!(
+            self.defs,
+            r#"<pattern id="p{id}" patternUnits="userSpaceOnUse" x="{}" y="{}" width="{}" height="{}"><image href="{href}" x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none"{attrs}/></pattern>"#,
+            num(area.min.x),
+            num(area.min.y),
+            num(tile_size.x),
+            num(tile_size.y),
+            num(image_pos.x),
+            num(image_pos.y),
+            num(image_size.x),
+            num(image_size.y),
+        );
+
+        Some(id)
+    }
+
+    fn image_href(&mut self, fill: &FillImage) -> Option<String> {
+        if let Some(href) = self.image_hrefs.get(&fill.image) {
+            return href.clone();
+        }
+
+        let href = self
+            .exporter
+            .texture_cache
+            .get_image_path(fill.image)
+            .map(|path| {
+                let data = self
+                    .exporter
+                    .asset_source
+                    .and_then(|source| source.load(path.as_borrowed()).ok());
+
+                match data {
+                    Some(data) => {
+                        let mime = image_mime_type(path.extension());
+                        format!("data:{mime};base64,{}", base64(&data))
+                    }
+                    None => escape(path.as_ref()),
+                }
+            });
+
+        self.image_hrefs.insert(fill.image, href.clone());
+        href
+    }
+}
+
+struct Outliner {
+    d: String,
+    transform: Affine2,
+}
+
+impl Outliner {
+    fn point(&mut self, x: f32, y: f32) {
+        let p = self.transform.transform_point2(Vec2::new(x, y));
+        let _ = write!(self.d, "{} {} ", num(p.x), num(p.y));
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outliner {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.d.push('M');
+        self.point(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.d.push('L');
+        self.point(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.d.push('Q');
+        self.point(x1, y1);
+        self.point(x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.d.push('C');
+        self.point(x1, y1);
+        self.point(x2, y2);
+        self.point(x, y);
+    }
+
+    fn close(&mut self) {
+        self.d.push_str("Z ");
+    }
+}
+
+fn transform_attr(transform: Affine2) -> String {
+    let [a, b, c, d, e, f] = transform.to_cols_array();
+    format!(
+        r#" transform="matrix({} {} {} {} {} {})""#,
+        num(a),
+        num(b),
+        num(c),
+        num(d),
+        num(e),
+        num(f),
+    )
+}
+
+fn fill_rule(fill_rule: FillRule) -> &'static str {
+    match fill_rule {
+        FillRule::EvenOdd => "evenodd",
+        FillRule::NonZero => "nonzero",
+    }
+}
+
+fn path_data(events: &[PathEvent], offset: Vec2) -> String {
+    let mut d = String::new();
+    let point = |d: &mut String, p: Vec2| {
+        let p = p + offset;
+        let _ = write!(d, "{} {} ", num(p.x), num(p.y));
+    };
+
+    for event in events {
+        match *event {
+            PathEvent::MoveTo { point: p } => {
+                d.push('M');
+                point(&mut d, p);
+            }
+            PathEvent::LineTo { point: p } => {
+                d.push('L');
+                point(&mut d, p);
+            }
+            PathEvent::QuadTo { control, point: p } => {
+                d.push('Q');
+                point(&mut d, control);
+                point(&mut d, p);
+            }
+            PathEvent::CubicTo { control, point: p } => {
+                d.push('C');
+                point(&mut d, control[0]);
+                point(&mut d, control[1]);
+                point(&mut d, p);
+            }
+            PathEvent::Close => d.push_str("Z "),
+        }
+    }
+
+    d.truncate(d.trim_end().len());
+    d
+}
+
+/// Bounds of all path points, including control points.
+fn path_bounds(events: &[PathEvent], offset: Vec2) -> Option<Rect> {
+    events
+        .iter()
+        .flat_map(|event| match *event {
+            PathEvent::MoveTo { point } | PathEvent::LineTo { point } => vec![point],
+            PathEvent::QuadTo { control, point } => vec![control, point],
+            PathEvent::CubicTo { control, point } => vec![control[0], control[1], point],
+            PathEvent::Close => vec![],
+        })
+        .map(|p| Rect::new(p + offset, p + offset))
+        .reduce(Rect::union)
+}
+
+// approximate, glyph outlines aren't measured
+fn glyph_bounds(glyph: &DrawGlyph) -> Rect {
+    let dilation = Vec2::splat(glyph.dilation.unwrap_or(0.0).max(0.0));
+    Rect::new(
+        glyph.pos - Vec2::new(0.0, glyph.size) - dilation,
+        glyph.pos + Vec2::new(glyph.size, glyph.size * 0.25) + dilation,
+    )
+}
+
+fn commands_bounds(commands: &[Command], transform: Affine2) -> Option<Rect> {
+    commands
+        .iter()
+        .filter_map(|command| {
+            let rect = match command {
+                Command::ClearRect(rect) => Rect::new(rect.pos, rect.pos + rect.size),
+                Command::DrawRect(rect) => {
+                    let mut bounds = Rect::new(rect.pos, rect.pos + rect.size);
+                    if let Some(shadow) = rect.shadow {
+                        let extent = Vec2::splat(shadow.spread_radius + shadow.blur_radius);
+                        let min = rect.pos + shadow.offset - extent;
+                        let max = rect.pos + rect.size + shadow.offset + extent;
+                        bounds = bounds.union(Rect::new(min, max));
+                    }
+                    bounds
+                }
+                Command::DrawGlyph(glyph) => glyph_bounds(glyph),
+                Command::DrawGlyphGrid(grid) => grid
+                    .cells
+                    .iter()
+                    .filter_map(|cell| {
+                        let &glyph = grid.glyphs.get(cell.glyph as usize)?;
+                        Some(glyph_bounds(&grid.draw_glyph(cell.pos, glyph, cell.color)))
+                    })
+                    .reduce(Rect::union)?,
+                Command::DrawLayer(layer) if layer.blur <= 0.0 && layer.shadow.is_none() => {
+                    return commands_bounds(layer.commands, transform * layer.transform);
+                }
+                Command::DrawLayer(layer) => {
+                    let rect = commands_bounds(layer.commands, Affine2::IDENTITY)?;
+                    let blur = Vec2::splat(layer.blur.max(0.0));
+                    let mut rect = Rect::new(rect.min - blur, rect.max + blur);
+                    if let Some(shadow) = layer.shadow {
+                        let extent = Vec2::splat(shadow.blur_radius.max(0.0));
+                        let min = rect.min + shadow.offset - extent;
+                        let max = rect.max + shadow.offset + extent;
+                        rect = rect.union(Rect::new(min, max));
+                    }
+                    return Some(rect.transform(&(transform * layer.transform)));
+                }
+                Command::FillPath(path) => path_bounds(path.path.events(), path.pos)?,
+                Command::StrokePath(path) => {
+                    let half = Vec2::splat(path.options.line_width / 2.0);
+                    let rect = path_bounds(path.path.events(), path.pos)?;
+                    Rect::new(rect.min - half, rect.max + half)
+                }
+            };
+
+            Some(rect.transform(&transform))
+        })
+        .reduce(Rect::union)
+}
+
+fn rounded_rect(pos: Vec2, size: Vec2, radii: CornerRadii) -> String {
+    let size = size.max(Vec2::ZERO);
+    let max_radius = size.min_element() / 2.0;
+    let [tl, tr, br, bl] = [
+        radii.top_left,
+        radii.top_right,
+        radii.bottom_right,
+        radii.bottom_left,
+    ]
+    .map(|r| r.clamp(0.0, max_radius));
+
+    let (x0, y0) = (pos.x, pos.y);
+    let (x1, y1) = (pos.x + size.x, pos.y + size.y);
+
+    let mut d = format!("M{} {} ", num(x0 + tl), num(y0));
+    let mut corner = |horizontal: bool, to: f32, r: f32, x: f32, y: f32| {
+        let dir = if horizontal { 'H' } else { 'V' };
+        let r = num(r);
+        let _ = write!(d, "{dir}{} A{r} {r} 0 0 1 {} {} ", num(to), num(x), num(y));
+    };
+
+    corner(true, x1 - tr, tr, x1, y0 + tr);
+    corner(false, y1 - br, br, x1 - br, y1);
+    corner(true, x0 + bl, bl, x0, y1 - bl);
+    corner(false, y0 + tl, tl, x0 + tl, y0);
+
+    d.push('Z');
+    d
+}
+
+fn offset_radii(radii: CornerRadii, offset: f32) -> CornerRadii {
+    CornerRadii {
+        top_left: (radii.top_left + offset).max(0.0),
+        top_right: (radii.top_right + offset).max(0.0),
+        bottom_right: (radii.bottom_right + offset).max(0.0),
+        bottom_left: (radii.bottom_left + offset).max(0.0),
+    }
+}
+
+/// Returns attributes for a color, converted to non-premultiplied sRGB.
+fn color_attrs(attr: &str, color: Color) -> String {
+    if color.a <= 0.0 {
+        return format!(r#" {attr}="none""#);
+    }
+
+    let [r, g, b] = [color.r, color.g, color.b].map(|v| encode_srgb(v / color.a));
+    let mut out = format!(r##" {attr}="#{r:02x}{g:02x}{b:02x}""##);
+
+    if color.a < 1.0 {
+        let _ = write!(out, r#" {attr}-opacity="{}""#, num(color.a));
+    }
+
+    out
+}
+
+fn encode_srgb(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+/// Formats a number with at most three decimal places.
+fn num(v: f32) -> String {
+    let v = (v * 1000.0).round() / 1000.0;
+    if v == 0.0 || !v.is_finite() {
+        "0".into()
+    } else {
+        v.to_string()
+    }
+}
+
+fn escape(str: &str) -> String {
+    let mut out = String::with_capacity(str.len());
+    for c in str.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn image_mime_type(extension: Option<&str>) -> &'static str {
+    match extension.map(|v| v.to_ascii_lowercase()).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("bmp") => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use ohm_core::asset::AssetPath;
+    use ohm_core::renderer::SurfaceId;
+    use ohm_core::text::DummyFontDatabase;
+    use ohm_core::texture::MipmapMode;
+    use ohm_core::{RepeatMode, Result, SamplerMode};
+
+    use super::*;
+
+    #[test]
+    fn base64_matches_rfc4648() {
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (input, output) in cases {
+            assert_eq!(base64(input.as_bytes()), output);
+        }
+
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn escape_xml_special_chars() {
+        assert_eq!(escape("file:a.png"), "file:a.png");
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn rounded_rect_paths() {
+        let pos = Vec2::new(1.0, 2.0);
+        let size = Vec2::new(10.0, 4.0);
+
+        assert_eq!(
+            rounded_rect(pos, size, CornerRadii::default()),
+            "M1 2 H11 A0 0 0 0 1 11 2 V6 A0 0 0 0 1 11 6 H1 A0 0 0 0 1 1 6 \
+             V2 A0 0 0 0 1 1 2 Z"
+        );
+
+        // radii are clamped to half of the shorter side
+        assert_eq!(
+            rounded_rect(pos, size, CornerRadii::new_equal(5.0)),
+            "M3 2 H9 A2 2 0 0 1 11 4 V4 A2 2 0 0 1 9 6 H3 A2 2 0 0 1 1 4 \
+             V4 A2 2 0 0 1 3 2 Z"
+        );
+    }
+
+    #[test]
+    fn num_rounds_to_three_decimals() {
+        assert_eq!(num(1.0), "1");
+        assert_eq!(num(0.12345), "0.123");
+        assert_eq!(num(-0.0001), "0");
+        assert_eq!(num(f32::NAN), "0");
+    }
+
+    struct TestAssetSource;
+
+    impl AssetSource for TestAssetSource {
+        fn load(&self, path: AssetPath<'_>) -> Result<Vec<u8>> {
+            assert_eq!(path.as_ref(), "file:image.png");
+            Ok(b"foo".to_vec())
+        }
+    }
+
+    #[test]
+    fn to_svg_embeds_images() {
+        let mut texture_cache = TextureCache::new();
+        let image = texture_cache.add_image_from_path("file:image.png", MipmapMode::Disabled);
+
+        let commands = [Command::DrawRect(DrawRect {
+            pos: Vec2::ZERO,
+            size: Vec2::splat(16.0),
+            fill: Fill::Image(FillImage {
+                image: image.id(),
+                tint: Color::WHITE,
+                clip_rect: None,
+                sampler: SamplerMode::Linear,
+                repeat: RepeatMode::Clamp,
+                tile_scale: Vec2::ONE,
+            }),
+            corner_radii: CornerRadii::default(),
+            border: None,
+            shadow: None,
+            pixel_snap: false,
+        })];
+
+        let draw_list = DrawList {
+            surface: SurfaceId::default(),
+            commands: &commands,
+            opaque: false,
+            transform: Affine2::IDENTITY,
+            scale: 1.0,
+        };
+
+        let svg = to_svg(
+            &draw_list,
+            &texture_cache,
+            &DummyFontDatabase,
+            &TestAssetSource,
+        );
+        assert!(svg.contains(r#"href="data:image/png;base64,Zm9v""#));
+    }
+}
@@ -1,14 +1,14 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use ohm_core::text::{
-    FontAttrs, FontDatabase, FontFace, FontFamily, FontId, FontStyle, FontWidth,
-};
+use ohm_core::text::{FontAttrs, FontDatabase, FontFace, FontFamily, FontId, FontStyle, FontWidth};
 use ohm_core::{Error, ErrorKind, Result};
 
 #[derive(Debug)]
 pub struct SystemFontDatabase {
     db: fontdb::Database,
     loaded_faces: HashMap<FontId, FontFace>,
+    last_resort_font: Option<FontId>,
 }
 
 impl SystemFontDatabase {
@@ -18,6 +18,55 @@ impl SystemFontDatabase {
         SystemFontDatabase {
             db,
             loaded_faces: HashMap::new(),
+            last_resort_font: None,
+        }
+    }
+
+    /// Registers every face of a font file or collection, returning their ids
+    /// in face index order.
+    pub fn add_font_data(
+        &mut self,
+        data: impl AsRef<[u8]> + Send + Sync + 'static,
+    ) -> Result<Vec<FontId>> {
+        let data: Arc<dyn AsRef<[u8]> + Send + Sync> = Arc::new(data);
+        let source = fontdb::Source::Binary(Arc::clone(&data));
+        let db_ids = self.db.load_font_source(source).to_vec();
+
+        let mut face_ids = HashMap::new();
+        for &id in &db_ids {
+            if let Some(face) = self.db.face(id) {
+                face_ids.insert(face.index, fontdb_id_to_u64(id));
+            }
+        }
+
+        let faces = FontFace::parse_collection(data, |index| {
+            face_ids.get(&index).copied().unwrap_or(FontId::DUMMY)
+        });
+
+        // fontdb skips faces it can't parse
+        let faces = match faces {
+            Ok(faces) if faces.iter().any(|face| face.id() == FontId::DUMMY) => Err(Error::new(
+                ErrorKind::InvalidFont,
+                "Failed to parse font face",
+            )),
+            faces => faces,
+        };
+
+        match faces {
+            Ok(faces) => Ok(faces
+                .into_iter()
+                .map(|face| {
+                    let id = face.id();
+                    self.loaded_faces.insert(id, face);
+                    id
+                })
+                .collect()),
+            Err(e) => {
+                for id in db_ids {
+                    self.db.remove_face(id);
+                }
+                Err(e)
+            }
         }
     }
 }
@@ -63,6 +112,61 @@ impl FontDatabase for SystemFontDatabase {
             self.load(id)
         }
     }
+
+    fn add_face(&mut self, data: Arc<dyn AsRef<[u8]> + Send + Sync>, index: u32) -> Result<FontId> {
+        let source = fontdb::Source::Binary(Arc::clone(&data));
+        let mut face_id = None;
+
+        // fontdb registers every face of a collection, keep only the requested one
+        for id in self.db.load_font_source(source) {
+            if face_id.is_none() && self.db.face(id).is_some_and(|face| face.index == index) {
+                face_id = Some(id);
+            } else {
+                self.db.remove_face(id);
+            }
+        }
+
+        let face_id = face_id
+            .ok_or_else(|| Error::new(ErrorKind::InvalidFont, "Failed to parse font face"))?;
+
+        let id = fontdb_id_to_u64(face_id);
+        match FontFace::new(id, data, index) {
+            Ok(face) => {
+                self.loaded_faces.insert(id, face);
+                Ok(id)
+            }
+            Err(e) => {
+                self.db.remove_face(face_id);
+                Err(e)
+            }
+        }
+    }
+
+    fn invalidate_font(&mut self, font: FontId) {
+        self.loaded_faces.remove(&font);
+    }
+
+    fn families(&self) -> Vec<String> {
+        // fontdb puts the english typographic family name first
+        let mut families = self
+            .db
+            .faces()
+            .filter_map(|face| face.families.first())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        families.sort_by_cached_key(|name| name.to_lowercase());
+        families.dedup_by(|a, b| a.to_lowercase() == b.to_lowercase());
+        families
+    }
+
+    fn set_last_resort_font(&mut self, font: Option<FontId>) {
+        self.last_resort_font = font;
+    }
+
+    fn last_resort_font(&self) -> Option<FontId> {
+        self.last_resort_font
+    }
 }
 
 fn fontdb_family(family: &FontFamily) -> fontdb::Family<'_> {
@@ -4,10 +4,14 @@ use std::sync::Arc;
 
 use freetype::bitmap::PixelMode;
 use freetype::face::LoadFlag;
-use freetype::{Face, Library, Matrix, Vector};
+use freetype::ffi::{FT_Face, FT_Fixed, FT_Set_Var_Design_Coordinates, FT_UInt};
+use freetype::{Face, FtResult, LcdFilter, Library, Matrix, Vector};
 use ohm_core::image::{ImageData, ImageFormat};
 use ohm_core::math::{UVec2, Vec2};
-use ohm_core::text::{FontFace, FontId, GlyphId, RasterizedGlyph, Rasterizer, SubpixelBin};
+use ohm_core::text::{
+    AntialiasMode, FontFace, FontId, FontVariations, GlyphId, RasterizedGlyph, Rasterizer,
+    SubpixelBin,
+};
 
 struct FaceBuffer(Arc<dyn AsRef<[u8]> + Send + Sync + 'static>);
 
@@ -18,15 +22,25 @@ impl Borrow<[u8]> for FaceBuffer {
 }
 
 pub struct FreetypeRasterizer {
-    faces: HashMap<FontId, Face<FaceBuffer>>,
+    faces: HashMap<(FontId, FontVariations), Face<FaceBuffer>>,
     library: Option<Library>,
+    antialias_mode: AntialiasMode,
 }
 
 impl FreetypeRasterizer {
     pub fn new() -> FreetypeRasterizer {
+        let library = Library::init().ok();
+
+        if let Some(library) = &library {
+            // reduces color fringes of subpixel glyphs. fails if freetype is
+            // built without it, in which case unfiltered output is used
+            let _ = library.set_lcd_filter(LcdFilter::LcdFilterDefault);
+        }
+
         FreetypeRasterizer {
             faces: HashMap::default(),
-            library: Library::init().ok(),
+            library,
+            antialias_mode: AntialiasMode::Grayscale,
         }
     }
 }
@@ -38,11 +52,12 @@ impl Rasterizer for FreetypeRasterizer {
         glyph_id: GlyphId,
         size: f32,
         subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
     ) -> Option<RasterizedGlyph> {
-        let face = match self.faces.entry(font_face.id()) {
+        let face = match self.faces.entry((font_face.id(), *variations)) {
             hash_map::Entry::Occupied(entry) => entry.into_mut(),
             hash_map::Entry::Vacant(entry) => {
-                let face = self
+                let mut face = self
                     .library
                     .as_mut()?
                     .new_memory_face2(
@@ -50,6 +65,8 @@ impl Rasterizer for FreetypeRasterizer {
                         font_face.face_index() as isize,
                     )
                     .ok()?;
+                // faces are only cached once the variations are applied
+                set_variations(&mut face, font_face, variations).ok()?;
                 entry.insert(face)
             }
         };
@@ -71,47 +88,118 @@ impl Rasterizer for FreetypeRasterizer {
         };
 
         face.set_transform(&mut matrix, &mut delta);
-        face.load_glyph(glyph_id.0 as u32, LoadFlag::RENDER).ok()?;
+        let flags = if self.antialias_mode.is_subpixel() {
+            LoadFlag::RENDER | LoadFlag::TARGET_LCD
+        } else {
+            LoadFlag::RENDER
+        };
+
+        face.load_glyph(glyph_id.0 as u32, flags).ok()?;
 
         let glyph = face.glyph();
         let bitmap = glyph.bitmap();
 
         let offset = Vec2::new(glyph.bitmap_left() as f32, -glyph.bitmap_top() as f32);
 
-        let (format, pixel_size) = match bitmap.pixel_mode().ok()? {
-            PixelMode::Gray => (ImageFormat::Gray8, 1),
-            _ => return None,
-        };
-
+        let pixel_mode = bitmap.pixel_mode().ok()?;
         let pitch = bitmap.pitch().unsigned_abs() as usize;
-        let width = bitmap.width() as usize;
         let height = bitmap.rows() as usize;
 
+        // LCD bitmaps have three horizontal samples per pixel
+        let (format, width) = match pixel_mode {
+            PixelMode::Gray => (ImageFormat::Gray8, bitmap.width() as usize),
+            PixelMode::Lcd => (ImageFormat::Rgba8, bitmap.width() as usize / 3),
+            _ => return None,
+        };
+
         if pitch == 0 || width == 0 || height == 0 {
             return None;
         }
 
-        let data = bitmap
+        let bgr = self.antialias_mode == AntialiasMode::SubpixelBgr;
+        let mut rows = bitmap
             .buffer()
             .chunks(pitch)
-            .map(|chunk| chunk[..width * pixel_size].iter().copied());
-
-        let reverse = bitmap.pitch() < 0;
+            .map(|chunk| match format {
+                ImageFormat::Rgba8 => lcd_to_rgba(&chunk[..width * 3], bgr),
+                _ => chunk[..width].to_vec(),
+            })
+            .collect::<Vec<_>>();
+
+        if bitmap.pitch() < 0 {
+            rows.reverse();
+        }
 
-        let data = if reverse {
-            data.rev().flatten().collect::<Vec<_>>()
-        } else {
-            data.flatten().collect::<Vec<_>>()
-        };
+        let data = rows.concat();
 
         let image = ImageData {
             format,
             size: UVec2::new(width as u32, height as u32),
             data,
+            premultiplied: true,
         };
 
         Some(RasterizedGlyph { image, offset })
     }
+
+    fn invalidate_font(&mut self, font: FontId) {
+        self.faces.retain(|&(id, _), _| id != font);
+    }
+
+    fn set_antialias_mode(&mut self, mode: AntialiasMode) {
+        self.antialias_mode = mode;
+    }
+}
+
+fn lcd_to_rgba(row: &[u8], bgr: bool) -> Vec<u8> {
+    row.chunks_exact(3)
+        .flat_map(|px| {
+            let (r, g, b) = if bgr {
+                (px[2], px[1], px[0])
+            } else {
+                (px[0], px[1], px[2])
+            };
+
+            [r, g, b, r.max(g).max(b)]
+        })
+        .collect()
+}
+
+fn set_variations(
+    face: &mut Face<FaceBuffer>,
+    font_face: &FontFace,
+    variations: &FontVariations,
+) -> FtResult<()> {
+    if variations.is_empty() {
+        return Ok(());
+    }
+
+    // design coordinates must be specified for every axis, in font order
+    let coords = font_face
+        .ttfp_face()
+        .variation_axes()
+        .into_iter()
+        .map(|axis| {
+            let value = variations.get(axis.tag).unwrap_or(axis.def_value);
+            let value = value.clamp(axis.min_value, axis.max_value);
+            (value * 65536.0).round() as FT_Fixed
+        })
+        .collect::<Vec<_>>();
+
+    if coords.is_empty() {
+        return Ok(());
+    }
+
+    // SAFETY: the face is valid, and `coords` has exactly `coords.len()` entries
+    let error = unsafe {
+        let raw: FT_Face = face.raw_mut();
+        FT_Set_Var_Design_Coordinates(raw, coords.len() as FT_UInt, coords.as_ptr())
+    };
+
+    match error {
+        0 => Ok(()),
+        error => Err(error.into()),
+    }
 }
 
 impl Default for FreetypeRasterizer {
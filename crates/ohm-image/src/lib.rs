@@ -1,17 +1,49 @@
-use ohm_core::image::{ImageData, ImageDecoder, ImageFormat};
+use std::io::Cursor;
+use std::time::Duration;
+
+use image::AnimationDecoder;
+use ohm_core::image::{AnimatedImage, AnimatedImageDecoder, ImageData, ImageDecoder, ImageFormat};
 use ohm_core::math::{UVec2, Vec2};
-use ohm_core::text::{FontFace, GlyphId, RasterizedGlyph, Rasterizer, SubpixelBin};
+use ohm_core::text::{FontFace, FontVariations, GlyphId, RasterizedGlyph, Rasterizer, SubpixelBin};
 use ohm_core::{Error, ErrorKind, Result};
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct ImageImageDecoder;
+#[derive(Debug, Clone, Copy)]
+pub struct ImageImageDecoder {
+    apply_orientation: bool,
+}
 
 impl ImageImageDecoder {
+    pub fn new() -> ImageImageDecoder {
+        ImageImageDecoder {
+            apply_orientation: true,
+        }
+    }
+
+    // rotates and flips images according to their EXIF orientation, e.g. of
+    // photos taken by phones. Enabled by default, disable to get raw pixels
+    pub fn with_orientation(mut self, apply_orientation: bool) -> ImageImageDecoder {
+        self.apply_orientation = apply_orientation;
+        self
+    }
+
     fn probe_format(&self, extension: Option<&str>, data: &[u8]) -> Option<image::ImageFormat> {
         image::guess_format(data)
             .ok()
             .or_else(|| extension.and_then(image::ImageFormat::from_extension))
     }
+
+    fn decode_single_frame(&self, extension: Option<&str>, data: &[u8]) -> Result<AnimatedImage> {
+        let image = self.decode(extension, data, None)?;
+        Ok(AnimatedImage {
+            frames: vec![(image, Duration::ZERO)],
+        })
+    }
+}
+
+impl Default for ImageImageDecoder {
+    fn default() -> ImageImageDecoder {
+        ImageImageDecoder::new()
+    }
 }
 
 impl ImageDecoder for ImageImageDecoder {
@@ -29,15 +61,81 @@ impl ImageDecoder for ImageImageDecoder {
             return Err(Error::new(ErrorKind::InvalidImage, "unrecognized image"));
         };
 
-        let image = image::load_from_memory_with_format(data, format).map_err(|e| match e {
-            image::ImageError::IoError(e) => e.into(),
-            _ => Error::wrap(ErrorKind::InvalidImage, e),
-        })?;
+        let mut decoder = image::ImageReader::with_format(Cursor::new(data), format)
+            .into_decoder()
+            .map_err(convert_error)?;
+
+        let orientation = if self.apply_orientation {
+            image::ImageDecoder::orientation(&mut decoder).map_err(convert_error)?
+        } else {
+            image::metadata::Orientation::NoTransforms
+        };
+
+        let mut image = image::DynamicImage::from_decoder(decoder).map_err(convert_error)?;
+
+        // before resizing, the requested size is of the oriented image
+        image.apply_orientation(orientation);
 
         Ok(convert_image(image.to_rgba8(), size))
     }
 }
 
+impl AnimatedImageDecoder for ImageImageDecoder {
+    fn probe_animated(&self, extension: Option<&str>, data: &[u8]) -> bool {
+        matches!(
+            self.probe_format(extension, data),
+            Some(image::ImageFormat::Gif | image::ImageFormat::Png | image::ImageFormat::WebP)
+        )
+    }
+
+    fn decode_animated(&self, extension: Option<&str>, data: &[u8]) -> Result<AnimatedImage> {
+        // the decoders composite frames, applying disposal and blending
+        let frames = match self.probe_format(extension, data) {
+            Some(image::ImageFormat::Gif) => {
+                let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(data))
+                    .map_err(convert_error)?;
+                decoder.into_frames()
+            }
+            Some(image::ImageFormat::Png) => {
+                let decoder = image::codecs::png::PngDecoder::new(Cursor::new(data))
+                    .map_err(convert_error)?;
+
+                if !decoder.is_apng().map_err(convert_error)? {
+                    return self.decode_single_frame(extension, data);
+                }
+
+                decoder.apng().map_err(convert_error)?.into_frames()
+            }
+            Some(image::ImageFormat::WebP) => {
+                let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(data))
+                    .map_err(convert_error)?;
+
+                if !decoder.has_animation() {
+                    return self.decode_single_frame(extension, data);
+                }
+
+                decoder.into_frames()
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidImage,
+                    "unrecognized animated image",
+                ));
+            }
+        };
+
+        let frames = frames
+            .map(|frame| {
+                let frame = frame.map_err(convert_error)?;
+                let delay = Duration::from(frame.delay());
+                Ok((convert_image(frame.into_buffer(), None), delay))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(AnimatedImage { frames })
+    }
+}
+
 pub struct EmbeddedImageRasterizer;
 
 impl Rasterizer for EmbeddedImageRasterizer {
@@ -47,6 +145,7 @@ impl Rasterizer for EmbeddedImageRasterizer {
         glyph_id: GlyphId,
         size: f32,
         _subpixel_bin: SubpixelBin,
+        _variations: &FontVariations,
     ) -> Option<RasterizedGlyph> {
         let face = font_face.ttfp_face();
 
@@ -68,6 +167,13 @@ impl Rasterizer for EmbeddedImageRasterizer {
     }
 }
 
+fn convert_error(error: image::ImageError) -> Error {
+    match error {
+        image::ImageError::IoError(e) => e.into(),
+        _ => Error::wrap(ErrorKind::InvalidImage, error),
+    }
+}
+
 fn convert_image(mut image: image::RgbaImage, size: Option<UVec2>) -> ImageData {
     let old_size = UVec2::new(image.width(), image.height());
 
@@ -84,5 +190,140 @@ fn convert_image(mut image: image::RgbaImage, size: Option<UVec2>) -> ImageData
         format: ImageFormat::Srgba8,
         size: UVec2::new(image.width(), image.height()),
         data: image.into_raw(),
+        premultiplied: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 16x16, VP8 with an alpha chunk
+    const LOSSY: &[u8] = include_bytes!("../test-data/lossy-alpha.webp");
+
+    // 8x4, left half opaque red, right half blue at 50% alpha
+    const LOSSLESS: &[u8] = include_bytes!("../test-data/lossless-alpha.webp");
+
+    fn pixel(image: &ImageData, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * image.size.x + x) * 4) as usize;
+        image.data[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn decode_lossy_webp() {
+        let decoder = ImageImageDecoder::new();
+        assert!(decoder.probe(Some("webp"), LOSSY));
+
+        let image = decoder.decode(Some("webp"), LOSSY, None).unwrap();
+        assert_eq!(image.format, ImageFormat::Srgba8);
+        assert_eq!(image.size, UVec2::new(16, 16));
+        assert_eq!(image.data.len(), 16 * 16 * 4);
+        assert!(!image.premultiplied);
+
+        // the alpha chunk is decoded
+        assert_eq!(pixel(&image, 0, 0)[3], 0);
+        assert_eq!(pixel(&image, 8, 8)[3], 255);
+    }
+
+    #[test]
+    fn decode_lossless_webp() {
+        let decoder = ImageImageDecoder::new();
+        assert!(decoder.probe(Some("webp"), LOSSLESS));
+
+        let image = decoder.decode(Some("webp"), LOSSLESS, None).unwrap();
+        assert_eq!(image.format, ImageFormat::Srgba8);
+        assert_eq!(image.size, UVec2::new(8, 4));
+        assert!(!image.premultiplied);
+
+        assert_eq!(pixel(&image, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&image, 7, 3), [0, 0, 255, 128]);
+    }
+
+    #[test]
+    fn decode_still_webp_as_animated() {
+        let decoder = ImageImageDecoder::new();
+
+        for (data, size) in [(LOSSY, UVec2::new(16, 16)), (LOSSLESS, UVec2::new(8, 4))] {
+            assert!(decoder.probe_animated(Some("webp"), data));
+
+            let image = decoder.decode_animated(Some("webp"), data).unwrap();
+            assert_eq!(image.frames.len(), 1);
+
+            let (frame, delay) = &image.frames[0];
+            assert_eq!(frame.format, ImageFormat::Srgba8);
+            assert_eq!(frame.size, size);
+            assert_eq!(*delay, Duration::ZERO);
+        }
+    }
+
+    // 24x32 letter F in a grid of 8px cells, stored so that applying the
+    // EXIF orientation of each file gives the upright layout
+    const ORIENTATIONS: [&[u8]; 8] = [
+        include_bytes!("../test-data/orientation-1.jpg"),
+        include_bytes!("../test-data/orientation-2.jpg"),
+        include_bytes!("../test-data/orientation-3.jpg"),
+        include_bytes!("../test-data/orientation-4.jpg"),
+        include_bytes!("../test-data/orientation-5.jpg"),
+        include_bytes!("../test-data/orientation-6.jpg"),
+        include_bytes!("../test-data/orientation-7.jpg"),
+        include_bytes!("../test-data/orientation-8.jpg"),
+    ];
+
+    const UPRIGHT: &[&str] = &["###", "#..", "##.", "#.."];
+
+    // raw pixels of each file, before the orientation is applied
+    const STORED: [&[&str]; 8] = [
+        &["###", "#..", "##.", "#.."],
+        &["###", "..#", ".##", "..#"],
+        &["..#", ".##", "..#", "###"],
+        &["#..", "##.", "#..", "###"],
+        &["####", "#.#.", "#..."],
+        &["#...", "#.#.", "####"],
+        &["...#", ".#.#", "####"],
+        &["####", ".#.#", "...#"],
+    ];
+
+    // dark cells as `#`, sampled at the cell centers
+    fn cells(image: &ImageData) -> Vec<String> {
+        (0..image.size.y / 8)
+            .map(|y| {
+                (0..image.size.x / 8)
+                    .map(|x| match pixel(image, x * 8 + 4, y * 8 + 4)[0] {
+                        0..=127 => '#',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn apply_exif_orientation() {
+        let decoder = ImageImageDecoder::new();
+
+        for (i, data) in ORIENTATIONS.into_iter().enumerate() {
+            assert!(decoder.probe(Some("jpg"), data));
+
+            let image = decoder.decode(Some("jpg"), data, None).unwrap();
+            assert_eq!(image.size, UVec2::new(24, 32), "orientation {}", i + 1);
+            assert_eq!(cells(&image), UPRIGHT, "orientation {}", i + 1);
+        }
+    }
+
+    #[test]
+    fn keep_raw_pixels_without_orientation() {
+        let decoder = ImageImageDecoder::new().with_orientation(false);
+
+        for (i, data) in ORIENTATIONS.into_iter().enumerate() {
+            // orientations 5 to 8 are rotated by 90 degrees
+            let size = match i + 1 {
+                1..=4 => UVec2::new(24, 32),
+                _ => UVec2::new(32, 24),
+            };
+
+            let image = decoder.decode(Some("jpg"), data, None).unwrap();
+            assert_eq!(image.size, size, "orientation {}", i + 1);
+            assert_eq!(cells(&image), STORED[i], "orientation {}", i + 1);
+        }
     }
 }
@@ -44,7 +44,9 @@ impl ImageDecoder for ResvgImageDecoder {
         Ok(ImageData {
             format: ImageFormat::Srgba8,
             size,
+            // tiny-skia pixmaps are premultiplied
             data: pixmap.take(),
+            premultiplied: true,
         })
     }
 }
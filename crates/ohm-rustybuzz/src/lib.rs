@@ -3,8 +3,10 @@ use std::fmt;
 use std::sync::Arc;
 
 use ohm_core::math::IVec2;
-use ohm_core::text::{FontFace, FontId, GlyphId, ShapedGlyph, TextShaper};
-use rustybuzz::{Direction, Face, UnicodeBuffer};
+use ohm_core::text::{
+    FontFace, FontId, FontVariations, GlyphId, OtFeatures, ShapedGlyph, TextShaper,
+};
+use rustybuzz::{Direction, Face, Feature, UnicodeBuffer};
 
 self_cell::self_cell! {
     struct CachedFace {
@@ -17,7 +19,8 @@ self_cell::self_cell! {
 #[derive(Default)]
 pub struct RustybuzzShaper {
     buffer: UnicodeBuffer,
-    faces: HashMap<FontId, CachedFace>,
+    features: Vec<Feature>,
+    faces: HashMap<(FontId, FontVariations), CachedFace>,
 }
 
 impl RustybuzzShaper {
@@ -33,15 +36,22 @@ impl TextShaper for RustybuzzShaper {
         text: &str,
         size: f32,
         is_rtl: bool,
+        features: &OtFeatures,
+        variations: &FontVariations,
         buf: &mut Vec<ShapedGlyph>,
     ) {
-        let face = match self.faces.entry(font_face.id()) {
+        let face = match self.faces.entry((font_face.id(), *variations)) {
             hash_map::Entry::Occupied(v) => v.into_mut().borrow_dependent(),
             hash_map::Entry::Vacant(v) => {
                 let index = font_face.face_index();
                 let cached_face = match CachedFace::try_new(Arc::clone(font_face.data()), |data| {
-                    rustybuzz::ttf_parser::Face::parse((**data).as_ref(), index)
-                        .map(Face::from_face)
+                    rustybuzz::ttf_parser::Face::parse((**data).as_ref(), index).map(|face| {
+                        let mut face = Face::from_face(face);
+                        for variation in variations.as_slice() {
+                            face.set_variation(variation.tag, variation.value);
+                        }
+                        face
+                    })
                 }) {
                     Ok(v) => v,
                     Err(_) => {
@@ -66,7 +76,14 @@ impl TextShaper for RustybuzzShaper {
             Direction::LeftToRight
         });
 
-        let glyphs = rustybuzz::shape(face, &[], buffer);
+        self.features.clear();
+        self.features.extend(
+            features
+                .iter()
+                .map(|feature| Feature::new(feature.tag, feature.value, ..)),
+        );
+
+        let glyphs = rustybuzz::shape(face, &self.features, buffer);
 
         let it = glyphs.glyph_infos().iter().zip(glyphs.glyph_positions());
         buf.extend(it.map(|(info, pos)| ShapedGlyph {
@@ -84,6 +101,10 @@ impl TextShaper for RustybuzzShaper {
 
         self.buffer = glyphs.clear();
     }
+
+    fn invalidate_font(&mut self, font: FontId) {
+        self.faces.retain(|&(id, _), _| id != font);
+    }
 }
 
 impl fmt::Debug for RustybuzzShaper {
@@ -26,3 +26,73 @@ fn vs_main(@builtin(vertex_index) vertex_id: u32) -> VertexOutput {
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(texture, texture_sampler, in.tex);
 }
+
+// colors are premultiplied, so the displayable range ends at alpha. Brighter
+// colors are scaled down as a whole, keeping their hue
+fn tone_map(color: vec4<f32>) -> vec4<f32> {
+    let max_channel = max(color.r, max(color.g, color.b));
+    if max_channel <= color.a {
+        return color;
+    }
+
+    return vec4(color.rgb * (color.a / max_channel), color.a);
+}
+
+fn encode_srgb(v: vec3<f32>) -> vec3<f32> {
+    let low = v * 12.92;
+    let high = 1.055 * pow(v, vec3(1.0 / 2.4)) - 0.055;
+    return select(high, low, v <= vec3(0.0031308));
+}
+
+@fragment
+fn fs_tone_map(in: VertexOutput) -> @location(0) vec4<f32> {
+    return tone_map(textureSample(texture, texture_sampler, in.tex));
+}
+
+@fragment
+fn fs_tone_map_encode(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = tone_map(textureSample(texture, texture_sampler, in.tex));
+    return vec4(encode_srgb(max(color.rgb, vec3(0.0))), color.a);
+}
+
+// separable gaussian blur of layers, one direction per pass. Only uses the
+// texture binding, the sampler is replaced by the parameters
+struct BlurParams {
+    // size of the blurred area, the textures may be larger
+    size: vec2<u32>,
+    sigma: f32,
+    radius: u32,
+}
+
+@group(0) @binding(2)
+var<uniform> blur: BlurParams;
+
+fn blur_along(pos: vec2<f32>, dir: vec2<i32>) -> vec4<f32> {
+    let center = vec2<i32>(pos);
+    let max_coord = vec2<i32>(blur.size) - 1;
+    let radius = i32(blur.radius);
+    let scale = -0.5 / (blur.sigma * blur.sigma);
+
+    var sum = vec4(0.0);
+    var total = 0.0;
+
+    for (var i = -radius; i <= radius; i++) {
+        let weight = exp(f32(i * i) * scale);
+        // clamped at the edges, instead of fading to transparent
+        let coord = clamp(center + dir * i, vec2(0), max_coord);
+        sum += weight * textureLoad(texture, coord, 0);
+        total += weight;
+    }
+
+    return sum / total;
+}
+
+@fragment
+fn fs_blur_x(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur_along(in.clip_pos.xy, vec2(1, 0));
+}
+
+@fragment
+fn fs_blur_y(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur_along(in.clip_pos.xy, vec2(0, 1));
+}
@@ -1,26 +1,64 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
-use std::sync::Arc;
+use std::num::NonZeroU64;
+use std::sync::{mpsc, Arc};
 
 use ohm_core::image::{ImageData, ImageFormat};
 use ohm_core::math::{URect, UVec2, Vec2, Vec4};
 use ohm_core::renderer::{
-    Batcher, BatcherScratch, Instance as BatcherInstance, PathCache, Renderer, Source, SurfaceId,
-    Target, Vertex, WindowHandle,
+    Batch, Batcher, BatcherScratch, ClipShape, DebugOverlay, GeometryStats,
+    Instance as BatcherInstance, PathCache, Renderer, RendererCapabilities, Source, SurfaceId,
+    Target, Vertex, WindowHandle, MAX_CLIP_SHAPES,
 };
 use ohm_core::texture::{MipmapMode, TextureCache, TextureCommand, TextureId};
-use ohm_core::{DrawList, Error, ErrorKind, Result};
+use ohm_core::{BlendMode, DrawList, Error, ErrorKind, Result, SamplerMode};
 use self_cell::self_cell;
 use slotmap::SlotMap;
 use wgpu::util::{BufferInitDescriptor, DeviceExt, TextureDataOrder};
 use wgpu::*;
 
+// size of the uniform instance array, for adapters without storage buffers
+// in fragment shaders
 const MAX_INSTANCES_PER_BUFFER: usize = 128;
 
+// initial size of growable buffers, in bytes
+const MIN_BUFFER_SIZE: u64 = 1024;
+
+// taps on each side of a blurred pixel, larger radii are truncated
+const MAX_BLUR_TAPS: u32 = 64;
+
+const STORAGE_INSTANCES_DECL: &str = "var<storage, read> rect_instances: array<RectInstance>;";
+
 #[derive(Debug)]
 pub struct WgpuRenderer {
     instance: Instance,
     context: Option<RendererContext>,
+    surface_options: SurfaceOptions,
+    msaa_samples: u32,
+    limits: FrameLimits,
+    debug_overlay: DebugOverlay,
+    adapter_options: AdapterOptions,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AdapterOptions {
+    power_preference: PowerPreference,
+    force_fallback_adapter: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SurfaceOptions {
+    direct_present: bool,
+    formats: Vec<TextureFormat>,
+    hdr: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameLimits {
+    max_intermediates: usize,
+    max_vertices: usize,
+    max_indices: usize,
 }
 
 impl WgpuRenderer {
@@ -29,13 +67,207 @@ impl WgpuRenderer {
         WgpuRenderer {
             instance,
             context: None,
+            surface_options: SurfaceOptions::default(),
+            msaa_samples: 8,
+            limits: FrameLimits {
+                max_intermediates: usize::MAX,
+                max_vertices: usize::MAX,
+                max_indices: usize::MAX,
+            },
+            debug_overlay: DebugOverlay::default(),
+            adapter_options: AdapterOptions {
+                power_preference: PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+            },
         }
     }
 
-    fn context(&self) -> &RendererContext {
+    /// Sets the power preference used when selecting the video adapter.
+    ///
+    /// Default: [`PowerPreference::HighPerformance`]. Only has effect before
+    /// the first surface is created.
+    pub fn set_power_preference(&mut self, power_preference: PowerPreference) {
+        self.adapter_options.power_preference = power_preference;
+    }
+
+    /// Forces the use of a fallback (software) adapter.
+    ///
+    /// Useful for benchmarks and golden tests, where output should not depend
+    /// on the installed GPU. Only has effect before the first surface is
+    /// created.
+    pub fn set_force_fallback_adapter(&mut self, force_fallback_adapter: bool) {
+        self.adapter_options.force_fallback_adapter = force_fallback_adapter;
+    }
+
+    /// Sets the maximum number of MSAA samples used for anti-aliasing paths.
+    ///
+    /// Must be 1, 2, 4 or 8. If the adapter doesn't support the requested
+    /// count, the largest supported smaller count is used instead. Passing `1`
+    /// disables MSAA. Default: `8`.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> Result<()> {
+        if !matches!(samples, 1 | 2 | 4 | 8) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("invalid MSAA sample count: {samples}"),
+            ));
+        }
+
+        self.msaa_samples = samples;
+
+        if let Some(context) = &mut self.context {
+            context.set_msaa_samples(samples);
+        }
+
+        Ok(())
+    }
+
+    /// Limits the number of intermediate textures allocated per frame.
+    ///
+    /// Intermediates are used for tinted layers and anti-aliased paths. Layers
+    /// exceeding the limit are drawn without one, so their tint may be
+    /// incorrect, but a runaway layer count doesn't exhaust video memory. See
+    /// [`WgpuRenderer::skipped_intermediates`]. Default: unlimited.
+    pub fn set_max_intermediates(&mut self, max_intermediates: usize) {
+        self.limits.max_intermediates = max_intermediates;
+    }
+
+    /// Returns the number of layers drawn without an intermediate during the
+    /// last frame, because the limit was exceeded.
+    pub fn skipped_intermediates(&self) -> usize {
         self.context
             .as_ref()
-            .expect("context hasn't been initialized yet")
+            .map_or(0, |context| context.skipped_intermediates)
+    }
+
+    /// Limits the number of vertices and indices of a frame.
+    ///
+    /// Guards against runaway geometry, e.g. a huge tessellated path. Frames
+    /// exceeding either limit aren't rendered and [`Renderer::render`]
+    /// returns an error, before any GPU buffers are allocated for them.
+    /// Default: unlimited.
+    pub fn set_max_geometry(&mut self, max_vertices: usize, max_indices: usize) {
+        self.limits.max_vertices = max_vertices;
+        self.limits.max_indices = max_indices;
+    }
+
+    /// Outlines batches, intermediate layers, glyphs and quads on top of the
+    /// rendered output, see [`DebugOverlay`]. Useful to find out why content
+    /// isn't batched together or needs an intermediate. Default: disabled.
+    pub fn set_debug_overlay(&mut self, debug_overlay: DebugOverlay) {
+        self.debug_overlay = debug_overlay;
+    }
+
+    /// Returns the amount of geometry of the last frame. If the frame exceeded
+    /// the limits, counts up to the point where it was stopped.
+    pub fn geometry_stats(&self) -> GeometryStats {
+        self.context
+            .as_ref()
+            .map_or(GeometryStats::default(), |context| context.geometry_stats)
+    }
+
+    /// Enables or disables rendering directly into the swapchain.
+    ///
+    /// By default, everything is first rendered into an intermediate
+    /// framebuffer, which is then blitted onto the swapchain texture. With
+    /// direct presentation enabled, surfaces whose swapchain format is
+    /// `Rgba8UnormSrgb` skip the framebuffer and the blit entirely. Note that
+    /// the surface contents are then not preserved between frames, so the
+    /// whole surface must be redrawn every frame.
+    ///
+    /// Only affects surfaces created after this call.
+    pub fn set_direct_present(&mut self, direct_present: bool) {
+        self.surface_options.direct_present = direct_present;
+    }
+
+    /// Restricts the swapchain formats of surfaces, in order of preference.
+    ///
+    /// The first format supported by the surface is used, and creating a
+    /// surface supporting none of them returns an error. With an empty list,
+    /// 8-bit formats are preferred, sRGB ones first, so 10-bit and float
+    /// formats are only picked if nothing else is available, unless HDR is
+    /// enabled (see [`WgpuRenderer::set_hdr`]). Default: empty.
+    ///
+    /// Only affects surfaces created after this call.
+    pub fn set_surface_formats(&mut self, formats: &[TextureFormat]) {
+        self.surface_options.formats = formats.to_vec();
+    }
+
+    /// Enables or disables extended range output.
+    ///
+    /// HDR surfaces are rendered into an `Rgba16Float` framebuffer, so colors
+    /// brighter than `1` aren't clipped until presenting. `Rgba16Float`
+    /// swapchains (extended linear sRGB, also known as scRGB) are preferred,
+    /// then `Rgb10a2Unorm`. On swapchains that can't represent extended range,
+    /// colors are scaled down into the displayable range, preserving their
+    /// hue. Intermediate layers are still limited to the `0..=1` range, and
+    /// HDR surfaces can't be read back. Direct presentation doesn't apply to
+    /// HDR surfaces. Default: disabled.
+    ///
+    /// Only affects surfaces created after this call.
+    pub fn set_hdr(&mut self, hdr: bool) {
+        self.surface_options.hdr = hdr;
+    }
+
+    /// Sets the present mode of a surface, e.g. to disable vsync.
+    ///
+    /// Falls back to [`PresentMode::AutoVsync`] if the requested mode isn't
+    /// supported. Does nothing before the renderer is initialized, as there
+    /// are no surfaces yet, or if the surface doesn't exist. Default:
+    /// [`PresentMode::AutoVsync`].
+    pub fn set_present_mode(&mut self, id: SurfaceId, mode: PresentMode) {
+        if let Some(context) = &mut self.context {
+            context.set_present_mode(id, mode);
+        }
+    }
+
+    /// Sets the usages of a surface's swapchain textures, in addition to
+    /// [`TextureUsages::RENDER_ATTACHMENT`], which is always included.
+    ///
+    /// Adding [`TextureUsages::COPY_SRC`] allows reading back directly
+    /// presented surfaces. Returns an error if the surface doesn't exist or
+    /// doesn't support the requested usages. Default:
+    /// [`TextureUsages::RENDER_ATTACHMENT`].
+    pub fn set_surface_usage(&mut self, id: SurfaceId, usage: TextureUsages) -> Result<()> {
+        self.try_context_mut()?.set_surface_usage(id, usage)
+    }
+
+    /// Reads back the contents of a surface, as of the last render.
+    ///
+    /// Blocks until the GPU finishes all submitted work. Surfaces using direct
+    /// presentation are read from the swapchain, which requires the
+    /// [`TextureUsages::COPY_SRC`] usage (see
+    /// [`WgpuRenderer::set_surface_usage`]) and is only possible between
+    /// rendering and presenting. Returns an error if the surface doesn't exist.
+    pub fn read_surface(&mut self, id: SurfaceId) -> Result<ImageData> {
+        self.try_context_mut()?.read_surface(id)
+    }
+
+    /// Recompiles the shaders from the provided WGSL sources and recreates all
+    /// render pipelines. Useful for iterating on shaders without recompiling.
+    ///
+    /// On error, the previous shaders remain in use.
+    #[cfg(feature = "dev")]
+    pub fn reload_shaders(&mut self, uber_src: &str, blit_src: &str) -> Result<()> {
+        self.try_context_mut()?.reload_shaders(uber_src, blit_src)
+    }
+
+    /// Returns `true` if the GPU context has been initialized, which happens
+    /// when the first surface is created.
+    pub fn is_initialized(&self) -> bool {
+        self.context.is_some()
+    }
+
+    /// Returns the size of the provided surface, or `None` if the renderer
+    /// hasn't been initialized yet or the surface doesn't exist.
+    pub fn try_get_surface_size(&self, id: SurfaceId) -> Option<UVec2> {
+        let config = &self.context.as_ref()?.surfaces.get(id)?.config;
+        Some(UVec2::new(config.width, config.height))
+    }
+
+    fn try_context_mut(&mut self) -> Result<&mut RendererContext> {
+        self.context
+            .as_mut()
+            .ok_or_else(|| Error::new(ErrorKind::Gpu, "context hasn't been initialized yet"))
     }
 
     fn context_mut(&mut self) -> &mut RendererContext {
@@ -52,31 +284,80 @@ impl Renderer for WgpuRenderer {
                 .map_err(|e| Error::wrap(ErrorKind::Gpu, e))?;
 
         if self.context.is_none() {
-            let context = RendererContext::new(&self.instance, &surface)?;
+            let context = RendererContext::new(
+                &self.instance,
+                Some(&surface),
+                self.adapter_options,
+                self.msaa_samples,
+            )?;
+            self.context = Some(context);
+        }
+
+        let options = self.surface_options.clone();
+        self.context_mut().create_surface(surface, size, &options)
+    }
+
+    fn create_offscreen_surface(&mut self, size: UVec2) -> Result<SurfaceId> {
+        if self.context.is_none() {
+            let context = RendererContext::new(
+                &self.instance,
+                None,
+                self.adapter_options,
+                self.msaa_samples,
+            )?;
             self.context = Some(context);
         }
 
-        self.context_mut().create_surface(surface, size)
+        self.context_mut().create_offscreen_surface(size)
     }
 
     fn resize_surface(&mut self, id: SurfaceId, new_size: UVec2) -> Result<()> {
-        self.context_mut().resize_surface(id, new_size);
+        self.try_context_mut()?.resize_surface(id, new_size);
         Ok(())
     }
 
     fn get_surface_size(&self, surface: SurfaceId) -> UVec2 {
-        self.context().get_surface_size(surface)
+        self.context
+            .as_ref()
+            .map_or(UVec2::ZERO, |context| context.get_surface_size(surface))
+    }
+
+    fn set_surface_scale(&mut self, id: SurfaceId, scale: f32) {
+        let Some(context) = &mut self.context else {
+            return;
+        };
+
+        if let Some(entry) = context.surfaces.get_mut(id) {
+            entry.scale = scale;
+        }
+    }
+
+    fn get_surface_scale(&self, id: SurfaceId) -> f32 {
+        self.context
+            .as_ref()
+            .and_then(|context| context.surfaces.get(id))
+            .map_or(1.0, |entry| entry.scale)
     }
 
     fn destroy_surface(&mut self, id: SurfaceId) {
-        self.context_mut().destroy_surface(id);
+        if let Some(context) = &mut self.context {
+            context.destroy_surface(id);
+        }
     }
 
     fn update_textures(&mut self, commands: &mut Vec<TextureCommand>) -> Result<()> {
-        self.context_mut().update_textures(commands);
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        self.try_context_mut()?.update_textures(commands);
         Ok(())
     }
 
+    fn read_texture_region(&mut self, id: TextureId, rect: URect) -> Result<ImageData> {
+        self.try_context_mut()?.read_texture_region(id, rect)
+    }
+
     fn render(
         &mut self,
         texture_cache: &TextureCache,
@@ -84,8 +365,15 @@ impl Renderer for WgpuRenderer {
         draw_lists: &[DrawList<'_>],
     ) -> Result<()> {
         if !draw_lists.is_empty() {
-            self.context_mut()
-                .render(texture_cache, path_cache, draw_lists);
+            let limits = self.limits;
+            let debug_overlay = self.debug_overlay;
+            self.try_context_mut()?.render(
+                texture_cache,
+                path_cache,
+                draw_lists,
+                limits,
+                debug_overlay,
+            )?;
         }
         Ok(())
     }
@@ -96,6 +384,12 @@ impl Renderer for WgpuRenderer {
         }
         Ok(())
     }
+
+    fn capabilities(&self) -> RendererCapabilities {
+        self.context
+            .as_ref()
+            .map_or_else(RendererCapabilities::default, RendererContext::capabilities)
+    }
 }
 
 impl Default for WgpuRenderer {
@@ -113,20 +407,42 @@ struct TextureEntry {
 }
 
 struct SurfaceEntry {
-    surface: OwnedSurface,
+    // `None` for offscreen surfaces
+    surface: Option<OwnedSurface>,
     config: SurfaceConfiguration,
-    texture_view: TextureView,
-    texture_view_srgbless: TextureView,
+    // `None` for directly presented surfaces, which skip the blit
+    framebuffer: Option<Framebuffer>,
+    // `Rgba16Float` for HDR surfaces
+    framebuffer_format: TextureFormat,
+    direct_present: bool,
+    scale: f32,
 }
 
 impl fmt::Debug for SurfaceEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SurfaceEntry")
             .field("config", &self.config)
+            .field("direct_present", &self.direct_present)
+            .field("scale", &self.scale)
             .finish_non_exhaustive()
     }
 }
 
+impl SurfaceEntry {
+    fn framebuffer(&self) -> &Framebuffer {
+        self.framebuffer
+            .as_ref()
+            .expect("directly presented surface has no framebuffer")
+    }
+}
+
+struct Framebuffer {
+    texture: Texture,
+    texture_view: TextureView,
+    // without an sRGB variant for HDR surfaces
+    texture_view_srgbless: TextureView,
+}
+
 self_cell! {
     struct OwnedSurface {
         owner: Arc<dyn WindowHandle>,
@@ -149,69 +465,131 @@ struct RendererContext {
     device: Device,
     queue: Queue,
     msaa_samples: u32,
+    // instances are in a single storage buffer, otherwise they're split into
+    // uniform buffers of `MAX_INSTANCES_PER_BUFFER`
+    storage_instances: bool,
+    max_instances_per_buffer: usize,
+    // reused across frames
+    vertex_buffer: GrowableBuffer,
+    index_buffer: GrowableBuffer,
+    instance_buffers: Vec<GrowableBuffer>,
+    globals_buffers: Vec<GrowableBuffer>,
     uber_bind_group_layout: BindGroupLayout,
+    uber_pipeline_layout: PipelineLayout,
+    uber_shader_module: ShaderModule,
     uber_render_pipeline: RenderPipeline,
     uber_render_pipeline_msaa: RenderPipeline,
     uber_render_pipeline_noblend: RenderPipeline,
     uber_render_pipeline_noblend_msaa: RenderPipeline,
+    uber_render_pipeline_subpixel: RenderPipeline,
+    uber_render_pipeline_subpixel_msaa: RenderPipeline,
+    // layer blend modes, clip masks and HDR framebuffers, created on demand
+    // and keyed by the sample count and target format
+    uber_render_pipelines_lazy: HashMap<(PipelineKind, u32, TextureFormat), RenderPipeline>,
     blit_bind_group_layout: BindGroupLayout,
     blit_render_pipeline_layout: PipelineLayout,
     blit_render_pipeline_shader_module: ShaderModule,
-    blit_render_pipelines: HashMap<TextureFormat, RenderPipeline>,
-    textures: HashMap<TextureId, TextureEntry>,
+    blit_render_pipelines: HashMap<(TextureFormat, BlitMode), RenderPipeline>,
+    blur_bind_group_layout: BindGroupLayout,
+    blur_render_pipeline_layout: PipelineLayout,
+    // ping-pong texture of blurred layers, grown to the largest one
+    blur_scratch: Option<(UVec2, TextureView)>,
+    // ordered, so that texture operations happen in a stable order
+    textures: BTreeMap<TextureId, TextureEntry>,
     white_texture_view: TextureView,
     intermediates: Vec<IntermediateEntry>,
     sampler: Sampler,
+    nearest_sampler: Sampler,
     surfaces: SlotMap<SurfaceId, SurfaceEntry>,
-    to_present: Vec<SurfaceTexture>,
+    to_present: Vec<(SurfaceId, SurfaceTexture)>,
+    skipped_intermediates: usize,
+    geometry_stats: GeometryStats,
 }
 
 impl RendererContext {
-    fn new(instance: &Instance, main_surface: &OwnedSurface) -> Result<RendererContext> {
-        let adapter =
-            pollster::block_on(create_adapter(instance, main_surface.borrow_dependent()))?;
+    fn new(
+        instance: &Instance,
+        main_surface: Option<&OwnedSurface>,
+        adapter_options: AdapterOptions,
+        max_msaa_samples: u32,
+    ) -> Result<RendererContext> {
+        let adapter = pollster::block_on(create_adapter(
+            instance,
+            main_surface.map(|surface| surface.borrow_dependent()),
+            adapter_options,
+        ))?;
         let (device, queue) = pollster::block_on(create_device(&adapter))?;
 
-        let format_flags = adapter
-            .get_texture_format_features(TextureFormat::Rgba8UnormSrgb)
-            .flags;
+        let msaa_samples = supported_msaa_samples(&adapter, max_msaa_samples);
 
-        let mut msaa_samples = 1;
-        if format_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X2) {
-            msaa_samples = 2;
-        }
-        if format_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X4) {
-            msaa_samples = 4;
-        }
-        if format_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X8) {
-            msaa_samples = 8;
-        }
+        let storage_instances = supports_storage_instances(&adapter, &device);
+        let max_instances_per_buffer = if storage_instances {
+            let instance_size = <OurInstance as encase::ShaderType>::min_size().get();
+            (u64::from(device.limits().max_storage_buffer_binding_size) / instance_size) as usize
+        } else {
+            MAX_INSTANCES_PER_BUFFER
+        };
 
-        let uber_bind_group_layout = create_uber_bind_group_layout(&device);
+        let uber_bind_group_layout = create_uber_bind_group_layout(&device, storage_instances);
 
         let pipeline_layout = create_pipeline_layout(&device, &uber_bind_group_layout);
-        let shader_module = create_shader_module(&device, include_str!("uber.wgsl"));
+        let shader_module = create_shader_module(
+            &device,
+            &uber_shader_source(include_str!("uber.wgsl"), storage_instances),
+        );
 
-        let uber_render_pipeline =
-            create_uber_render_pipeline(&device, &pipeline_layout, &shader_module, true, 1);
+        let uber_render_pipeline = create_uber_render_pipeline(
+            &device,
+            &pipeline_layout,
+            &shader_module,
+            BLEND,
+            1,
+            TextureFormat::Rgba8UnormSrgb,
+        );
 
         let uber_render_pipeline_msaa = create_uber_render_pipeline(
             &device,
             &pipeline_layout,
             &shader_module,
-            true,
+            BLEND,
             msaa_samples,
+            TextureFormat::Rgba8UnormSrgb,
         );
 
-        let uber_render_pipeline_noblend =
-            create_uber_render_pipeline(&device, &pipeline_layout, &shader_module, false, 1);
+        let uber_render_pipeline_noblend = create_uber_render_pipeline(
+            &device,
+            &pipeline_layout,
+            &shader_module,
+            None,
+            1,
+            TextureFormat::Rgba8UnormSrgb,
+        );
 
         let uber_render_pipeline_noblend_msaa = create_uber_render_pipeline(
             &device,
             &pipeline_layout,
             &shader_module,
-            false,
+            None,
             msaa_samples,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        let uber_render_pipeline_subpixel = create_uber_render_pipeline(
+            &device,
+            &pipeline_layout,
+            &shader_module,
+            SUBPIXEL_BLEND,
+            1,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        let uber_render_pipeline_subpixel_msaa = create_uber_render_pipeline(
+            &device,
+            &pipeline_layout,
+            &shader_module,
+            SUBPIXEL_BLEND,
+            msaa_samples,
+            TextureFormat::Rgba8UnormSrgb,
         );
 
         let blit_bind_group_layout = create_blit_bind_group_layout(&device);
@@ -220,8 +598,15 @@ impl RendererContext {
             create_shader_module(&device, include_str!("blit.wgsl"));
         let blit_render_pipelines = HashMap::new();
 
+        let blur_bind_group_layout = create_blur_bind_group_layout(&device);
+        let blur_render_pipeline_layout = create_pipeline_layout(&device, &blur_bind_group_layout);
+
+        let vertex_buffer = GrowableBuffer::new(&device, BufferUsages::VERTEX);
+        let index_buffer = GrowableBuffer::new(&device, BufferUsages::INDEX);
+
         let white_texture_view = create_white_texture_view(&device, &queue);
-        let sampler = create_sampler(&device);
+        let sampler = create_sampler(&device, FilterMode::Linear);
+        let nearest_sampler = create_sampler(&device, FilterMode::Nearest);
 
         Ok(RendererContext {
             batcher_scratch: BatcherScratch::default(),
@@ -229,31 +614,225 @@ impl RendererContext {
             device,
             queue,
             msaa_samples,
+            storage_instances,
+            max_instances_per_buffer,
+            vertex_buffer,
+            index_buffer,
+            instance_buffers: Vec::new(),
+            globals_buffers: Vec::new(),
             uber_bind_group_layout,
+            uber_pipeline_layout: pipeline_layout,
+            uber_shader_module: shader_module,
             uber_render_pipeline,
             uber_render_pipeline_msaa,
             uber_render_pipeline_noblend,
             uber_render_pipeline_noblend_msaa,
+            uber_render_pipeline_subpixel,
+            uber_render_pipeline_subpixel_msaa,
+            uber_render_pipelines_lazy: HashMap::new(),
             blit_bind_group_layout,
             blit_render_pipeline_layout,
             blit_render_pipeline_shader_module,
             blit_render_pipelines,
-            textures: HashMap::default(),
+            blur_bind_group_layout,
+            blur_render_pipeline_layout,
+            blur_scratch: None,
+            textures: BTreeMap::new(),
             white_texture_view,
             intermediates: Vec::new(),
             sampler,
+            nearest_sampler,
             surfaces: SlotMap::default(),
             to_present: Vec::new(),
+            skipped_intermediates: 0,
+            geometry_stats: GeometryStats::default(),
         })
     }
 
-    fn create_surface(&mut self, surface: OwnedSurface, size: UVec2) -> Result<SurfaceId> {
-        let caps = surface.borrow_dependent().get_capabilities(&self.adapter);
+    fn capabilities(&self) -> RendererCapabilities {
+        let msaa_sample_counts = [1, 2, 4, 8]
+            .into_iter()
+            .filter(|&samples| supported_msaa_samples(&self.adapter, samples) == samples)
+            .collect();
+
+        RendererCapabilities {
+            max_texture_size: self.device.limits().max_texture_dimension_2d,
+            msaa_sample_counts,
+            storage_buffers: self.storage_instances,
+            image_formats: [ImageFormat::Srgba8, ImageFormat::Gray8, ImageFormat::Rgba8]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn set_msaa_samples(&mut self, max_msaa_samples: u32) {
+        let msaa_samples = supported_msaa_samples(&self.adapter, max_msaa_samples);
+        if msaa_samples == self.msaa_samples {
+            return;
+        }
+
+        self.msaa_samples = msaa_samples;
+
+        self.uber_render_pipeline_msaa = create_uber_render_pipeline(
+            &self.device,
+            &self.uber_pipeline_layout,
+            &self.uber_shader_module,
+            BLEND,
+            msaa_samples,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        self.uber_render_pipeline_noblend_msaa = create_uber_render_pipeline(
+            &self.device,
+            &self.uber_pipeline_layout,
+            &self.uber_shader_module,
+            None,
+            msaa_samples,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        self.uber_render_pipeline_subpixel_msaa = create_uber_render_pipeline(
+            &self.device,
+            &self.uber_pipeline_layout,
+            &self.uber_shader_module,
+            SUBPIXEL_BLEND,
+            msaa_samples,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        self.uber_render_pipelines_lazy
+            .retain(|&(_, samples, _), _| samples == 1);
+
+        // recreated with the new sample count on the next render
+        self.intermediates.clear();
+    }
 
-        let formats = caps.formats.iter().copied();
-        let format = formats
-            .max_by_key(|format| format.is_srgb() as u8 + format.components())
-            .unwrap_or(TextureFormat::Bgra8Unorm);
+    #[cfg(feature = "dev")]
+    fn reload_shaders(&mut self, uber_src: &str, blit_src: &str) -> Result<()> {
+        let device = &self.device;
+        device.push_error_scope(ErrorFilter::Validation);
+
+        let uber_src = uber_shader_source(uber_src, self.storage_instances);
+        let uber_shader_module = create_shader_module(device, &uber_src);
+        let blit_shader_module = create_shader_module(device, blit_src);
+
+        let layout = &self.uber_pipeline_layout;
+        let samples = self.msaa_samples;
+        let format = TextureFormat::Rgba8UnormSrgb;
+        let pipelines = [
+            create_uber_render_pipeline(device, layout, &uber_shader_module, BLEND, 1, format),
+            create_uber_render_pipeline(
+                device,
+                layout,
+                &uber_shader_module,
+                BLEND,
+                samples,
+                format,
+            ),
+            create_uber_render_pipeline(device, layout, &uber_shader_module, None, 1, format),
+            create_uber_render_pipeline(device, layout, &uber_shader_module, None, samples, format),
+            create_uber_render_pipeline(
+                device,
+                layout,
+                &uber_shader_module,
+                SUBPIXEL_BLEND,
+                1,
+                format,
+            ),
+            create_uber_render_pipeline(
+                device,
+                layout,
+                &uber_shader_module,
+                SUBPIXEL_BLEND,
+                samples,
+                format,
+            ),
+        ];
+
+        // blit pipelines are created lazily, so validate the module right away
+        let blit_pipeline = create_blit_render_pipeline(
+            device,
+            &self.blit_render_pipeline_layout,
+            &blit_shader_module,
+            TextureFormat::Rgba8UnormSrgb,
+            BlitMode::Copy,
+        );
+
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(Error::new(ErrorKind::Gpu, error.to_string()));
+        }
+
+        let [blend, blend_msaa, noblend, noblend_msaa, subpixel, subpixel_msaa] = pipelines;
+        self.uber_render_pipeline = blend;
+        self.uber_render_pipeline_msaa = blend_msaa;
+        self.uber_render_pipeline_noblend = noblend;
+        self.uber_render_pipeline_noblend_msaa = noblend_msaa;
+        self.uber_render_pipeline_subpixel = subpixel;
+        self.uber_render_pipeline_subpixel_msaa = subpixel_msaa;
+        self.uber_shader_module = uber_shader_module;
+        self.uber_render_pipelines_lazy.clear();
+
+        self.blit_render_pipeline_shader_module = blit_shader_module;
+        self.blit_render_pipelines.clear();
+        self.blit_render_pipelines.insert(
+            (TextureFormat::Rgba8UnormSrgb, BlitMode::Copy),
+            blit_pipeline,
+        );
+
+        Ok(())
+    }
+
+    fn create_surface(
+        &mut self,
+        surface: OwnedSurface,
+        size: UVec2,
+        options: &SurfaceOptions,
+    ) -> Result<SurfaceId> {
+        let caps = surface.borrow_dependent().get_capabilities(&self.adapter);
+        let direct_present = options.direct_present && !options.hdr;
+        let preferred_formats = &options.formats[..];
+
+        let format = if preferred_formats.is_empty() {
+            let formats = caps.formats.iter().copied();
+            formats
+                .max_by_key(|&format| {
+                    // prefer the framebuffer format when rendering directly
+                    let is_direct = direct_present && format == TextureFormat::Rgba8UnormSrgb;
+                    let is_8bit = matches!(
+                        format,
+                        TextureFormat::Rgba8Unorm
+                            | TextureFormat::Rgba8UnormSrgb
+                            | TextureFormat::Bgra8Unorm
+                            | TextureFormat::Bgra8UnormSrgb
+                    );
+                    let hdr_rank = match format {
+                        TextureFormat::Rgba16Float if options.hdr => 2,
+                        TextureFormat::Rgb10a2Unorm if options.hdr => 1,
+                        _ => 0,
+                    };
+                    (
+                        hdr_rank,
+                        is_8bit,
+                        format.is_srgb() as u8 + format.components(),
+                        is_direct,
+                    )
+                })
+                .unwrap_or(TextureFormat::Bgra8Unorm)
+        } else {
+            let mut formats = preferred_formats.iter().copied();
+            formats
+                .find(|format| caps.formats.contains(format))
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::Gpu,
+                        format!(
+                            "none of the surface formats {preferred_formats:?} are supported, \
+                             available: {:?}",
+                            caps.formats
+                        ),
+                    )
+                })?
+        };
 
         let alpha_mode = if caps
             .alpha_modes
@@ -277,45 +856,213 @@ impl RendererContext {
 
         surface.borrow_dependent().configure(&self.device, &config);
 
-        let texture = create_draw_texture(&self.device, size.x, size.y, 1);
+        let direct_present = direct_present && format == TextureFormat::Rgba8UnormSrgb;
+        let framebuffer_format = if options.hdr {
+            TextureFormat::Rgba16Float
+        } else {
+            TextureFormat::Rgba8UnormSrgb
+        };
 
-        let texture_view = texture.create_view(&Default::default());
+        Ok(self.insert_surface(Some(surface), config, framebuffer_format, direct_present))
+    }
 
-        let texture_view_srgbless = texture.create_view(&TextureViewDescriptor {
-            format: Some(TextureFormat::Rgba8Unorm),
-            ..Default::default()
+    fn create_offscreen_surface(&mut self, size: UVec2) -> Result<SurfaceId> {
+        // never used to configure anything, only keeps the size and format
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format: TextureFormat::Rgba8UnormSrgb,
+            width: size.x,
+            height: size.y,
+            present_mode: PresentMode::AutoVsync,
+            alpha_mode: CompositeAlphaMode::PreMultiplied,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        Ok(self.insert_surface(None, config, TextureFormat::Rgba8UnormSrgb, false))
+    }
+
+    fn insert_surface(
+        &mut self,
+        surface: Option<OwnedSurface>,
+        config: SurfaceConfiguration,
+        framebuffer_format: TextureFormat,
+        direct_present: bool,
+    ) -> SurfaceId {
+        let framebuffer = (!direct_present).then(|| {
+            create_framebuffer(
+                &self.device,
+                config.width,
+                config.height,
+                framebuffer_format,
+            )
         });
 
-        let id = self.surfaces.insert(SurfaceEntry {
+        self.surfaces.insert(SurfaceEntry {
             surface,
             config,
-            texture_view,
-            texture_view_srgbless,
-        });
-
-        Ok(id)
+            framebuffer,
+            framebuffer_format,
+            direct_present,
+            scale: 1.0,
+        })
     }
 
     fn resize_surface(&mut self, id: SurfaceId, size: UVec2) {
-        let entry = &mut self.surfaces[id];
+        // stale ids are ignored, resizes race with windows being closed
+        let Some(entry) = self.surfaces.get_mut(id) else {
+            return;
+        };
+
         entry.config.width = size.x;
         entry.config.height = size.y;
-        entry
-            .surface
-            .borrow_dependent()
-            .configure(&self.device, &entry.config);
 
-        let texture = create_draw_texture(&self.device, size.x, size.y, 1);
+        if let Some(surface) = &entry.surface {
+            surface
+                .borrow_dependent()
+                .configure(&self.device, &entry.config);
+        }
 
-        let texture_view = texture.create_view(&Default::default());
+        // only surfaces drawn through the blit path have a framebuffer
+        entry.framebuffer = (!entry.direct_present)
+            .then(|| create_framebuffer(&self.device, size.x, size.y, entry.framebuffer_format));
+    }
 
-        let texture_view_srgbless = texture.create_view(&TextureViewDescriptor {
-            format: Some(TextureFormat::Rgba8Unorm),
-            ..Default::default()
-        });
+    fn set_present_mode(&mut self, id: SurfaceId, mode: PresentMode) {
+        let Some(entry) = self.surfaces.get_mut(id) else {
+            return;
+        };
+
+        let Some(surface) = &entry.surface else {
+            entry.config.present_mode = mode;
+            return;
+        };
+
+        let surface = surface.borrow_dependent();
+        let caps = surface.get_capabilities(&self.adapter);
+
+        // automatic modes are always supported
+        let is_auto = matches!(mode, PresentMode::AutoVsync | PresentMode::AutoNoVsync);
+        entry.config.present_mode = if is_auto || caps.present_modes.contains(&mode) {
+            mode
+        } else {
+            PresentMode::AutoVsync
+        };
+
+        surface.configure(&self.device, &entry.config);
+    }
+
+    fn set_surface_usage(&mut self, id: SurfaceId, usage: TextureUsages) -> Result<()> {
+        let Some(entry) = self.surfaces.get_mut(id) else {
+            return Err(Error::new(ErrorKind::Other, "invalid surface"));
+        };
+
+        let usage = usage | TextureUsages::RENDER_ATTACHMENT;
+
+        let Some(surface) = &entry.surface else {
+            entry.config.usage = usage;
+            return Ok(());
+        };
+
+        let surface = surface.borrow_dependent();
+        let caps = surface.get_capabilities(&self.adapter);
+        if !caps.usages.contains(usage) {
+            return Err(Error::new(
+                ErrorKind::Gpu,
+                format!("unsupported surface usage: {usage:?}"),
+            ));
+        }
+
+        entry.config.usage = usage;
+        surface.configure(&self.device, &entry.config);
+
+        Ok(())
+    }
+
+    fn read_surface(&mut self, id: SurfaceId) -> Result<ImageData> {
+        let Some(entry) = self.surfaces.get(id) else {
+            return Err(Error::new(ErrorKind::Other, "invalid surface"));
+        };
+
+        let size = UVec2::new(entry.config.width, entry.config.height);
+
+        if entry.framebuffer_format != TextureFormat::Rgba8UnormSrgb {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "can't read back an HDR surface",
+            ));
+        }
+
+        // the framebuffer is skipped, so read the swapchain texture, which is
+        // always Rgba8UnormSrgb
+        let texture = if entry.direct_present {
+            if !entry.config.usage.contains(TextureUsages::COPY_SRC) {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "can't read back a directly presented surface without COPY_SRC usage",
+                ));
+            }
 
-        entry.texture_view = texture_view;
-        entry.texture_view_srgbless = texture_view_srgbless;
+            let frame = self
+                .to_present
+                .iter()
+                .find(|(surface, _)| *surface == id)
+                .map(|(_, frame)| frame);
+
+            let Some(frame) = frame else {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "directly presented surface has no frame pending presentation",
+                ));
+            };
+
+            &frame.texture
+        } else {
+            &entry.framebuffer().texture
+        };
+
+        read_texture(
+            &self.device,
+            &self.queue,
+            texture,
+            ImageFormat::Srgba8,
+            URect::new(UVec2::ZERO, size),
+        )
+    }
+
+    fn read_texture_region(&mut self, id: TextureId, rect: URect) -> Result<ImageData> {
+        let Some(entry) = self.textures.get(&id) else {
+            return Err(Error::new(ErrorKind::Other, "texture doesn't exist"));
+        };
+
+        if !entry.desc.usage.contains(TextureUsages::COPY_SRC) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "can't read back a texture without COPY_SRC usage",
+            ));
+        }
+
+        let format = match entry.desc.format {
+            TextureFormat::R8Unorm => ImageFormat::Gray8,
+            TextureFormat::Rgba8Unorm => ImageFormat::Rgba8,
+            TextureFormat::Rgba8UnormSrgb => ImageFormat::Srgba8,
+            format => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("can't read back a texture with format {format:?}"),
+                ))
+            }
+        };
+
+        let size = UVec2::new(entry.desc.size.width, entry.desc.size.height);
+        if rect.min.cmpge(rect.max).any() || rect.max.cmpgt(size).any() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "texture region is empty or out of bounds",
+            ));
+        }
+
+        read_texture(&self.device, &self.queue, &entry.texture, format, rect)
     }
 
     fn destroy_surface(&mut self, id: SurfaceId) {
@@ -495,6 +1242,76 @@ impl RendererContext {
         self.texture_mark_mipmaps_dirty(dst_id);
     }
 
+    fn texture_cmd_write_many(&mut self, dst_id: TextureId, writes: Vec<(URect, ImageData)>) {
+        let texture = &self.textures[&dst_id].texture;
+
+        // all writes share one staging buffer, with rows aligned for copying
+        let mut staging = Vec::new();
+        let mut layouts = Vec::with_capacity(writes.len());
+
+        for (dst_rect, data) in &writes {
+            let size = dst_rect.size();
+            if size.x == 0 || size.y == 0 {
+                continue;
+            }
+
+            let row_size = data.data.len() / (size.y as usize);
+            let padded_row_size = row_size.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+            let offset = staging.len();
+
+            for row in data.data.chunks_exact(row_size) {
+                staging.extend_from_slice(row);
+                staging.resize(staging.len() + padded_row_size - row_size, 0);
+            }
+
+            layouts.push((*dst_rect, offset as u64, padded_row_size as u32));
+        }
+
+        if layouts.is_empty() {
+            return;
+        }
+
+        let buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: &staging,
+            usage: BufferUsages::COPY_SRC,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+
+        for (dst_rect, offset, bytes_per_row) in layouts {
+            let size = dst_rect.size();
+            encoder.copy_buffer_to_texture(
+                ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: ImageDataLayout {
+                        offset,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(size.y),
+                    },
+                },
+                ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: dst_rect.min.x,
+                        y: dst_rect.min.y,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.texture_mark_mipmaps_dirty(dst_id);
+    }
+
     fn texture_generate_mipmaps(&mut self, encoder: &mut CommandEncoder, id: TextureId) {
         let entry = &self.textures[&id];
 
@@ -543,13 +1360,14 @@ impl RendererContext {
 
             let blit_render_pipeline = self
                 .blit_render_pipelines
-                .entry(entry.desc.format)
+                .entry((entry.desc.format, BlitMode::Copy))
                 .or_insert_with(|| {
                     create_blit_render_pipeline(
                         &self.device,
                         &self.blit_render_pipeline_layout,
                         &self.blit_render_pipeline_shader_module,
                         entry.desc.format,
+                        BlitMode::Copy,
                     )
                 });
 
@@ -578,8 +1396,9 @@ impl RendererContext {
     }
 
     fn get_surface_size(&self, id: SurfaceId) -> UVec2 {
-        let config = &self.surfaces[id].config;
-        UVec2::new(config.width, config.height)
+        self.surfaces.get(id).map_or(UVec2::ZERO, |entry| {
+            UVec2::new(entry.config.width, entry.config.height)
+        })
     }
 
     fn update_textures(&mut self, commands: &mut Vec<TextureCommand>) {
@@ -587,6 +1406,8 @@ impl RendererContext {
             return;
         }
 
+        TextureCommand::coalesce_writes(commands);
+
         for command in commands.drain(..) {
             match command {
                 TextureCommand::CreateStatic {
@@ -623,6 +1444,10 @@ impl RendererContext {
                     self.texture_cmd_write(dst_id, dst_rect, data);
                 }
 
+                TextureCommand::WriteMany { dst_id, writes } => {
+                    self.texture_cmd_write_many(dst_id, writes);
+                }
+
                 TextureCommand::Free { id } => {
                     self.textures.remove(&id);
                 }
@@ -636,7 +1461,7 @@ impl RendererContext {
                 continue;
             }
 
-            if entry.desc.format != TextureFormat::Rgba8UnormSrgb {
+            if !can_generate_mipmaps(entry.desc.format) {
                 entry.mipmaps_dirty = false;
                 continue;
             }
@@ -661,18 +1486,49 @@ impl RendererContext {
         texture_cache: &TextureCache,
         path_cache: &mut PathCache,
         draw_lists: &[DrawList<'_>],
-    ) {
+        limits: FrameLimits,
+        debug_overlay: DebugOverlay,
+    ) -> Result<()> {
         let mut batcher = Batcher::new(
             &mut self.batcher_scratch,
             texture_cache,
             path_cache,
-            MAX_INSTANCES_PER_BUFFER,
+            self.max_instances_per_buffer,
         );
 
+        batcher.set_max_intermediates(limits.max_intermediates);
+        batcher.set_max_vertices(limits.max_vertices);
+        batcher.set_max_indices(limits.max_indices);
+        batcher.set_debug_overlay(debug_overlay);
+
         for list in draw_lists {
             batcher.prepare(list);
         }
 
+        batcher.prepare_debug_overlay();
+
+        // reported when it changes, not on every frame
+        let skipped_intermediates = batcher.skipped_intermediates();
+        if skipped_intermediates > 0 && skipped_intermediates != self.skipped_intermediates {
+            log::warn!(
+                "{skipped_intermediates} layers exceed the limit of {} intermediates and are drawn inline",
+                limits.max_intermediates
+            );
+        }
+
+        self.skipped_intermediates = skipped_intermediates;
+        self.geometry_stats = batcher.geometry_stats();
+
+        if batcher.geometry_exceeded() {
+            return Err(Error::new(
+                ErrorKind::Gpu,
+                format!(
+                    "frame geometry exceeds the limit of {} vertices and {} indices",
+                    limits.max_vertices, limits.max_indices
+                ),
+            ));
+        }
+
         for (i, intermediate) in batcher.intermediates().iter().enumerate() {
             let size = intermediate.size;
 
@@ -686,18 +1542,36 @@ impl RendererContext {
             let entry = if intermediate.msaa && self.msaa_samples > 1 {
                 IntermediateEntry {
                     size: intermediate.size,
-                    texture_view: create_draw_texture(&self.device, size.x, size.y, 1)
-                        .create_view(&Default::default()),
+                    texture_view: create_draw_texture(
+                        &self.device,
+                        size.x,
+                        size.y,
+                        1,
+                        TextureFormat::Rgba8UnormSrgb,
+                    )
+                    .create_view(&Default::default()),
                     texture_view_msaa: Some(
-                        create_draw_texture(&self.device, size.x, size.y, self.msaa_samples)
-                            .create_view(&Default::default()),
+                        create_draw_texture(
+                            &self.device,
+                            size.x,
+                            size.y,
+                            self.msaa_samples,
+                            TextureFormat::Rgba8UnormSrgb,
+                        )
+                        .create_view(&Default::default()),
                     ),
                 }
             } else {
                 IntermediateEntry {
                     size: intermediate.size,
-                    texture_view: create_draw_texture(&self.device, size.x, size.y, 1)
-                        .create_view(&Default::default()),
+                    texture_view: create_draw_texture(
+                        &self.device,
+                        size.x,
+                        size.y,
+                        1,
+                        TextureFormat::Rgba8UnormSrgb,
+                    )
+                    .create_view(&Default::default()),
                     texture_view_msaa: None,
                 }
             };
@@ -709,66 +1583,248 @@ impl RendererContext {
             }
         }
 
-        let vertex_buffer = create_vertex_buffer(&self.device, batcher.vertices());
-        let index_buffer = create_index_buffer(&self.device, batcher.indices());
+        let device = &self.device;
+        let queue = &self.queue;
+
+        self.vertex_buffer
+            .write(device, queue, &encode_vertices(batcher.vertices()));
+        self.index_buffer
+            .write(device, queue, &encode_indices(batcher.indices()));
+
+        let instance_chunks = encode_instances(
+            batcher.instances(),
+            self.storage_instances,
+            self.max_instances_per_buffer,
+        );
+
+        let instances_usage = if self.storage_instances {
+            BufferUsages::STORAGE
+        } else {
+            BufferUsages::UNIFORM
+        };
+
+        for (i, data) in instance_chunks.iter().enumerate() {
+            if self.instance_buffers.len() <= i {
+                let buffer = GrowableBuffer::new(device, instances_usage);
+                self.instance_buffers.push(buffer);
+            }
+
+            self.instance_buffers[i].write(device, queue, data);
+        }
+
+        // globals of each bind group, in the order of first use
+        let mut bind_group_globals = Vec::new();
+        let mut bind_group_keys = HashMap::new();
+
+        for batch in batcher.batches() {
+            let key = (
+                batch.target,
+                batch.source,
+                batch.sampler,
+                batch.instance_buffer_id,
+                batch.clip,
+            );
+
+            bind_group_keys.entry(key).or_insert_with(|| {
+                let resolution = match batch.target {
+                    Target::Surface(id) => {
+                        let config = &self.surfaces[id].config;
+                        UVec2::new(config.width, config.height).as_vec2()
+                    }
+                    Target::Intermediate(intermediate) => {
+                        self.intermediates[intermediate.0].size.as_vec2()
+                    }
+                };
+
+                let mut globals = Globals {
+                    resolution,
+                    num_clips: 0,
+                    clips: [OurClip::default(); MAX_CLIP_SHAPES],
+                };
+
+                if let Some(clip) = batch.clip {
+                    let shapes = batcher.clip_shapes(clip);
+                    globals.num_clips = shapes.len() as u32;
+                    for (dst, shape) in globals.clips.iter_mut().zip(shapes) {
+                        *dst = OurClip::from(*shape);
+                    }
+                }
+
+                bind_group_globals.push((batch, globals));
+                bind_group_globals.len() - 1
+            });
+        }
+
+        for (i, (_, globals)) in bind_group_globals.iter().enumerate() {
+            if self.globals_buffers.len() <= i {
+                let buffer = GrowableBuffer::new(device, BufferUsages::UNIFORM);
+                self.globals_buffers.push(buffer);
+            }
+
+            self.globals_buffers[i].write(device, queue, &encode_uniform(globals));
+        }
 
         let mut bind_groups = HashMap::new();
 
+        for (key, i) in bind_group_keys {
+            let batch = bind_group_globals[i].0;
+            let texture_view = match batch.source {
+                Source::White => &self.white_texture_view,
+                Source::Texture(id) => self
+                    .textures
+                    .get(&id)
+                    .map(|t| &t.view)
+                    .unwrap_or(&self.white_texture_view),
+                Source::Intermediate(intermediate) => {
+                    &self.intermediates[intermediate.0].texture_view
+                }
+            };
+
+            let bind_group = create_uber_bind_group(
+                &self.device,
+                &self.uber_bind_group_layout,
+                &self.globals_buffers[i],
+                &self.instance_buffers[batch.instance_buffer_id],
+                texture_view,
+                match batch.sampler {
+                    SamplerMode::Linear => &self.sampler,
+                    SamplerMode::Nearest => &self.nearest_sampler,
+                },
+            );
+
+            bind_groups.insert(key, bind_group);
+        }
+
+        // render passes borrow their pipelines, so create them beforehand
         for batch in batcher.batches() {
-            bind_groups
-                .entry((batch.target, batch.source, batch.instance_buffer_id))
-                .or_insert_with(|| {
-                    let resolution = match batch.target {
-                        Target::Surface(id) => {
-                            let config = &self.surfaces[id].config;
-                            UVec2::new(config.width, config.height).as_vec2()
-                        }
-                        Target::Intermediate(intermediate) => {
-                            self.intermediates[intermediate.0].size.as_vec2()
-                        }
-                    };
+            let kind = pipeline_kind(batch);
+            let format = target_format(&self.surfaces, batch.target);
+            if format == TextureFormat::Rgba8UnormSrgb
+                && !matches!(kind, PipelineKind::BlendMode(_) | PipelineKind::Mask)
+            {
+                continue;
+            }
 
-                    let globals = Globals { resolution };
-
-                    let texture_view = match batch.source {
-                        Source::White => &self.white_texture_view,
-                        Source::Texture(id) => self
-                            .textures
-                            .get(&id)
-                            .map(|t| &t.view)
-                            .unwrap_or(&self.white_texture_view),
-                        Source::Intermediate(intermediate) => {
-                            &self.intermediates[intermediate.0].texture_view
-                        }
-                    };
+            let samples = match batch.target {
+                Target::Intermediate(intermediate)
+                    if self.intermediates[intermediate.0]
+                        .texture_view_msaa
+                        .is_some() =>
+                {
+                    self.msaa_samples
+                }
+                _ => 1,
+            };
 
-                    create_uber_bind_group(
+            self.uber_render_pipelines_lazy
+                .entry((kind, samples, format))
+                .or_insert_with(|| {
+                    create_uber_render_pipeline(
                         &self.device,
-                        &self.uber_bind_group_layout,
-                        &globals,
-                        batcher
-                            .instances()
-                            .chunks(MAX_INSTANCES_PER_BUFFER)
-                            .nth(batch.instance_buffer_id)
-                            .unwrap_or(&[]),
-                        texture_view,
-                        &self.sampler,
+                        &self.uber_pipeline_layout,
+                        &self.uber_shader_module,
+                        pipeline_blend(kind),
+                        samples,
+                        format,
                     )
                 });
         }
 
+        let blur_size = batcher
+            .batches()
+            .iter()
+            .filter(|batch| batch.blur_radius > 0.0)
+            .filter_map(|batch| match batch.target {
+                Target::Intermediate(intermediate) => Some(self.intermediates[intermediate.0].size),
+                Target::Surface(_) => None,
+            })
+            .reduce(UVec2::max);
+
+        if let Some(size) = blur_size {
+            for mode in [BlitMode::BlurX, BlitMode::BlurY] {
+                self.blit_render_pipelines
+                    .entry((TextureFormat::Rgba8UnormSrgb, mode))
+                    .or_insert_with(|| {
+                        create_blit_render_pipeline(
+                            &self.device,
+                            &self.blur_render_pipeline_layout,
+                            &self.blit_render_pipeline_shader_module,
+                            TextureFormat::Rgba8UnormSrgb,
+                            mode,
+                        )
+                    });
+            }
+
+            let old_size = self.blur_scratch.as_ref().map_or(UVec2::ZERO, |v| v.0);
+            if size.cmpgt(old_size).any() {
+                let size = size.max(old_size);
+                let texture = create_draw_texture(
+                    &self.device,
+                    size.x,
+                    size.y,
+                    1,
+                    TextureFormat::Rgba8UnormSrgb,
+                );
+                self.blur_scratch = Some((size, texture.create_view(&Default::default())));
+            }
+        }
+
         let mut encoder = self.device.create_command_encoder(&Default::default());
         let mut batches = batcher.batches().iter().peekable();
 
-        let mut touched_surfaces = HashSet::new();
+        // ordered, so that blits are always encoded in the same order
+        let mut touched_surfaces = BTreeSet::new();
+        let mut direct_views = HashMap::new();
+        let mut skipped_surfaces = BTreeSet::new();
+        let mut cleared_surfaces = BTreeSet::new();
+
+        self.to_present.clear();
+
+        for batch in batcher.batches() {
+            let Target::Surface(id) = batch.target else {
+                continue;
+            };
+
+            if !self.surfaces[id].direct_present
+                || direct_views.contains_key(&id)
+                || skipped_surfaces.contains(&id)
+            {
+                continue;
+            }
+
+            match acquire_frame(&self.device, &self.surfaces[id])? {
+                Some(frame) => {
+                    let view = frame.texture.create_view(&TextureViewDescriptor::default());
+                    self.to_present.push((id, frame));
+                    direct_views.insert(id, view);
+                }
+                None => {
+                    skipped_surfaces.insert(id);
+                }
+            }
+        }
 
         encoder.push_debug_group("ohm");
 
         while let Some(batch) = batches.peek() {
+            let mut load = LoadOp::Load;
+
             let view = match batch.target {
+                Target::Surface(id) if skipped_surfaces.contains(&id) => {
+                    batches.next();
+                    continue;
+                }
+                Target::Surface(id) if self.surfaces[id].direct_present => {
+                    // swapchain contents are undefined, so clear on first use
+                    if cleared_surfaces.insert(id) {
+                        load = LoadOp::Clear(wgpu::Color::TRANSPARENT);
+                    }
+
+                    &direct_views[&id]
+                }
                 Target::Surface(id) => {
                     touched_surfaces.insert(id);
-                    &self.surfaces[id].texture_view
+                    &self.surfaces[id].framebuffer().texture_view
                 }
                 Target::Intermediate(intermediate) => {
                     let intermediate = &self.intermediates[intermediate.0];
@@ -802,7 +1858,7 @@ impl RendererContext {
                     view,
                     resolve_target,
                     ops: Operations {
-                        load: LoadOp::Load,
+                        load,
                         store: StoreOp::Store,
                     },
                 })],
@@ -811,27 +1867,71 @@ impl RendererContext {
                 occlusion_query_set: None,
             });
 
-            let pipeline = match (is_msaa, batch.clear) {
-                (true, true) => &self.uber_render_pipeline_noblend_msaa,
-                (true, false) => &self.uber_render_pipeline_msaa,
-                (false, true) => &self.uber_render_pipeline_noblend,
-                (false, false) => &self.uber_render_pipeline,
-            };
-
-            pass.set_pipeline(pipeline);
-            pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint32);
+            pass.set_vertex_buffer(0, self.vertex_buffer.buffer.slice(..));
+            pass.set_index_buffer(self.index_buffer.buffer.slice(..), IndexFormat::Uint32);
 
             let target = batch.target;
-            let clear = batch.clear;
+            let target_format = target_format(&self.surfaces, target);
+            let mut cur_pipeline = None;
+            let mut cur_blend_constant = None;
+            let mut blur_radius = 0.0;
+
+            while let Some(batch) = batches.next_if(|b| b.target == target) {
+                if batch.blur_radius > 0.0 {
+                    blur_radius = batch.blur_radius;
+                }
 
-            while let Some(batch) = batches.next_if(|b| b.clear == clear && b.target == target) {
                 if batch.index_range.is_empty() {
                     continue;
                 }
 
+                let pipeline = pipeline_kind(batch);
+
+                if cur_pipeline != Some(pipeline) {
+                    let pipeline = match (is_msaa, pipeline) {
+                        (_, kind) if target_format != TextureFormat::Rgba8UnormSrgb => {
+                            &self.uber_render_pipelines_lazy[&(kind, 1, target_format)]
+                        }
+                        (true, PipelineKind::Blend) => &self.uber_render_pipeline_msaa,
+                        (true, PipelineKind::NoBlend) => &self.uber_render_pipeline_noblend_msaa,
+                        (true, PipelineKind::Subpixel) => &self.uber_render_pipeline_subpixel_msaa,
+                        (false, PipelineKind::Blend) => &self.uber_render_pipeline,
+                        (false, PipelineKind::NoBlend) => &self.uber_render_pipeline_noblend,
+                        (false, PipelineKind::Subpixel) => &self.uber_render_pipeline_subpixel,
+                        (_, kind @ (PipelineKind::BlendMode(_) | PipelineKind::Mask)) => {
+                            let samples = if is_msaa { self.msaa_samples } else { 1 };
+                            &self.uber_render_pipelines_lazy[&(kind, samples, target_format)]
+                        }
+                    };
+
+                    pass.set_pipeline(pipeline);
+                }
+
+                cur_pipeline = Some(pipeline);
+
+                if let Some(color) = batch.subpixel_color {
+                    if cur_blend_constant != Some(color) {
+                        // the blend constant is the non-premultiplied color, as
+                        // alpha is already applied to the coverage
+                        let a = color.a.max(1e-6);
+                        pass.set_blend_constant(wgpu::Color {
+                            r: f64::from(color.r / a),
+                            g: f64::from(color.g / a),
+                            b: f64::from(color.b / a),
+                            a: 1.0,
+                        });
+                        cur_blend_constant = Some(color);
+                    }
+                }
+
                 let bind_group = bind_groups
-                    .get(&(batch.target, batch.source, batch.instance_buffer_id))
+                    .get(&(
+                        batch.target,
+                        batch.source,
+                        batch.sampler,
+                        batch.instance_buffer_id,
+                        batch.clip,
+                    ))
                     .unwrap();
                 pass.set_bind_group(0, bind_group, &[]);
                 pass.draw_indexed(batch.index_range.clone(), 0, 0..1);
@@ -839,30 +1939,66 @@ impl RendererContext {
 
             drop(pass);
             encoder.pop_debug_group(); // pass
-        }
 
-        self.to_present.clear();
+            if let (Target::Intermediate(intermediate), true) = (target, blur_radius > 0.0) {
+                let intermediate = &self.intermediates[intermediate.0];
+                let (_, scratch_view) = self.blur_scratch.as_ref().unwrap();
+                let pipelines = [BlitMode::BlurX, BlitMode::BlurY].map(|mode| {
+                    &self.blit_render_pipelines[&(TextureFormat::Rgba8UnormSrgb, mode)]
+                });
+
+                encoder.push_debug_group("blur");
+                encode_blur(
+                    &self.device,
+                    &mut encoder,
+                    &self.blur_bind_group_layout,
+                    pipelines,
+                    &intermediate.texture_view,
+                    scratch_view,
+                    intermediate.size,
+                    blur_radius,
+                );
+                encoder.pop_debug_group(); // blur
+            }
+        }
 
         encoder.push_debug_group("blit");
 
         for surface in touched_surfaces {
             let surface_entry = &self.surfaces[surface];
+
+            // offscreen surfaces have nothing to blit onto
+            if surface_entry.surface.is_none() {
+                continue;
+            }
+
             let surface_format = surface_entry.config.format;
-            let frame = surface_entry
-                .surface
-                .borrow_dependent()
-                .get_current_texture()
-                .expect("Failed to acquire next swap chain texture");
+            let Some(frame) = acquire_frame(&self.device, surface_entry)? else {
+                continue;
+            };
             let surface_view = frame.texture.create_view(&TextureViewDescriptor::default());
-            self.to_present.push(frame);
+            self.to_present.push((surface, frame));
+
+            // float swapchains are linear, others are sRGB encoded, either by
+            // the format or by copying the encoded framebuffer as is
+            let is_float = is_float_format(surface_format);
+            let is_hdr_framebuffer =
+                surface_entry.framebuffer_format != TextureFormat::Rgba8UnormSrgb;
+            let blit_mode = if !is_hdr_framebuffer || is_float {
+                BlitMode::Copy
+            } else if surface_format.is_srgb() {
+                BlitMode::ToneMap
+            } else {
+                BlitMode::ToneMapEncode
+            };
 
             let bind_group = create_blit_bind_group(
                 &self.device,
                 &self.blit_bind_group_layout,
-                if surface_format.is_srgb() {
-                    &surface_entry.texture_view
+                if surface_format.is_srgb() || is_float {
+                    &surface_entry.framebuffer().texture_view
                 } else {
-                    &surface_entry.texture_view_srgbless
+                    &surface_entry.framebuffer().texture_view_srgbless
                 },
                 &self.sampler,
             );
@@ -884,13 +2020,14 @@ impl RendererContext {
 
             let blit_render_pipeline = self
                 .blit_render_pipelines
-                .entry(surface_format)
+                .entry((surface_format, blit_mode))
                 .or_insert_with(|| {
                     create_blit_render_pipeline(
                         &self.device,
                         &self.blit_render_pipeline_layout,
                         &self.blit_render_pipeline_shader_module,
                         surface_format,
+                        blit_mode,
                     )
                 });
 
@@ -905,10 +2042,12 @@ impl RendererContext {
         encoder.pop_debug_group(); // ohm
 
         self.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(())
     }
 
     fn present(&mut self) {
-        for frame in self.to_present.drain(..) {
+        for (_, frame) in self.to_present.drain(..) {
             frame.present();
         }
     }
@@ -923,6 +2062,7 @@ struct OurVertex {
     tex: Vec2,
     color: Vec4,
     instance_id: u32,
+    custom: Vec4,
 }
 
 #[repr(C)]
@@ -937,26 +2077,84 @@ struct OurInstance {
     border_width: f32,
     shadow_blur_radius: f32,
     shadow_spread_radius: f32,
+    flags: u32,
+    gradient: Vec4,
+    gradient_tex: Vec4,
 }
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, encase::ShaderType)]
 struct Globals {
     resolution: Vec2,
+    num_clips: u32,
+    clips: [OurClip; MAX_CLIP_SHAPES],
 }
 
-#[repr(C)]
 #[derive(Debug, Clone, Copy, encase::ShaderType)]
-struct RectInstances {
-    arr: [OurInstance; MAX_INSTANCES_PER_BUFFER],
+struct BlurParams {
+    size: UVec2,
+    sigma: f32,
+    radius: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, encase::ShaderType)]
+struct OurClip {
+    // columns of the matrix, from target pixels to the local space of the rect
+    transform: Vec4,
+    translation: Vec2,
+    // center and half size
+    rect: Vec4,
+    corner_radii: Vec4,
+}
+
+impl From<ClipShape> for OurClip {
+    fn from(shape: ClipShape) -> OurClip {
+        let matrix = shape.transform.matrix2;
+        let center = (shape.rect.min + shape.rect.max) * 0.5;
+        let half_size = shape.rect.size() * 0.5;
+        OurClip {
+            transform: Vec4::new(
+                matrix.x_axis.x,
+                matrix.x_axis.y,
+                matrix.y_axis.x,
+                matrix.y_axis.y,
+            ),
+            translation: shape.transform.translation,
+            rect: Vec4::new(center.x, center.y, half_size.x, half_size.y),
+            corner_radii: shape.corner_radii.into(),
+        }
+    }
+}
+
+impl From<&BatcherInstance> for OurInstance {
+    fn from(v: &BatcherInstance) -> OurInstance {
+        OurInstance {
+            corner_radii: v.corner_radii,
+            border_color: v.border_color,
+            shadow_color: v.shadow_color,
+            shadow_offset: v.shadow_offset,
+            size: v.size,
+            border_width: v.border_width,
+            shadow_blur_radius: v.shadow_blur_radius,
+            shadow_spread_radius: v.shadow_spread_radius,
+            flags: v.flags,
+            gradient: v.gradient,
+            gradient_tex: v.gradient_tex,
+        }
+    }
 }
 
-async fn create_adapter(instance: &Instance, main_surface: &Surface<'_>) -> Result<Adapter> {
+async fn create_adapter(
+    instance: &Instance,
+    main_surface: Option<&Surface<'_>>,
+    options: AdapterOptions,
+) -> Result<Adapter> {
     let adapter = instance
         .request_adapter(&RequestAdapterOptions {
-            power_preference: PowerPreference::HighPerformance,
-            force_fallback_adapter: false,
-            compatible_surface: Some(main_surface),
+            power_preference: options.power_preference,
+            force_fallback_adapter: options.force_fallback_adapter,
+            compatible_surface: main_surface,
         })
         .await;
 
@@ -967,6 +2165,25 @@ async fn create_adapter(instance: &Instance, main_surface: &Surface<'_>) -> Resu
     Ok(adapter)
 }
 
+fn supported_msaa_samples(adapter: &Adapter, max_samples: u32) -> u32 {
+    let format_flags = adapter
+        .get_texture_format_features(TextureFormat::Rgba8UnormSrgb)
+        .flags;
+
+    let mut msaa_samples = 1;
+    if max_samples >= 2 && format_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X2) {
+        msaa_samples = 2;
+    }
+    if max_samples >= 4 && format_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X4) {
+        msaa_samples = 4;
+    }
+    if max_samples >= 8 && format_flags.contains(TextureFormatFeatureFlags::MULTISAMPLE_X8) {
+        msaa_samples = 8;
+    }
+
+    msaa_samples
+}
+
 async fn create_device(adapter: &Adapter) -> Result<(Device, Queue)> {
     adapter
         .request_device(
@@ -981,7 +2198,29 @@ async fn create_device(adapter: &Adapter) -> Result<(Device, Queue)> {
         .map_err(|e| Error::new(ErrorKind::Gpu, "failed to create graphics device").with_source(e))
 }
 
-fn create_uber_bind_group_layout(device: &Device) -> BindGroupLayout {
+fn supports_storage_instances(adapter: &Adapter, device: &Device) -> bool {
+    let flags = adapter.get_downlevel_capabilities().flags;
+    flags.contains(DownlevelFlags::FRAGMENT_STORAGE)
+        && device.limits().max_storage_buffers_per_shader_stage > 0
+}
+
+fn uber_shader_source(src: &str, storage_instances: bool) -> Cow<'_, str> {
+    if storage_instances {
+        return Cow::Borrowed(src);
+    }
+
+    let decl =
+        format!("var<uniform> rect_instances: array<RectInstance, {MAX_INSTANCES_PER_BUFFER}>;");
+    Cow::Owned(src.replace(STORAGE_INSTANCES_DECL, &decl))
+}
+
+fn create_uber_bind_group_layout(device: &Device, storage_instances: bool) -> BindGroupLayout {
+    let instances_ty = if storage_instances {
+        BufferBindingType::Storage { read_only: true }
+    } else {
+        BufferBindingType::Uniform
+    };
+
     device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         label: None,
         entries: &[
@@ -997,9 +2236,9 @@ fn create_uber_bind_group_layout(device: &Device) -> BindGroupLayout {
             },
             BindGroupLayoutEntry {
                 binding: 1,
-                visibility: ShaderStages::VERTEX_FRAGMENT,
+                visibility: ShaderStages::FRAGMENT,
                 ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
+                    ty: instances_ty,
                     has_dynamic_offset: false,
                     min_binding_size: None,
                 },
@@ -1049,7 +2288,102 @@ fn create_blit_bind_group_layout(device: &Device) -> BindGroupLayout {
     })
 }
 
-fn create_vertex_buffer(device: &Device, vertices: &[Vertex]) -> Buffer {
+fn create_blur_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+// blurs the top left `size` pixels of the view horizontally into the scratch
+// texture, then vertically back
+#[allow(clippy::too_many_arguments)]
+fn encode_blur(
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    layout: &BindGroupLayout,
+    [pipeline_x, pipeline_y]: [&RenderPipeline; 2],
+    view: &TextureView,
+    scratch_view: &TextureView,
+    size: UVec2,
+    radius: f32,
+) {
+    let params = BlurParams {
+        size,
+        sigma: (radius * 0.5).max(1e-3),
+        radius: (radius.ceil() as u32).min(MAX_BLUR_TAPS),
+    };
+
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: &encode_uniform(&params),
+        usage: BufferUsages::UNIFORM,
+    });
+
+    for (src, dst, pipeline) in [
+        (view, scratch_view, pipeline_x),
+        (scratch_view, view, pipeline_y),
+    ] {
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(src),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: dst,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        // the scratch texture may be larger than the layer
+        pass.set_viewport(0.0, 0.0, size.x as f32, size.y as f32, 0.0, 1.0);
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn encode_vertices(vertices: &[Vertex]) -> Vec<u8> {
     let vertices = vertices
         .iter()
         .map(|v| OurVertex {
@@ -1058,6 +2392,7 @@ fn create_vertex_buffer(device: &Device, vertices: &[Vertex]) -> Buffer {
             tex: v.tex,
             color: v.color,
             instance_id: v.instance_id,
+            custom: v.custom,
         })
         .collect::<Vec<_>>();
 
@@ -1068,83 +2403,148 @@ fn create_vertex_buffer(device: &Device, vertices: &[Vertex]) -> Buffer {
         )
     };
 
-    device.create_buffer_init(&BufferInitDescriptor {
-        label: None,
-        contents,
-        usage: BufferUsages::VERTEX,
-    })
+    contents.to_vec()
 }
 
-fn create_index_buffer(device: &Device, data: &[u32]) -> Buffer {
+fn encode_indices(data: &[u32]) -> Vec<u8> {
     let contents = unsafe {
         std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
     };
 
-    device.create_buffer_init(&BufferInitDescriptor {
-        label: None,
-        contents,
-        usage: BufferUsages::INDEX,
-    })
+    contents.to_vec()
 }
 
-fn create_uniform_buffer<T: encase::ShaderType + encase::internal::WriteInto>(
-    device: &Device,
-    data: &T,
-) -> Buffer {
+// `None` if the frame should be skipped, e.g. after a resize
+fn acquire_frame(device: &Device, entry: &SurfaceEntry) -> Result<Option<SurfaceTexture>> {
+    let Some(surface) = &entry.surface else {
+        return Ok(None);
+    };
+
+    let surface = surface.borrow_dependent();
+    match surface.get_current_texture() {
+        Ok(frame) => Ok(Some(frame)),
+        Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+            surface.configure(device, &entry.config);
+            Ok(None)
+        }
+        Err(SurfaceError::Timeout) => Ok(None),
+        Err(error @ SurfaceError::OutOfMemory) => Err(Error::wrap(ErrorKind::Gpu, error)),
+    }
+}
+
+fn encode_uniform<T: encase::ShaderType + encase::internal::WriteInto>(data: &T) -> Vec<u8> {
     let mut buffer = encase::UniformBuffer::new(Vec::new());
     buffer.write(data).unwrap();
+    buffer.into_inner()
+}
+
+// one chunk per `Batch::instance_buffer_id`, there's always at least one with
+// at least one instance, as empty bindings aren't allowed
+fn encode_instances(
+    instances: &[BatcherInstance],
+    storage_instances: bool,
+    max_instances_per_buffer: usize,
+) -> Vec<Vec<u8>> {
+    let mut chunks = instances
+        .chunks(max_instances_per_buffer)
+        .collect::<Vec<_>>();
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+
+    chunks
+        .into_iter()
+        .map(|chunk| {
+            if !storage_instances {
+                let mut arr = [OurInstance::default(); MAX_INSTANCES_PER_BUFFER];
+                for (dst, v) in arr.iter_mut().zip(chunk) {
+                    *dst = OurInstance::from(v);
+                }
+
+                return encode_uniform(&arr);
+            }
+
+            let mut arr = chunk.iter().map(OurInstance::from).collect::<Vec<_>>();
+            if arr.is_empty() {
+                arr.push(OurInstance::default());
+            }
+
+            let mut buffer = encase::StorageBuffer::new(Vec::new());
+            buffer.write(&arr).unwrap();
+            buffer.into_inner()
+        })
+        .collect()
+}
+
+// GPU buffer that is written every frame, grows to the next power of two when
+// the data doesn't fit
+#[derive(Debug)]
+struct GrowableBuffer {
+    buffer: Buffer,
+    usage: BufferUsages,
+    len: u64,
+}
+
+impl GrowableBuffer {
+    fn new(device: &Device, usage: BufferUsages) -> GrowableBuffer {
+        GrowableBuffer {
+            buffer: create_buffer(device, usage, MIN_BUFFER_SIZE),
+            usage,
+            len: 0,
+        }
+    }
+
+    fn write(&mut self, device: &Device, queue: &Queue, data: &[u8]) {
+        let len = data.len() as u64;
+        if len > self.buffer.size() {
+            self.buffer = create_buffer(device, self.usage, len.next_power_of_two());
+        }
+
+        if len > 0 {
+            queue.write_buffer(&self.buffer, 0, data);
+        }
+
+        self.len = len;
+    }
+
+    // only the written part, bindings of uniform buffers are limited in size
+    fn binding(&self) -> BindingResource<'_> {
+        BindingResource::Buffer(BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: NonZeroU64::new(self.len),
+        })
+    }
+}
 
-    let encoded = buffer.into_inner();
-    device.create_buffer_init(&BufferInitDescriptor {
+fn create_buffer(device: &Device, usage: BufferUsages, size: u64) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
         label: None,
-        contents: &encoded,
-        usage: BufferUsages::UNIFORM,
+        size,
+        usage: usage | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
     })
 }
 
 fn create_uber_bind_group(
     device: &Device,
     layout: &BindGroupLayout,
-    globals: &Globals,
-    instances: &[BatcherInstance],
+    globals_buffer: &GrowableBuffer,
+    instances_buffer: &GrowableBuffer,
     texture_view: &TextureView,
     sampler: &Sampler,
 ) -> BindGroup {
-    let globals_buffer = create_uniform_buffer(device, globals);
-
-    let mut rect_instances_arr = [OurInstance::default(); MAX_INSTANCES_PER_BUFFER];
-
-    for (i, v) in instances.iter().enumerate() {
-        rect_instances_arr[i] = OurInstance {
-            corner_radii: v.corner_radii,
-            border_color: v.border_color,
-            shadow_color: v.shadow_color,
-            shadow_offset: v.shadow_offset,
-            size: v.size,
-            border_width: v.border_width,
-            shadow_blur_radius: v.shadow_blur_radius,
-            shadow_spread_radius: v.shadow_spread_radius,
-        };
-    }
-
-    let rect_instances_buffer = create_uniform_buffer(
-        device,
-        &RectInstances {
-            arr: rect_instances_arr,
-        },
-    );
-
     device.create_bind_group(&BindGroupDescriptor {
         label: None,
         layout,
         entries: &[
             BindGroupEntry {
                 binding: 0,
-                resource: globals_buffer.as_entire_binding(),
+                resource: globals_buffer.binding(),
             },
             BindGroupEntry {
                 binding: 1,
-                resource: rect_instances_buffer.as_entire_binding(),
+                resource: instances_buffer.binding(),
             },
             BindGroupEntry {
                 binding: 2,
@@ -1195,12 +2595,80 @@ fn create_shader_module(device: &Device, source: &str) -> ShaderModule {
     })
 }
 
+const BLEND: Option<BlendState> = Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+
+// subpixel glyphs output per-channel coverage, which is multiplied by the
+// glyph color set as the blend constant
+const SUBPIXEL_BLEND: Option<BlendState> = Some(BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Constant,
+        dst_factor: BlendFactor::OneMinusSrc,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent::OVER,
+});
+
+// keeps the destination where the mask is opaque
+const MASK_BLEND: Option<BlendState> = Some(BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::SrcAlpha,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::SrcAlpha,
+        operation: BlendOperation::Add,
+    },
+});
+
+fn pipeline_blend(kind: PipelineKind) -> Option<BlendState> {
+    match kind {
+        PipelineKind::Blend => BLEND,
+        PipelineKind::NoBlend => None,
+        PipelineKind::Subpixel => SUBPIXEL_BLEND,
+        PipelineKind::BlendMode(blend_mode) => blend_mode_state(blend_mode),
+        PipelineKind::Mask => MASK_BLEND,
+    }
+}
+
+// colors are premultiplied, alpha is always composited with "over"
+fn blend_mode_state(blend_mode: BlendMode) -> Option<BlendState> {
+    let (src_factor, dst_factor, operation) = match blend_mode {
+        BlendMode::Normal => return BLEND,
+        // exact for an opaque backdrop
+        BlendMode::Multiply => (
+            BlendFactor::Dst,
+            BlendFactor::OneMinusSrcAlpha,
+            BlendOperation::Add,
+        ),
+        BlendMode::Screen => (
+            BlendFactor::One,
+            BlendFactor::OneMinusSrc,
+            BlendOperation::Add,
+        ),
+        BlendMode::Add => (BlendFactor::One, BlendFactor::One, BlendOperation::Add),
+        BlendMode::Lighten => (BlendFactor::One, BlendFactor::One, BlendOperation::Max),
+        BlendMode::Darken => (BlendFactor::One, BlendFactor::One, BlendOperation::Min),
+    };
+
+    Some(BlendState {
+        color: BlendComponent {
+            src_factor,
+            dst_factor,
+            operation,
+        },
+        alpha: BlendComponent::OVER,
+    })
+}
+
 fn create_uber_render_pipeline(
     device: &Device,
     layout: &PipelineLayout,
     shader_module: &ShaderModule,
-    blend: bool,
+    blend: Option<BlendState>,
     samples: u32,
+    format: TextureFormat,
 ) -> RenderPipeline {
     device.create_render_pipeline(&RenderPipelineDescriptor {
         label: None,
@@ -1209,7 +2677,7 @@ fn create_uber_render_pipeline(
             module: shader_module,
             entry_point: "vs_main",
             buffers: &[VertexBufferLayout {
-                array_stride: 44,
+                array_stride: 60,
                 step_mode: VertexStepMode::Vertex,
                 attributes: &[
                     VertexAttribute {
@@ -1237,6 +2705,11 @@ fn create_uber_render_pipeline(
                         offset: 40,
                         shader_location: 4,
                     },
+                    VertexAttribute {
+                        format: VertexFormat::Float32x4,
+                        offset: 44,
+                        shader_location: 5,
+                    },
                 ],
             }],
             compilation_options: Default::default(),
@@ -1252,8 +2725,8 @@ fn create_uber_render_pipeline(
             module: shader_module,
             entry_point: "fs_main",
             targets: &[Some(ColorTargetState {
-                format: TextureFormat::Rgba8UnormSrgb,
-                blend: blend.then_some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                format,
+                blend,
                 write_mask: ColorWrites::all(),
             })],
             compilation_options: Default::default(),
@@ -1267,7 +2740,16 @@ fn create_blit_render_pipeline(
     layout: &PipelineLayout,
     shader_module: &ShaderModule,
     format: TextureFormat,
+    mode: BlitMode,
 ) -> RenderPipeline {
+    let entry_point = match mode {
+        BlitMode::Copy => "fs_main",
+        BlitMode::ToneMap => "fs_tone_map",
+        BlitMode::ToneMapEncode => "fs_tone_map_encode",
+        BlitMode::BlurX => "fs_blur_x",
+        BlitMode::BlurY => "fs_blur_y",
+    };
+
     device.create_render_pipeline(&RenderPipelineDescriptor {
         label: None,
         layout: Some(layout),
@@ -1282,7 +2764,7 @@ fn create_blit_render_pipeline(
         multisample: MultisampleState::default(),
         fragment: Some(FragmentState {
             module: shader_module,
-            entry_point: "fs_main",
+            entry_point,
             targets: &[Some(ColorTargetState {
                 format,
                 blend: None,
@@ -1318,24 +2800,42 @@ fn create_white_texture_view(device: &Device, queue: &Queue) -> TextureView {
     texture.create_view(&Default::default())
 }
 
-fn create_sampler(device: &Device) -> Sampler {
+fn create_sampler(device: &Device, filter: FilterMode) -> Sampler {
     device.create_sampler(&SamplerDescriptor {
         label: None,
         address_mode_u: AddressMode::ClampToEdge,
         address_mode_v: AddressMode::ClampToEdge,
         address_mode_w: AddressMode::ClampToEdge,
-        mag_filter: FilterMode::Linear,
-        min_filter: FilterMode::Linear,
-        mipmap_filter: FilterMode::Linear,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter: filter,
         lod_min_clamp: 0.0,
-        lod_max_clamp: 32.0,
+        // nearest filtering only samples the base level
+        lod_max_clamp: if filter == FilterMode::Nearest {
+            0.0
+        } else {
+            32.0
+        },
         compare: None,
         anisotropy_clamp: 1,
         border_color: None,
     })
 }
 
-fn create_draw_texture(device: &Device, width: u32, height: u32, samples: u32) -> Texture {
+fn create_draw_texture(
+    device: &Device,
+    width: u32,
+    height: u32,
+    samples: u32,
+    format: TextureFormat,
+) -> Texture {
+    // sRGB textures can be viewed without the sRGB conversion
+    let view_formats: &[TextureFormat] = if format == TextureFormat::Rgba8UnormSrgb {
+        &[TextureFormat::Rgba8Unorm]
+    } else {
+        &[]
+    };
+
     device.create_texture(&TextureDescriptor {
         label: None,
         size: Extent3d {
@@ -1346,19 +2846,191 @@ fn create_draw_texture(device: &Device, width: u32, height: u32, samples: u32) -
         mip_level_count: 1,
         sample_count: samples,
         dimension: TextureDimension::D2,
-        format: TextureFormat::Rgba8UnormSrgb,
-        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[TextureFormat::Rgba8Unorm],
+        format,
+        usage: TextureUsages::TEXTURE_BINDING
+            | TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_SRC,
+        view_formats,
+    })
+}
+
+// returns the texture, its view and its view without the sRGB conversion
+fn create_framebuffer(
+    device: &Device,
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+) -> Framebuffer {
+    let texture = create_draw_texture(device, width, height, 1, format);
+    let texture_view = texture.create_view(&Default::default());
+
+    let texture_view_srgbless = if format == TextureFormat::Rgba8UnormSrgb {
+        texture.create_view(&TextureViewDescriptor {
+            format: Some(TextureFormat::Rgba8Unorm),
+            ..Default::default()
+        })
+    } else {
+        texture.create_view(&Default::default())
+    };
+
+    Framebuffer {
+        texture,
+        texture_view,
+        texture_view_srgbless,
+    }
+}
+
+fn read_texture(
+    device: &Device,
+    queue: &Queue,
+    texture: &Texture,
+    format: ImageFormat,
+    rect: URect,
+) -> Result<ImageData> {
+    let size = rect.size();
+    let (width, height) = (size.x, size.y);
+
+    // rows in the buffer must be aligned to 256 bytes
+    let row_size = width * format.pixel_size() as u32;
+    let padded_row_size = row_size.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: u64::from(padded_row_size) * u64::from(height),
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: rect.min.x,
+                y: rect.min.y,
+                z: 0,
+            },
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_size),
+                rows_per_image: Some(height),
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let (sender, receiver) = mpsc::channel();
+    let slice = buffer.slice(..);
+    slice.map_async(MapMode::Read, move |res| {
+        let _ = sender.send(res);
+    });
+
+    device.poll(Maintain::Wait);
+
+    receiver
+        .recv()
+        .map_err(|e| Error::wrap(ErrorKind::Gpu, e))?
+        .map_err(|e| Error::wrap(ErrorKind::Gpu, e))?;
+
+    let mut data = Vec::with_capacity((row_size * height) as usize);
+    {
+        let mapped = slice.get_mapped_range();
+        for row in mapped.chunks_exact(padded_row_size as usize) {
+            data.extend_from_slice(&row[..row_size as usize]);
+        }
+    }
+
+    buffer.unmap();
+
+    Ok(ImageData {
+        format,
+        size,
+        data,
+        premultiplied: true,
     })
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BlitMode {
+    Copy,
+    // HDR framebuffer onto an SDR swapchain
+    ToneMap,
+    // same, but the swapchain format doesn't encode sRGB by itself
+    ToneMapEncode,
+    // passes of a layer blur, using the blur pipeline layout
+    BlurX,
+    BlurY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PipelineKind {
+    Blend,
+    NoBlend,
+    Subpixel,
+    BlendMode(BlendMode),
+    Mask,
+}
+
+fn pipeline_kind(batch: &Batch) -> PipelineKind {
+    // clears and opaque draws replace the contents without blending
+    if batch.clear || batch.opaque {
+        PipelineKind::NoBlend
+    } else if batch.mask {
+        PipelineKind::Mask
+    } else if batch.subpixel_color.is_some() {
+        PipelineKind::Subpixel
+    } else if batch.blend_mode != BlendMode::Normal {
+        PipelineKind::BlendMode(batch.blend_mode)
+    } else {
+        PipelineKind::Blend
+    }
+}
+
 fn map_format(format: ImageFormat) -> TextureFormat {
     match format {
         ImageFormat::Srgba8 => TextureFormat::Rgba8UnormSrgb,
         ImageFormat::Gray8 => TextureFormat::R8Unorm,
+        ImageFormat::Rgba8 => TextureFormat::Rgba8Unorm,
+    }
+}
+
+// formats with a blit pipeline for downsampling, sampling an R8 texture
+// returns the value in the red channel, which is all an R8 target stores
+fn can_generate_mipmaps(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::R8Unorm
+    )
+}
+
+// directly presented surfaces are always `Rgba8UnormSrgb`
+fn target_format(surfaces: &SlotMap<SurfaceId, SurfaceEntry>, target: Target) -> TextureFormat {
+    match target {
+        Target::Surface(id) if !surfaces[id].direct_present => surfaces[id].framebuffer_format,
+        _ => TextureFormat::Rgba8UnormSrgb,
     }
 }
 
+fn is_float_format(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Rgba16Float | TextureFormat::Rgba32Float
+    )
+}
+
+// the larger side decides, smaller sides are clamped to 1 in lower levels
 fn mip_count(size: UVec2) -> u32 {
     size.max_element().ilog2() + 1
 }
@@ -1,5 +1,14 @@
+struct Clip {
+    transform: vec4<f32>,
+    translation: vec2<f32>,
+    rect: vec4<f32>,
+    corner_radii: vec4<f32>,
+}
+
 struct Globals {
     resolution: vec2<f32>,    
+    num_clips: u32,
+    clips: array<Clip, 4>,
 }
 
 @group(0) @binding(0)
@@ -14,14 +23,21 @@ struct RectInstance {
     border_width: f32,
     shadow_blur_radius: f32,
     shadow_spread_radius: f32,
+    flags: u32,
+    gradient: vec4<f32>,
+    gradient_tex: vec4<f32>,
 }
 
-struct RectInstances {
-    arr: array<RectInstance, 128>,
-}
+const FLAG_MESH: u32 = 1u;
+const FLAG_CONIC_GRADIENT: u32 = 2u;
+const FLAG_REPEAT_X: u32 = 4u;
+const FLAG_REPEAT_Y: u32 = 8u;
+
+const TAU: f32 = 6.283185307179586;
 
+// becomes a uniform array when fragment shaders can't read storage buffers
 @group(0) @binding(1)
-var<uniform> rect_instances: RectInstances;
+var<storage, read> rect_instances: array<RectInstance>;
 
 @group(0) @binding(2)
 var texture: texture_2d<f32>;
@@ -35,6 +51,7 @@ struct VertexInput {
     @location(2) tex: vec2<f32>,    
     @location(3) color: vec4<f32>,
     @location(4) instance_id: u32,
+    @location(5) custom: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -43,6 +60,7 @@ struct VertexOutput {
     @location(1) tex: vec2<f32>,
     @location(2) color: vec4<f32>,
     @location(3) instance_id: u32,
+    @location(4) custom: vec4<f32>,
 }
 
 @vertex
@@ -60,23 +78,83 @@ fn vs_main(in: VertexInput) -> VertexOutput {
     out.tex = in.tex;
     out.color = in.color;
     out.instance_id = in.instance_id;
+    out.custom = in.custom;
 
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let clip = clip_mask(in.clip_pos.xy);
+    let color = fs_shade(in) * clip;
+
+    // also keeps clears from writing outside of the clip
+    if clip <= 0.0 {
+        discard;
+    }
+
+    // the layer over white, so that min blending keeps transparent areas
+    if in.instance_id == 4294967292u {
+        return vec4(color.rgb + (1.0 - color.a), color.a);
+    }
+
+    return color;
+}
+
+fn clip_mask(pos: vec2<f32>) -> f32 {
+    var mask = 1.0;
+
+    for (var i = 0u; i < globals.num_clips; i++) {
+        let clip = globals.clips[i];
+        let matrix = mat2x2(clip.transform.xy, clip.transform.zw);
+        let p = matrix * pos + clip.translation - clip.rect.xy;
+
+        let dist = sdf_rounded_rect(p, clip.rect.zw, clip.corner_radii);
+        let dist_change = fwidth(dist) * 0.5;
+        mask *= smoothstep(dist_change, -dist_change, dist);
+    }
+
+    return mask;
+}
+
+fn fs_shade(in: VertexOutput) -> vec4<f32> {
     var base_color = textureSample(texture, texture_sampler, in.tex);
 
+    // derivatives must be computed in uniform control flow
+    let tex_dx = dpdx(in.tex);
+    let tex_dy = dpdy(in.tex);
+
     if in.instance_id == 4294967294u {
         return in.color * base_color.r;
     }
 
-    if in.instance_id == 4294967295u {
+    if in.instance_id == 4294967295u || in.instance_id == 4294967292u {
         return in.color * base_color;
     }
 
-    let rect = rect_instances.arr[in.instance_id];
+    // layer shadows, tinted by the shadow color
+    if in.instance_id == 4294967291u {
+        return in.color * base_color.a;
+    }
+
+    // per-channel coverage, the color is applied by the blend constant
+    if in.instance_id == 4294967293u {
+        return base_color * in.color.a;
+    }
+
+    let rect = rect_instances[in.instance_id];
+
+    if (rect.flags & FLAG_CONIC_GRADIENT) != 0u {
+        base_color = conic_gradient(in.pos, rect);
+    }
+
+    if (rect.flags & (FLAG_REPEAT_X | FLAG_REPEAT_Y)) != 0u {
+        base_color = repeated_image(in.tex, tex_dx, tex_dy, rect);
+    }
+
+    if (rect.flags & FLAG_MESH) != 0u {
+        return in.color * base_color;
+    }
 
     let pos = in.pos - rect.size / 2.0;
 
@@ -113,6 +191,37 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return color;
 }
 
+fn conic_gradient(pos: vec2<f32>, rect: RectInstance) -> vec4<f32> {
+    // angle is measured clockwise from the up direction
+    let d = pos - rect.gradient.xy;
+    let angle = atan2(d.x, -d.y) - rect.gradient.z;
+    let t = fract(angle / TAU);
+
+    // ramp is sampled without mipmaps, so that the discontinuity of `t` at the
+    // start angle doesn't produce a seam of wrongly filtered texels
+    let tex = vec2(mix(rect.gradient_tex.x, rect.gradient_tex.y, t), rect.gradient_tex.z);
+    let color = textureSampleLevel(texture, texture_sampler, tex, 0.0);
+    return vec4(color.rgb * color.a, color.a);
+}
+
+fn repeated_image(tex: vec2<f32>, tex_dx: vec2<f32>, tex_dy: vec2<f32>, rect: RectInstance) -> vec4<f32> {
+    let repeat = vec2(
+        (rect.flags & FLAG_REPEAT_X) != 0u,
+        (rect.flags & FLAG_REPEAT_Y) != 0u,
+    );
+    let t = select(clamp(tex, vec2(0.0), vec2(1.0)), fract(tex), repeat);
+
+    // wrap within the image region, without sampling outside of it
+    let region_min = rect.gradient_tex.xy;
+    let region_max = rect.gradient_tex.zw;
+    let half_texel = 0.5 / vec2<f32>(textureDimensions(texture));
+    let uv = clamp(mix(region_min, region_max, t), region_min + half_texel, region_max - half_texel);
+
+    // gradients of the unwrapped coordinates avoid seams at tile edges
+    let scale = region_max - region_min;
+    return textureSampleGrad(texture, texture_sampler, uv, tex_dx * scale, tex_dy * scale);
+}
+
 fn sdf_rounded_rect(p: vec2<f32>, b: vec2<f32>, radius: vec4<f32>) -> f32 {
     let rr = select(radius.xw, radius.yz, p.x > 0.0);
     let r = select(rr.x, rr.y, p.y > 0.0);
@@ -0,0 +1,379 @@
+use ohm_core::image::{ImageData, ImageFormat};
+use ohm_core::math::{IVec2, UVec2};
+use ohm_core::text::{
+    FontFace, FontId, FontVariations, GlyphId, RasterizedGlyph, Rasterizer, SubpixelBin,
+    VariationFaceCache,
+};
+use ttf_parser::colr::{ClipBox, CompositeMode, Paint, Painter};
+use ttf_parser::{Face, RgbaColor, Transform};
+use zeno::{Command, Format, Mask, PathBuilder, Placement, Scratch};
+
+/// Rasterizer for layered color glyphs from the `COLR` and `CPAL` tables.
+///
+/// Layers are composited with source-over blending, regardless of their
+/// composite mode. Gradients are approximated by the average color of their
+/// stops. Layers using the foreground color are painted black.
+#[derive(Default)]
+pub struct ColrRasterizer {
+    scratch: Scratch,
+    faces: VariationFaceCache,
+}
+
+impl ColrRasterizer {
+    pub fn new() -> ColrRasterizer {
+        ColrRasterizer::default()
+    }
+}
+
+impl Rasterizer for ColrRasterizer {
+    fn rasterize(
+        &mut self,
+        font_face: &FontFace,
+        glyph_id: GlyphId,
+        size: f32,
+        subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
+    ) -> Option<RasterizedGlyph> {
+        let face = self.faces.get(font_face, variations);
+        if !face.is_color_glyph(glyph_id) {
+            return None;
+        }
+
+        let scale = size / (font_face.metrics().units_per_em as f32);
+        let offset = subpixel_bin.offset();
+
+        let transform = Transform::new(scale, 0.0, 0.0, scale, offset.x, offset.y);
+        let (clips, layers) = paint_glyph(face, glyph_id, transform)?;
+        self.composite(&clips, &layers)
+    }
+
+    fn invalidate_font(&mut self, font: FontId) {
+        self.faces.invalidate_font(font);
+    }
+}
+
+fn paint_glyph<'a>(
+    face: &'a Face<'a>,
+    glyph_id: GlyphId,
+    transform: Transform,
+) -> Option<(Vec<Vec<Command>>, Vec<Layer>)> {
+    let mut painter = ColrPainter {
+        face,
+        transform,
+        transform_stack: Vec::new(),
+        outline: Vec::new(),
+        clips: Vec::new(),
+        clip_stack: Vec::new(),
+        layers: Vec::new(),
+    };
+
+    face.paint_color_glyph(glyph_id, 0, RgbaColor::new(0, 0, 0, 255), &mut painter)?;
+
+    Some((painter.clips, painter.layers))
+}
+
+impl ColrRasterizer {
+    fn composite(&mut self, clips: &[Vec<Command>], layers: &[Layer]) -> Option<RasterizedGlyph> {
+        let clip_masks = clips
+            .iter()
+            .map(|path| CoverageMask::render(path, &mut self.scratch))
+            .collect::<Vec<_>>();
+
+        let layer_masks = layers
+            .iter()
+            .map(|layer| CoverageMask::render(&layer.path, &mut self.scratch))
+            .collect::<Vec<_>>();
+
+        let mut min = IVec2::MAX;
+        let mut max = IVec2::MIN;
+        for mask in &layer_masks {
+            if mask.is_empty() {
+                continue;
+            }
+
+            min = min.min(mask.min());
+            max = max.max(mask.max());
+        }
+
+        if min.cmpge(max).any() {
+            return None;
+        }
+
+        let size = (max - min).as_uvec2();
+        let mut canvas = vec![[0.0f32; 4]; (size.x * size.y) as usize];
+
+        for (layer, mask) in layers.iter().zip(&layer_masks) {
+            if mask.is_empty() {
+                continue;
+            }
+
+            let from = mask.min().max(min);
+            let to = mask.max().min(max);
+
+            for y in from.y..to.y {
+                for x in from.x..to.x {
+                    let coverage = layer.clips.iter().fold(mask.sample(x, y), |acc, &clip| {
+                        acc * clip_masks[clip].sample(x, y)
+                    });
+
+                    if coverage <= 0.0 {
+                        continue;
+                    }
+
+                    let idx = ((y - min.y) as u32 * size.x + (x - min.x) as u32) as usize;
+                    let dst = &mut canvas[idx];
+                    let inv_alpha = 1.0 - layer.color[3] * coverage;
+                    for (dst, src) in dst.iter_mut().zip(layer.color) {
+                        *dst = src * coverage + *dst * inv_alpha;
+                    }
+                }
+            }
+        }
+
+        // rows are flipped, as glyphs are painted with Y pointing up. layers are
+        // blended premultiplied in sRGB space, but images are premultiplied in
+        // linear space on upload, so the output has straight alpha
+        let data = canvas
+            .chunks(size.x as usize)
+            .rev()
+            .flatten()
+            .flat_map(|&[r, g, b, a]| {
+                let (r, g, b) = if a > 0.0 {
+                    (r / a, g / a, b / a)
+                } else {
+                    (0.0, 0.0, 0.0)
+                };
+
+                [r, g, b, a].map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect::<Vec<_>>();
+
+        let offset = IVec2::new(min.x, -max.y).as_vec2();
+        let image = ImageData {
+            size,
+            format: ImageFormat::Srgba8,
+            data,
+            premultiplied: false,
+        };
+
+        Some(RasterizedGlyph { image, offset })
+    }
+}
+
+struct Layer {
+    path: Vec<Command>,
+    // premultiplied sRGB
+    color: [f32; 4],
+    clips: Vec<usize>,
+}
+
+struct CoverageMask {
+    data: Vec<u8>,
+    placement: Placement,
+}
+
+impl CoverageMask {
+    fn render(path: &[Command], scratch: &mut Scratch) -> CoverageMask {
+        let (data, placement) = Mask::with_scratch(path, scratch)
+            .format(Format::Alpha)
+            .render();
+        CoverageMask { data, placement }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty() || self.placement.width == 0 || self.placement.height == 0
+    }
+
+    fn min(&self) -> IVec2 {
+        IVec2::new(self.placement.left, self.placement.top)
+    }
+
+    fn max(&self) -> IVec2 {
+        self.min() + UVec2::new(self.placement.width, self.placement.height).as_ivec2()
+    }
+
+    fn sample(&self, x: i32, y: i32) -> f32 {
+        let (min, max) = (self.min(), self.max());
+        if x < min.x || y < min.y || x >= max.x || y >= max.y {
+            return 0.0;
+        }
+
+        let idx = (y - min.y) as usize * self.placement.width as usize + (x - min.x) as usize;
+        f32::from(self.data[idx]) / 255.0
+    }
+}
+
+struct ColrPainter<'a> {
+    face: &'a Face<'a>,
+    transform: Transform,
+    transform_stack: Vec<Transform>,
+    // in pixels, with the current transform applied
+    outline: Vec<Command>,
+    clips: Vec<Vec<Command>>,
+    clip_stack: Vec<usize>,
+    layers: Vec<Layer>,
+}
+
+impl ColrPainter<'_> {
+    fn push_clip_path(&mut self, path: Vec<Command>) {
+        self.clip_stack.push(self.clips.len());
+        self.clips.push(path);
+    }
+}
+
+impl<'a> Painter<'a> for ColrPainter<'a> {
+    fn outline_glyph(&mut self, glyph_id: GlyphId) {
+        self.outline.clear();
+        self.face.outline_glyph(
+            glyph_id,
+            &mut Outliner {
+                buf: &mut self.outline,
+                transform: self.transform,
+            },
+        );
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        let coords = self.face.variation_coordinates();
+        let color = match paint {
+            Paint::Solid(color) => premultiply(color),
+            Paint::LinearGradient(gradient) => average(gradient.stops(0, coords)),
+            Paint::RadialGradient(gradient) => average(gradient.stops(0, coords)),
+            Paint::SweepGradient(gradient) => average(gradient.stops(0, coords)),
+        };
+
+        // without a pending outline, the paint fills the innermost clip
+        let path = if !self.outline.is_empty() {
+            self.outline.clone()
+        } else if let Some(&clip) = self.clip_stack.last() {
+            self.clips[clip].clone()
+        } else {
+            return;
+        };
+
+        self.layers.push(Layer {
+            path,
+            color,
+            clips: self.clip_stack.clone(),
+        });
+    }
+
+    fn push_clip(&mut self) {
+        let path = std::mem::take(&mut self.outline);
+        self.push_clip_path(path);
+    }
+
+    fn push_clip_box(&mut self, clip_box: ClipBox) {
+        let mut path = Vec::new();
+        let mut outliner = Outliner {
+            buf: &mut path,
+            transform: self.transform,
+        };
+
+        use ttf_parser::OutlineBuilder;
+        outliner.move_to(clip_box.x_min, clip_box.y_min);
+        outliner.line_to(clip_box.x_max, clip_box.y_min);
+        outliner.line_to(clip_box.x_max, clip_box.y_max);
+        outliner.line_to(clip_box.x_min, clip_box.y_max);
+        outliner.close();
+
+        self.push_clip_path(path);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clip_stack.pop();
+    }
+
+    fn push_layer(&mut self, _mode: CompositeMode) {}
+
+    fn pop_layer(&mut self) {}
+
+    fn push_translate(&mut self, tx: f32, ty: f32) {
+        self.push_transform(Transform::new(1.0, 0.0, 0.0, 1.0, tx, ty));
+    }
+
+    fn push_scale(&mut self, sx: f32, sy: f32) {
+        self.push_transform(Transform::new(sx, 0.0, 0.0, sy, 0.0, 0.0));
+    }
+
+    fn push_rotate(&mut self, angle: f32) {
+        // angles are in multiples of 180 degrees
+        let (sin, cos) = (angle * std::f32::consts::PI).sin_cos();
+        self.push_transform(Transform::new(cos, sin, -sin, cos, 0.0, 0.0));
+    }
+
+    fn push_skew(&mut self, skew_x: f32, skew_y: f32) {
+        let x = (-skew_x * std::f32::consts::PI).tan();
+        let y = (skew_y * std::f32::consts::PI).tan();
+        self.push_transform(Transform::new(1.0, y, x, 1.0, 0.0, 0.0));
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.transform_stack.push(self.transform);
+        self.transform = Transform::combine(self.transform, transform);
+    }
+
+    fn pop_transform(&mut self) {
+        if let Some(transform) = self.transform_stack.pop() {
+            self.transform = transform;
+        }
+    }
+}
+
+fn premultiply(color: RgbaColor) -> [f32; 4] {
+    let a = f32::from(color.alpha) / 255.0;
+    let [r, g, b] = [color.red, color.green, color.blue].map(|v| f32::from(v) / 255.0 * a);
+    [r, g, b, a]
+}
+
+fn average(stops: impl Iterator<Item = ttf_parser::colr::ColorStop>) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+    let mut count = 0;
+
+    for stop in stops {
+        for (sum, v) in sum.iter_mut().zip(premultiply(stop.color)) {
+            *sum += v;
+        }
+        count += 1;
+    }
+
+    sum.map(|v| v / count.max(1) as f32)
+}
+
+struct Outliner<'a> {
+    buf: &'a mut Vec<Command>,
+    transform: Transform,
+}
+
+impl Outliner<'_> {
+    fn point(&self, x: f32, y: f32) -> [f32; 2] {
+        let t = &self.transform;
+        [t.a * x + t.c * y + t.e, t.b * x + t.d * y + t.f]
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outliner<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.buf.move_to(p);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.buf.line_to(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p1, p) = (self.point(x1, y1), self.point(x, y));
+        self.buf.quad_to(p1, p);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p1, p2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.buf.curve_to(p1, p2, p);
+    }
+
+    fn close(&mut self) {
+        self.buf.close();
+    }
+}
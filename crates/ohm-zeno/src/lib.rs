@@ -1,68 +1,221 @@
 use ohm_core::image::{ImageData, ImageFormat};
 use ohm_core::math::{IVec2, UVec2};
-use ohm_core::text::{FontFace, GlyphId, RasterizedGlyph, Rasterizer, SubpixelBin};
-use zeno::{Command, Format, Mask, PathBuilder, Scratch, Transform};
+use ohm_core::text::{
+    AntialiasMode, FontFace, FontId, FontVariations, GlyphId, RasterizedGlyph, Rasterizer,
+    SubpixelBin, VariationFaceCache,
+};
+use zeno::{Command, Format, Join, Mask, PathBuilder, Placement, Scratch, Stroke, Transform};
+
+mod colr;
+
+pub use self::colr::ColrRasterizer;
 
-#[derive(Default)]
 pub struct ZenoRasterizer {
     scratch: Scratch,
     command_buffer: Vec<Command>,
+    faces: VariationFaceCache,
+    antialias_mode: AntialiasMode,
+    gamma: f32,
+    // corrected coverage for each coverage value, `None` for identity gamma
+    gamma_table: Option<Box<[u8; 256]>>,
 }
 
 impl ZenoRasterizer {
+    const DEFAULT_GAMMA: f32 = 0.5;
+
     pub fn new() -> ZenoRasterizer {
-        ZenoRasterizer::default()
+        ZenoRasterizer::with_gamma(Self::DEFAULT_GAMMA)
     }
-}
 
-impl Rasterizer for ZenoRasterizer {
-    fn rasterize(
+    /// Creates a rasterizer which raises coverage to the power of `gamma`.
+    ///
+    /// Values below `1.0` make glyphs bolder, values above `1.0` make them
+    /// thinner. `1.0` leaves coverage unchanged. Default: `0.5`.
+    pub fn with_gamma(gamma: f32) -> ZenoRasterizer {
+        let mut rasterizer = ZenoRasterizer {
+            scratch: Scratch::new(),
+            command_buffer: Vec::new(),
+            faces: VariationFaceCache::new(),
+            antialias_mode: AntialiasMode::default(),
+            gamma: 1.0,
+            gamma_table: None,
+        };
+
+        rasterizer.set_gamma(gamma);
+        rasterizer
+    }
+
+    /// Returns the coverage gamma.
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    /// Sets the coverage gamma, see [`ZenoRasterizer::with_gamma`]. Glyphs
+    /// that are already rasterized are not affected.
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.gamma = gamma;
+        self.gamma_table = (gamma != 1.0).then(|| {
+            Box::new(std::array::from_fn(|v| {
+                ((v as f32 / 255.0).powf(gamma) * 255.0) as u8
+            }))
+        });
+    }
+
+    fn outline_glyph(
         &mut self,
         font_face: &FontFace,
         glyph_id: GlyphId,
-        size: f32,
-        subpixel_bin: SubpixelBin,
-    ) -> Option<RasterizedGlyph> {
+        variations: &FontVariations,
+    ) {
         self.command_buffer.clear();
 
-        font_face.ttfp_face().outline_glyph(
+        self.faces.get(font_face, variations).outline_glyph(
             glyph_id,
             &mut Outliner {
                 buf: &mut self.command_buffer,
             },
         );
+    }
 
-        let scale = size / (font_face.metrics().units_per_em as f32);
-        let offset = subpixel_bin.offset();
-
-        let (data, placement) = Mask::with_scratch(&self.command_buffer[..], &mut self.scratch)
-            .transform(Some(
-                Transform::scale(scale, scale).then_translate(offset.x, offset.y),
-            ))
-            .format(Format::Alpha)
-            .render();
-
-        if data.is_empty() {
-            return None;
-        }
-
-        let data = data
-            .chunks(placement.width as usize)
+    fn finish_glyph(
+        &self,
+        data: Vec<u8>,
+        placement: Placement,
+        image_format: ImageFormat,
+    ) -> RasterizedGlyph {
+        let row_size = placement.width as usize * image_format.pixel_size();
+        let mut data = data
+            .chunks(row_size)
             .rev()
             .flatten()
-            .map(|&v| ((v as f32 / 255.0).powf(0.5) * 255.0) as u8)
-            // .copied()
+            .copied()
             .collect::<Vec<_>>();
 
+        if let Some(table) = &self.gamma_table {
+            for v in &mut data {
+                *v = table[usize::from(*v)];
+            }
+        }
+
+        if image_format == ImageFormat::Rgba8 {
+            // zeno leaves alpha empty, use the maximum coverage
+            for px in data.chunks_exact_mut(4) {
+                px[3] = px[0].max(px[1]).max(px[2]);
+            }
+        }
+
         let offset =
             IVec2::new(placement.left, -(placement.height as i32) - placement.top).as_vec2();
         let image = ImageData {
             size: UVec2::new(placement.width, placement.height),
-            format: ImageFormat::Gray8,
+            format: image_format,
             data,
+            premultiplied: true,
         };
 
-        Some(RasterizedGlyph { image, offset })
+        RasterizedGlyph { image, offset }
+    }
+}
+
+impl Default for ZenoRasterizer {
+    fn default() -> Self {
+        ZenoRasterizer::new()
+    }
+}
+
+fn glyph_transform(font_face: &FontFace, size: f32, subpixel_bin: SubpixelBin) -> Transform {
+    let scale = size / (font_face.metrics().units_per_em as f32);
+    let offset = subpixel_bin.offset();
+    Transform::scale(scale, scale).then_translate(offset.x, offset.y)
+}
+
+impl Rasterizer for ZenoRasterizer {
+    fn rasterize(
+        &mut self,
+        font_face: &FontFace,
+        glyph_id: GlyphId,
+        size: f32,
+        subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
+    ) -> Option<RasterizedGlyph> {
+        self.outline_glyph(font_face, glyph_id, variations);
+
+        let (format, image_format) = match self.antialias_mode {
+            AntialiasMode::Grayscale => (Format::Alpha, ImageFormat::Gray8),
+            AntialiasMode::SubpixelRgb => (Format::Subpixel, ImageFormat::Rgba8),
+            AntialiasMode::SubpixelBgr => (Format::subpixel_bgra(), ImageFormat::Rgba8),
+        };
+
+        let (data, placement) = Mask::with_scratch(&self.command_buffer[..], &mut self.scratch)
+            .transform(Some(glyph_transform(font_face, size, subpixel_bin)))
+            .format(format)
+            .render();
+
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(self.finish_glyph(data, placement, image_format))
+    }
+
+    fn rasterize_dilated(
+        &mut self,
+        font_face: &FontFace,
+        glyph_id: GlyphId,
+        size: f32,
+        subpixel_bin: SubpixelBin,
+        variations: &FontVariations,
+        radius: f32,
+    ) -> Option<RasterizedGlyph> {
+        self.outline_glyph(font_face, glyph_id, variations);
+
+        let transform = glyph_transform(font_face, size, subpixel_bin);
+        let (fill, fill_placement) =
+            Mask::with_scratch(&self.command_buffer[..], &mut self.scratch)
+                .transform(Some(transform))
+                .format(Format::Alpha)
+                .render();
+
+        if fill.is_empty() {
+            return None;
+        }
+
+        if radius <= 0.0 {
+            return Some(self.finish_glyph(fill, fill_placement, ImageFormat::Gray8));
+        }
+
+        // the stroke covers the radius around the outline, and the fill
+        // covers the inside further away from it
+        let mut stroke = Stroke::new(radius * 2.0);
+        stroke.join(Join::Round).scale(false);
+
+        let (mut data, placement) = Mask::with_scratch(&self.command_buffer[..], &mut self.scratch)
+            .style(stroke)
+            .transform(Some(transform))
+            .format(Format::Alpha)
+            .render();
+
+        // the stroke extends past the fill on all sides
+        let dx = (fill_placement.left - placement.left) as usize;
+        let dy = (fill_placement.top - placement.top) as usize;
+        let width = placement.width as usize;
+
+        for (y, row) in fill.chunks(fill_placement.width as usize).enumerate() {
+            let start = (y + dy) * width + dx;
+            for (dst, &src) in data[start..start + row.len()].iter_mut().zip(row) {
+                *dst = (*dst).max(src);
+            }
+        }
+
+        Some(self.finish_glyph(data, placement, ImageFormat::Gray8))
+    }
+
+    fn invalidate_font(&mut self, font: FontId) {
+        self.faces.invalidate_font(font);
+    }
+
+    fn set_antialias_mode(&mut self, mode: AntialiasMode) {
+        self.antialias_mode = mode;
     }
 }
 
@@ -91,3 +244,189 @@ impl ttf_parser::OutlineBuilder for Outliner<'_> {
         self.buf.close();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ohm_core::math::Vec2;
+    use ohm_core::text::FontId;
+
+    use super::*;
+
+    const SIZE: f32 = 16.0;
+
+    // minimal TrueType font, glyph 1 is a right triangle filling the em square
+    fn test_font_data() -> Vec<u8> {
+        fn be16(out: &mut Vec<u8>, v: u16) {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+
+        let mut head = Vec::new();
+        head.extend_from_slice(&0x0001_0000u32.to_be_bytes()); // version
+        head.extend_from_slice(&[0; 8]); // font revision, checksum adjustment
+        head.extend_from_slice(&0x5F0F_3CF5u32.to_be_bytes()); // magic
+        be16(&mut head, 0); // flags
+        be16(&mut head, 1000); // units per em
+        head.extend_from_slice(&[0; 30]); // dates, bounding box, mac style, ...
+        be16(&mut head, 0); // short loca offsets
+        be16(&mut head, 0); // glyph data format
+
+        let mut hhea = Vec::new();
+        hhea.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        be16(&mut hhea, 800); // ascender
+        be16(&mut hhea, -200i16 as u16); // descender
+        hhea.extend_from_slice(&[0; 28]);
+
+        let mut maxp = Vec::new();
+        maxp.extend_from_slice(&0x0000_5000u32.to_be_bytes());
+        be16(&mut maxp, 2); // number of glyphs
+
+        let mut glyf = Vec::new();
+        for v in [1, 0, 0, 1000, 1000, 2, 0] {
+            // contours, bounding box, end point, instructions
+            be16(&mut glyf, v);
+        }
+        glyf.extend_from_slice(&[1; 3]); // on curve, long coordinates
+        for v in [0i16, 1000, -1000, 0, 0, 1000] {
+            be16(&mut glyf, v as u16); // x, then y deltas
+        }
+        glyf.resize(glyf.len().next_multiple_of(2), 0);
+
+        let mut loca = Vec::new();
+        for v in [0, 0, glyf.len() as u16 / 2] {
+            be16(&mut loca, v);
+        }
+
+        let family = "Test".encode_utf16().flat_map(u16::to_be_bytes);
+        let family = family.collect::<Vec<_>>();
+        let mut name = Vec::new();
+        for v in [0, 1, 6 + 12, 3, 1, 0x0409, 1, family.len() as u16, 0] {
+            be16(&mut name, v);
+        }
+        name.extend_from_slice(&family);
+
+        let tables = [
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"loca", loca),
+            (b"maxp", maxp),
+            (b"name", name),
+        ];
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        for v in [tables.len() as u16, 0, 0, 0] {
+            be16(&mut data, v);
+        }
+
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in &tables {
+            data.extend_from_slice(*tag);
+            data.extend_from_slice(&0u32.to_be_bytes()); // checksum
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len().next_multiple_of(4);
+        }
+
+        for (_, table) in &tables {
+            data.extend_from_slice(table);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+
+        data
+    }
+
+    fn rasterize(gamma: f32) -> RasterizedGlyph {
+        let face = FontFace::new(FontId(0), Arc::new(test_font_data()), 0).unwrap();
+        let mut rasterizer = ZenoRasterizer::with_gamma(gamma);
+        let bin = SubpixelBin::new(Vec2::new(0.25, 0.5));
+        let glyph = rasterizer.rasterize(&face, GlyphId(1), SIZE, bin, &FontVariations::new());
+        glyph.unwrap()
+    }
+
+    // coverage of the same triangle straight from zeno, flipped like glyphs
+    fn coverage() -> (Vec<u8>, UVec2) {
+        let mut commands = Vec::new();
+        commands.move_to([0.0, 0.0]);
+        commands.line_to([1000.0, 0.0]);
+        commands.line_to([0.0, 1000.0]);
+        commands.close();
+
+        let scale = SIZE / 1000.0;
+        let (data, placement) = Mask::new(&commands[..])
+            .transform(Some(
+                Transform::scale(scale, scale).then_translate(0.25, 0.5),
+            ))
+            .format(Format::Alpha)
+            .render();
+
+        let data = data.chunks(placement.width as usize).rev().flatten();
+        let size = UVec2::new(placement.width, placement.height);
+        (data.copied().collect(), size)
+    }
+
+    #[test]
+    fn identity_gamma_keeps_coverage() {
+        let glyph = rasterize(1.0);
+        let (coverage, size) = coverage();
+
+        // the diagonal edge is antialiased
+        assert!(coverage.iter().any(|&v| v > 0 && v < 255));
+
+        assert_eq!(glyph.image.format, ImageFormat::Gray8);
+        assert_eq!(glyph.image.size, size);
+        assert_eq!(glyph.image.data, coverage);
+    }
+
+    #[test]
+    fn gamma_below_one_raises_coverage() {
+        let glyph = rasterize(0.5);
+        let (coverage, _) = coverage();
+
+        for (&v, &expected) in glyph.image.data.iter().zip(&coverage) {
+            assert!(v >= expected);
+            assert_eq!(v == 0, expected == 0);
+            assert_eq!(v == 255, expected == 255);
+        }
+
+        assert_ne!(glyph.image.data, coverage);
+    }
+
+    #[test]
+    fn dilation_grows_coverage() {
+        let face = FontFace::new(FontId(0), Arc::new(test_font_data()), 0).unwrap();
+        let mut rasterizer = ZenoRasterizer::with_gamma(1.0);
+        let bin = SubpixelBin::new(Vec2::new(0.25, 0.5));
+        let variations = FontVariations::new();
+
+        let glyph = rasterizer.rasterize(&face, GlyphId(1), SIZE, bin, &variations);
+        let glyph = glyph.unwrap();
+        let (glyph, glyph_offset) = (glyph.image, glyph.offset);
+
+        let undilated =
+            rasterizer.rasterize_dilated(&face, GlyphId(1), SIZE, bin, &variations, 0.0);
+        assert_eq!(undilated.unwrap().image.data, glyph.data);
+
+        let dilated = rasterizer.rasterize_dilated(&face, GlyphId(1), SIZE, bin, &variations, 2.0);
+        let dilated = dilated.unwrap();
+        assert_eq!(dilated.image.format, ImageFormat::Gray8);
+        assert!(dilated.image.size.cmpge(glyph.size + 4).all());
+        assert!(dilated.image.size.cmple(glyph.size + 5).all());
+
+        // the glyph is covered at least as much
+        let shift = (glyph_offset - dilated.offset).as_uvec2();
+        for y in 0..glyph.size.y {
+            for x in 0..glyph.size.x {
+                let v = glyph.data[(y * glyph.size.x + x) as usize];
+                let (dx, dy) = (x + shift.x, y + shift.y);
+                let dilated_v = dilated.image.data[(dy * dilated.image.size.x + dx) as usize];
+                assert!(dilated_v >= v);
+            }
+        }
+
+        let sum = |data: &[u8]| data.iter().map(|&v| u32::from(v)).sum::<u32>();
+        assert!(sum(&dilated.image.data) > sum(&glyph.data));
+    }
+}
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::ops::{Deref, DerefMut};
 
 use bumpalo::collections::Vec as BumpVec;
@@ -9,16 +10,19 @@ use crate::asset::AssetPath;
 use crate::image::ImageId;
 use crate::math::{Affine2, Rect, Vec2};
 use crate::renderer::SurfaceId;
-use crate::text::{FontDatabase, TextBuffer, TextShaper};
+use crate::text::{FontDatabase, MonoAtlas, Run, TextAttrs, TextBuffer, TextShaper};
 use crate::texture::{MipmapMode, TextureCache};
 use crate::{
-    Border, ClearRect, Color, Command, CornerRadii, DrawGlyph, DrawLayer, DrawList, DrawRect, Fill,
-    FillImage, FillOptions, FillPath, Path, Scissor, Shadow,
+    BlendMode, Border, ClearRect, ClipPath, Color, Command, CornerRadii, DrawGlyph, DrawLayer,
+    DrawList, DrawRect, Edges, Fill, FillConicGradient, FillImage, FillOptions, FillPath, FillRule,
+    GlyphCell, GradientStops, LineCap, LineJoin, Path, PathBuilder, RepeatMode, SamplerMode,
+    Scissor, Shadow,
 };
 
 #[derive(Default)]
 pub struct EncoderScratch {
     bump: Bump,
+    text_buffer: RefCell<TextBuffer>,
 }
 
 impl EncoderScratch {
@@ -33,7 +37,12 @@ pub struct Encoder<'g, 's> {
     pub texture_cache: &'g mut TextureCache,
 
     bump: &'s Bump,
+    text_buffer: &'s RefCell<TextBuffer>,
     surface: SurfaceId,
+    pub(crate) scale: f32,
+    dpi_scaling: bool,
+    pixel_snap: bool,
+    opaque: bool,
     commands: BumpVec<'s, Command<'s>>,
 }
 
@@ -47,18 +56,45 @@ impl<'g, 's> Encoder<'g, 's> {
     ) -> Encoder<'g, 's> {
         Encoder {
             bump: &scratch.bump,
+            text_buffer: &scratch.text_buffer,
             font_db,
             text_shaper,
             texture_cache,
             surface,
+            scale: 1.0,
+            dpi_scaling: false,
+            pixel_snap: false,
+            opaque: false,
             commands: BumpVec::new_in(&scratch.bump),
         }
     }
 
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // coordinates are in logical pixels, multiplied by the surface scale when
+    // rendering
+    pub fn set_dpi_scaling(&mut self, enabled: bool) {
+        self.dpi_scaling = enabled;
+    }
+
+    // default for rects drawn afterwards, see `RectBuilder::pixel_snap`
+    pub fn set_pixel_snap(&mut self, enabled: bool) {
+        self.pixel_snap = enabled;
+    }
+
+    pub fn set_opaque(&mut self, opaque: bool) {
+        self.opaque = opaque;
+    }
+
     pub fn finish(self) -> DrawList<'s> {
         DrawList {
             surface: self.surface,
             commands: self.commands.into_bump_slice(),
+            opaque: self.opaque,
+            transform: Affine2::IDENTITY,
+            scale: if self.dpi_scaling { self.scale } else { 1.0 },
         }
     }
 
@@ -66,6 +102,28 @@ impl<'g, 's> Encoder<'g, 's> {
         self.commands.push(command);
     }
 
+    // shapes other than rects get their shadow from a layer around them
+    fn command_with_shadow(&mut self, command: Command<'s>, shadow: Option<Shadow>) {
+        let Some(shadow) = shadow else {
+            self.command(command);
+            return;
+        };
+
+        let commands = self.bump.alloc([command]);
+        self.command(Command::DrawLayer(DrawLayer {
+            commands,
+            tint: Color::WHITE,
+            opacity: 1.0,
+            scissor: None,
+            transform: Affine2::IDENTITY,
+            crisp_text: false,
+            blend_mode: BlendMode::Normal,
+            clip_path: None,
+            blur: 0.0,
+            shadow: Some(shadow),
+        }));
+    }
+
     pub fn clear_rect(
         &mut self,
         pos: impl Into<Vec2>,
@@ -80,6 +138,7 @@ impl<'g, 's> Encoder<'g, 's> {
     }
 
     pub fn rect(&mut self, pos: impl Into<Vec2>, size: impl Into<Vec2>) -> RectBuilder<'_, 'g, 's> {
+        let pixel_snap = self.pixel_snap;
         RectBuilder {
             encoder: self,
             pos: pos.into(),
@@ -88,6 +147,74 @@ impl<'g, 's> Encoder<'g, 's> {
             fill: Fill::Solid(Color::BLACK),
             border: None,
             shadow: None,
+            pixel_snap,
+        }
+    }
+
+    pub fn nine_patch(
+        &mut self,
+        pos: impl Into<Vec2>,
+        size: impl Into<Vec2>,
+        image: &ImageHandle,
+        insets: impl Into<Edges>,
+    ) {
+        let pos = pos.into();
+        let size = size.into();
+        let insets = insets.into();
+
+        let Some(image_size) = self.texture_cache.get_image_size(image.id()) else {
+            // the size is unknown until the image is loaded
+            self.rect(pos, size).image(image);
+            return;
+        };
+
+        let image_size = image_size.as_vec2();
+        let src_x = [
+            0.0,
+            insets.left / image_size.x,
+            1.0 - insets.right / image_size.x,
+            1.0,
+        ];
+        let src_y = [
+            0.0,
+            insets.top / image_size.y,
+            1.0 - insets.bottom / image_size.y,
+            1.0,
+        ];
+
+        // corners keep their size, unless they don't fit
+        let scale_x = (size.x / (insets.left + insets.right)).min(1.0);
+        let scale_y = (size.y / (insets.top + insets.bottom)).min(1.0);
+        let dst_x = [
+            0.0,
+            insets.left * scale_x,
+            size.x - insets.right * scale_x,
+            size.x,
+        ];
+        let dst_y = [
+            0.0,
+            insets.top * scale_y,
+            size.y - insets.bottom * scale_y,
+            size.y,
+        ];
+
+        for y in 0..3 {
+            for x in 0..3 {
+                let dst_min = Vec2::new(dst_x[x], dst_y[y]);
+                let dst_max = Vec2::new(dst_x[x + 1], dst_y[y + 1]);
+                let src_min = Vec2::new(src_x[x], src_y[y]);
+                let src_max = Vec2::new(src_x[x + 1], src_y[y + 1]);
+
+                if dst_min.cmpge(dst_max).any() || src_min.cmpge(src_max).any() {
+                    continue;
+                }
+
+                // snapping patches separately would open gaps between them
+                self.rect(pos + dst_min, dst_max - dst_min)
+                    .image(image)
+                    .image_clip_rect(Rect::new(src_min, src_max))
+                    .pixel_snap(false);
+            }
         }
     }
 
@@ -95,20 +222,121 @@ impl<'g, 's> Encoder<'g, 's> {
         self.text_inner(pos.into(), buffer);
     }
 
+    pub fn draw_text(&mut self, pos: impl Into<Vec2>, text: &str, attrs: TextAttrs) {
+        self.draw_text_wrapped(pos, text, attrs, f32::INFINITY);
+    }
+
+    pub fn draw_text_wrapped(
+        &mut self,
+        pos: impl Into<Vec2>,
+        text: &str,
+        attrs: TextAttrs,
+        max_width: f32,
+    ) {
+        let mut buffer = self.text_buffer.borrow_mut();
+        buffer.reset();
+        buffer.set_max_width(max_width);
+        buffer.push(attrs, text);
+        buffer.compute_layout(self.font_db, self.text_shaper);
+        self.text_inner(pos.into(), &buffer);
+    }
+
     fn text_inner(&mut self, pos: Vec2, buffer: &TextBuffer) {
+        // shadows and outlines of all runs go first, so they never cover glyphs
+        for run in buffer.runs() {
+            if let Some(shadow) = run.shadow {
+                let pos = pos + shadow.offset;
+                let (color, spread) = (shadow.color, shadow.spread_radius);
+                self.run_mask(pos, buffer, run, color, spread, shadow.blur_radius);
+            }
+        }
+
+        for run in buffer.runs() {
+            if let Some((color, width)) = run.outline {
+                self.run_mask(pos, buffer, run, color, width, 0.0);
+            }
+        }
+
         for run in buffer.runs() {
-            let mut pos = pos + run.pos;
-            for glyph in &buffer.glyphs()[run.glyph_range.clone()] {
-                self.command(Command::DrawGlyph(DrawGlyph {
-                    pos: pos + glyph.offset,
-                    size: run.font_size,
-                    font: run.font,
-                    glyph: glyph.glyph_id,
-                    color: run.color,
-                }));
-                pos.x += glyph.x_advance;
+            self.run_glyphs(pos, buffer, run, None);
+        }
+    }
+
+    // cells covered by the atlas are drawn as one grid, other characters are
+    // looked up in the font
+    pub fn mono_grid(
+        &mut self,
+        pos: impl Into<Vec2>,
+        atlas: &MonoAtlas,
+        cells: impl IntoIterator<Item = (u32, u32, char, Color)>,
+    ) {
+        let pos = pos.into();
+        let mut grid_cells = BumpVec::new_in(self.bump);
+
+        for (row, col, c, color) in cells {
+            if c == ' ' {
+                continue;
+            }
+
+            let cell_pos = atlas.cell_pos(row, col);
+
+            if let Some(glyph) = atlas.glyph_index(c) {
+                grid_cells.push(GlyphCell {
+                    pos: cell_pos,
+                    glyph,
+                    color,
+                });
+                continue;
+            }
+
+            let glyph = self
+                .font_db
+                .get(atlas.font())
+                .and_then(|face| face.ttfp_face().glyph_index(c));
+
+            if let Some(glyph) = glyph {
+                let glyph = atlas.draw_glyph(pos + cell_pos, glyph, color);
+                self.command(Command::DrawGlyph(glyph));
             }
         }
+
+        let glyphs = self.bump.alloc_slice_copy(atlas.glyphs());
+        let grid = atlas.grid(pos, glyphs, grid_cells.into_bump_slice());
+        self.command(Command::DrawGlyphGrid(grid));
+    }
+
+    // draws the glyphs of a run grown by `radius` in a single color. They're
+    // drawn opaque into a layer faded as a whole, so overlapping glyphs don't
+    // add up
+    fn run_mask(
+        &mut self,
+        pos: Vec2,
+        buffer: &TextBuffer,
+        run: &Run,
+        color: Color,
+        radius: f32,
+        blur: f32,
+    ) {
+        if color.a <= 0.0 {
+            return;
+        }
+
+        let opaque = Color::rgba(color.r / color.a, color.g / color.a, color.b / color.a, 1.0);
+        let mut layer = self.layer().opacity(color.a).blur(blur);
+
+        for glyph in run_draw_glyphs(pos, buffer, run, Some(opaque)) {
+            layer.command(Command::DrawGlyph(DrawGlyph {
+                dilation: Some(radius.max(0.0)),
+                ..glyph
+            }));
+        }
+    }
+
+    // `None` draws glyphs in their own colors, including color overrides
+    fn run_glyphs(&mut self, pos: Vec2, buffer: &TextBuffer, run: &Run, color: Option<Color>) {
+        for glyph in run_draw_glyphs(pos, buffer, run, color) {
+            self.command(Command::DrawGlyph(glyph));
+        }
     }
 
     pub fn fill_path(&mut self, pos: impl Into<Vec2>, path: &Path) -> FillPathBuilder<'_, 'g, 's> {
@@ -118,6 +346,7 @@ impl<'g, 's> Encoder<'g, 's> {
             path: Some(path.clone()),
             options: FillOptions::default(),
             fill: Fill::Solid(Color::BLACK),
+            shadow: None,
         }
     }
 
@@ -133,21 +362,94 @@ impl<'g, 's> Encoder<'g, 's> {
             path: Some(path.clone()),
             options: StrokeOptions::default(),
             fill: Fill::Solid(Color::BLACK),
+            shadow: None,
+        }
+    }
+
+    // fills and strokes the same path, the fill is drawn first
+    pub fn path(&mut self, pos: impl Into<Vec2>, path: &Path) -> DrawPathBuilder<'_, 'g, 's> {
+        DrawPathBuilder {
+            encoder: self,
+            pos: pos.into(),
+            path: Some(path.clone()),
+            fill: None,
+            stroke: None,
         }
     }
 
+    // all lines are filled as a single mesh, which is tessellated once and
+    // cached while the lines don't change
+    pub fn lines(&mut self, lines: &[(Vec2, Vec2)], thickness: f32, color: impl Into<Color>) {
+        let half_thickness = thickness * 0.5;
+        let mut builder = PathBuilder::new();
+
+        for &(start, end) in lines {
+            let normal = (end - start).normalize_or_zero().perp() * half_thickness;
+            if normal == Vec2::ZERO {
+                continue;
+            }
+
+            builder.move_to(start + normal);
+            builder.line_to(end + normal);
+            builder.line_to(end - normal);
+            builder.line_to(start - normal);
+            builder.close();
+        }
+
+        let path = builder.finish();
+        if path.events().is_empty() {
+            return;
+        }
+
+        // crossing lines overlap, even-odd would cut out the intersections
+        self.fill_path(Vec2::ZERO, &path)
+            .fill_rule(FillRule::NonZero)
+            .color(color);
+    }
+
     pub fn layer(&mut self) -> LayerEncoder<'_, 'g, 's> {
         let parent_commands = std::mem::replace(&mut self.commands, BumpVec::new_in(self.bump));
         LayerEncoder {
             encoder: self,
             parent_commands: Some(parent_commands),
             tint: Color::WHITE,
+            opacity: 1.0,
             scissor: None,
             transform: Affine2::IDENTITY,
+            crisp_text: false,
+            blend_mode: BlendMode::Normal,
+            clip_path: None,
+            blur: 0.0,
+            shadow: None,
         }
     }
 }
 
+pub(crate) fn run_draw_glyphs<'a>(
+    pos: Vec2,
+    buffer: &'a TextBuffer,
+    run: &'a Run,
+    color: Option<Color>,
+) -> impl Iterator<Item = DrawGlyph> + 'a {
+    let mut pos = pos + run.pos;
+    buffer.glyphs()[run.glyph_range.clone()]
+        .iter()
+        .map(move |glyph| {
+            let draw_glyph = DrawGlyph {
+                pos: pos + glyph.offset,
+                size: run.font_size,
+                font: run.font,
+                glyph: glyph.glyph_id,
+                color: color.unwrap_or_else(|| buffer.glyph_color(run, glyph)),
+                variations: run.variations,
+                format: run.glyph_format,
+                dilation: None,
+            };
+            pos.x += glyph.x_advance;
+            draw_glyph
+        })
+}
+
 pub struct RectBuilder<'e, 'g, 's> {
     encoder: &'e mut Encoder<'g, 's>,
     pos: Vec2,
@@ -156,6 +458,7 @@ pub struct RectBuilder<'e, 'g, 's> {
     fill: Fill,
     border: Option<Border>,
     shadow: Option<Shadow>,
+    pixel_snap: bool,
 }
 
 impl RectBuilder<'_, '_, '_> {
@@ -181,6 +484,9 @@ impl RectBuilder<'_, '_, '_> {
             image,
             tint: Color::WHITE,
             clip_rect: None,
+            sampler: SamplerMode::Linear,
+            repeat: RepeatMode::Clamp,
+            tile_scale: Vec2::ONE,
         });
 
         self
@@ -194,6 +500,34 @@ impl RectBuilder<'_, '_, '_> {
         self
     }
 
+    pub fn image_sampler(mut self, sampler: SamplerMode) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.sampler = sampler;
+        }
+
+        self
+    }
+
+    pub fn image_nearest(self) -> Self {
+        self.image_sampler(SamplerMode::Nearest)
+    }
+
+    pub fn image_repeat(mut self, repeat: RepeatMode) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.repeat = repeat;
+        }
+
+        self
+    }
+
+    pub fn image_tile_scale(mut self, tile_scale: impl Into<Vec2>) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.tile_scale = tile_scale.into();
+        }
+
+        self
+    }
+
     pub fn image_clip_rect(mut self, clip_rect: impl Into<Rect>) -> Self {
         if let Fill::Image(image) = &mut self.fill {
             image.clip_rect = Some(clip_rect.into());
@@ -202,6 +536,21 @@ impl RectBuilder<'_, '_, '_> {
         self
     }
 
+    pub fn conic_gradient(
+        mut self,
+        center: impl Into<Vec2>,
+        start_angle: f32,
+        stops: impl Into<GradientStops>,
+    ) -> Self {
+        self.fill = Fill::ConicGradient(FillConicGradient {
+            center: center.into(),
+            start_angle,
+            stops: stops.into(),
+        });
+
+        self
+    }
+
     pub fn corner_radii(mut self, corner_radii: impl Into<CornerRadii>) -> Self {
         self.corner_radii = corner_radii.into();
         self
@@ -220,6 +569,12 @@ impl RectBuilder<'_, '_, '_> {
         self.shadow = Some(shadow.into());
         self
     }
+
+    // rounds the rect to whole physical pixels, the shadow follows it
+    pub fn pixel_snap(mut self, pixel_snap: bool) -> Self {
+        self.pixel_snap = pixel_snap;
+        self
+    }
 }
 
 impl Drop for RectBuilder<'_, '_, '_> {
@@ -231,6 +586,7 @@ impl Drop for RectBuilder<'_, '_, '_> {
             corner_radii: self.corner_radii,
             border: self.border,
             shadow: self.shadow,
+            pixel_snap: self.pixel_snap,
         }));
     }
 }
@@ -241,6 +597,7 @@ pub struct FillPathBuilder<'e, 'g, 's> {
     path: Option<Path>,
     options: FillOptions,
     fill: Fill,
+    shadow: Option<Shadow>,
 }
 
 impl FillPathBuilder<'_, '_, '_> {
@@ -249,6 +606,23 @@ impl FillPathBuilder<'_, '_, '_> {
         self
     }
 
+    pub fn fill_rule(mut self, fill_rule: FillRule) -> Self {
+        self.options.fill_rule = fill_rule;
+        self
+    }
+
+    // maximum distance between curves and their flattened approximation
+    pub fn tolerance(mut self, tolerance: f32) -> Self {
+        self.options.tolerance = tolerance.max(0.001);
+        self
+    }
+
+    // follows the outline of the path, the spread radius is ignored
+    pub fn shadow(mut self, shadow: impl Into<Shadow>) -> Self {
+        self.shadow = Some(shadow.into());
+        self
+    }
+
     pub fn image(self, image: &ImageHandle) -> Self {
         self.image_id(image.id())
     }
@@ -266,6 +640,9 @@ impl FillPathBuilder<'_, '_, '_> {
             image,
             tint: Color::WHITE,
             clip_rect: None,
+            sampler: SamplerMode::Linear,
+            repeat: RepeatMode::Clamp,
+            tile_scale: Vec2::ONE,
         });
 
         self
@@ -279,6 +656,34 @@ impl FillPathBuilder<'_, '_, '_> {
         self
     }
 
+    pub fn image_sampler(mut self, sampler: SamplerMode) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.sampler = sampler;
+        }
+
+        self
+    }
+
+    pub fn image_nearest(self) -> Self {
+        self.image_sampler(SamplerMode::Nearest)
+    }
+
+    pub fn image_repeat(mut self, repeat: RepeatMode) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.repeat = repeat;
+        }
+
+        self
+    }
+
+    pub fn image_tile_scale(mut self, tile_scale: impl Into<Vec2>) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.tile_scale = tile_scale.into();
+        }
+
+        self
+    }
+
     pub fn image_clip_rect(mut self, clip_rect: impl Into<Rect>) -> Self {
         if let Fill::Image(image) = &mut self.fill {
             image.clip_rect = Some(clip_rect.into());
@@ -286,16 +691,32 @@ impl FillPathBuilder<'_, '_, '_> {
 
         self
     }
+
+    pub fn conic_gradient(
+        mut self,
+        center: impl Into<Vec2>,
+        start_angle: f32,
+        stops: impl Into<GradientStops>,
+    ) -> Self {
+        self.fill = Fill::ConicGradient(FillConicGradient {
+            center: center.into(),
+            start_angle,
+            stops: stops.into(),
+        });
+
+        self
+    }
 }
 
 impl Drop for FillPathBuilder<'_, '_, '_> {
     fn drop(&mut self) {
-        self.encoder.command(Command::FillPath(FillPath {
+        let command = Command::FillPath(FillPath {
             pos: self.pos,
             path: self.path.take().unwrap(),
             options: self.options,
             fill: self.fill,
-        }))
+        });
+        self.encoder.command_with_shadow(command, self.shadow);
     }
 }
 
@@ -305,6 +726,7 @@ pub struct StrokePathBuilder<'e, 'g, 's> {
     path: Option<Path>,
     options: StrokeOptions,
     fill: Fill,
+    shadow: Option<Shadow>,
 }
 
 impl StrokePathBuilder<'_, '_, '_> {
@@ -312,16 +734,201 @@ impl StrokePathBuilder<'_, '_, '_> {
         self.fill = Fill::Solid(color.into());
         self
     }
+
+    pub fn width(mut self, width: f32) -> Self {
+        self.options.line_width = width.max(0.0);
+        self
+    }
+
+    pub fn line_cap(mut self, line_cap: LineCap) -> Self {
+        self.options.line_cap = line_cap;
+        self
+    }
+
+    pub fn line_join(mut self, line_join: LineJoin) -> Self {
+        self.options.line_join = line_join;
+        self
+    }
+
+    // only used by miter joins, lyon requires at least 1
+    pub fn miter_limit(mut self, miter_limit: f32) -> Self {
+        self.options.mitter_limit = miter_limit.max(1.0);
+        self
+    }
+
+    // follows the outline of the stroke, the spread radius is ignored
+    pub fn shadow(mut self, shadow: impl Into<Shadow>) -> Self {
+        self.shadow = Some(shadow.into());
+        self
+    }
+
+    pub fn image(self, image: &ImageHandle) -> Self {
+        self.image_id(image.id())
+    }
+
+    pub fn image_path<'a>(self, image: impl Into<AssetPath<'a>>) -> Self {
+        let image = self
+            .encoder
+            .texture_cache
+            .add_image_from_path(image, MipmapMode::Enabled);
+        self.image_id(image.id())
+    }
+
+    pub fn image_id(mut self, image: ImageId) -> Self {
+        self.fill = Fill::Image(FillImage {
+            image,
+            tint: Color::WHITE,
+            clip_rect: None,
+            sampler: SamplerMode::Linear,
+            repeat: RepeatMode::Clamp,
+            tile_scale: Vec2::ONE,
+        });
+
+        self
+    }
+
+    pub fn image_tint(mut self, color: impl Into<Color>) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.tint = color.into();
+        }
+
+        self
+    }
+
+    pub fn image_sampler(mut self, sampler: SamplerMode) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.sampler = sampler;
+        }
+
+        self
+    }
+
+    pub fn image_nearest(self) -> Self {
+        self.image_sampler(SamplerMode::Nearest)
+    }
+
+    pub fn image_repeat(mut self, repeat: RepeatMode) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.repeat = repeat;
+        }
+
+        self
+    }
+
+    pub fn image_tile_scale(mut self, tile_scale: impl Into<Vec2>) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.tile_scale = tile_scale.into();
+        }
+
+        self
+    }
+
+    pub fn image_clip_rect(mut self, clip_rect: impl Into<Rect>) -> Self {
+        if let Fill::Image(image) = &mut self.fill {
+            image.clip_rect = Some(clip_rect.into());
+        }
+
+        self
+    }
+
+    pub fn conic_gradient(
+        mut self,
+        center: impl Into<Vec2>,
+        start_angle: f32,
+        stops: impl Into<GradientStops>,
+    ) -> Self {
+        self.fill = Fill::ConicGradient(FillConicGradient {
+            center: center.into(),
+            start_angle,
+            stops: stops.into(),
+        });
+
+        self
+    }
 }
 
 impl Drop for StrokePathBuilder<'_, '_, '_> {
     fn drop(&mut self) {
-        self.encoder.command(Command::StrokePath(StrokePath {
+        let command = Command::StrokePath(StrokePath {
             pos: self.pos,
             path: self.path.take().unwrap(),
             options: self.options,
             fill: self.fill,
-        }))
+        });
+        self.encoder.command_with_shadow(command, self.shadow);
+    }
+}
+
+pub struct DrawPathBuilder<'e, 'g, 's> {
+    encoder: &'e mut Encoder<'g, 's>,
+    pos: Vec2,
+    path: Option<Path>,
+    fill: Option<(Fill, FillOptions)>,
+    stroke: Option<(Fill, StrokeOptions)>,
+}
+
+impl DrawPathBuilder<'_, '_, '_> {
+    pub fn fill(mut self, color: impl Into<Color>) -> Self {
+        let options = self.fill.map(|(_, options)| options).unwrap_or_default();
+        self.fill = Some((Fill::Solid(color.into()), options));
+        self
+    }
+
+    pub fn fill_rule(mut self, fill_rule: FillRule) -> Self {
+        let (_, options) = self
+            .fill
+            .get_or_insert((Fill::Solid(Color::BLACK), FillOptions::default()));
+        options.fill_rule = fill_rule;
+        self
+    }
+
+    pub fn stroke(mut self, color: impl Into<Color>, width: f32) -> Self {
+        let mut options = self.stroke.map(|(_, options)| options).unwrap_or_default();
+        options.line_width = width.max(0.0);
+        self.stroke = Some((Fill::Solid(color.into()), options));
+        self
+    }
+
+    pub fn line_cap(mut self, line_cap: LineCap) -> Self {
+        self.stroke_options().line_cap = line_cap;
+        self
+    }
+
+    pub fn line_join(mut self, line_join: LineJoin) -> Self {
+        self.stroke_options().line_join = line_join;
+        self
+    }
+
+    fn stroke_options(&mut self) -> &mut StrokeOptions {
+        let (_, options) = self
+            .stroke
+            .get_or_insert((Fill::Solid(Color::BLACK), StrokeOptions::default()));
+        options
+    }
+}
+
+impl Drop for DrawPathBuilder<'_, '_, '_> {
+    fn drop(&mut self) {
+        // both commands share the path, cache lookups compare it by pointer
+        let path = self.path.take().unwrap();
+
+        if let Some((fill, options)) = self.fill {
+            self.encoder.command(Command::FillPath(FillPath {
+                pos: self.pos,
+                path: path.clone(),
+                options,
+                fill,
+            }));
+        }
+
+        if let Some((fill, options)) = self.stroke {
+            self.encoder.command(Command::StrokePath(StrokePath {
+                pos: self.pos,
+                path,
+                options,
+                fill,
+            }));
+        }
     }
 }
 
@@ -329,8 +936,14 @@ pub struct LayerEncoder<'e, 'g, 's> {
     encoder: &'e mut Encoder<'g, 's>,
     parent_commands: Option<BumpVec<'s, Command<'s>>>,
     tint: Color,
+    opacity: f32,
     scissor: Option<Scissor>,
     transform: Affine2,
+    crisp_text: bool,
+    blend_mode: BlendMode,
+    clip_path: Option<ClipPath>,
+    blur: f32,
+    shadow: Option<Shadow>,
 }
 
 impl LayerEncoder<'_, '_, '_> {
@@ -339,6 +952,13 @@ impl LayerEncoder<'_, '_, '_> {
         self
     }
 
+    // fades the layer as a whole, overlapping contents don't show through
+    // each other
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
     pub fn transform(mut self, transform: impl Into<Affine2>) -> Self {
         if self.transform == Affine2::IDENTITY {
             self.transform = transform.into();
@@ -348,6 +968,55 @@ impl LayerEncoder<'_, '_, '_> {
 
         self
     }
+
+    // the scissor is in the local space of the layer, before its transform
+    pub fn scissor(
+        mut self,
+        pos: impl Into<Vec2>,
+        size: impl Into<Vec2>,
+        corner_radii: impl Into<CornerRadii>,
+    ) -> Self {
+        self.scissor = Some(Scissor {
+            pos: pos.into(),
+            size: size.into(),
+            corner_radii: corner_radii.into(),
+        });
+        self
+    }
+
+    pub fn crisp_text(mut self, crisp_text: bool) -> Self {
+        self.crisp_text = crisp_text;
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    // the path is in the local space of the layer, before its transform
+    pub fn clip_path(mut self, path: &Path, options: FillOptions) -> Self {
+        self.clip_path = Some(ClipPath {
+            path: path.clone(),
+            options,
+        });
+        self
+    }
+
+    // blurs the layer as a whole, the standard deviation is half the radius.
+    // The radius is in the local space of the layer, and the blur spreads
+    // past its scissor
+    pub fn blur(mut self, radius: f32) -> Self {
+        self.blur = radius.max(0.0);
+        self
+    }
+
+    // casts a shadow following the alpha of the contents, beneath them. The
+    // spread radius is ignored
+    pub fn shadow(mut self, shadow: impl Into<Shadow>) -> Self {
+        self.shadow = Some(shadow.into());
+        self
+    }
 }
 
 impl<'g, 's> Deref for LayerEncoder<'_, 'g, 's> {
@@ -368,11 +1037,21 @@ impl Drop for LayerEncoder<'_, '_, '_> {
     fn drop(&mut self) {
         let parent_commands = self.parent_commands.take().unwrap();
         let child_commands = std::mem::replace(&mut self.encoder.commands, parent_commands);
+        let clip_path = self
+            .clip_path
+            .take()
+            .map(|clip_path| &*self.encoder.bump.alloc(clip_path));
         self.encoder.command(Command::DrawLayer(DrawLayer {
             commands: child_commands.into_bump_slice(),
             tint: self.tint,
+            opacity: self.opacity,
             scissor: self.scissor,
             transform: self.transform,
+            crisp_text: self.crisp_text,
+            blend_mode: self.blend_mode,
+            clip_path,
+            blur: self.blur,
+            shadow: self.shadow,
         }));
     }
 }
@@ -1,19 +1,24 @@
+use std::sync::Arc;
+
 use ohm_core::renderer::PathCache;
 
 use crate::asset::AssetSources;
-use crate::encoder::EncoderScratch;
-use crate::image::ImageDecoders;
+use crate::encoder::{run_draw_glyphs, EncoderScratch};
+use crate::image::{ImageDecodePool, ImageDecoders, ImageFormat};
+use crate::math::{Rect, Vec2};
 use crate::renderer::{Renderer, SurfaceId};
 use crate::text::{
-    DefaultFontDatabase, DefaultTextShaper, FontDatabase, FontRasterizers, TextShaper,
+    AntialiasMode, DefaultFontDatabase, DefaultTextShaper, FontDatabase, FontId, FontRasterizers,
+    GlyphKey, MonoAtlas, Rasterizer, TextBuffer, TextShaper,
 };
-use crate::texture::TextureCache;
-use crate::{DrawList, Encoder, Result};
+use crate::texture::{TextureCache, TextureId};
+use crate::{Color, DrawList, Encoder, Result};
 
 pub struct Graphics {
     pub renderer: Box<dyn Renderer>,
     pub asset_sources: AssetSources,
     pub image_decoders: ImageDecoders,
+    pub image_decode_pool: Option<ImageDecodePool>,
     pub texture_cache: TextureCache,
     pub path_cache: PathCache,
     pub font_db: Box<dyn FontDatabase>,
@@ -34,6 +39,7 @@ impl Graphics {
             renderer: Box::new(renderer),
             asset_sources: AssetSources::new(),
             image_decoders: ImageDecoders::new(),
+            image_decode_pool: None,
             texture_cache: TextureCache::new(),
             path_cache: PathCache::new(),
             font_db: Box::new(DefaultFontDatabase::new()),
@@ -49,7 +55,7 @@ impl Graphics {
     fn default_init(&mut self) {
         #[cfg(feature = "image")]
         self.image_decoders
-            .add_decoder(ohm_image::ImageImageDecoder);
+            .add_decoder(ohm_image::ImageImageDecoder::new());
 
         #[cfg(feature = "resvg")]
         self.image_decoders
@@ -59,6 +65,10 @@ impl Graphics {
         self.font_rasterizers
             .add_rasterizer(ohm_image::EmbeddedImageRasterizer);
 
+        #[cfg(feature = "zeno")]
+        self.font_rasterizers
+            .add_rasterizer(ohm_zeno::ColrRasterizer::new());
+
         #[cfg(feature = "freetype")]
         self.font_rasterizers
             .add_rasterizer(ohm_freetype::FreetypeRasterizer::new());
@@ -68,24 +78,152 @@ impl Graphics {
             .add_rasterizer(ohm_zeno::ZenoRasterizer::new());
     }
 
+    // decoders and sources are snapshotted, so call this again after adding
+    // new ones
+    pub fn enable_background_decoding(&mut self, num_threads: usize) {
+        self.image_decode_pool = Some(ImageDecodePool::new(
+            num_threads,
+            Arc::new(self.asset_sources.clone()),
+            Arc::new(self.image_decoders.clone()),
+        ));
+    }
+
     pub fn create_encoder<'g, 's>(
         &'g mut self,
         scratch: &'s EncoderScratch,
         surface: SurfaceId,
     ) -> Encoder<'g, 's> {
-        Encoder::new(
+        let mut encoder = Encoder::new(
             scratch,
             &mut *self.font_db,
             &mut *self.text_shaper,
             &mut self.texture_cache,
             surface,
-        )
+        );
+        encoder.scale = self.renderer.get_surface_scale(surface);
+        encoder
+    }
+
+    // reloads the font from its source, e.g. after the font file changed
+    pub fn invalidate_font(&mut self, font: FontId) -> Result<()> {
+        let mut commands = Vec::new();
+        self.font_db.invalidate_font(font);
+        self.texture_cache.invalidate_font(font, &mut commands);
+        self.font_rasterizers.invalidate_font(font);
+        self.text_shaper.invalidate_font(font);
+        self.renderer.update_textures(&mut commands)
+    }
+
+    // glyphs are rasterized again with the new mode when drawn
+    pub fn set_antialias_mode(&mut self, mode: AntialiasMode) -> Result<()> {
+        let mut commands = Vec::new();
+        self.font_rasterizers.set_antialias_mode(mode);
+        self.texture_cache.invalidate_glyphs(&mut commands);
+        self.renderer.update_textures(&mut commands)
+    }
+
+    // releases all texture memory, e.g. when switching documents
+    pub fn clear_textures(&mut self) -> Result<()> {
+        let mut commands = Vec::new();
+        self.texture_cache.clear_all(&mut commands);
+        self.renderer.update_textures(&mut commands)
+    }
+
+    // pre-rasterizes the glyphs at `scale`, the scale of the surface the grid
+    // is drawn on with DPI scaling, or `1.0`. Glyphs drawn at other scales
+    // are rasterized when first drawn
+    pub fn build_monospace_atlas(
+        &mut self,
+        font: FontId,
+        size: f32,
+        scale: f32,
+    ) -> Result<MonoAtlas> {
+        let face = self.font_db.get_or_load(font)?;
+        let atlas = MonoAtlas::new(face, size);
+
+        for key in atlas.glyph_keys(scale) {
+            self.texture_cache.add_glyph(key);
+        }
+
+        let mut commands = Vec::new();
+        self.texture_cache.load_glyphs(
+            &*self.font_db,
+            &mut self.font_rasterizers,
+            &mut commands,
+        )?;
+        self.renderer.update_textures(&mut commands)?;
+
+        Ok(atlas)
+    }
+
+    // rasterizes the glyphs of a buffer placed at `pos`, for drawing them
+    // with a custom renderer. `pos` and the buffer are in the units of a draw
+    // list with the given `scale` (`1.0` for physical pixels), and glyphs are
+    // rasterized and placed like the batcher does for such a list. Shadows
+    // and outlines aren't included, and the textures are only valid until
+    // glyphs are loaded again
+    pub fn resolve_glyphs(
+        &mut self,
+        pos: impl Into<Vec2>,
+        buffer: &TextBuffer,
+        scale: f32,
+    ) -> Result<Vec<ResolvedGlyph>> {
+        let pos = pos.into();
+        let glyphs = buffer
+            .runs()
+            .iter()
+            .flat_map(|run| run_draw_glyphs(pos, buffer, run, None))
+            .collect::<Vec<_>>();
+
+        for glyph in &glyphs {
+            self.texture_cache.add_glyph(GlyphKey::new(glyph, scale));
+        }
+
+        let mut commands = Vec::new();
+        self.texture_cache.load_glyphs(
+            &*self.font_db,
+            &mut self.font_rasterizers,
+            &mut commands,
+        )?;
+        self.renderer.update_textures(&mut commands)?;
+
+        let resolved = glyphs
+            .iter()
+            .filter_map(|glyph| {
+                // empty glyphs, like spaces, aren't allocated
+                let allocated = self.texture_cache.get_glyph(&GlyphKey::new(glyph, scale))?;
+
+                // matches the snapping of the batcher
+                let pos = (glyph.pos * scale).trunc() + allocated.offset;
+                let size = allocated.rect.size().as_vec2();
+                let texture_size = allocated.texture_size.as_vec2();
+
+                Some(ResolvedGlyph {
+                    texture: allocated.texture,
+                    format: allocated.format,
+                    uv: Rect::new(
+                        allocated.rect.min.as_vec2() / texture_size,
+                        allocated.rect.max.as_vec2() / texture_size,
+                    ),
+                    rect: Rect::new(pos, pos + size),
+                    color: glyph.color,
+                })
+            })
+            .collect();
+
+        Ok(resolved)
     }
 
     pub fn render(&mut self, draw_lists: &[DrawList]) -> Result<()> {
         {
+            // atlases must fit into the renderer's textures
+            let max_texture_size = self.renderer.capabilities().max_texture_size;
+            self.texture_cache
+                .set_max_texture_size(Some(max_texture_size));
+
             let mut commands = Vec::new();
             self.texture_cache.add_glyphs_from_lists(draw_lists);
+            self.texture_cache.add_gradients_from_lists(draw_lists);
             self.texture_cache
                 .set_image_sizes_from_lists(&mut self.path_cache, draw_lists);
             self.texture_cache.load_glyphs(
@@ -93,11 +231,17 @@ impl Graphics {
                 &mut self.font_rasterizers,
                 &mut commands,
             )?;
-            self.texture_cache.load_images(
-                &self.asset_sources,
-                &self.image_decoders,
-                &mut commands,
-            )?;
+            if let Some(pool) = &self.image_decode_pool {
+                self.texture_cache
+                    .load_images_in_background(pool, &mut commands)?;
+            } else {
+                self.texture_cache.load_images(
+                    &self.asset_sources,
+                    &self.image_decoders,
+                    &mut commands,
+                )?;
+            }
+            self.texture_cache.load_gradients(&mut commands)?;
             self.renderer.update_textures(&mut commands)?;
         }
 
@@ -109,3 +253,17 @@ impl Graphics {
         self.renderer.present()
     }
 }
+
+// a rasterized glyph, see `Graphics::resolve_glyphs`
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedGlyph {
+    pub texture: TextureId,
+    // color glyphs are `Srgba8` and drawn as is, others are coverage to be
+    // multiplied by `color`
+    pub format: ImageFormat,
+    // normalized texture coordinates
+    pub uv: Rect,
+    // position on the screen, in physical pixels
+    pub rect: Rect,
+    pub color: Color,
+}
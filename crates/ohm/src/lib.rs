@@ -1,6 +1,8 @@
 pub use ohm_core::*;
 
 pub mod text {
+    use std::sync::Arc;
+
     pub use ohm_core::text::*;
     use ohm_core::Result;
     #[cfg(feature = "fontdb")]
@@ -8,7 +10,7 @@ pub mod text {
     #[cfg(feature = "rustybuzz")]
     pub use ohm_rustybuzz::RustybuzzShaper;
     #[cfg(feature = "zeno")]
-    pub use ohm_zeno::ZenoRasterizer;
+    pub use ohm_zeno::{ColrRasterizer, ZenoRasterizer};
 
     #[derive(Debug, Default)]
     pub struct DefaultTextShaper {
@@ -31,9 +33,12 @@ pub mod text {
             text: &str,
             size: f32,
             is_rtl: bool,
+            features: &OtFeatures,
+            variations: &FontVariations,
             buf: &mut Vec<ShapedGlyph>,
         ) {
-            self.inner.shape(font_face, text, size, is_rtl, buf);
+            self.inner
+                .shape(font_face, text, size, is_rtl, features, variations, buf);
         }
     }
 
@@ -67,6 +72,30 @@ pub mod text {
         fn get_or_load(&mut self, id: FontId) -> Result<&FontFace> {
             self.inner.get_or_load(id)
         }
+
+        fn add_face(
+            &mut self,
+            data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+            index: u32,
+        ) -> Result<FontId> {
+            self.inner.add_face(data, index)
+        }
+
+        fn invalidate_font(&mut self, font: FontId) {
+            self.inner.invalidate_font(font)
+        }
+
+        fn families(&self) -> Vec<String> {
+            self.inner.families()
+        }
+
+        fn set_last_resort_font(&mut self, font: Option<FontId>) {
+            self.inner.set_last_resort_font(font)
+        }
+
+        fn last_resort_font(&self) -> Option<FontId> {
+            self.inner.last_resort_font()
+        }
     }
 }
 
@@ -80,4 +109,4 @@ mod encoder;
 mod graphics;
 
 pub use self::encoder::{Encoder, EncoderScratch};
-pub use self::graphics::Graphics;
+pub use self::graphics::{Graphics, ResolvedGlyph};
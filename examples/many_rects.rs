@@ -0,0 +1,53 @@
+//! Renders 10k rectangles to an offscreen surface and prints the average
+//! frame time, to measure the per-frame overhead of the renderer.
+
+use std::time::{Duration, Instant};
+
+use ohm::math::{vec2, UVec2};
+use ohm::{Color, EncoderScratch, Graphics};
+
+const NUM_RECTS: usize = 10_000;
+const NUM_FRAMES: u32 = 300;
+const WARMUP_FRAMES: u32 = 10;
+
+fn main() {
+    let mut graphics = Graphics::new_wgpu();
+    let surface = graphics
+        .renderer
+        .create_offscreen_surface(UVec2::new(1024, 1024))
+        .unwrap();
+
+    let mut total = Duration::ZERO;
+
+    for frame in 0..WARMUP_FRAMES + NUM_FRAMES {
+        let start = Instant::now();
+
+        // the scratch arena only grows, so use a fresh one every frame
+        let encoder_scratch = EncoderScratch::new();
+        let mut encoder = graphics.create_encoder(&encoder_scratch, surface);
+
+        for i in 0..NUM_RECTS {
+            let x = (i % 100) as f32 * 10.0;
+            let y = (i / 100) as f32 * 10.0;
+            let t = (i + frame as usize) as f32 / NUM_RECTS as f32;
+
+            encoder
+                .rect(vec2(x, y), vec2(8.0, 8.0))
+                .color(Color::rgb(t.fract(), 0.5, 1.0 - t.fract()))
+                .corner_radii(2.0);
+        }
+
+        let draw_list = encoder.finish();
+        graphics.render(&[draw_list]).unwrap();
+        graphics.present().unwrap();
+
+        if frame >= WARMUP_FRAMES {
+            total += start.elapsed();
+        }
+    }
+
+    println!(
+        "{NUM_RECTS} rects: {:.3} ms/frame over {NUM_FRAMES} frames",
+        total.as_secs_f64() * 1000.0 / f64::from(NUM_FRAMES)
+    );
+}